@@ -0,0 +1,384 @@
+// Derive macro front-end for fractic_aws_dynamo::dynamo_object!.
+//
+// Usage (on the data struct):
+//
+//   #[derive(Debug, Serialize, Deserialize, Default, Clone, DynamoObject)]
+//   #[dynamo(label = "TASK", id = "uuid", nesting = "inline_child_of(GROUP)")]
+//   pub struct TaskData {
+//       #[dynamo(sk_component)]
+//       status: String,
+//       title: String,
+//   }
+//
+// This generates the 'Task' wrapper struct and its DynamoObject impl (exactly
+// as dynamo_object! does), as well as a 'TaskDataPatch' struct with all fields
+// optional, which can be passed to DynamoUtil::update_fields.
+//
+// Struct attributes (#[dynamo(...)]):
+//   label = "..."       Required. The object's ID label.
+//   id = "..."          IdLogic: uuid (default), timestamp, timestamp_millis,
+//                       ulid, ksuid, singleton, singleton_family(<field>), or
+//                       custom(<field>).
+//   nesting = "..."     NestingLogic: root (default), sharded_root(<n>),
+//                       top_level_child_of_any, top_level_child_of(<LABEL>),
+//                       top_level_child_of_path(<LABEL>, ...),
+//                       inline_child_of_any, inline_child_of(<LABEL>), or
+//                       inline_child_of_path(<LABEL>, ...).
+//   object = "..."      Name of the generated wrapper struct. Defaults to the
+//                       data struct's name without the 'Data' suffix.
+//   indexes = "..."     Path to a function returning the object's
+//                       Vec<IndexDefinition<..>>.
+//   versioned           Enables optimistic locking.
+//   preserve_unknown_fields
+//                       Keeps unknown fields when replacing items (see
+//                       DynamoObject::preserve_unknown_fields).
+//   migrations = "..."  Path to a function returning the object's
+//                       Migrations<..>.
+//
+// Field attributes (#[dynamo(...)]):
+//   sk_component        Uses the field (via ToString) as the object's sk
+//                       component (see DynamoObject::sk_component).
+//   skip_patch          Leaves the field out of the generated patch struct.
+//   alias = "..."       Legacy attribute name of the field, which is still
+//                       accepted when parsing items (see
+//                       DynamoObject::attribute_aliases). Can be repeated.
+//
+// The same items as for dynamo_object! must be in scope at the call site
+// (Serialize, Deserialize, PkSk, AutoFields, DynamoObject, DynamoObjectData
+// and IdLogic).
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, Ident, LitInt, LitStr, Path};
+
+#[proc_macro_derive(DynamoObject, attributes(dynamo))]
+pub fn derive_dynamo_object(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+#[derive(Default)]
+struct ObjectAttrs {
+    label: Option<LitStr>,
+    id: Option<LitStr>,
+    nesting: Option<LitStr>,
+    object: Option<LitStr>,
+    indexes: Option<LitStr>,
+    versioned: bool,
+    preserve_unknown_fields: bool,
+    migrations: Option<LitStr>,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let data_ident = &input.ident;
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "DynamoObject can't be derived for generic structs",
+        ));
+    }
+
+    // Struct attributes:
+    let mut attrs = ObjectAttrs::default();
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("dynamo"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("label") {
+                attrs.label = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("id") {
+                attrs.id = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("nesting") {
+                attrs.nesting = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("object") {
+                attrs.object = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("indexes") {
+                attrs.indexes = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("versioned") {
+                attrs.versioned = true;
+            } else if meta.path.is_ident("preserve_unknown_fields") {
+                attrs.preserve_unknown_fields = true;
+            } else if meta.path.is_ident("migrations") {
+                attrs.migrations = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unsupported dynamo attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    let label = attrs.label.ok_or_else(|| {
+        syn::Error::new_spanned(data_ident, "missing #[dynamo(label = \"...\")] attribute")
+    })?;
+    let object_ident = match &attrs.object {
+        Some(object) => object.parse::<Ident>()?,
+        None => match data_ident.to_string().strip_suffix("Data") {
+            Some(name) if !name.is_empty() => format_ident!("{}", name),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    data_ident,
+                    "data struct name doesn't end in 'Data', so the object name must be given with #[dynamo(object = \"...\")]",
+                ))
+            }
+        },
+    };
+    let id_logic = match &attrs.id {
+        Some(id) => id_logic(id, data_ident)?,
+        None => quote!(::fractic_aws_dynamo::schema::IdLogic::Uuid),
+    };
+    let nesting_logic = match &attrs.nesting {
+        Some(nesting) => nesting_logic(nesting)?,
+        None => quote!(::fractic_aws_dynamo::schema::NestingLogic::Root),
+    };
+
+    // Fields:
+    let fields: Vec<&syn::Field> = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unit => Vec::new(),
+            Fields::Unnamed(_) => {
+                return Err(syn::Error::new_spanned(
+                    data_ident,
+                    "DynamoObject can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                data_ident,
+                "DynamoObject can only be derived for structs",
+            ))
+        }
+    };
+    let mut sk_component: Option<Ident> = None;
+    let mut aliases = Vec::new();
+    let mut patch_fields = Vec::new();
+    for field in fields {
+        let field_ident = field.ident.as_ref().expect("named field");
+        let mut skip_patch = false;
+        let mut field_aliases: Vec<LitStr> = Vec::new();
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("dynamo"))
+        {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("sk_component") {
+                    if sk_component.is_some() {
+                        return Err(meta.error("only one field can be the sk_component"));
+                    }
+                    sk_component = Some(field_ident.clone());
+                } else if meta.path.is_ident("skip_patch") {
+                    skip_patch = true;
+                } else if meta.path.is_ident("alias") {
+                    field_aliases.push(meta.value()?.parse()?);
+                } else {
+                    return Err(meta.error("unsupported dynamo field attribute"));
+                }
+                Ok(())
+            })?;
+        }
+        let rename = serde_attr_value(&field.attrs, "rename")?;
+        if !field_aliases.is_empty() {
+            // The alias maps to the field's attribute name, which can't be
+            // derived here if the struct renames all fields.
+            let name = match &rename {
+                Some(rename) => rename.value(),
+                None if serde_attr_value(&input.attrs, "rename_all")?.is_some() => {
+                    return Err(syn::Error::new_spanned(
+                        field_ident,
+                        "aliased fields of structs with #[serde(rename_all)] need an explicit #[serde(rename = \"...\")]",
+                    ))
+                }
+                None => field_ident.to_string().trim_start_matches("r#").to_string(),
+            };
+            aliases.extend(
+                field_aliases
+                    .into_iter()
+                    .map(|alias| quote!((#alias, #name))),
+            );
+        }
+        if !skip_patch {
+            let vis = &field.vis;
+            let ty = &field.ty;
+            let rename = rename.map(|rename| quote!(#[serde(rename = #rename)]));
+            patch_fields.push(quote! {
+                #rename
+                #[serde(skip_serializing_if = "Option::is_none")]
+                #vis #field_ident: Option<#ty>,
+            });
+        }
+    }
+
+    // Optional dynamo_object! arguments (in the order expected by the macro):
+    let indexes_arg = match &attrs.indexes {
+        Some(indexes) => {
+            let indexes = indexes.parse::<Path>()?;
+            Some(quote!(, indexes: #indexes()))
+        }
+        None => None,
+    };
+    let versioned_arg = attrs.versioned.then(|| quote!(, versioned: true));
+    let preserve_unknown_fields_arg = attrs
+        .preserve_unknown_fields
+        .then(|| quote!(, preserve_unknown_fields: true));
+    let sk_component_arg = sk_component
+        .map(|field| quote!(, sk_component: |data: &#data_ident| data.#field.to_string()));
+    let aliases_arg = (!aliases.is_empty()).then(|| quote!(, aliases: &[#(#aliases),*]));
+    let migrations_arg = match &attrs.migrations {
+        Some(migrations) => {
+            let migrations = migrations.parse::<Path>()?;
+            Some(quote!(, migrations: #migrations()))
+        }
+        None => None,
+    };
+
+    let vis = &input.vis;
+    let patch_ident = format_ident!("{}Patch", data_ident);
+    let patch_doc = format!(
+        "Partial update of {}, for use with DynamoUtil::update_fields. Only fields which are Some(...) are written.",
+        data_ident
+    );
+    let rename_all = serde_attr_value(&input.attrs, "rename_all")?
+        .map(|rename_all| quote!(#[serde(rename_all = #rename_all)]));
+
+    Ok(quote! {
+        ::fractic_aws_dynamo::dynamo_object!(
+            #object_ident, #data_ident, #label, #id_logic, #nesting_logic
+            #indexes_arg
+            #versioned_arg
+            #preserve_unknown_fields_arg
+            #sk_component_arg
+            #aliases_arg
+            #migrations_arg
+        );
+
+        #[doc = #patch_doc]
+        #[derive(Debug, Default, Clone, ::serde::Serialize)]
+        #rename_all
+        #vis struct #patch_ident {
+            #(#patch_fields)*
+        }
+    })
+}
+
+// Splits 'name(arg, ...)' into the name and its arguments.
+fn split_call(value: &str) -> (&str, Vec<&str>) {
+    match value.split_once('(') {
+        Some((name, args)) => (
+            name.trim(),
+            args.trim_end()
+                .trim_end_matches(')')
+                .split(',')
+                .map(str::trim)
+                .filter(|arg| !arg.is_empty())
+                .collect(),
+        ),
+        None => (value.trim(), Vec::new()),
+    }
+}
+
+fn id_logic(lit: &LitStr, data_ident: &Ident) -> syn::Result<TokenStream2> {
+    let value = lit.value();
+    let id_logic = quote!(::fractic_aws_dynamo::schema::IdLogic);
+    let field_fn = |args: &[&str]| match args {
+        [field] => {
+            let field = syn::parse_str::<Ident>(field)
+                .map_err(|_| syn::Error::new_spanned(lit, "invalid field name"))?;
+            Ok(quote!(Box::new(|data: &#data_ident| data.#field.to_string())))
+        }
+        _ => Err(syn::Error::new_spanned(lit, "expected a single field name")),
+    };
+    Ok(match split_call(&value) {
+        ("uuid", args) if args.is_empty() => quote!(#id_logic::Uuid),
+        ("timestamp", args) if args.is_empty() => quote!(#id_logic::Timestamp),
+        ("timestamp_millis", args) if args.is_empty() => quote!(#id_logic::TimestampMillis),
+        ("ulid", args) if args.is_empty() => quote!(#id_logic::Ulid),
+        ("ksuid", args) if args.is_empty() => quote!(#id_logic::Ksuid),
+        ("singleton", args) if args.is_empty() => quote!(#id_logic::Singleton),
+        ("singleton_family", args) => {
+            let key = field_fn(&args)?;
+            quote!(#id_logic::SingletonFamily(#key))
+        }
+        ("custom", args) => {
+            let key = field_fn(&args)?;
+            quote!(#id_logic::Custom(#key))
+        }
+        _ => return Err(syn::Error::new_spanned(lit, "unsupported id logic")),
+    })
+}
+
+fn nesting_logic(lit: &LitStr) -> syn::Result<TokenStream2> {
+    let value = lit.value();
+    let nesting_logic = quote!(::fractic_aws_dynamo::schema::NestingLogic);
+    let single_label = |args: &[&str]| match args {
+        [label] => Ok(*label),
+        _ => Err(syn::Error::new_spanned(
+            lit,
+            "expected a single object label",
+        )),
+    };
+    Ok(match split_call(&value) {
+        ("root", args) if args.is_empty() => quote!(#nesting_logic::Root),
+        ("sharded_root", args) => {
+            let shards = match args.as_slice() {
+                [shards] => shards.parse::<u8>().ok().filter(|shards| *shards > 0),
+                _ => None,
+            }
+            .ok_or_else(|| syn::Error::new_spanned(lit, "expected the number of shards (1-255)"))?;
+            let shards = LitInt::new(&format!("{}u8", shards), lit.span());
+            quote!(#nesting_logic::ShardedRoot(#shards))
+        }
+        ("top_level_child_of_any", args) if args.is_empty() => {
+            quote!(#nesting_logic::TopLevelChildOfAny)
+        }
+        ("top_level_child_of", args) => {
+            let label = single_label(&args)?;
+            quote!(#nesting_logic::TopLevelChildOf(#label))
+        }
+        ("top_level_child_of_path", args) if !args.is_empty() => {
+            quote!(#nesting_logic::TopLevelChildOfPath(&[#(#args),*]))
+        }
+        ("inline_child_of_any", args) if args.is_empty() => {
+            quote!(#nesting_logic::InlineChildOfAny)
+        }
+        ("inline_child_of", args) => {
+            let label = single_label(&args)?;
+            quote!(#nesting_logic::InlineChildOf(#label))
+        }
+        ("inline_child_of_path", args) if !args.is_empty() => {
+            quote!(#nesting_logic::InlineChildOfPath(&[#(#args),*]))
+        }
+        _ => return Err(syn::Error::new_spanned(lit, "unsupported nesting logic")),
+    })
+}
+
+// Finds the value of a '#[serde(<key> = "...")]' attribute, if present.
+fn serde_attr_value(attrs: &[Attribute], key: &str) -> syn::Result<Option<LitStr>> {
+    let mut found = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident(key) && meta.input.peek(syn::Token![=]) {
+                found = Some(meta.value()?.parse::<LitStr>()?);
+            } else if meta.input.peek(syn::Token![=]) {
+                // Skip values of other serde attributes.
+                meta.value()?.parse::<syn::Expr>()?;
+            } else if meta.input.peek(syn::token::Paren) {
+                // Skip nested lists, such as rename(serialize = "...").
+                meta.parse_nested_meta(|nested| {
+                    if nested.input.peek(syn::Token![=]) {
+                        nested.value()?.parse::<syn::Expr>()?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(found)
+}
@@ -1,8 +1,13 @@
 use fractic_env_config::{define_env_config, define_env_variable, EnvConfigEnum};
 
 define_env_variable!(DYNAMO_REGION);
+// Optional, see TableNaming.
+define_env_variable!(DYNAMO_TABLE_PREFIX);
+define_env_variable!(DYNAMO_TABLE_SUFFIX);
 
 define_env_config!(
     DynamoEnvConfig,
     DynamoRegion => DYNAMO_REGION,
+    DynamoTablePrefix => DYNAMO_TABLE_PREFIX,
+    DynamoTableSuffix => DYNAMO_TABLE_SUFFIX,
 );
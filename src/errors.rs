@@ -1,12 +1,48 @@
 use fractic_server_error::{define_client_error, define_internal_error, define_user_error};
 
 define_user_error!(DynamoNotFound, "Requested item does not exist.");
+define_user_error!(DynamoAlreadyExists, "Item already exists.");
 define_internal_error!(DynamoCalloutError, "Generic DynamoDB error.");
 define_internal_error!(
     DynamoItemParsingError,
     "DynamoDB item parsing error: {details}.",
     { details: &str }
 );
+define_internal_error!(
+    DynamoEncryptionError,
+    "DynamoDB field encryption error: {details}.",
+    { details: &str }
+);
+define_internal_error!(
+    DynamoExportFailed,
+    "DynamoDB export failed: {details}.",
+    { details: &str }
+);
+define_internal_error!(
+    DynamoTableNotReady,
+    "DynamoDB table did not become ready: {details}.",
+    { details: &str }
+);
+define_internal_error!(
+    DynamoTimeout,
+    "DynamoDB request timed out: {details}.",
+    { details: &str }
+);
+define_internal_error!(
+    DynamoUnavailable,
+    "DynamoDB is unavailable: {details}.",
+    { details: &str }
+);
+define_internal_error!(
+    DynamoCancelled,
+    "DynamoDB operation cancelled: {details}.",
+    { details: &str }
+);
+define_internal_error!(
+    DynamoUnprocessedItems,
+    "DynamoDB batch write incomplete: {details}.",
+    { details: &str }
+);
 define_client_error!(
     DynamoInvalidId,
     "DynamoDB invalid ID: {details}.",
@@ -22,3 +58,28 @@ define_client_error!(
     "Invalid parent object type: {details}.",
     { details: &str }
 );
+define_client_error!(
+    DynamoTransactionCancelled,
+    "DynamoDB transaction cancelled: {details}.",
+    { details: &str }
+);
+define_client_error!(
+    DynamoVersionConflict,
+    "DynamoDB item was modified since it was read: {details}.",
+    { details: &str }
+);
+define_client_error!(
+    DynamoConditionFailed,
+    "DynamoDB condition check failed: {details}.",
+    { details: &str }
+);
+define_client_error!(
+    DynamoTransactionConflict,
+    "DynamoDB item was modified concurrently: {details}.",
+    { details: &str }
+);
+define_client_error!(
+    DynamoItemTooLarge,
+    "DynamoDB item is too large: {details}.",
+    { details: &str }
+);
@@ -1,3 +1,7 @@
+// Allows the derive macros (which refer to ::fractic_aws_dynamo) to also be
+// used within this crate.
+extern crate self as fractic_aws_dynamo;
+
 pub mod env;
 pub mod errors;
 pub mod schema;
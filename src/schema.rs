@@ -1,12 +1,28 @@
 use std::collections::HashMap;
 
+use aws_sdk_dynamodb::types::AttributeValue;
+use fractic_server_error::ServerError;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use crate::util::{DynamoMap, IndexConfig};
+
 pub mod add_ons;
+pub mod binary;
+pub mod contact;
+pub mod date_range;
+pub mod decimal;
 pub mod display;
+pub mod duration;
+pub mod encryption;
+pub mod enums;
+pub mod foreign_key;
 pub(crate) mod id_calculations;
+pub mod migrations;
+pub mod money;
 pub mod parsing;
 pub mod pk_sk;
+pub mod registry;
+pub mod set;
 pub mod timestamp;
 
 pub enum IdLogic<T: DynamoObjectData> {
@@ -25,7 +41,7 @@ pub enum IdLogic<T: DynamoObjectData> {
     // - IDs are "guessable", which could be a security concern.
     // - If multiple children for the same parent are written in the same
     // millisecond, they will have the same ID, and the second write will
-    // overwrite the first.
+    // overwrite the first (see TimestampMillis).
     // - Changing ID logic later can be very risky / complex, so should consider
     // all future use-cases from the beginning.
     //
@@ -36,6 +52,44 @@ pub enum IdLogic<T: DynamoObjectData> {
     // <new-obj-id>: LABEL#<timestamp>
     Timestamp,
 
+    // Same as Timestamp, but a short random suffix is appended to the
+    // timestamp, so objects created in the same millisecond get distinct IDs
+    // instead of overwriting each other (which also makes it safe to use with
+    // batch_create_item). IDs are still ordered by creation time, and can be
+    // filtered by date range in the same way.
+    //
+    // <new-obj-id>: LABEL#<timestamp><suffix>
+    TimestampMillis,
+
+    // New IDs are ULIDs (48-bit millisecond timestamp followed by 80 random
+    // bits, Crockford base32 encoded to 26 chars). Like Timestamp, IDs are
+    // ordered by creation time and can be filtered by date range, but objects
+    // created in the same millisecond get distinct IDs, so it's also safe to
+    // use with batch_create_item. The creation date is still leaked by the ID.
+    //
+    // <new-obj-id>: LABEL#<ulid>
+    Ulid,
+
+    // New IDs are KSUIDs (32-bit timestamp in seconds followed by 128 random
+    // bits, base62 encoded to 27 chars). Similar to Ulid, but the timestamp
+    // prefix only has second precision, so less is leaked about the creation
+    // time, while IDs remain non-guessable and ordered by creation time.
+    //
+    // <new-obj-id>: LABEL#<ksuid>
+    Ksuid,
+
+    // The ID is derived from the object's data by the given function, for
+    // example a slug or an external ID. Objects with the same derived ID under
+    // the same parent overwrite each other, so the function should produce
+    // unique values. The returned ID must be non-empty and can't contain '#',
+    // '|' or '@', since those are used as separators in keys.
+    //
+    // Note that copy_subtree assigns copied children random UUID-based IDs,
+    // since it doesn't know how their IDs were derived.
+    //
+    // <new-obj-id>: LABEL#<custom-id>
+    Custom(Box<dyn Fn(&T) -> String>),
+
     // Only one version of this object exists for a given parent, prefixed with
     // a '@'. Subsequent writes always overwrite the existing object.
     //
@@ -50,6 +104,19 @@ pub enum IdLogic<T: DynamoObjectData> {
     SingletonFamily(Box<dyn Fn(&T) -> String>),
 }
 
+// Declares a GSI used to query objects of a given type. When the object is
+// written (by create_item or update_item), the index key attributes are
+// automatically populated using 'keys'.
+//
+// If 'keys' returns None, the index attributes are omitted (or removed, on
+// update), so the object is not included in the index. This can be used to
+// build sparse indexes.
+pub struct IndexDefinition<T: DynamoObjectData> {
+    pub config: IndexConfig,
+    // Computes (partition_value, sort_value) for the index.
+    pub keys: Box<dyn Fn(&T) -> Option<(String, String)>>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum NestingLogic {
     // Warning:
@@ -57,7 +124,8 @@ pub enum NestingLogic {
     //   likely get very hot (and therefore throttled) if this data is
     //   frequently used. If clients need frequent access, consider ways to move
     //   the data down into more specific pk's using the other types, using
-    //   phantom parents, or -- if the data is fairly static -- consider using a
+    //   phantom parents, spreading the objects across multiple partitions
+    //   (ShardedRoot), or -- if the data is fairly static -- consider using a
     //   separate access pattern outside of DynamoDB (such as S3 behind a cached
     //   API function).
 
@@ -67,18 +135,37 @@ pub enum NestingLogic {
     //   used kind of like a placement indicator for the ID-generation logic,
     //   but may be a phantom object (i.e. not actually written to database).
 
+    // Note:
+    //   The *Path variants validate the parent's ancestry instead of only its
+    //   object type. The path lists the expected object types ending with the
+    //   parent itself (e.g. &["GROUP", "LIST"] for a TASK that must be created
+    //   under a LIST inside a GROUP). The ancestry is read from the parent's
+    //   pk and sk, so only ancestors encoded in those keys can be checked; the
+    //   path must match the end of that chain.
+
     // Objects are placed under 'ROOT' partition (regardless of parent IDs
     // provided).
     //   pk: ROOT
     //   sk: <new-obj-id>
     Root,
 
+    // Same as Root, but objects are spread across the given number of 'ROOT#'
+    // partitions (shards) to avoid the hot partition issue described above.
+    // The shard is derived from the object's ID, so that singletons always
+    // end up in the same shard. Querying all objects requires one query per
+    // shard (see DynamoUtil::query_all). Note that the number of shards can't
+    // be reduced later without moving the existing objects.
+    //   pk: ROOT#<shard> (zero-padded, 00 to shards - 1)
+    //   sk: <new-obj-id>
+    ShardedRoot(u8),
+
     // Objects are placed under separate partition based on parent's sk. As
     // such, the child objects require a separate query to fetch.
     //   pk: parent.sk
     //   sk: <new-obj-id>
     TopLevelChildOfAny,
     TopLevelChildOf(&'static str), // Validates parent's object type.
+    TopLevelChildOfPath(&'static [&'static str]), // Validates parent's ancestry.
 
     // Objects are placed under the same partition as the parent object. This
     // way the child objects can often be directly inlined into the search
@@ -87,8 +174,57 @@ pub enum NestingLogic {
     //   sk: parent.sk#<new-obj-id>
     InlineChildOfAny,
     InlineChildOf(&'static str), // Validates parent's object type.
+    InlineChildOfPath(&'static [&'static str]), // Validates parent's ancestry.
 }
 
+// Registry of upgraders for a type's stored items, keyed by the schema version
+// they upgrade from. Each upgrader receives the item's current version and the
+// raw item, and returns the item in the next version's format. The latest
+// schema version is one more than the highest registered version (0 if none
+// are registered).
+//
+// Items without a 'schema_version' field are treated as version 0. For
+// example, after two changes to the data struct:
+//
+//   Migrations::new()
+//       .add(0, |_, item| rename_field(item, "name", "title"))
+//       .add(1, |_, item| add_default(item, "tags"))
+pub struct Migrations<D: DynamoObjectData> {
+    upgraders: std::collections::BTreeMap<u32, Migration>,
+    _data: std::marker::PhantomData<fn() -> D>,
+}
+
+pub type Migration = fn(u32, DynamoMap) -> DynamoMap;
+
+// Runtime registry of the application's DynamoObject types, keyed by
+// id_label. Allows generic tooling (such as admin dumps or stream event
+// decoding) to map a stored item back to its concrete Rust type.
+//
+// Types are registered once at startup:
+//   let registry = DynamoRegistry::new()
+//       .register::<Project>()
+//       .register::<Task>();
+#[derive(Debug, Clone, Default)]
+pub struct DynamoRegistry {
+    types: HashMap<&'static str, RegisteredType>,
+}
+
+// Type-erased operations of a registered DynamoObject type.
+#[derive(Clone, Copy)]
+pub struct RegisteredType {
+    id_label: &'static str,
+    type_id: std::any::TypeId,
+    type_name: &'static str,
+    nesting_logic: fn() -> NestingLogic,
+    regenerate_id: fn(&str, &chrono::DateTime<chrono::Utc>) -> String,
+    parse: fn(&DynamoMap) -> Result<Box<dyn std::any::Any + Send>, ServerError>,
+    to_json: fn(&DynamoMap) -> Result<serde_json::Value, ServerError>,
+}
+
+// Derive alternative to the 'dynamo_object!' macro, applied to the data struct
+// (see the fractic-aws-dynamo-derive crate for the supported attributes).
+pub use fractic_aws_dynamo_derive::DynamoObject;
+
 pub trait DynamoObject: Serialize + DeserializeOwned + std::fmt::Debug {
     type Data: DynamoObjectData;
 
@@ -106,6 +242,66 @@ pub trait DynamoObject: Serialize + DeserializeOwned + std::fmt::Debug {
     fn id_logic() -> IdLogic<Self::Data>;
     fn nesting_logic() -> NestingLogic;
 
+    // Optional part of the sk derived from the object's data, placed before the
+    // unique ID (LABEL#<component>~<id>), so that objects can be efficiently
+    // queried by that value without a GSI (see
+    // DynamoUtil::query_by_sk_component). Since the sk can't be updated in
+    // place, objects must be re-keyed with DynamoUtil::update_sk_component
+    // when the value changes. Not applied to singletons.
+    fn sk_component(_data: &Self::Data) -> Option<String> {
+        None
+    }
+
+    // Attribute aliases:
+    //
+    // Legacy attribute names, as (alias, current name) pairs. When parsing an
+    // item, attributes stored under an alias are read as the current name
+    // (unless the item also has the current name), and update_item removes
+    // them while writing the current name. This allows renaming attributes
+    // gradually, without a bulk data migration.
+    fn attribute_aliases() -> &'static [(&'static str, &'static str)] {
+        &[]
+    }
+
+    // Schema migrations:
+    //
+    // Upgraders for items stored with older versions of the data struct, which
+    // are applied lazily when items are parsed (see Migrations). New and
+    // updated items are stored with the latest schema version.
+    fn migrations() -> Migrations<Self::Data> {
+        Migrations::new()
+    }
+
+    // Indexes:
+    fn indexes() -> Vec<IndexDefinition<Self::Data>> {
+        Vec::new()
+    }
+
+    // Optimistic locking:
+    //
+    // If enabled, DynamoUtil maintains a 'version' auto field, which is set to 1
+    // on creation and incremented on every update (including single-field
+    // updates such as increment_field, which don't check it). Updates are
+    // rejected with DynamoVersionConflict if the stored version doesn't match
+    // the version of the object being written (i.e. if it was modified since it
+    // was read).
+    fn versioned() -> bool {
+        false
+    }
+
+    // Forward compatibility:
+    //
+    // Fields of a stored item which aren't part of the data struct (for
+    // example, fields added by another service using a newer version of the
+    // schema) are read into AutoFields::unknown_fields, and never written
+    // back. update_item leaves them untouched, but replace_item overwrites
+    // the whole item, dropping them. If enabled, replace_item instead only
+    // overwrites (or removes) the fields known to the type, so unknown fields
+    // are preserved.
+    fn preserve_unknown_fields() -> bool {
+        false
+    }
+
     // Data:
     fn data(&self) -> &Self::Data;
     fn data_mut(&mut self) -> &mut Self::Data;
@@ -119,12 +315,31 @@ pub trait DynamoObject: Serialize + DeserializeOwned + std::fmt::Debug {
     fn updated_at(&self) -> Option<&Timestamp> {
         self.auto_fields().updated_at.as_ref()
     }
+    // Principal which created / last updated the object, if a principal was
+    // set on the DynamoUtil at the time (see DynamoUtil::with_principal).
+    fn created_by(&self) -> Option<&str> {
+        self.auto_fields().created_by.as_deref()
+    }
+    fn updated_by(&self) -> Option<&str> {
+        self.auto_fields().updated_by.as_deref()
+    }
     fn sort(&self) -> Option<f64> {
         self.auto_fields().sort
     }
+    // Expiry time in epoch seconds, if set (see CreateOptions::ttl and
+    // DynamoUtil::set_ttl).
     fn ttl(&self) -> Option<i64> {
         self.auto_fields().ttl
     }
+    fn version(&self) -> Option<u64> {
+        self.auto_fields().version
+    }
+    // Schema version of the object (see DynamoObject::migrations). Since items
+    // are migrated when read, this is the latest version for types with
+    // migrations, and None otherwise.
+    fn schema_version(&self) -> Option<u32> {
+        self.auto_fields().schema_version
+    }
     fn has_unknown_fields(&self) -> bool {
         !self.auto_fields().unknown_fields.is_empty()
     }
@@ -142,9 +357,66 @@ pub trait DynamoObjectData:
 {
 }
 
+// Indexes can optionally be registered by passing 'indexes: <expr>' as an
+// additional argument, where the expression evaluates to
+// Vec<IndexDefinition<$datatype>>. Optimistic locking can be enabled by passing
+// 'versioned: true', and preservation of unknown fields (see
+// DynamoObject::preserve_unknown_fields) by passing
+// 'preserve_unknown_fields: true'. A data-derived sk component (see
+// DynamoObject::sk_component) can be declared by passing 'sk_component: <fn>',
+// where the function maps &$datatype to a String. Attribute aliases (see
+// DynamoObject::attribute_aliases) can be declared by passing 'aliases: <expr>',
+// where the expression is a &'static [(alias, current_name)]. Schema
+// migrations can be registered by passing 'migrations: <expr>' as a final
+// argument, where the expression evaluates to Migrations<$datatype>.
 #[macro_export]
 macro_rules! dynamo_object {
-    ($type:ident, $datatype:ident, $id_label:expr, $id_logic:expr, $nesting_logic:expr) => {
+    (
+        $type:ident, $datatype:ident, $id_label:expr, $id_logic:expr, $nesting_logic:expr
+        $(, indexes: $indexes:expr)?
+        $(, versioned: $versioned:expr)?
+        $(, preserve_unknown_fields: $preserve_unknown_fields:expr)?
+        $(, sk_component: $sk_component:expr)?
+        $(, aliases: $aliases:expr)?
+        $(, migrations: $migrations:expr)?
+    ) => {
+        $crate::dynamo_object!(
+            @impl $type, $datatype, $id_label, $id_logic, $nesting_logic, {
+                $(
+                    fn indexes() -> Vec<$crate::schema::IndexDefinition<$datatype>> {
+                        $indexes
+                    }
+                )?
+                $(
+                    fn versioned() -> bool {
+                        $versioned
+                    }
+                )?
+                $(
+                    fn preserve_unknown_fields() -> bool {
+                        $preserve_unknown_fields
+                    }
+                )?
+                $(
+                    fn sk_component(data: &$datatype) -> Option<String> {
+                        let sk_component: fn(&$datatype) -> String = $sk_component;
+                        Some(sk_component(data))
+                    }
+                )?
+                $(
+                    fn attribute_aliases() -> &'static [(&'static str, &'static str)] {
+                        $aliases
+                    }
+                )?
+                $(
+                    fn migrations() -> $crate::schema::Migrations<$datatype> {
+                        $migrations
+                    }
+                )?
+            }
+        );
+    };
+    (@impl $type:ident, $datatype:ident, $id_label:expr, $id_logic:expr, $nesting_logic:expr, { $($extra:tt)* }) => {
         #[derive(Debug, Serialize, Deserialize, Clone)]
         pub struct $type {
             pub id: PkSk,
@@ -195,6 +467,156 @@ macro_rules! dynamo_object {
             fn nesting_logic() -> NestingLogic {
                 $nesting_logic
             }
+
+            $($extra)*
+        }
+    };
+}
+
+// Enum of several DynamoObject types, which can be used to query objects of
+// different types from a single partition (for example, an object together
+// with its inline children). See 'dynamo_object_family!' macro.
+pub trait DynamoObjectFamily: Sized + std::fmt::Debug {
+    // Parses the item into the variant matching its object type. Returns None
+    // if the item is not of any of the family's object types.
+    fn parse_dynamo_map(map: &DynamoMap) -> Option<Result<Self, ServerError>>;
+}
+
+// Generates an enum implementing DynamoObjectFamily (and TryFrom<DynamoMap>),
+// with one variant per object type:
+//
+// dynamo_object_family!(ProjectItem {
+//     Task(Task),
+//     Note(Note),
+// });
+#[macro_export]
+macro_rules! dynamo_object_family {
+    ($name:ident { $($variant:ident($type:ty)),+ $(,)? }) => {
+        #[derive(Debug, Clone)]
+        pub enum $name {
+            $($variant($type)),+
+        }
+
+        impl $crate::schema::DynamoObjectFamily for $name {
+            fn parse_dynamo_map(
+                map: &$crate::util::DynamoMap,
+            ) -> Option<Result<Self, ::fractic_server_error::ServerError>> {
+                $(
+                    if let Some(result) =
+                        $crate::schema::parsing::parse_dynamo_map_if_type::<$type>(map)
+                    {
+                        return Some(result.map($name::$variant));
+                    }
+                )+
+                None
+            }
+        }
+
+        impl TryFrom<$crate::util::DynamoMap> for $name {
+            type Error = ::fractic_server_error::ServerError;
+
+            fn try_from(map: $crate::util::DynamoMap) -> Result<Self, Self::Error> {
+                <Self as $crate::schema::DynamoObjectFamily>::parse_dynamo_map(&map)
+                    .unwrap_or_else(|| {
+                        Err($crate::errors::DynamoItemParsingError::new(
+                            "item is not of any of the family's object types",
+                        ))
+                    })
+            }
+        }
+    };
+}
+
+// Unit-only enum with explicit stored names and integer codes, so that
+// variants can be renamed in Rust without affecting stored items. See
+// 'dynamo_enum!' macro.
+//
+// Values are stored as their name by default (and can then be used in filters
+// and indexes like any string). Fields can instead store the integer code:
+//   #[serde(with = "fractic_aws_dynamo::schema::enums::code")]
+//   pub priority: Priority,
+//
+// Stored values not matching any variant (for example, written by a newer
+// version of the application) are read as the fallback variant if one is
+// declared, and fail to parse otherwise.
+pub trait DynamoEnum: Sized {
+    fn to_name(&self) -> &'static str;
+    fn to_code(&self) -> i64;
+    fn from_name(name: &str) -> Option<Self>;
+    fn from_code(code: i64) -> Option<Self>;
+
+    // Variant used for unknown stored values.
+    fn fallback() -> Option<Self> {
+        None
+    }
+}
+
+// Generates a unit-only enum implementing DynamoEnum (and Serialize /
+// Deserialize, using the stored names), with the stored name and code of each
+// variant. A fallback variant for unknown values can be declared by passing
+// 'fallback = <variant>' as a final argument:
+//
+// dynamo_enum!(Priority {
+//     Low = ("low", 1),
+//     Normal = ("normal", 2),
+//     High = ("high", 3),
+// }, fallback = Normal);
+#[macro_export]
+macro_rules! dynamo_enum {
+    (
+        $name:ident { $($variant:ident = ($stored_name:literal, $code:literal)),+ $(,)? }
+        $(, fallback = $fallback:ident)? $(,)?
+    ) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant),+
+        }
+
+        impl $crate::schema::DynamoEnum for $name {
+            fn to_name(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $stored_name),+
+                }
+            }
+            fn to_code(&self) -> i64 {
+                match self {
+                    $($name::$variant => $code),+
+                }
+            }
+            fn from_name(name: &str) -> Option<Self> {
+                match name {
+                    $($stored_name => Some($name::$variant),)+
+                    _ => None,
+                }
+            }
+            fn from_code(code: i64) -> Option<Self> {
+                match code {
+                    $($code => Some($name::$variant),)+
+                    _ => None,
+                }
+            }
+            $(
+                fn fallback() -> Option<Self> {
+                    Some($name::$fallback)
+                }
+            )?
+        }
+
+        impl ::serde::Serialize for $name {
+            fn serialize<S: ::serde::Serializer>(
+                &self,
+                serializer: S,
+            ) -> Result<S::Ok, S::Error> {
+                $crate::schema::enums::name::serialize(self, serializer)
+            }
+        }
+
+        impl<'de> ::serde::Deserialize<'de> for $name {
+            fn deserialize<D: ::serde::Deserializer<'de>>(
+                deserializer: D,
+            ) -> Result<Self, D::Error> {
+                $crate::schema::enums::name::deserialize(deserializer)
+            }
         }
     };
 }
@@ -219,6 +641,28 @@ pub struct PkSk {
     pub sk: String,
 }
 
+// Reference to another object which carries the target type, so that for
+// example a TASK reference can't be fetched as an EVENT. Serialized as "pk|sk"
+// like PkSk, but the ID's label is checked against T when constructed or
+// deserialized (see the foreign_key module).
+pub struct ForeignKey<T: DynamoObject> {
+    id: PkSk,
+    _target: std::marker::PhantomData<fn() -> T>,
+}
+
+// Minimal, untyped reference to another object, storing only the object's own
+// ID (LABEL#<id>, @LABEL, ...) without its parent's part of the key. The full
+// ID is rebuilt from the target type's nesting logic and the parent scope when
+// resolved (see the foreign_key module). Serialized as the plain ID string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ForeignRef(String);
+
+// Ordered list of ForeignRefs without duplicates, stored compactly as a
+// comma-separated string. Can be resolved to the referenced objects with a
+// batch get (see the foreign_key module).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct ForeignRefList(Vec<ForeignRef>);
+
 // Fields automatically populated by DynamoUtil. This struct is automatically
 // included in all DynamoObjects as a flattened field:
 //
@@ -237,19 +681,109 @@ pub struct AutoFields {
     #[serde(skip_serializing)] // Read-only.
     pub updated_at: Option<Timestamp>,
     #[serde(skip_serializing)] // Read-only.
+    pub created_by: Option<String>,
+    #[serde(skip_serializing)] // Read-only.
+    pub updated_by: Option<String>,
+    #[serde(skip_serializing)] // Read-only.
     pub sort: Option<f64>,
     #[serde(skip_serializing)] // Read-only.
     pub ttl: Option<i64>,
+    #[serde(skip_serializing)] // Read-only.
+    pub version: Option<u64>,
+    #[serde(skip_serializing)] // Read-only.
+    pub schema_version: Option<u32>,
     #[serde(flatten, skip_serializing)] // Read-only.
     pub unknown_fields: HashMap<String, serde_json::Value>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Timestamp {
     pub seconds: i64,
     pub nanos: u32,
 }
 
+// Time range from 'start' (inclusive) to 'end' (exclusive), or open-ended if
+// 'end' is None. Serialized compactly as "<start>/<end>" (see the date_range
+// module). Construct with DateRange::new, which checks that start <= end.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DateRange {
+    pub start: Timestamp,
+    pub end: Option<Timestamp>,
+}
+
+// Length of time in whole seconds (which may be negative). Stored compactly as
+// a number of seconds, but ISO 8601 durations ("PT1H30M") are also accepted
+// when deserializing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Duration {
+    pub seconds: i64,
+}
+
+// Validated email address, normalized to lowercase (see the contact module).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Email(String);
+
+// Validated phone number, normalized to E.164 format ("+14155550123"), which
+// requires the country code (see the contact module).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PhoneNumber(String);
+
+// Amount of money in the currency's minor unit (such as cents), with its ISO
+// 4217 currency code. Serialized compactly as "1999,USD".
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Money {
+    pub amount_minor: i64,
+    pub currency: String,
+}
+
+// Exact decimal number, stored as a DynamoDB number (N) attribute without the
+// precision loss of f64 (for money, quantities, etc.). Values are kept in
+// normalized form, so equal numbers compare equal (see the decimal module).
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Decimal(String);
+
+// Binary data stored as a DynamoDB binary (B) attribute, rather than as a list
+// of numbers (see the binary module, which can also be used directly on Vec<u8>
+// or bytes::Bytes fields).
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Binary(pub Vec<u8>);
+
+// Wrapper for data fields which are encrypted before being written and
+// decrypted when read, for PII stored alongside normal attributes. The value is
+// serialized to JSON, encrypted by the current KeyProvider (see
+// encryption::set_key_provider), and stored as a base64 string. Debug output is
+// redacted.
+//
+// Since the stored value is opaque, encrypted fields can't be used in filters,
+// indexes or sk components.
+#[derive(Clone, PartialEq, Default)]
+pub struct Encrypted<T>(pub T);
+
+// Wrapper for enum fields which keeps stored values not matching any variant of
+// E (for example, tagged maps written by a newer version of the application)
+// instead of failing to parse the whole item. Unknown values are written back
+// unchanged (see the enums module). Typically used with internally tagged
+// enums, which are stored as queryable maps:
+//   #[derive(Serialize, Deserialize)]
+//   #[serde(tag = "type")]
+//   pub enum Trigger { Schedule { cron: String }, Webhook { url: String } }
+//
+//   pub trigger: MaybeUnknown<Trigger>,
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaybeUnknown<E> {
+    Known(E),
+    Unknown(serde_json::Value),
+}
+
+// Encrypts and decrypts Encrypted<T> values. Implementations can use a local
+// key (such as AES-GCM) or call out to a key management service. Ciphertexts
+// should include everything needed to decrypt them (such as the nonce and a key
+// ID), so that keys can be rotated without rewriting existing items.
+pub trait KeyProvider: Send + Sync {
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, ServerError>;
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, ServerError>;
+}
+
 /// Can be used to represent a rare state that can be used in a sparse index
 /// GSI.
 ///
@@ -267,6 +801,43 @@ pub enum True {
     Unit,
 }
 
+/// Counter field which can be atomically incremented or decremented using
+/// DynamoUtil::increment_field, without a read-modify-write cycle.
+///
+/// Regular writes should not overwrite the counter (otherwise concurrent
+/// increments could be lost), so it should be declared as read-only:
+///   #[serde(default, skip_serializing)]
+///   pub view_count: AtomicCounter,
+///
+/// Counters not yet written are treated as 0 by DynamoDB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct AtomicCounter(pub i64);
+
+/// Element types which can be stored in DynamoDB string / number sets (SS /
+/// NS), modified atomically using DynamoUtil::add_to_set and
+/// DynamoUtil::remove_from_set.
+///
+/// Sets are read into HashSet<String> / HashSet<i64> fields, which should be
+/// stored as native sets (rather than lists) using the set module:
+///   #[serde(default, with = "fractic_aws_dynamo::schema::set")]
+///   pub tags: HashSet<String>,
+pub trait DynamoSetElement: Ord + Sized {
+    fn to_set_attribute_value(values: Vec<Self>) -> AttributeValue;
+}
+
+impl DynamoSetElement for String {
+    fn to_set_attribute_value(values: Vec<Self>) -> AttributeValue {
+        AttributeValue::Ss(values)
+    }
+}
+
+impl DynamoSetElement for i64 {
+    fn to_set_attribute_value(values: Vec<Self>) -> AttributeValue {
+        AttributeValue::Ns(values.into_iter().map(|n| n.to_string()).collect())
+    }
+}
+
 // Tests.
 // ---------------------------
 
@@ -312,6 +883,66 @@ mod tests {
         NestingLogic::InlineChildOf("TEST3")
     );
 
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, DynamoObject)]
+    #[dynamo(
+        label = "TEST5",
+        id = "ulid",
+        nesting = "inline_child_of_path(TEST2, TEST3)",
+        versioned
+    )]
+    pub struct Test5Data {
+        #[dynamo(sk_component)]
+        status: String,
+        #[serde(rename = "displayName")]
+        #[dynamo(alias = "name", alias = "title")]
+        name: Option<String>,
+        #[dynamo(skip_patch)]
+        internal: u32,
+    }
+
+    #[test]
+    fn test_derive_dynamo_object() {
+        assert_eq!(Test5::id_label(), "TEST5");
+        assert!(matches!(Test5::id_logic(), IdLogic::Ulid));
+        assert!(matches!(
+            Test5::nesting_logic(),
+            NestingLogic::InlineChildOfPath(&["TEST2", "TEST3"])
+        ));
+        assert!(Test5::versioned());
+        assert_eq!(
+            Test5::attribute_aliases(),
+            &[("name", "displayName"), ("title", "displayName")]
+        );
+        let data = Test5Data {
+            status: "open".to_string(),
+            name: None,
+            internal: 1,
+        };
+        assert_eq!(Test5::sk_component(&data), Some("open".to_string()));
+        let object = Test5::new(PkSk::root(), data);
+        assert_eq!(object.data().internal, 1);
+
+        // Only the provided fields are included in the patch, with the same
+        // serialized names as in the data struct.
+        let patch = Test5DataPatch {
+            name: Some(Some("Name".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(patch).unwrap(),
+            json!({ "displayName": "Name" })
+        );
+    }
+
+    #[test]
+    fn test_atomic_counter_serialization() {
+        assert_eq!(serde_json::to_value(AtomicCounter(5)).unwrap(), json!(5));
+        assert_eq!(
+            serde_json::from_value::<AtomicCounter>(json!(-3)).unwrap(),
+            AtomicCounter(-3)
+        );
+    }
+
     #[test]
     fn test_auto_fields_default() {
         let obj = Test1 {
@@ -356,8 +987,12 @@ mod tests {
                 seconds: 1625247601,
                 nanos: 0,
             }),
+            created_by: Some("user_1".to_string()),
+            updated_by: Some("service:sync".to_string()),
             sort: Some(1.0),
             ttl: Some(1625247602),
+            version: Some(4),
+            schema_version: None,
             unknown_fields,
         };
 
@@ -369,8 +1004,11 @@ mod tests {
 
         assert_eq!(obj.created_at().unwrap().seconds, 1625247600);
         assert_eq!(obj.updated_at().unwrap().seconds, 1625247601);
+        assert_eq!(obj.created_by(), Some("user_1"));
+        assert_eq!(obj.updated_by(), Some("service:sync"));
         assert_eq!(obj.sort().unwrap(), 1.0);
         assert_eq!(obj.ttl().unwrap(), 1625247602);
+        assert_eq!(obj.version().unwrap(), 4);
         assert!(obj.has_unknown_fields());
         assert_eq!(obj.unknown_field_keys(), vec![&String::from("key")]);
     }
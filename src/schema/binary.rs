@@ -0,0 +1,447 @@
+use aws_sdk_dynamodb::{primitives::Blob, types::AttributeValue};
+use fractic_server_error::ServerError;
+use serde::{
+    de::{MapAccess, SeqAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{errors::DynamoItemParsingError, schema::parsing::is_serializing_item};
+
+use super::Binary;
+
+// Binary (B) and binary set (BS) attributes.
+// --------------------------------------------------
+//
+// Items are converted to DynamoDB attributes via serde_json::Value, which has
+// no binary type, so when serialized as part of an item, binary values produce
+// a single-key marker object, which serde_value_to_attribute_value converts to a
+// B / BS attribute (and vice versa when parsing). The marker is never stored.
+// Otherwise, binary values serialize as base64 strings.
+//
+// Fields can either use the Binary wrapper, or keep their own type (such as
+// Vec<u8> or bytes::Bytes) using:
+//   #[serde(with = "fractic_aws_dynamo::schema::binary")]
+//   pub thumbnail: Vec<u8>,
+//
+// and for binary sets (of Vec<u8>, Binary, etc.), which are skipped if empty:
+//   #[serde(default, with = "fractic_aws_dynamo::schema::binary::set")]
+//   pub signatures: HashSet<Vec<u8>>,
+
+const BINARY_MARKER: &str = "$dynamo_binary";
+const BINARY_SET_MARKER: &str = "$dynamo_binary_set";
+
+pub fn serialize<S, B>(value: &B, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    B: AsRef<[u8]>,
+{
+    let encoded = base64_encode(value.as_ref());
+    if !is_serializing_item() {
+        return serializer.serialize_str(&encoded);
+    }
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(BINARY_MARKER, &encoded)?;
+    map.end()
+}
+
+pub fn deserialize<'de, D, B>(deserializer: D) -> Result<B, D::Error>
+where
+    D: Deserializer<'de>,
+    B: From<Vec<u8>>,
+{
+    deserializer.deserialize_any(BinaryVisitor).map(B::from)
+}
+
+pub mod set {
+    use super::*;
+
+    pub fn serialize<S, C, B>(values: &C, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        for<'a> &'a C: IntoIterator<Item = &'a B>,
+        B: AsRef<[u8]>,
+    {
+        // DynamoDB rejects empty sets and sets with duplicates.
+        let encoded: std::collections::BTreeSet<String> = values
+            .into_iter()
+            .map(|value| base64_encode(value.as_ref()))
+            .collect();
+        if !is_serializing_item() {
+            return encoded.serialize(serializer);
+        }
+        if encoded.is_empty() {
+            return serializer.serialize_none();
+        }
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(BINARY_SET_MARKER, &encoded)?;
+        map.end()
+    }
+
+    pub fn deserialize<'de, D, C, B>(deserializer: D) -> Result<C, D::Error>
+    where
+        D: Deserializer<'de>,
+        C: FromIterator<B>,
+        B: From<Vec<u8>>,
+    {
+        deserializer
+            .deserialize_any(BinarySetVisitor)
+            .map(|values| values.into_iter().map(B::from).collect())
+    }
+}
+
+impl Serialize for Binary {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Binary {
+    fn deserialize<D>(deserializer: D) -> Result<Binary, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize(deserializer)
+    }
+}
+
+impl From<Vec<u8>> for Binary {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Binary {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Binary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Binary({} bytes)", self.0.len())
+    }
+}
+
+// Accepts the marker object, a base64 string, raw bytes (for non-JSON
+// deserializers), or a list of numbers (as Vec<u8> fields were stored before
+// being marked as binary).
+struct BinaryVisitor;
+impl<'de> Visitor<'de> for BinaryVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a binary value")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Vec<u8>, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        match map.next_entry::<String, String>()? {
+            Some((key, encoded)) if key == BINARY_MARKER => base64_decode(&encoded)
+                .ok_or_else(|| serde::de::Error::custom("invalid binary value")),
+            _ => Err(serde::de::Error::custom("expected a binary attribute")),
+        }
+    }
+
+    fn visit_str<E>(self, encoded: &str) -> Result<Vec<u8>, E>
+    where
+        E: serde::de::Error,
+    {
+        base64_decode(encoded).ok_or_else(|| serde::de::Error::custom("invalid binary value"))
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<Vec<u8>, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Vec<u8>, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+// Accepts a list of binary values (as binary sets are parsed), or null (as
+// empty sets are serialized as part of an item).
+struct BinarySetVisitor;
+impl<'de> Visitor<'de> for BinarySetVisitor {
+    type Value = Vec<Vec<u8>>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a binary set")
+    }
+
+    fn visit_unit<E>(self) -> Result<Vec<Vec<u8>>, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Vec::new())
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Vec<Vec<u8>>, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut values = Vec::new();
+        while let Some(value) = seq.next_element::<Binary>()? {
+            values.push(value.0);
+        }
+        Ok(values)
+    }
+}
+
+// Converts the marker objects to attributes, used by
+// serde_value_to_attribute_value. Returns None for regular objects.
+pub(crate) fn marker_to_attribute_value(
+    map: &serde_json::Map<String, serde_json::Value>,
+) -> Option<Result<AttributeValue, ServerError>> {
+    if map.len() != 1 {
+        return None;
+    }
+    let decode = |value: &serde_json::Value| {
+        value
+            .as_str()
+            .and_then(base64_decode)
+            .map(Blob::new)
+            .ok_or_else(|| DynamoItemParsingError::new("invalid binary value"))
+    };
+    match map.iter().next()? {
+        (key, value) if key == BINARY_MARKER => Some(decode(value).map(AttributeValue::B)),
+        (key, serde_json::Value::Array(values)) if key == BINARY_SET_MARKER => Some(
+            values
+                .iter()
+                .map(decode)
+                .collect::<Result<Vec<_>, ServerError>>()
+                .map(AttributeValue::Bs),
+        ),
+        _ => None,
+    }
+}
+
+pub(crate) fn binary_to_marker(blob: &Blob) -> serde_json::Value {
+    marker(
+        BINARY_MARKER,
+        serde_json::Value::String(base64_encode(blob.as_ref())),
+    )
+}
+
+// Binary sets are parsed as lists of binary values, so they can be read by
+// both binary::set and the set module.
+pub(crate) fn binary_set_to_markers(blobs: &[Blob]) -> serde_json::Value {
+    blobs.iter().map(binary_to_marker).collect()
+}
+
+fn marker(key: &str, value: serde_json::Value) -> serde_json::Value {
+    serde_json::Value::Object(serde_json::Map::from_iter([(key.to_string(), value)]))
+}
+
+// Base64 (standard alphabet, with padding).
+// --------------------------------------------------
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = ((b[0] as u32) << 16) | ((b[1] as u32) << 8) | (b[2] as u32);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[((n >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn base64_decode(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    if encoded.len() % 4 != 0 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(encoded.len() / 4 * 3);
+    for (index, chunk) in encoded.chunks(4).enumerate() {
+        let is_last = index == encoded.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            return None;
+        }
+        let mut n: u32 = 0;
+        for c in &chunk[..4 - padding] {
+            let value = BASE64_ALPHABET.iter().position(|a| a == c)? as u32;
+            n = (n << 6) | value;
+        }
+        n <<= 6 * padding as u32;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{
+            parsing::{build_dynamo_map_for_new_obj, parse_dynamo_map},
+            AutoFields, DynamoObject, DynamoObjectData, IdLogic, NestingLogic, PkSk,
+        },
+        util::DynamoMap,
+    };
+    use fractic_core::collection;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_base64() {
+        let cases: [(&[u8], &str); 6] = [
+            (b"", ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+            (b"\xff\x00\xfe", "/wD+"),
+        ];
+        for (bytes, encoded) in cases {
+            assert_eq!(base64_encode(bytes), encoded);
+            assert_eq!(base64_decode(encoded).unwrap(), bytes);
+        }
+        assert!(base64_decode("Zg=").is_none());
+        assert!(base64_decode("Zg==Zm9v").is_none());
+        assert!(base64_decode("Z!==").is_none());
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct AttachmentData {
+        #[serde(with = "crate::schema::binary")]
+        content: Vec<u8>,
+        thumbnail: Option<Binary>,
+        #[serde(default, with = "crate::schema::binary::set")]
+        signatures: HashSet<Vec<u8>>,
+    }
+    dynamo_object!(
+        Attachment,
+        AttachmentData,
+        "ATTACHMENT",
+        IdLogic::Uuid,
+        NestingLogic::Root
+    );
+
+    #[test]
+    fn test_binary_round_trip() {
+        let data = AttachmentData {
+            content: vec![0, 1, 2, 255],
+            thumbnail: Some(Binary(vec![9, 8])),
+            signatures: HashSet::from([vec![1], vec![2, 3]]),
+        };
+        let map = build_dynamo_map_for_new_obj::<Attachment>(
+            &data,
+            "ROOT".to_string(),
+            "ATTACHMENT#1".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            map.get("content").unwrap(),
+            &AttributeValue::B(Blob::new(vec![0, 1, 2, 255]))
+        );
+        assert_eq!(
+            map.get("thumbnail").unwrap(),
+            &AttributeValue::B(Blob::new(vec![9, 8]))
+        );
+        let mut signatures = map.get("signatures").unwrap().as_bs().unwrap().clone();
+        signatures.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        assert_eq!(signatures, vec![Blob::new(vec![1]), Blob::new(vec![2, 3])]);
+
+        let parsed = parse_dynamo_map::<Attachment>(&map).unwrap();
+        assert_eq!(parsed.data, data);
+    }
+
+    #[test]
+    fn test_binary_empty_set_skipped() {
+        let map = build_dynamo_map_for_new_obj::<Attachment>(
+            &AttachmentData::default(),
+            "ROOT".to_string(),
+            "ATTACHMENT#1".to_string(),
+            None,
+        )
+        .unwrap();
+        assert!(map.get("signatures").is_none());
+        assert!(map.get("thumbnail").is_none());
+        let parsed = parse_dynamo_map::<Attachment>(&map).unwrap();
+        assert!(parsed.data.signatures.is_empty());
+    }
+
+    #[test]
+    fn test_binary_parse_legacy_list() {
+        // Before being marked as binary, Vec<u8> fields were stored as lists.
+        let map: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("ATTACHMENT#1".to_string()),
+            "content".to_string() => AttributeValue::L(vec![
+                AttributeValue::N("7".to_string()),
+                AttributeValue::N("255".to_string()),
+            ]),
+        };
+        let parsed = parse_dynamo_map::<Attachment>(&map).unwrap();
+        assert_eq!(parsed.data.content, vec![7, 255]);
+    }
+
+    #[test]
+    fn test_binary_plain_json() {
+        // Outside of items (for example in API responses), binary values
+        // serialize to base64 strings.
+        let data = AttachmentData {
+            content: vec![0, 1, 2, 255],
+            thumbnail: None,
+            signatures: HashSet::from([vec![1]]),
+        };
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "content": "AAEC/w==",
+                "thumbnail": null,
+                "signatures": ["AQ=="],
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<AttachmentData>(json).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_binary_debug() {
+        assert_eq!(format!("{:?}", Binary(vec![1, 2, 3])), "Binary(3 bytes)");
+    }
+}
@@ -0,0 +1,232 @@
+use std::str::FromStr;
+
+use fractic_server_error::ServerError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::DynamoInvalidOperation;
+
+use super::{Email, PhoneNumber};
+
+// Email and phone number fields are validated when constructed or
+// deserialized, so malformed values are rejected before they are written, and
+// stored in normalized form so that they can be compared and queried exactly.
+
+// Email.
+// --------------------------------------------------
+
+impl Email {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    pub fn domain(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for Email {
+    type Err = ServerError;
+
+    // Only the basic structure is checked (local@domain.tld, without
+    // whitespace), since full RFC 5322 validation rejects few real typos, and
+    // deliverability can only be verified by sending an email.
+    fn from_str(s: &str) -> Result<Email, ServerError> {
+        let email = s.trim().to_lowercase();
+        let invalid = || DynamoInvalidOperation::with_debug("invalid email address", &s);
+        let (local, domain) = email.rsplit_once('@').ok_or_else(invalid)?;
+        let valid_local = !local.is_empty()
+            && local.len() <= 64
+            && !local.contains('@')
+            && local
+                .chars()
+                .all(|c| !c.is_whitespace() && !c.is_control() && c != '"');
+        let labels: Vec<&str> = domain.split('.').collect();
+        let valid_domain = labels.len() >= 2
+            && labels.iter().all(|label| {
+                !label.is_empty()
+                    && label.len() <= 63
+                    && !label.starts_with('-')
+                    && !label.ends_with('-')
+                    && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+            });
+        if !valid_local || !valid_domain || email.len() > 254 {
+            return Err(invalid());
+        }
+        Ok(Email(email))
+    }
+}
+
+impl std::fmt::Display for Email {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for Email {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Email {
+    fn deserialize<D>(deserializer: D) -> Result<Email, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+// Phone number.
+// --------------------------------------------------
+
+impl PhoneNumber {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for PhoneNumber {
+    type Err = ServerError;
+
+    // Accepts common formatting ("+1 (415) 555-0123", "0044 20 7946 0958"),
+    // as long as the number includes the country code.
+    fn from_str(s: &str) -> Result<PhoneNumber, ServerError> {
+        let invalid = || {
+            DynamoInvalidOperation::with_debug(
+                "invalid phone number; expected international format, such as +14155550123",
+                &s,
+            )
+        };
+        let stripped: String = s
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+            .collect();
+        let digits = stripped
+            .strip_prefix('+')
+            .or_else(|| stripped.strip_prefix("00"))
+            .ok_or_else(invalid)?;
+        // E.164 numbers have at most 15 digits, and country codes don't start
+        // with 0.
+        if !(8..=15).contains(&digits.len())
+            || digits.starts_with('0')
+            || !digits.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        Ok(PhoneNumber(format!("+{}", digits)))
+    }
+}
+
+impl std::fmt::Display for PhoneNumber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for PhoneNumber {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for PhoneNumber {
+    fn deserialize<D>(deserializer: D) -> Result<PhoneNumber, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_email_normalization() {
+        let email: Email = " Jane.Doe+News@Example.COM ".parse().unwrap();
+        assert_eq!(email.as_str(), "jane.doe+news@example.com");
+        assert_eq!(email.domain(), "example.com");
+        assert_eq!(
+            serde_json::to_value(&email).unwrap(),
+            serde_json::json!("jane.doe+news@example.com")
+        );
+        assert_eq!(
+            serde_json::from_value::<Email>(serde_json::json!("JANE.DOE+news@example.com"))
+                .unwrap(),
+            email
+        );
+    }
+
+    #[test]
+    fn test_email_invalid() {
+        for invalid in [
+            "",
+            "jane",
+            "jane@",
+            "@example.com",
+            "jane@example",
+            "jane@@example.com",
+            "jane doe@example.com",
+            "jane@exa mple.com",
+            "jane@-example.com",
+            "jane@example..com",
+        ] {
+            assert!(invalid.parse::<Email>().is_err(), "input: {}", invalid);
+            assert!(serde_json::from_value::<Email>(serde_json::json!(invalid)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_phone_number_normalization() {
+        for (input, expected) in [
+            ("+14155550123", "+14155550123"),
+            ("+1 (415) 555-0123", "+14155550123"),
+            ("0044 20 7946 0958", "+442079460958"),
+            ("+81.3.1234.5678", "+81312345678"),
+        ] {
+            let phone: PhoneNumber = input.parse().unwrap();
+            assert_eq!(phone.as_str(), expected, "input: {}", input);
+            assert_eq!(
+                serde_json::to_value(&phone).unwrap(),
+                serde_json::json!(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn test_phone_number_invalid() {
+        for invalid in [
+            "",
+            "4155550123",
+            "+",
+            "+0123456789",
+            "+1415555012345678",
+            "+1415555",
+            "+1 415 CALL NOW",
+        ] {
+            assert!(
+                invalid.parse::<PhoneNumber>().is_err(),
+                "input: {}",
+                invalid
+            );
+            assert!(serde_json::from_value::<PhoneNumber>(serde_json::json!(invalid)).is_err());
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use fractic_server_error::ServerError;
+use serde::{de::IntoDeserializer, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::DynamoInvalidOperation;
+
+use super::{DateRange, Duration, Timestamp};
+
+impl DateRange {
+    pub fn new(start: Timestamp, end: Option<Timestamp>) -> Result<Self, ServerError> {
+        if end.as_ref().is_some_and(|end| *end < start) {
+            return Err(DynamoInvalidOperation::new(&format!(
+                "date range ends before it starts ({:?} to {:?})",
+                start, end
+            )));
+        }
+        Ok(Self { start, end })
+    }
+    pub fn open_ended(start: Timestamp) -> Self {
+        Self { start, end: None }
+    }
+
+    pub fn contains(&self, timestamp: &Timestamp) -> bool {
+        self.start <= *timestamp && self.end.as_ref().is_none_or(|end| timestamp < end)
+    }
+    // Whether the ranges share any time. Since ends are exclusive, adjacent
+    // ranges (one ending when the other starts) don't overlap, so back-to-back
+    // bookings are allowed.
+    pub fn overlaps(&self, other: &DateRange) -> bool {
+        let starts_before_other_ends = other.end.as_ref().is_none_or(|end| self.start < *end);
+        let other_starts_before_end = self.end.as_ref().is_none_or(|end| other.start < *end);
+        starts_before_other_ends && other_starts_before_end
+    }
+    // None for open-ended ranges.
+    pub fn duration(&self) -> Option<Duration> {
+        self.end
+            .as_ref()
+            .map(|end| Duration::between(&self.start, end))
+    }
+}
+
+impl Serialize for DateRange {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        format!(
+            "{}/{}",
+            self.start.to_compact_string(),
+            self.end
+                .as_ref()
+                .map(Timestamp::to_compact_string)
+                .unwrap_or_default()
+        )
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DateRange {
+    fn deserialize<D>(deserializer: D) -> Result<DateRange, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(DateRangeVisitor)
+    }
+}
+
+// The main serialization format is "<start>/<end>" as a string (with an empty
+// end for open-ended ranges), where both are in the Timestamp format. However,
+// the map form is also accepted.
+struct DateRangeVisitor;
+impl<'de> serde::de::Visitor<'de> for DateRangeVisitor {
+    type Value = DateRange;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a date range as a string or a map")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<DateRange, E>
+    where
+        E: serde::de::Error,
+    {
+        let (start, end) = value
+            .split_once('/')
+            .ok_or_else(|| serde::de::Error::custom("invalid date range format"))?;
+        let parse_timestamp =
+            |part: &str| Timestamp::deserialize(IntoDeserializer::<E>::into_deserializer(part));
+        let start = parse_timestamp(start)?;
+        let end = match end {
+            "" => None,
+            end => Some(parse_timestamp(end)?),
+        };
+        DateRange::new(start, end).map_err(serde::de::Error::custom)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<DateRange, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        #[derive(Deserialize)]
+        struct LegacyMap {
+            start: Timestamp,
+            end: Option<Timestamp>,
+        }
+        let legacy_map: LegacyMap =
+            Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+        DateRange::new(legacy_map.start, legacy_map.end).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(start: i64, end: Option<i64>) -> DateRange {
+        DateRange::new(
+            Timestamp::from_seconds(start),
+            end.map(Timestamp::from_seconds),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(DateRange::new(
+            Timestamp::from_seconds(10),
+            Some(Timestamp::from_seconds(5))
+        )
+        .is_err());
+        assert!(DateRange::new(
+            Timestamp::from_seconds(10),
+            Some(Timestamp::from_seconds(10))
+        )
+        .is_ok());
+        assert!(serde_json::from_value::<DateRange>(serde_json::json!(
+            "00000000010.000000000/00000000005.000000000"
+        ))
+        .is_err());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let closed = range(1625247600, Some(1625251200));
+        let json = serde_json::to_value(&closed).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!("01625247600.000000000/01625251200.000000000")
+        );
+        assert_eq!(serde_json::from_value::<DateRange>(json).unwrap(), closed);
+
+        let open = range(1625247600, None);
+        let json = serde_json::to_value(&open).unwrap();
+        assert_eq!(json, serde_json::json!("01625247600.000000000/"));
+        assert_eq!(serde_json::from_value::<DateRange>(json).unwrap(), open);
+
+        assert_eq!(
+            serde_json::from_value::<DateRange>(serde_json::json!({
+                "start": "01625247600.000000000",
+                "end": null,
+            }))
+            .unwrap(),
+            open
+        );
+        assert!(
+            serde_json::from_value::<DateRange>(serde_json::json!("01625247600.000000000"))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_contains() {
+        let closed = range(100, Some(200));
+        assert!(!closed.contains(&Timestamp::from_seconds(99)));
+        assert!(closed.contains(&Timestamp::from_seconds(100)));
+        assert!(closed.contains(&Timestamp::from_seconds(199)));
+        assert!(!closed.contains(&Timestamp::from_seconds(200)));
+        assert!(range(100, None).contains(&Timestamp::from_seconds(1_000_000)));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        let closed = range(100, Some(200));
+        assert!(closed.overlaps(&range(150, Some(250))));
+        assert!(closed.overlaps(&range(50, Some(101))));
+        assert!(closed.overlaps(&range(120, Some(130))));
+        assert!(closed.overlaps(&range(0, None)));
+        assert!(!closed.overlaps(&range(200, Some(300))));
+        assert!(!closed.overlaps(&range(0, Some(100))));
+        assert!(!closed.overlaps(&range(300, None)));
+        assert!(range(0, None).overlaps(&range(500, None)));
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(
+            range(100, Some(160)).duration(),
+            Some(Duration::from_minutes(1))
+        );
+        assert_eq!(range(100, None).duration(), None);
+    }
+}
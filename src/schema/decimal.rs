@@ -0,0 +1,407 @@
+use std::str::FromStr;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use fractic_server_error::ServerError;
+use serde::{de::Visitor, ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    errors::{DynamoInvalidOperation, DynamoItemParsingError},
+    schema::parsing::is_serializing_item,
+};
+
+use super::Decimal;
+
+// Exact numbers.
+// --------------------------------------------------
+//
+// DynamoDB stores numbers as decimal strings with up to 38 significant digits,
+// but serde_json::Number (used to convert items) only holds an i64, u64 or
+// f64. To round-trip exactly, Decimal values are serialized as part of an item
+// as a marker object, which serde_value_to_attribute_value converts to an N
+// attribute. When parsing, numbers which can't be represented exactly by
+// serde_json::Number are kept as strings, which Decimal reads back exactly.
+// Otherwise (for example in API responses), Decimal serializes as a string.
+
+const NUMBER_MARKER: &str = "$dynamo_number";
+
+// Significant digits and magnitude supported by DynamoDB (1E-130 to 9.9E+125).
+const MAX_DIGITS: usize = 38;
+const MAX_INTEGER_DIGITS: i64 = 126;
+const MIN_INTEGER_DIGITS: i64 = -129;
+
+impl Decimal {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+    // Lossy conversion, for display or approximate calculations.
+    pub fn to_f64(&self) -> f64 {
+        self.0.parse().unwrap_or(f64::NAN)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ServerError;
+
+    // Accepts decimal notation (with optional exponent), which is normalized
+    // the same way as DynamoDB normalizes stored numbers: "012.50" -> "12.5",
+    // "1.5e3" -> "1500", "-0" -> "0".
+    fn from_str(s: &str) -> Result<Decimal, ServerError> {
+        let parts = DecimalParts::parse(s)
+            .ok_or_else(|| DynamoInvalidOperation::with_debug("invalid decimal", &s))?;
+        if parts.digits.len() > MAX_DIGITS {
+            return Err(DynamoInvalidOperation::with_debug(
+                "decimal has more than 38 significant digits",
+                &s,
+            ));
+        }
+        let integer_digits = parts.digits.len() as i64 + parts.exponent;
+        if !parts.digits.is_empty()
+            && !(MIN_INTEGER_DIGITS..=MAX_INTEGER_DIGITS).contains(&integer_digits)
+        {
+            return Err(DynamoInvalidOperation::with_debug(
+                "decimal is out of the range supported by DynamoDB",
+                &s,
+            ));
+        }
+        Ok(Decimal(parts.to_plain_string()))
+    }
+}
+
+impl Default for Decimal {
+    fn default() -> Self {
+        Decimal("0".to_string())
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(value: i64) -> Self {
+        Decimal(value.to_string())
+    }
+}
+
+impl From<u64> for Decimal {
+    fn from(value: u64) -> Self {
+        Decimal(value.to_string())
+    }
+}
+
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::fmt::Debug for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Decimal({})", self.0)
+    }
+}
+
+impl Serialize for Decimal {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if !is_serializing_item() {
+            return serializer.serialize_str(&self.0);
+        }
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(NUMBER_MARKER, &self.0)?;
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D>(deserializer: D) -> Result<Decimal, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(DecimalVisitor)
+    }
+}
+
+// Accepts strings (exact), as well as numbers (which were exactly representable
+// when parsed, or were stored by a float field).
+struct DecimalVisitor;
+impl<'de> Visitor<'de> for DecimalVisitor {
+    type Value = Decimal;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a decimal as a string or a number")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Decimal, E>
+    where
+        E: serde::de::Error,
+    {
+        value.parse().map_err(serde::de::Error::custom)
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Decimal, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(value.into())
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Decimal, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(value.into())
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<Decimal, E>
+    where
+        E: serde::de::Error,
+    {
+        if !value.is_finite() {
+            return Err(serde::de::Error::custom("decimal must be finite"));
+        }
+        // Display prints the shortest representation which round-trips.
+        self.visit_str(&value.to_string())
+    }
+}
+
+// Converts the marker object to an N attribute, used by
+// serde_value_to_attribute_value. Returns None for regular objects.
+pub(crate) fn marker_to_number_attribute_value(
+    map: &serde_json::Map<String, serde_json::Value>,
+) -> Option<Result<AttributeValue, ServerError>> {
+    match map.iter().next()? {
+        (key, serde_json::Value::String(n)) if map.len() == 1 && key == NUMBER_MARKER => {
+            Some(Ok(AttributeValue::N(n.clone())))
+        }
+        _ => None,
+    }
+}
+
+// Parses a stored number as a serde_json::Number if that represents it exactly,
+// or otherwise keeps it as a string (see Decimal).
+pub(crate) fn number_to_serde_value(n: String) -> Result<serde_json::Value, ServerError> {
+    let number: serde_json::Number = n
+        .parse()
+        .map_err(|e| DynamoItemParsingError::with_debug("failed to parse number", &e))?;
+    let exact = match (
+        DecimalParts::parse(&n),
+        DecimalParts::parse(&number.to_string()),
+    ) {
+        (Some(stored), Some(parsed)) => stored == parsed,
+        _ => false,
+    };
+    Ok(match exact {
+        true => serde_json::Value::Number(number),
+        false => serde_json::Value::String(n),
+    })
+}
+
+// Normalized decimal: (-1)^negative * digits * 10^exponent, where digits has
+// no leading or trailing zeros (and is empty for zero).
+#[derive(Debug, PartialEq)]
+struct DecimalParts {
+    negative: bool,
+    digits: String,
+    exponent: i64,
+}
+
+impl DecimalParts {
+    fn parse(s: &str) -> Option<DecimalParts> {
+        let (negative, s) = match s.as_bytes().first()? {
+            b'-' => (true, &s[1..]),
+            b'+' => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(index) => (&s[..index], s[index + 1..].parse::<i64>().ok()?),
+            None => (s, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if (integer.is_empty() && fraction.is_empty())
+            || !integer
+                .bytes()
+                .chain(fraction.bytes())
+                .all(|c| c.is_ascii_digit())
+        {
+            return None;
+        }
+        let digits = format!("{}{}", integer, fraction);
+        let mut exponent = exponent.checked_sub(fraction.len() as i64)?;
+        let digits = digits.trim_start_matches('0');
+        let trimmed = digits.trim_end_matches('0');
+        exponent = exponent.checked_add((digits.len() - trimmed.len()) as i64)?;
+        if trimmed.is_empty() {
+            return Some(DecimalParts {
+                negative: false,
+                digits: String::new(),
+                exponent: 0,
+            });
+        }
+        Some(DecimalParts {
+            negative,
+            digits: trimmed.to_string(),
+            exponent,
+        })
+    }
+
+    // Plain notation, without exponent.
+    fn to_plain_string(&self) -> String {
+        if self.digits.is_empty() {
+            return "0".to_string();
+        }
+        let sign = if self.negative { "-" } else { "" };
+        let point = self.digits.len() as i64 + self.exponent;
+        if self.exponent >= 0 {
+            format!(
+                "{}{}{}",
+                sign,
+                self.digits,
+                "0".repeat(self.exponent as usize)
+            )
+        } else if point > 0 {
+            let (integer, fraction) = self.digits.split_at(point as usize);
+            format!("{}{}.{}", sign, integer, fraction)
+        } else {
+            format!("{}0.{}{}", sign, "0".repeat(-point as usize), self.digits)
+        }
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{
+            parsing::{build_dynamo_map_for_new_obj, parse_dynamo_map},
+            AutoFields, DynamoObject, DynamoObjectData, IdLogic, NestingLogic, PkSk,
+        },
+        util::DynamoMap,
+    };
+    use fractic_core::collection;
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct InvoiceData {
+        total: Option<Decimal>,
+        rate: f64,
+    }
+    dynamo_object!(
+        Invoice,
+        InvoiceData,
+        "INVOICE",
+        IdLogic::Uuid,
+        NestingLogic::Root
+    );
+
+    fn decimal(s: &str) -> Decimal {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_decimal_normalization() {
+        for (input, expected) in [
+            ("0", "0"),
+            ("-0.000", "0"),
+            ("012.50", "12.5"),
+            ("+7", "7"),
+            ("-3.14", "-3.14"),
+            ("1.5e3", "1500"),
+            ("1E-7", "0.0000001"),
+            (".5", "0.5"),
+            ("5.", "5"),
+            ("100", "100"),
+            (
+                "12345678901234567890.123456789012345678",
+                "12345678901234567890.123456789012345678",
+            ),
+        ] {
+            assert_eq!(decimal(input).as_str(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_decimal_invalid() {
+        for input in ["", "-", ".", "1.2.3", "1e", "abc", "1,5", "NaN"] {
+            assert!(input.parse::<Decimal>().is_err(), "input: {}", input);
+        }
+        // 39 significant digits.
+        assert!("123456789012345678901234567890123456789"
+            .parse::<Decimal>()
+            .is_err());
+        assert!("1234567890123456789012345678901234567800"
+            .parse::<Decimal>()
+            .is_ok());
+        // Out of range.
+        assert!("1e126".parse::<Decimal>().is_err());
+        assert!("9.9e125".parse::<Decimal>().is_ok());
+        assert!("1e-130".parse::<Decimal>().is_ok());
+        assert!("1e-131".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn test_decimal_round_trip() {
+        let data = InvoiceData {
+            total: Some(decimal("1234567890123456789.0123456789")),
+            rate: 0.0000001,
+        };
+        let map = build_dynamo_map_for_new_obj::<Invoice>(
+            &data,
+            "ROOT".to_string(),
+            "INVOICE#1".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            map.get("total").unwrap(),
+            &AttributeValue::N("1234567890123456789.0123456789".to_string())
+        );
+        let parsed = parse_dynamo_map::<Invoice>(&map).unwrap();
+        assert_eq!(parsed.data, data);
+    }
+
+    #[test]
+    fn test_decimal_parse_stored_numbers() {
+        // Numbers as returned by DynamoDB, including ones which are exactly
+        // representable as f64 (and so reach Decimal as a number).
+        let map: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("INVOICE#1".to_string()),
+            "total".to_string() => AttributeValue::N("19.99".to_string()),
+            "rate".to_string() => AttributeValue::N("0.0000001".to_string()),
+        };
+        let parsed = parse_dynamo_map::<Invoice>(&map).unwrap();
+        assert_eq!(parsed.data.total, Some(decimal("19.99")));
+        assert_eq!(parsed.data.rate, 0.0000001);
+    }
+
+    #[test]
+    fn test_number_to_serde_value() {
+        assert_eq!(
+            number_to_serde_value("42".to_string()).unwrap(),
+            serde_json::json!(42)
+        );
+        assert_eq!(
+            number_to_serde_value("0.0000001".to_string()).unwrap(),
+            serde_json::json!(0.0000001)
+        );
+        assert_eq!(
+            number_to_serde_value("0.12345678901234567890".to_string()).unwrap(),
+            serde_json::json!("0.12345678901234567890")
+        );
+        assert!(number_to_serde_value("abc".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_decimal_plain_json() {
+        assert_eq!(
+            serde_json::to_value(decimal("-1.50")).unwrap(),
+            serde_json::json!("-1.5")
+        );
+        assert_eq!(
+            serde_json::from_value::<Decimal>(serde_json::json!(2.25)).unwrap(),
+            decimal("2.25")
+        );
+    }
+}
@@ -0,0 +1,319 @@
+use fractic_server_error::{CriticalError, ServerError};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::DynamoInvalidOperation;
+
+use super::{Duration, Timestamp};
+
+impl Duration {
+    pub fn from_seconds(seconds: i64) -> Self {
+        Self { seconds }
+    }
+    pub fn from_minutes(minutes: i64) -> Self {
+        Self {
+            seconds: minutes * 60,
+        }
+    }
+    pub fn from_hours(hours: i64) -> Self {
+        Self {
+            seconds: hours * 3600,
+        }
+    }
+    pub fn from_days(days: i64) -> Self {
+        Self {
+            seconds: days * 86400,
+        }
+    }
+    // Whole minutes / hours, rounded towards zero.
+    pub fn as_minutes(&self) -> i64 {
+        self.seconds / 60
+    }
+    pub fn as_hours(&self) -> i64 {
+        self.seconds / 3600
+    }
+
+    pub fn to_chrono(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.seconds)
+    }
+    // Fails for negative durations, which std::time::Duration can't represent.
+    pub fn to_std(&self) -> Result<std::time::Duration, ServerError> {
+        u64::try_from(self.seconds)
+            .map(std::time::Duration::from_secs)
+            .map_err(|_| CriticalError::new("negative Duration can't be converted to std"))
+    }
+
+    // The timestamp this duration after 'timestamp'.
+    pub fn after(&self, timestamp: &Timestamp) -> Timestamp {
+        Timestamp {
+            seconds: timestamp.seconds + self.seconds,
+            nanos: timestamp.nanos,
+        }
+    }
+    // The duration between two timestamps (negative if 'end' is before
+    // 'start'), truncated to whole seconds.
+    pub fn between(start: &Timestamp, end: &Timestamp) -> Self {
+        let nanos = (end.seconds - start.seconds) as i128 * 1_000_000_000 + end.nanos as i128
+            - start.nanos as i128;
+        Self {
+            seconds: (nanos / 1_000_000_000) as i64,
+        }
+    }
+
+    // Print as ISO 8601 ("PT1H30M", "P2DT5S", "-PT10M", "PT0S").
+    pub fn to_iso_8601_string(&self) -> String {
+        let sign = if self.seconds < 0 { "-" } else { "" };
+        let total = self.seconds.unsigned_abs();
+        let (days, hours, minutes, seconds) = (
+            total / 86400,
+            total % 86400 / 3600,
+            total % 3600 / 60,
+            total % 60,
+        );
+        let mut out = format!("{}P", sign);
+        if days > 0 {
+            out.push_str(&format!("{}D", days));
+        }
+        if hours > 0 || minutes > 0 || seconds > 0 || days == 0 {
+            out.push('T');
+            for (value, unit) in [(hours, 'H'), (minutes, 'M'), (seconds, 'S')] {
+                if value > 0 {
+                    out.push_str(&format!("{}{}", value, unit));
+                }
+            }
+            if total == 0 {
+                out.push_str("0S");
+            }
+        }
+        out
+    }
+
+    // Parses ISO 8601 durations using weeks, days, hours, minutes and whole
+    // seconds. Years and months are rejected, since their length varies.
+    pub fn from_iso_8601_string(s: &str) -> Result<Self, ServerError> {
+        let invalid = || DynamoInvalidOperation::with_debug("invalid ISO 8601 duration", &s);
+        let (negative, rest) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+        let (date, time) = match rest.split_once('T') {
+            Some((_, "")) => return Err(invalid()),
+            Some((date, time)) => (date, time),
+            None => (rest, ""),
+        };
+        if date.is_empty() && time.is_empty() {
+            return Err(invalid());
+        }
+        let mut seconds: i64 = 0;
+        for (part, units) in [
+            (date, &[('W', 604800), ('D', 86400)][..]),
+            (time, &[('H', 3600), ('M', 60), ('S', 1)][..]),
+        ] {
+            let mut part = part;
+            let mut units = units.iter();
+            while !part.is_empty() {
+                let end = part
+                    .find(|c: char| !c.is_ascii_digit())
+                    .ok_or_else(invalid)?;
+                let value: i64 = part[..end].parse().map_err(|_| invalid())?;
+                let designator = part[end..].chars().next().ok_or_else(invalid)?;
+                // Designators must be in order, each at most once.
+                let (_, multiplier) = units
+                    .find(|(unit, _)| *unit == designator)
+                    .ok_or_else(invalid)?;
+                seconds = value
+                    .checked_mul(*multiplier)
+                    .and_then(|value| seconds.checked_add(value))
+                    .ok_or_else(invalid)?;
+                part = &part[end + 1..];
+            }
+        }
+        Ok(Self {
+            seconds: if negative { -seconds } else { seconds },
+        })
+    }
+}
+
+impl From<chrono::Duration> for Duration {
+    // Truncated to whole seconds.
+    fn from(duration: chrono::Duration) -> Self {
+        Self {
+            seconds: duration.num_seconds(),
+        }
+    }
+}
+
+impl TryFrom<std::time::Duration> for Duration {
+    type Error = ServerError;
+
+    // Truncated to whole seconds.
+    fn try_from(duration: std::time::Duration) -> Result<Self, ServerError> {
+        i64::try_from(duration.as_secs())
+            .map(Duration::from_seconds)
+            .map_err(|_| CriticalError::new("std Duration is too long"))
+    }
+}
+
+impl std::fmt::Display for Duration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_iso_8601_string())
+    }
+}
+
+impl Serialize for Duration {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_i64(self.seconds)
+    }
+}
+
+impl<'de> Deserialize<'de> for Duration {
+    fn deserialize<D>(deserializer: D) -> Result<Duration, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(DurationVisitor)
+    }
+}
+
+// The main serialization format is the number of seconds. However, ISO 8601
+// strings are also accepted (for example from API requests), as well as the
+// legacy map form.
+struct DurationVisitor;
+impl<'de> serde::de::Visitor<'de> for DurationVisitor {
+    type Value = Duration;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a duration as seconds, an ISO 8601 string or a map")
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<Duration, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Duration::from_seconds(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Duration, E>
+    where
+        E: serde::de::Error,
+    {
+        i64::try_from(value)
+            .map(Duration::from_seconds)
+            .map_err(serde::de::Error::custom)
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Duration, E>
+    where
+        E: serde::de::Error,
+    {
+        Duration::from_iso_8601_string(value).map_err(serde::de::Error::custom)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Duration, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        #[derive(Deserialize)]
+        struct LegacyMap {
+            seconds: i64,
+        }
+        let legacy_map: LegacyMap =
+            Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+        Ok(Duration::from_seconds(legacy_map.seconds))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize() {
+        assert_eq!(
+            serde_json::to_value(Duration::from_minutes(90)).unwrap(),
+            serde_json::json!(5400)
+        );
+    }
+
+    #[test]
+    fn test_deserialize() {
+        for value in [
+            serde_json::json!(5400),
+            serde_json::json!("PT1H30M"),
+            serde_json::json!("PT90M"),
+            serde_json::json!({ "seconds": 5400 }),
+        ] {
+            assert_eq!(
+                serde_json::from_value::<Duration>(value.clone()).unwrap(),
+                Duration::from_minutes(90),
+                "input: {}",
+                value
+            );
+        }
+        assert_eq!(
+            serde_json::from_value::<Duration>(serde_json::json!(-30)).unwrap(),
+            Duration::from_seconds(-30)
+        );
+        assert!(serde_json::from_value::<Duration>(serde_json::json!("1h30m")).is_err());
+    }
+
+    #[test]
+    fn test_iso_8601() {
+        for (seconds, iso) in [
+            (0, "PT0S"),
+            (5, "PT5S"),
+            (5400, "PT1H30M"),
+            (86400, "P1D"),
+            (2 * 86400 + 5, "P2DT5S"),
+            (-600, "-PT10M"),
+        ] {
+            let duration = Duration::from_seconds(seconds);
+            assert_eq!(duration.to_iso_8601_string(), iso);
+            assert_eq!(Duration::from_iso_8601_string(iso).unwrap(), duration);
+        }
+        assert_eq!(
+            Duration::from_iso_8601_string("P1W").unwrap(),
+            Duration::from_days(7)
+        );
+        for invalid in [
+            "", "P", "PT", "P1Y", "P1M", "PT1.5S", "PT1M1H", "PT1H1H", "P1H", "1H",
+        ] {
+            assert!(
+                Duration::from_iso_8601_string(invalid).is_err(),
+                "input: {}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_interop() {
+        let duration = Duration::from_hours(2);
+        assert_eq!(duration.to_chrono(), chrono::Duration::hours(2));
+        assert_eq!(
+            Duration::from(chrono::Duration::minutes(3)),
+            Duration::from_minutes(3)
+        );
+        assert_eq!(
+            duration.to_std().unwrap(),
+            std::time::Duration::from_secs(7200)
+        );
+        assert!(Duration::from_seconds(-1).to_std().is_err());
+        assert_eq!(
+            Duration::try_from(std::time::Duration::from_millis(1500)).unwrap(),
+            Duration::from_seconds(1)
+        );
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let start = Timestamp::from_seconds(1000);
+        let end = Duration::from_minutes(1).after(&start);
+        assert_eq!(end, Timestamp::from_seconds(1060));
+        assert_eq!(Duration::between(&start, &end), Duration::from_minutes(1));
+        assert_eq!(Duration::between(&end, &start), Duration::from_minutes(-1));
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    cell::RefCell,
+    sync::{Arc, RwLock},
+};
+
+use fractic_server_error::ServerError;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::DynamoEncryptionError;
+
+use super::{
+    binary::{base64_decode, base64_encode},
+    Encrypted, KeyProvider,
+};
+
+// Key provider selection.
+// --------------------------------------------------
+//
+// Values are encrypted and decrypted during (de)serialization, which has no
+// access to the DynamoUtil, so the key provider is registered process-wide. It
+// can be overridden for the current thread with with_key_provider (for example
+// in tests, or to use a per-tenant key for a block of synchronous code).
+
+static KEY_PROVIDER: RwLock<Option<Arc<dyn KeyProvider>>> = RwLock::new(None);
+
+thread_local! {
+    static SCOPED_KEY_PROVIDER: RefCell<Option<Arc<dyn KeyProvider>>> =
+        const { RefCell::new(None) };
+}
+
+// Sets the key provider used for all Encrypted<T> values.
+pub fn set_key_provider(provider: Arc<dyn KeyProvider>) {
+    *KEY_PROVIDER.write().unwrap() = Some(provider);
+}
+
+// Runs 'f' with 'provider' used instead of the process-wide key provider on the
+// current thread. Since the override is thread-local, 'f' should not await.
+pub fn with_key_provider<R>(provider: Arc<dyn KeyProvider>, f: impl FnOnce() -> R) -> R {
+    struct Restore(Option<Arc<dyn KeyProvider>>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SCOPED_KEY_PROVIDER.with(|scoped| *scoped.borrow_mut() = self.0.take());
+        }
+    }
+    let _restore = Restore(SCOPED_KEY_PROVIDER.with(|scoped| scoped.replace(Some(provider))));
+    f()
+}
+
+fn key_provider() -> Result<Arc<dyn KeyProvider>, ServerError> {
+    SCOPED_KEY_PROVIDER
+        .with(|scoped| scoped.borrow().clone())
+        .or_else(|| KEY_PROVIDER.read().unwrap().clone())
+        .ok_or_else(|| {
+            DynamoEncryptionError::new("no key provider set; see encryption::set_key_provider")
+        })
+}
+
+// Encrypted<T>.
+// --------------------------------------------------
+
+impl<T> Encrypted<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> From<T> for Encrypted<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T> std::ops::Deref for Encrypted<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> std::ops::DerefMut for Encrypted<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T> std::fmt::Debug for Encrypted<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Encrypted(<redacted>)")
+    }
+}
+
+impl<T: Serialize> Serialize for Encrypted<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        encrypt_value(&self.0)
+            .map_err(serde::ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Encrypted<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Encrypted<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let encoded = String::deserialize(deserializer)?;
+        decrypt_value(&encoded)
+            .map(Encrypted)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+fn encrypt_value<T: Serialize>(value: &T) -> Result<String, ServerError> {
+    let plaintext = serde_json::to_vec(value)
+        .map_err(|e| DynamoEncryptionError::with_debug("failed to serialize value", &e))?;
+    Ok(base64_encode(&key_provider()?.encrypt(&plaintext)?))
+}
+
+fn decrypt_value<T: DeserializeOwned>(encoded: &str) -> Result<T, ServerError> {
+    let ciphertext = base64_decode(encoded)
+        .ok_or_else(|| DynamoEncryptionError::new("stored value is not valid base64"))?;
+    let plaintext = key_provider()?.decrypt(&ciphertext)?;
+    serde_json::from_slice(&plaintext)
+        .map_err(|e| DynamoEncryptionError::with_debug("failed to deserialize value", &e))
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{
+            parsing::{build_dynamo_map_for_new_obj, parse_dynamo_map},
+            AutoFields, DynamoObject, DynamoObjectData, IdLogic, NestingLogic, PkSk,
+        },
+    };
+
+    // Reversible stand-in for a real cipher.
+    struct XorKeyProvider(u8);
+    impl KeyProvider for XorKeyProvider {
+        fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, ServerError> {
+            Ok(plaintext.iter().map(|b| b ^ self.0).collect())
+        }
+        fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>, ServerError> {
+            Ok(ciphertext.iter().map(|b| b ^ self.0).collect())
+        }
+    }
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct PersonData {
+        name: String,
+        email: Encrypted<String>,
+        phone: Option<Encrypted<String>>,
+    }
+    dynamo_object!(
+        Person,
+        PersonData,
+        "PERSON",
+        IdLogic::Uuid,
+        NestingLogic::Root
+    );
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        with_key_provider(Arc::new(XorKeyProvider(0x5a)), || {
+            let data = PersonData {
+                name: "Jane".to_string(),
+                email: "jane@example.com".to_string().into(),
+                phone: None,
+            };
+            let map = build_dynamo_map_for_new_obj::<Person>(
+                &data,
+                "ROOT".to_string(),
+                "PERSON#1".to_string(),
+                None,
+            )
+            .unwrap();
+
+            // Unencrypted fields are stored as-is, and null encrypted fields
+            // are skipped.
+            assert_eq!(map.get("name").unwrap().as_s().unwrap(), "Jane");
+            assert!(map.get("phone").is_none());
+            let stored_email = map.get("email").unwrap().as_s().unwrap();
+            assert!(!stored_email.contains("jane"));
+            assert_eq!(
+                base64_decode(stored_email).unwrap(),
+                XorKeyProvider(0x5a)
+                    .encrypt(b"\"jane@example.com\"")
+                    .unwrap()
+            );
+
+            let parsed = parse_dynamo_map::<Person>(&map).unwrap();
+            assert_eq!(parsed.data, data);
+            assert_eq!(*parsed.data.email, "jane@example.com");
+        });
+    }
+
+    #[test]
+    fn test_encrypted_wrong_key() {
+        let map = with_key_provider(Arc::new(XorKeyProvider(0x5a)), || {
+            build_dynamo_map_for_new_obj::<Person>(
+                &PersonData {
+                    email: "jane@example.com".to_string().into(),
+                    ..Default::default()
+                },
+                "ROOT".to_string(),
+                "PERSON#1".to_string(),
+                None,
+            )
+            .unwrap()
+        });
+        with_key_provider(Arc::new(XorKeyProvider(0x11)), || {
+            assert!(parse_dynamo_map::<Person>(&map).is_err());
+        });
+    }
+
+    #[test]
+    fn test_with_key_provider_restores_previous() {
+        with_key_provider(Arc::new(XorKeyProvider(1)), || {
+            with_key_provider(Arc::new(XorKeyProvider(2)), || {
+                assert_eq!(encrypt_value(&0u8).unwrap(), base64_encode(&[b'0' ^ 2]));
+            });
+            assert_eq!(encrypt_value(&0u8).unwrap(), base64_encode(&[b'0' ^ 1]));
+        });
+    }
+
+    #[test]
+    fn test_encrypted_debug_redacted() {
+        let email: Encrypted<String> = "jane@example.com".to_string().into();
+        assert_eq!(format!("{:?}", email), "Encrypted(<redacted>)");
+    }
+}
@@ -0,0 +1,254 @@
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{DynamoEnum, MaybeUnknown};
+
+// Enum serialization strategies.
+// --------------------------------------------------
+//
+// Unit-only enums declared with 'dynamo_enum!' are stored as their name
+// (string) by default, or as their integer code using:
+//   #[serde(with = "fractic_aws_dynamo::schema::enums::code")]
+//   pub priority: Priority,
+//
+// and for optional fields:
+//   #[serde(default, with = "fractic_aws_dynamo::schema::enums::code::option")]
+//   pub priority: Option<Priority>,
+//
+// Enums carrying data are best stored as tagged maps (using serde's
+// #[serde(tag = "...")]), wrapped in MaybeUnknown to tolerate unknown
+// variants.
+
+// Returns the fallback variant for a stored value not matching any variant.
+fn unknown_value<E: DynamoEnum, DE: serde::de::Error>(
+    value: impl std::fmt::Display,
+) -> Result<E, DE> {
+    E::fallback().ok_or_else(|| DE::custom(format!("unknown enum value '{}'", value)))
+}
+
+pub mod name {
+    use super::*;
+
+    pub fn serialize<S, E>(value: &E, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        E: DynamoEnum,
+    {
+        serializer.serialize_str(value.to_name())
+    }
+
+    pub fn deserialize<'de, D, E>(deserializer: D) -> Result<E, D::Error>
+    where
+        D: Deserializer<'de>,
+        E: DynamoEnum,
+    {
+        let name = String::deserialize(deserializer)?;
+        match E::from_name(&name) {
+            Some(value) => Ok(value),
+            None => unknown_value(name),
+        }
+    }
+}
+
+pub mod code {
+    use super::*;
+
+    pub fn serialize<S, E>(value: &E, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        E: DynamoEnum,
+    {
+        serializer.serialize_i64(value.to_code())
+    }
+
+    pub fn deserialize<'de, D, E>(deserializer: D) -> Result<E, D::Error>
+    where
+        D: Deserializer<'de>,
+        E: DynamoEnum,
+    {
+        let code = i64::deserialize(deserializer)?;
+        match E::from_code(code) {
+            Some(value) => Ok(value),
+            None => unknown_value(code),
+        }
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S, E>(value: &Option<E>, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+            E: DynamoEnum,
+        {
+            value.as_ref().map(E::to_code).serialize(serializer)
+        }
+
+        pub fn deserialize<'de, D, E>(deserializer: D) -> Result<Option<E>, D::Error>
+        where
+            D: Deserializer<'de>,
+            E: DynamoEnum,
+        {
+            match Option::<i64>::deserialize(deserializer)? {
+                Some(code) => match E::from_code(code) {
+                    Some(value) => Ok(Some(value)),
+                    None => unknown_value(code).map(Some),
+                },
+                None => Ok(None),
+            }
+        }
+    }
+}
+
+// MaybeUnknown<E>.
+// --------------------------------------------------
+
+impl<E> MaybeUnknown<E> {
+    pub fn known(&self) -> Option<&E> {
+        match self {
+            MaybeUnknown::Known(value) => Some(value),
+            MaybeUnknown::Unknown(_) => None,
+        }
+    }
+
+    pub fn into_known(self) -> Option<E> {
+        match self {
+            MaybeUnknown::Known(value) => Some(value),
+            MaybeUnknown::Unknown(_) => None,
+        }
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        matches!(self, MaybeUnknown::Unknown(_))
+    }
+}
+
+impl<E> From<E> for MaybeUnknown<E> {
+    fn from(value: E) -> Self {
+        MaybeUnknown::Known(value)
+    }
+}
+
+impl<E: Default> Default for MaybeUnknown<E> {
+    fn default() -> Self {
+        MaybeUnknown::Known(E::default())
+    }
+}
+
+impl<E: Serialize> Serialize for MaybeUnknown<E> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match self {
+            MaybeUnknown::Known(value) => value.serialize(serializer),
+            MaybeUnknown::Unknown(value) => value.serialize(serializer),
+        }
+    }
+}
+
+impl<'de, E: DeserializeOwned> Deserialize<'de> for MaybeUnknown<E> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Any value which doesn't parse as E is kept as-is, since serde's
+        // errors don't reliably distinguish unknown variants from other
+        // mismatches.
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Ok(match serde_json::from_value::<E>(value.clone()) {
+            Ok(known) => MaybeUnknown::Known(known),
+            Err(_) => MaybeUnknown::Unknown(value),
+        })
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dynamo_enum;
+    use serde_json::json;
+
+    dynamo_enum!(Priority {
+        Low = ("low", 1),
+        Normal = ("normal", 2),
+        High = ("high", 3),
+    }, fallback = Normal);
+
+    dynamo_enum!(Color {
+        Red = ("red", 1),
+        Blue = ("blue", 2),
+    });
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Data {
+        priority: Priority,
+        #[serde(with = "crate::schema::enums::code")]
+        color: Color,
+        #[serde(default, with = "crate::schema::enums::code::option")]
+        other_color: Option<Color>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    #[serde(tag = "type")]
+    enum Trigger {
+        Schedule { cron: String },
+        Webhook { url: String },
+    }
+
+    #[test]
+    fn test_dynamo_enum_names_and_codes() {
+        let data = Data {
+            priority: Priority::High,
+            color: Color::Blue,
+            other_color: None,
+        };
+        let value = serde_json::to_value(&data).unwrap();
+        assert_eq!(
+            value,
+            json!({"priority": "high", "color": 2, "other_color": null})
+        );
+        assert_eq!(serde_json::from_value::<Data>(value).unwrap(), data);
+
+        let value = json!({"priority": "low", "color": 1, "other_color": 2});
+        let data = serde_json::from_value::<Data>(value).unwrap();
+        assert_eq!(data.priority, Priority::Low);
+        assert_eq!(data.color, Color::Red);
+        assert_eq!(data.other_color, Some(Color::Blue));
+    }
+
+    #[test]
+    fn test_dynamo_enum_unknown_values() {
+        // Priority falls back to Normal.
+        let value = json!({"priority": "urgent", "color": 1});
+        let data = serde_json::from_value::<Data>(value).unwrap();
+        assert_eq!(data.priority, Priority::Normal);
+
+        // Color has no fallback.
+        let value = json!({"priority": "low", "color": 3});
+        assert!(serde_json::from_value::<Data>(value).is_err());
+        assert!(serde_json::from_value::<Color>(json!("green")).is_err());
+    }
+
+    #[test]
+    fn test_maybe_unknown() {
+        let known: MaybeUnknown<Trigger> = serde_json::from_value(json!({
+            "type": "Webhook",
+            "url": "https://example.com",
+        }))
+        .unwrap();
+        assert_eq!(
+            known.known(),
+            Some(&Trigger::Webhook {
+                url: "https://example.com".to_string()
+            })
+        );
+
+        let stored = json!({"type": "Email", "address": "a@example.com"});
+        let unknown: MaybeUnknown<Trigger> = serde_json::from_value(stored.clone()).unwrap();
+        assert!(unknown.is_unknown());
+        assert_eq!(serde_json::to_value(&unknown).unwrap(), stored);
+    }
+}
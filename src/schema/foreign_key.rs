@@ -0,0 +1,607 @@
+use std::{fmt, hash::Hash, marker::PhantomData, str::FromStr};
+
+use fractic_server_error::ServerError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    errors::DynamoInvalidId,
+    util::{backend::DynamoBackendImpl, DynamoUtil},
+};
+
+use super::{
+    id_calculations::{get_object_type, get_own_obj_id, nest_own_obj_id},
+    DynamoObject, ForeignKey, ForeignRef, ForeignRefList, PkSk,
+};
+
+// ForeignKey<T>.
+// --------------------------------------------------
+
+impl<T: DynamoObject> ForeignKey<T> {
+    // Fails if the ID doesn't belong to an object of type T.
+    pub fn new(id: PkSk) -> Result<Self, ServerError> {
+        if id.object_type()? != T::id_label() {
+            return Err(DynamoInvalidId::new(&format!(
+                "expected reference to object type '{}', got ID '{}'",
+                T::id_label(),
+                id
+            )));
+        }
+        Ok(Self {
+            id,
+            _target: PhantomData,
+        })
+    }
+
+    pub fn of(object: &T) -> Self {
+        Self {
+            id: object.id().clone(),
+            _target: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> &PkSk {
+        &self.id
+    }
+
+    pub fn into_id(self) -> PkSk {
+        self.id
+    }
+
+    // Fetches the referenced object, or None if it no longer exists.
+    pub async fn fetch<B: DynamoBackendImpl>(
+        &self,
+        dynamo_util: &DynamoUtil<B>,
+    ) -> Result<Option<T>, ServerError> {
+        dynamo_util.get_item::<T>(self.id.clone()).await
+    }
+}
+
+impl<T: DynamoObject> TryFrom<PkSk> for ForeignKey<T> {
+    type Error = ServerError;
+
+    fn try_from(id: PkSk) -> Result<Self, ServerError> {
+        Self::new(id)
+    }
+}
+
+impl<T: DynamoObject> From<ForeignKey<T>> for PkSk {
+    fn from(foreign_key: ForeignKey<T>) -> PkSk {
+        foreign_key.id
+    }
+}
+
+// Implemented manually, since derives would require the same traits on T.
+
+impl<T: DynamoObject> Clone for ForeignKey<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            _target: PhantomData,
+        }
+    }
+}
+
+impl<T: DynamoObject> PartialEq for ForeignKey<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T: DynamoObject> Eq for ForeignKey<T> {}
+
+impl<T: DynamoObject> Hash for ForeignKey<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T: DynamoObject> fmt::Debug for ForeignKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ForeignKey<{}>({})", T::id_label(), self.id)
+    }
+}
+
+impl<T: DynamoObject> fmt::Display for ForeignKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.id.fmt(f)
+    }
+}
+
+impl<T: DynamoObject> Serialize for ForeignKey<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.id.serialize(serializer)
+    }
+}
+
+impl<'de, T: DynamoObject> Deserialize<'de> for ForeignKey<T> {
+    fn deserialize<D>(deserializer: D) -> Result<ForeignKey<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        ForeignKey::new(PkSk::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+// ForeignRef.
+// --------------------------------------------------
+
+impl ForeignRef {
+    // Reference to the object with the given (full) ID.
+    pub fn new(id: &PkSk) -> Result<Self, ServerError> {
+        Ok(Self(get_own_obj_id(&id.sk)?.to_string()))
+    }
+
+    pub fn object_type(&self) -> Result<&str, ServerError> {
+        get_object_type("", &self.0)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    // Rebuilds the full ID of the referenced object, which must be of type T.
+    // 'parent_scope' is the parent the object was created under (ignored for
+    // root objects).
+    pub fn to_id<T: DynamoObject>(&self, parent_scope: &PkSk) -> Result<PkSk, ServerError> {
+        let (pk, sk) = nest_own_obj_id::<T>(&self.0, &parent_scope.pk, &parent_scope.sk)?;
+        Ok(PkSk { pk, sk })
+    }
+
+    pub fn to_foreign_key<T: DynamoObject>(
+        &self,
+        parent_scope: &PkSk,
+    ) -> Result<ForeignKey<T>, ServerError> {
+        ForeignKey::new(self.to_id::<T>(parent_scope)?)
+    }
+
+    // Fetches the referenced object, or None if it no longer exists.
+    pub async fn resolve<T: DynamoObject, B: DynamoBackendImpl>(
+        &self,
+        dynamo_util: &DynamoUtil<B>,
+        parent_scope: &PkSk,
+    ) -> Result<Option<T>, ServerError> {
+        dynamo_util
+            .get_item::<T>(self.to_id::<T>(parent_scope)?)
+            .await
+    }
+}
+
+impl<T: DynamoObject> From<&ForeignKey<T>> for ForeignRef {
+    fn from(foreign_key: &ForeignKey<T>) -> ForeignRef {
+        // ForeignKey IDs are validated on construction, so always contain the
+        // object's own ID.
+        ForeignRef(
+            get_own_obj_id(&foreign_key.id.sk)
+                .unwrap_or(&foreign_key.id.sk)
+                .to_string(),
+        )
+    }
+}
+
+impl FromStr for ForeignRef {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<ForeignRef, ServerError> {
+        if s.contains('|') || get_own_obj_id(s)? != s {
+            return Err(DynamoInvalidId::with_debug(
+                "foreign ref must be an object's own ID (LABEL#id)",
+                &s.to_string(),
+            ));
+        }
+        Ok(ForeignRef(s.to_string()))
+    }
+}
+
+impl fmt::Display for ForeignRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for ForeignRef {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ForeignRef {
+    fn deserialize<D>(deserializer: D) -> Result<ForeignRef, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+// ForeignRefList.
+// --------------------------------------------------
+
+impl ForeignRefList {
+    pub fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, ForeignRef> {
+        self.0.iter()
+    }
+
+    pub fn contains(&self, foreign_ref: &ForeignRef) -> bool {
+        self.0.contains(foreign_ref)
+    }
+
+    pub fn position(&self, foreign_ref: &ForeignRef) -> Option<usize> {
+        self.0.iter().position(|r| r == foreign_ref)
+    }
+
+    // Appends the ref, unless it's already in the list. Returns whether it was
+    // added.
+    pub fn insert(&mut self, foreign_ref: ForeignRef) -> bool {
+        if self.contains(&foreign_ref) {
+            return false;
+        }
+        self.0.push(foreign_ref);
+        true
+    }
+
+    // Inserts the ref at the given index (clamped to the length of the list),
+    // moving it there if it's already in the list.
+    pub fn insert_at(&mut self, index: usize, foreign_ref: ForeignRef) {
+        self.remove(&foreign_ref);
+        self.0.insert(index.min(self.0.len()), foreign_ref);
+    }
+
+    // Returns whether the ref was in the list.
+    pub fn remove(&mut self, foreign_ref: &ForeignRef) -> bool {
+        match self.position(foreign_ref) {
+            Some(index) => {
+                self.0.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Fetches the referenced objects (all of type T, created under
+    // 'parent_scope') in batches, in list order. Refs to objects which no
+    // longer exist are skipped.
+    pub async fn resolve<T: DynamoObject, B: DynamoBackendImpl>(
+        &self,
+        dynamo_util: &DynamoUtil<B>,
+        parent_scope: &PkSk,
+    ) -> Result<Vec<T>, ServerError> {
+        let ids = self
+            .0
+            .iter()
+            .map(|r| r.to_id::<T>(parent_scope))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(dynamo_util
+            .get_items::<T>(ids)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+}
+
+impl FromIterator<ForeignRef> for ForeignRefList {
+    // Duplicates are dropped, keeping the first occurrence.
+    fn from_iter<I: IntoIterator<Item = ForeignRef>>(iter: I) -> Self {
+        let mut list = ForeignRefList::new();
+        for foreign_ref in iter {
+            list.insert(foreign_ref);
+        }
+        list
+    }
+}
+
+impl IntoIterator for ForeignRefList {
+    type Item = ForeignRef;
+    type IntoIter = std::vec::IntoIter<ForeignRef>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a ForeignRefList {
+    type Item = &'a ForeignRef;
+    type IntoIter = std::slice::Iter<'a, ForeignRef>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+impl Serialize for ForeignRefList {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        // Refs can only contain commas in singleton family keys, in which case
+        // the list form is used instead.
+        if self.0.iter().any(|r| r.0.contains(',')) {
+            self.0.serialize(serializer)
+        } else {
+            self.0
+                .iter()
+                .map(ForeignRef::as_str)
+                .collect::<Vec<_>>()
+                .join(",")
+                .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ForeignRefList {
+    fn deserialize<D>(deserializer: D) -> Result<ForeignRefList, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(ForeignRefListVisitor)
+    }
+}
+
+// The main serialization format is a comma-separated string of refs, but a list
+// of refs is also accepted.
+struct ForeignRefListVisitor;
+impl<'de> serde::de::Visitor<'de> for ForeignRefListVisitor {
+    type Value = ForeignRefList;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a comma-separated string or a list of foreign refs")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<ForeignRefList, E>
+    where
+        E: serde::de::Error,
+    {
+        if value.is_empty() {
+            return Ok(ForeignRefList::new());
+        }
+        value
+            .split(',')
+            .map(|r| r.parse().map_err(serde::de::Error::custom))
+            .collect()
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<ForeignRefList, A::Error>
+    where
+        A: serde::de::SeqAccess<'de>,
+    {
+        let mut list = ForeignRefList::new();
+        while let Some(foreign_ref) = seq.next_element::<ForeignRef>()? {
+            list.insert(foreign_ref);
+        }
+        Ok(list)
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{AutoFields, DynamoObjectData, IdLogic, NestingLogic},
+    };
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct TaskData {}
+    dynamo_object!(Task, TaskData, "TASK", IdLogic::Uuid, NestingLogic::Root);
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct EventData {
+        task: Option<ForeignKey<Task>>,
+    }
+    dynamo_object!(Event, EventData, "EVENT", IdLogic::Uuid, NestingLogic::Root);
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct CommentData {}
+    dynamo_object!(
+        Comment,
+        CommentData,
+        "COMMENT",
+        IdLogic::Uuid,
+        NestingLogic::InlineChildOf("TASK")
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct TenantData {}
+    dynamo_object!(
+        Tenant,
+        TenantData,
+        "TENANT",
+        IdLogic::Uuid,
+        NestingLogic::ShardedRoot(4)
+    );
+
+    fn task_id() -> PkSk {
+        PkSk {
+            pk: "ROOT".to_string(),
+            sk: "TASK#123".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_new_validates_label() {
+        let foreign_key = ForeignKey::<Task>::new(task_id()).unwrap();
+        assert_eq!(foreign_key.id(), &task_id());
+        assert!(ForeignKey::<Event>::new(task_id()).is_err());
+        assert!(ForeignKey::<Event>::try_from(task_id()).is_err());
+    }
+
+    #[test]
+    fn test_of() {
+        let task = Task::new(task_id(), TaskData {});
+        assert_eq!(ForeignKey::of(&task).into_id(), task_id());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let data = EventData {
+            task: Some(ForeignKey::new(task_id()).unwrap()),
+        };
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json, serde_json::json!({ "task": "ROOT|TASK#123" }));
+        let parsed: EventData = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.task, data.task);
+
+        // References to the wrong object type are rejected.
+        assert!(serde_json::from_value::<EventData>(
+            serde_json::json!({ "task": "ROOT|EVENT#123" })
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_foreign_ref_round_trip() {
+        // Root.
+        let foreign_ref = ForeignRef::new(&task_id()).unwrap();
+        assert_eq!(foreign_ref.as_str(), "TASK#123");
+        assert_eq!(foreign_ref.object_type().unwrap(), "TASK");
+        assert_eq!(foreign_ref.to_id::<Task>(&PkSk::root()).unwrap(), task_id());
+
+        // Inline child, rebuilt under its parent.
+        let comment_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "TASK#123#COMMENT#456".to_string(),
+        };
+        let foreign_ref = ForeignRef::new(&comment_id).unwrap();
+        assert_eq!(foreign_ref.as_str(), "COMMENT#456");
+        assert_eq!(
+            foreign_ref.to_id::<Comment>(&task_id()).unwrap(),
+            comment_id
+        );
+        // Parent must match the type's nesting logic.
+        assert!(foreign_ref
+            .to_id::<Comment>(&PkSk {
+                pk: "ROOT".to_string(),
+                sk: "EVENT#123".to_string(),
+            })
+            .is_err());
+
+        // Sharded root, where the shard is derived from the ID.
+        let tenant_id = PkSk::generate::<Tenant>(&TenantData {}, &PkSk::root()).unwrap();
+        let foreign_ref = ForeignRef::new(&tenant_id).unwrap();
+        assert_eq!(
+            foreign_ref.to_id::<Tenant>(&PkSk::root()).unwrap(),
+            tenant_id
+        );
+    }
+
+    #[test]
+    fn test_foreign_ref_validates_label() {
+        let foreign_ref = ForeignRef::new(&task_id()).unwrap();
+        assert!(foreign_ref.to_id::<Event>(&PkSk::root()).is_err());
+        assert!(foreign_ref.to_foreign_key::<Task>(&PkSk::root()).is_ok());
+        assert!(foreign_ref.to_foreign_key::<Event>(&PkSk::root()).is_err());
+    }
+
+    #[test]
+    fn test_foreign_ref_serialization() {
+        let foreign_key = ForeignKey::<Task>::new(task_id()).unwrap();
+        let foreign_ref = ForeignRef::from(&foreign_key);
+        let json = serde_json::to_value(&foreign_ref).unwrap();
+        assert_eq!(json, serde_json::json!("TASK#123"));
+        assert_eq!(
+            serde_json::from_value::<ForeignRef>(json).unwrap(),
+            foreign_ref
+        );
+        // Full IDs and nested IDs are rejected.
+        assert!(serde_json::from_value::<ForeignRef>(serde_json::json!("ROOT|TASK#123")).is_err());
+        assert!(
+            serde_json::from_value::<ForeignRef>(serde_json::json!("TASK#123#COMMENT#456"))
+                .is_err()
+        );
+    }
+
+    fn refs(ids: &[&str]) -> Vec<ForeignRef> {
+        ids.iter().map(|id| id.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_foreign_ref_list_membership() {
+        let [a, b, c] = <[ForeignRef; 3]>::try_from(refs(&["TASK#a", "TASK#b", "TASK#c"])).unwrap();
+        let mut list = ForeignRefList::new();
+        assert!(list.insert(a.clone()));
+        assert!(list.insert(b.clone()));
+        assert!(!list.insert(a.clone()));
+        assert_eq!(list.len(), 2);
+        assert!(list.contains(&b));
+        assert!(!list.contains(&c));
+
+        list.insert_at(0, c.clone());
+        assert_eq!(
+            list.iter().cloned().collect::<Vec<_>>(),
+            vec![c.clone(), a.clone(), b.clone()]
+        );
+        // Existing refs are moved rather than duplicated.
+        list.insert_at(99, c.clone());
+        assert_eq!(list.position(&c), Some(2));
+        assert_eq!(list.len(), 3);
+
+        assert!(list.remove(&a));
+        assert!(!list.remove(&a));
+        assert_eq!(list.into_iter().collect::<Vec<_>>(), vec![b, c]);
+
+        // Duplicates are dropped when collecting.
+        let list: ForeignRefList = refs(&["TASK#a", "TASK#b", "TASK#a"]).into_iter().collect();
+        assert_eq!(list.len(), 2);
+    }
+
+    #[test]
+    fn test_foreign_ref_list_serialization() {
+        let list: ForeignRefList = refs(&["TASK#a", "TASK#b"]).into_iter().collect();
+        let json = serde_json::to_value(&list).unwrap();
+        assert_eq!(json, serde_json::json!("TASK#a,TASK#b"));
+        assert_eq!(
+            serde_json::from_value::<ForeignRefList>(json).unwrap(),
+            list
+        );
+
+        // List form.
+        assert_eq!(
+            serde_json::from_value::<ForeignRefList>(serde_json::json!(["TASK#a", "TASK#b"]))
+                .unwrap(),
+            list
+        );
+        // Refs containing commas are stored in list form.
+        let list: ForeignRefList = refs(&["TASK#a", "@CONFIG[x,y]"]).into_iter().collect();
+        let json = serde_json::to_value(&list).unwrap();
+        assert_eq!(json, serde_json::json!(["TASK#a", "@CONFIG[x,y]"]));
+        assert_eq!(
+            serde_json::from_value::<ForeignRefList>(json).unwrap(),
+            list
+        );
+
+        // Empty.
+        let json = serde_json::to_value(ForeignRefList::new()).unwrap();
+        assert_eq!(json, serde_json::json!(""));
+        assert!(serde_json::from_value::<ForeignRefList>(json)
+            .unwrap()
+            .is_empty());
+
+        assert!(
+            serde_json::from_value::<ForeignRefList>(serde_json::json!("TASK#a,ROOT|TASK#b"))
+                .is_err()
+        );
+    }
+}
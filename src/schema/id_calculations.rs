@@ -4,11 +4,11 @@ use aws_sdk_dynamodb::types::AttributeValue;
 use fractic_server_error::{CriticalError, ServerError};
 
 use crate::{
-    errors::{DynamoInvalidId, DynamoInvalidParent},
+    errors::{DynamoInvalidId, DynamoInvalidOperation, DynamoInvalidParent},
     util::DynamoMap,
 };
 
-use super::{DynamoObject, IdLogic, NestingLogic};
+use super::{DynamoObject, IdLogic, NestingLogic, PkSk};
 
 const ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
 
@@ -28,9 +28,132 @@ fn _uuid_16_chars() -> String {
     _base62_encode(uuid.as_u128(), 16)
 }
 
+// Derives the ID from the given seed instead of a random UUID, so that
+// repeated calls with the same seed generate the same ID.
+fn _seeded_uuid_16_chars(seed: &uuid::Uuid) -> String {
+    _base62_encode(seed.as_u128(), 16)
+}
+
 fn _epoch_timestamp_16_chars() -> String {
-    let timestamp = chrono::Utc::now().timestamp_millis();
-    format!("{:016}", timestamp)
+    timestamp_id_16_chars(&chrono::Utc::now())
+}
+
+// Random suffix appended to IdLogic::TimestampMillis IDs (62^6 ~= 5.7e10
+// possible values per millisecond).
+const TIMESTAMP_SUFFIX_CHARS: usize = 6;
+
+fn _epoch_timestamp_with_suffix_22_chars() -> String {
+    _timestamp_with_suffix_22_chars_at(&chrono::Utc::now())
+}
+
+fn _timestamp_with_suffix_22_chars_at(dt: &chrono::DateTime<chrono::Utc>) -> String {
+    format!(
+        "{}{}",
+        timestamp_id_16_chars(dt),
+        _base62_encode(rand::random::<u128>(), TIMESTAMP_SUFFIX_CHARS)
+    )
+}
+
+// Lowest / highest IdLogic::TimestampMillis IDs which could be generated at the
+// given datetime, for filtering by date range.
+pub(crate) fn timestamp_with_suffix_bounds_22_chars(
+    dt: &chrono::DateTime<chrono::Utc>,
+) -> (String, String) {
+    let timestamp = timestamp_id_16_chars(dt);
+    (
+        format!("{}{}", timestamp, "0".repeat(TIMESTAMP_SUFFIX_CHARS)),
+        format!("{}{}", timestamp, "z".repeat(TIMESTAMP_SUFFIX_CHARS)),
+    )
+}
+
+const CROCKFORD_ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+fn _crockford_encode(mut n: u128, num_chars: usize) -> String {
+    let mut result = vec![' '; num_chars];
+
+    for i in 0..num_chars {
+        result[num_chars - 1 - i] = CROCKFORD_ALPHABET[(n % 32) as usize] as char;
+        n /= 32;
+    }
+
+    result.into_iter().collect()
+}
+
+fn _ulid_26_chars() -> String {
+    _ulid_26_chars_at(&chrono::Utc::now())
+}
+
+fn _ulid_26_chars_at(dt: &chrono::DateTime<chrono::Utc>) -> String {
+    let timestamp = dt.timestamp_millis().max(0) as u128;
+    let randomness = rand::random::<u128>() & ((1u128 << 80) - 1);
+    _crockford_encode((timestamp << 80) | randomness, 26)
+}
+
+// Lowest / highest IdLogic::Ulid IDs which could be generated at the given
+// datetime, for filtering by date range.
+pub(crate) fn ulid_bounds_26_chars(dt: &chrono::DateTime<chrono::Utc>) -> (String, String) {
+    let timestamp = dt.timestamp_millis().max(0) as u128;
+    (
+        _crockford_encode(timestamp << 80, 26),
+        _crockford_encode((timestamp << 80) | ((1u128 << 80) - 1), 26),
+    )
+}
+
+// KSUID timestamps are offset from 2014-05-13T16:53:20Z so that 32 bits last
+// well into the future.
+const KSUID_EPOCH_SECONDS: i64 = 1_400_000_000;
+
+// Same as _base62_encode, but for numbers too large to fit in a u128, given as
+// big-endian bytes.
+fn _base62_encode_bytes(bytes: &[u8], num_chars: usize) -> String {
+    let mut n = bytes.to_vec();
+    let mut result = vec![' '; num_chars];
+
+    for i in 0..num_chars {
+        let mut remainder = 0u32;
+        for byte in n.iter_mut() {
+            let acc = (remainder << 8) | *byte as u32;
+            *byte = (acc / 62) as u8;
+            remainder = acc % 62;
+        }
+        result[num_chars - 1 - i] = ALPHABET[remainder as usize] as char;
+    }
+
+    result.into_iter().collect()
+}
+
+fn _ksuid_timestamp_bytes(dt: &chrono::DateTime<chrono::Utc>) -> [u8; 4] {
+    ((dt.timestamp() - KSUID_EPOCH_SECONDS).clamp(0, u32::MAX as i64) as u32).to_be_bytes()
+}
+
+fn _ksuid_27_chars() -> String {
+    _ksuid_27_chars_at(&chrono::Utc::now())
+}
+
+fn _ksuid_27_chars_at(dt: &chrono::DateTime<chrono::Utc>) -> String {
+    let mut bytes = [0u8; 20];
+    bytes[..4].copy_from_slice(&_ksuid_timestamp_bytes(dt));
+    bytes[4..].copy_from_slice(&rand::random::<u128>().to_be_bytes());
+    _base62_encode_bytes(&bytes, 27)
+}
+
+// Lowest / highest IdLogic::Ksuid IDs which could be generated at the given
+// datetime, for filtering by date range.
+pub(crate) fn ksuid_bounds_27_chars(dt: &chrono::DateTime<chrono::Utc>) -> (String, String) {
+    let mut lowest = [0u8; 20];
+    lowest[..4].copy_from_slice(&_ksuid_timestamp_bytes(dt));
+    let mut highest = [0xFFu8; 20];
+    highest[..4].copy_from_slice(&_ksuid_timestamp_bytes(dt));
+    (
+        _base62_encode_bytes(&lowest, 27),
+        _base62_encode_bytes(&highest, 27),
+    )
+}
+
+// Encodes a datetime as used in IdLogic::Timestamp IDs (epoch milliseconds,
+// zero-padded to 16 chars so that IDs are ordered lexicographically).
+pub(crate) fn timestamp_id_16_chars(dt: &chrono::DateTime<chrono::Utc>) -> String {
+    format!("{:016}", dt.timestamp_millis())
 }
 
 pub(crate) fn generate_pk_sk<T: DynamoObject>(
@@ -38,7 +161,80 @@ pub(crate) fn generate_pk_sk<T: DynamoObject>(
     parent_pk: &str,
     parent_sk: &str,
 ) -> Result<(String, String), ServerError> {
-    // Validate parent ID:
+    _generate_pk_sk::<T>(data, parent_pk, parent_sk, None)
+}
+
+// Same as generate_pk_sk, but IdLogic::Uuid IDs are derived from 'seed', so the
+// same ID is generated for the same seed. Time-based IDs (IdLogic::Timestamp,
+// IdLogic::TimestampMillis, IdLogic::Ulid and IdLogic::Ksuid) are not
+// supported, since those IDs can't be reproduced.
+pub(crate) fn generate_pk_sk_seeded<T: DynamoObject>(
+    data: &T::Data,
+    parent_pk: &str,
+    parent_sk: &str,
+    seed: &uuid::Uuid,
+) -> Result<(String, String), ServerError> {
+    if matches!(
+        T::id_logic(),
+        IdLogic::Timestamp | IdLogic::TimestampMillis | IdLogic::Ulid | IdLogic::Ksuid
+    ) {
+        return Err(DynamoInvalidId::new(
+            "deterministic IDs are not supported for timestamp-based IDs",
+        ));
+    }
+    _generate_pk_sk::<T>(data, parent_pk, parent_sk, Some(seed))
+}
+
+fn _generate_pk_sk<T: DynamoObject>(
+    data: &T::Data,
+    parent_pk: &str,
+    parent_sk: &str,
+    seed: Option<&uuid::Uuid>,
+) -> Result<(String, String), ServerError> {
+    _validate_parent::<T>(parent_pk, parent_sk)?;
+    // Build pk / sk:
+    let new_obj_id = match T::id_logic() {
+        IdLogic::Uuid => format!(
+            "{}#{}",
+            T::id_label(),
+            match seed {
+                Some(seed) => _seeded_uuid_16_chars(seed),
+                None => _uuid_16_chars(),
+            }
+        ),
+        IdLogic::Timestamp => format!("{}#{}", T::id_label(), _epoch_timestamp_16_chars()),
+        IdLogic::TimestampMillis => format!(
+            "{}#{}",
+            T::id_label(),
+            _epoch_timestamp_with_suffix_22_chars()
+        ),
+        IdLogic::Ulid => format!("{}#{}", T::id_label(), _ulid_26_chars()),
+        IdLogic::Ksuid => format!("{}#{}", T::id_label(), _ksuid_27_chars()),
+        IdLogic::Custom(generate_id) => {
+            let id = generate_id(data);
+            if id.is_empty() || id.contains(['#', '|', '@', SK_COMPONENT_SEPARATOR]) {
+                return Err(DynamoInvalidId::with_debug(
+                    "custom ID must be non-empty and can't contain '#', '|', '@' or '~'",
+                    &id,
+                ));
+            }
+            format!("{}#{}", T::id_label(), id)
+        }
+        IdLogic::Singleton => format!("@{}", T::id_label()),
+        IdLogic::SingletonFamily(key) => format!("@{}[{}]", T::id_label(), key(data)),
+    };
+    // LABEL#<id> -> LABEL#<component>~<id>
+    let new_obj_id = match (T::sk_component(data), new_obj_id.split_once('#')) {
+        (Some(component), Some((label, id))) => {
+            _validate_sk_component(&component)?;
+            format!("{}#{}{}{}", label, component, SK_COMPONENT_SEPARATOR, id)
+        }
+        _ => new_obj_id,
+    };
+    Ok(_nest_obj_id::<T>(new_obj_id, parent_pk, parent_sk))
+}
+
+fn _validate_parent<T: DynamoObject>(parent_pk: &str, parent_sk: &str) -> Result<(), ServerError> {
     if is_singleton(parent_pk, parent_sk) {
         return Err(DynamoInvalidParent::new("singletons cannot have children"));
     }
@@ -52,27 +248,267 @@ pub(crate) fn generate_pk_sk<T: DynamoObject>(
                 )));
             }
         }
+        NestingLogic::InlineChildOfPath(path) | NestingLogic::TopLevelChildOfPath(path) => {
+            let ancestry = get_ancestry(parent_pk, parent_sk);
+            if !ancestry.ends_with(path) {
+                return Err(DynamoInvalidParent::new(&format!(
+                    "{} does not match {}",
+                    ancestry.join(" > "),
+                    path.join(" > ")
+                )));
+            }
+        }
         _ => {}
     }
-    // Build pk / sk:
-    let new_obj_id = match T::id_logic() {
-        IdLogic::Uuid => format!("{}#{}", T::id_label(), _uuid_16_chars()),
-        IdLogic::Timestamp => format!("{}#{}", T::id_label(), _epoch_timestamp_16_chars()),
-        IdLogic::Singleton => format!("@{}", T::id_label()),
-        IdLogic::SingletonFamily(key) => format!("@{}[{}]", T::id_label(), key(data)),
-    };
+    Ok(())
+}
+
+// Places the object's own ID (LABEL#<id>, @LABEL, ...) under the given parent,
+// according to T's nesting logic.
+fn _nest_obj_id<T: DynamoObject>(
+    obj_id: String,
+    parent_pk: &str,
+    parent_sk: &str,
+) -> (String, String) {
     match T::nesting_logic() {
-        NestingLogic::Root => Ok(("ROOT".to_string(), new_obj_id)),
-        NestingLogic::TopLevelChildOf(_) | NestingLogic::TopLevelChildOfAny => {
-            Ok((parent_sk.to_string(), new_obj_id))
+        NestingLogic::Root => ("ROOT".to_string(), obj_id),
+        NestingLogic::ShardedRoot(shards) => {
+            (_root_shard_pk(_shard_for_id(&obj_id, shards)), obj_id)
+        }
+        NestingLogic::TopLevelChildOf(_)
+        | NestingLogic::TopLevelChildOfPath(_)
+        | NestingLogic::TopLevelChildOfAny => (parent_sk.to_string(), obj_id),
+        NestingLogic::InlineChildOf(_)
+        | NestingLogic::InlineChildOfPath(_)
+        | NestingLogic::InlineChildOfAny => {
+            (parent_pk.to_string(), format!("{}#{}", parent_sk, obj_id))
         }
-        NestingLogic::InlineChildOf(_) | NestingLogic::InlineChildOfAny => Ok((
-            parent_pk.to_string(),
-            format!("{}#{}", parent_sk, new_obj_id),
+    }
+}
+
+// Returns the object's own ID (LABEL#<id>, @LABEL, ...) from its sk, without
+// the part contributed by its parent.
+pub(crate) fn get_own_obj_id(sk: &str) -> Result<&str, ServerError> {
+    if let Some(pos) = sk.find('@') {
+        // Singletons can't have children, so the singleton is always last.
+        return Ok(&sk[pos..]);
+    }
+    match sk.rmatch_indices('#').nth(1) {
+        Some((pos, _)) => Ok(&sk[pos + 1..]),
+        None if sk.contains('#') => Ok(sk),
+        None => Err(DynamoInvalidId::with_debug(
+            "sk not in LABEL#id format",
+            &sk.to_string(),
         )),
     }
 }
 
+// Rebuilds the full pk / sk of an object of type T from its own ID (see
+// get_own_obj_id) and the parent it was created under.
+pub(crate) fn nest_own_obj_id<T: DynamoObject>(
+    obj_id: &str,
+    parent_pk: &str,
+    parent_sk: &str,
+) -> Result<(String, String), ServerError> {
+    if get_object_type("", obj_id)? != T::id_label() {
+        return Err(DynamoInvalidId::new(&format!(
+            "expected ID of object type '{}', got '{}'",
+            T::id_label(),
+            obj_id
+        )));
+    }
+    _validate_parent::<T>(parent_pk, parent_sk)?;
+    Ok(_nest_obj_id::<T>(obj_id.to_string(), parent_pk, parent_sk))
+}
+
+fn _root_shard_pk(shard: u8) -> String {
+    format!("ROOT#{:02}", shard)
+}
+
+// Picks the shard for an object based on its ID (FNV-1a hash, which unlike the
+// std hasher is stable across Rust versions, so IDs always map to the same
+// shard).
+fn _shard_for_id(obj_id: &str, shards: u8) -> u8 {
+    let hash = obj_id.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    (hash % shards.max(1) as u64) as u8
+}
+
+// Same as generate_pk_sk_prefix, but returns the prefix for each partition the
+// objects of type T could be stored in (one per shard for
+// NestingLogic::ShardedRoot, otherwise only one).
+pub(crate) fn generate_pk_sk_prefixes<T: DynamoObject>(
+    parent_pk: &str,
+    parent_sk: &str,
+) -> Result<Vec<(String, String)>, ServerError> {
+    match T::nesting_logic() {
+        NestingLogic::ShardedRoot(shards) => {
+            _validate_parent::<T>(parent_pk, parent_sk)?;
+            let sk_prefix = _obj_id_prefix::<T>();
+            Ok((0..shards.max(1))
+                .map(|shard| (_root_shard_pk(shard), sk_prefix.clone()))
+                .collect())
+        }
+        _ => Ok(vec![generate_pk_sk_prefix::<T>(parent_pk, parent_sk)?]),
+    }
+}
+
+// Builds the pk and the sk prefix shared by all objects of type T under the
+// given parent (i.e. the sk that would be generated for a new object, up to and
+// including its label).
+pub(crate) fn generate_pk_sk_prefix<T: DynamoObject>(
+    parent_pk: &str,
+    parent_sk: &str,
+) -> Result<(String, String), ServerError> {
+    if matches!(T::nesting_logic(), NestingLogic::ShardedRoot(_)) {
+        return Err(DynamoInvalidOperation::new(
+            "objects with sharded nesting are spread across multiple partitions, so there is no single prefix (use query_all)",
+        ));
+    }
+    _validate_parent::<T>(parent_pk, parent_sk)?;
+    Ok(_nest_obj_id::<T>(
+        _obj_id_prefix::<T>(),
+        parent_pk,
+        parent_sk,
+    ))
+}
+
+fn _obj_id_prefix<T: DynamoObject>() -> String {
+    match T::id_logic() {
+        // LABEL#<id> -> LABEL#
+        IdLogic::Uuid
+        | IdLogic::Timestamp
+        | IdLogic::TimestampMillis
+        | IdLogic::Ulid
+        | IdLogic::Ksuid
+        | IdLogic::Custom(_) => format!("{}#", T::id_label()),
+        // @LABEL -> @LABEL
+        IdLogic::Singleton => format!("@{}", T::id_label()),
+        // @LABEL[<key>] -> @LABEL[
+        IdLogic::SingletonFamily(_) => format!("@{}[", T::id_label()),
+    }
+}
+
+// Splits an sk into the ID of the top-level object in its partition (either
+// LABEL#<id> or a singleton's @LABEL) and the remaining suffix, which is
+// non-empty for inline children of that object.
+pub(crate) fn split_top_level_id(sk: &str) -> (&str, &str) {
+    if sk.starts_with('@') {
+        // Singletons can't have children.
+        return (sk, "");
+    }
+    match sk.match_indices('#').nth(1) {
+        Some((pos, _)) => sk.split_at(pos),
+        None => (sk, ""),
+    }
+}
+
+// Generates a new ID for a copy of the top-level object of type T with the
+// given ID (see split_top_level_id), based on T's IdLogic. Timestamp-based IDs
+// use the provided timestamp, so that the caller can keep copies unique and in
+// their original order. Singleton IDs are unchanged, and Custom IDs (derived
+// from data which isn't available here) are replaced by UUID-based IDs.
+pub(crate) fn regenerate_top_level_id<T: DynamoObject>(
+    id: &str,
+    timestamp: &chrono::DateTime<chrono::Utc>,
+) -> String {
+    let Some((label, old_id)) = id.split_once('#') else {
+        return id.to_string();
+    };
+    let new_id = match T::id_logic() {
+        IdLogic::Uuid | IdLogic::Custom(_) => _uuid_16_chars(),
+        IdLogic::Timestamp => timestamp_id_16_chars(timestamp),
+        IdLogic::TimestampMillis => _timestamp_with_suffix_22_chars_at(timestamp),
+        IdLogic::Ulid => _ulid_26_chars_at(timestamp),
+        IdLogic::Ksuid => _ksuid_27_chars_at(timestamp),
+        IdLogic::Singleton | IdLogic::SingletonFamily(_) => return id.to_string(),
+    };
+    // Keep the sk component (see DynamoObject::sk_component), if any.
+    match split_sk_component(old_id).0 {
+        Some(component) => format!(
+            "{}#{}{}{}",
+            label, component, SK_COMPONENT_SEPARATOR, new_id
+        ),
+        None => format!("{}#{}", label, new_id),
+    }
+}
+
+// Separates the sk component (see DynamoObject::sk_component) from the unique
+// part of an object's own ID: <component>~<id>.
+pub(crate) const SK_COMPONENT_SEPARATOR: char = '~';
+
+// Splits an object's own ID (the part after LABEL#) into its sk component, if
+// any, and the unique part.
+pub(crate) fn split_sk_component(obj_id: &str) -> (Option<&str>, &str) {
+    match obj_id.split_once(SK_COMPONENT_SEPARATOR) {
+        Some((component, id)) => (Some(component), id),
+        None => (None, obj_id),
+    }
+}
+
+fn _validate_sk_component(component: &str) -> Result<(), ServerError> {
+    if component.is_empty() || component.contains(['#', '|', '@', SK_COMPONENT_SEPARATOR]) {
+        return Err(DynamoInvalidId::with_debug(
+            "sk component must be non-empty and can't contain '#', '|', '@' or '~'",
+            &component.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// Returns the ID the given object should have for the (possibly changed) data,
+// by replacing the sk component in its own ID. The rest of the ID is unchanged.
+pub(crate) fn regenerate_sk_component<T: DynamoObject>(
+    id: &PkSk,
+    data: &T::Data,
+) -> Result<PkSk, ServerError> {
+    if is_singleton(&id.pk, &id.sk) {
+        return Ok(id.clone());
+    }
+    let Some((prefix, own_id)) = id.sk.rsplit_once('#') else {
+        return Err(DynamoInvalidId::with_debug(
+            "sk not in LABEL#id format",
+            &id.sk,
+        ));
+    };
+    let (_, unique_id) = split_sk_component(own_id);
+    let sk = match T::sk_component(data) {
+        Some(component) => {
+            _validate_sk_component(&component)?;
+            format!(
+                "{}#{}{}{}",
+                prefix, component, SK_COMPONENT_SEPARATOR, unique_id
+            )
+        }
+        None => format!("{}#{}", prefix, unique_id),
+    };
+    Ok(PkSk {
+        pk: id.pk.clone(),
+        sk,
+    })
+}
+
+// Builds the pk and the sk prefix shared by all objects of type T under the
+// given parent with the given sk component (see DynamoObject::sk_component).
+pub(crate) fn generate_pk_sk_component_prefix<T: DynamoObject>(
+    parent_pk: &str,
+    parent_sk: &str,
+    component: &str,
+) -> Result<(String, String), ServerError> {
+    _validate_sk_component(component)?;
+    let (pk, sk_prefix) = generate_pk_sk_prefix::<T>(parent_pk, parent_sk)?;
+    if !sk_prefix.ends_with('#') {
+        return Err(DynamoInvalidOperation::new(
+            "sk components are not supported for singletons",
+        ));
+    }
+    Ok((
+        pk,
+        format!("{}{}{}", sk_prefix, component, SK_COMPONENT_SEPARATOR),
+    ))
+}
+
 pub(crate) fn is_singleton(_pk: &str, sk: &str) -> bool {
     sk.contains('@')
 }
@@ -101,6 +537,19 @@ pub(crate) fn get_object_type<'a>(_pk: &'a str, sk: &'a str) -> Result<&'a str,
     }
 }
 
+// Object types of the given object and its ancestors (as far as they are
+// encoded in the object's keys), from the outermost ancestor to the object
+// itself. For example, pk 'GROUP#1' and sk 'LIST#2#TASK#3' give
+// [GROUP, LIST, TASK]. The ROOT partition (and its shards) is not included.
+pub(crate) fn get_ancestry<'a>(pk: &'a str, sk: &'a str) -> Vec<&'a str> {
+    [pk, sk]
+        .into_iter()
+        .filter(|key| *key != "ROOT" && !key.starts_with("ROOT#"))
+        .flat_map(|key| key.split('#').step_by(2))
+        .filter(|label| !label.is_empty())
+        .collect()
+}
+
 // Helper function to grab the pk/sk from a "pk|sk" string.
 pub(crate) fn get_pk_sk_from_string(id: &str) -> Result<(&str, &str), ServerError> {
     if let Some((pk, sk)) = id.split_once('|') {
@@ -176,6 +625,12 @@ mod tests {
         assert!(timestamp_2 > timestamp_1);
     }
 
+    #[test]
+    fn test_timestamp_id_16_chars() {
+        let dt = chrono::DateTime::from_timestamp_millis(1630000000123).unwrap();
+        assert_eq!(timestamp_id_16_chars(&dt), "0001630000000123");
+    }
+
     #[test]
     fn test_is_singleton() {
         assert!(!is_singleton("USER#123", "ORDER#456#ITEM#789"));
@@ -185,6 +640,64 @@ mod tests {
         assert!(is_singleton("USER#123", "ORDER#56#ITEM#1#@SIGNATURE"));
     }
 
+    #[test]
+    fn test_split_top_level_id() {
+        assert_eq!(split_top_level_id("ORDER#456"), ("ORDER#456", ""));
+        assert_eq!(
+            split_top_level_id("ORDER#456#ITEM#789"),
+            ("ORDER#456", "#ITEM#789")
+        );
+        assert_eq!(
+            split_top_level_id("ORDER#456#@SETTINGS"),
+            ("ORDER#456", "#@SETTINGS")
+        );
+        assert_eq!(split_top_level_id("@SETTINGS"), ("@SETTINGS", ""));
+    }
+
+    #[test]
+    fn test_regenerate_top_level_id() {
+        let now = chrono::Utc::now();
+        let uuid_id = regenerate_top_level_id::<TestObjectRootUuid>("TEST#abcdefgh12345678", &now);
+        assert!(uuid_id.starts_with("TEST#"));
+        assert_eq!(uuid_id.len(), "TEST#".len() + 16);
+        assert_ne!(uuid_id, "TEST#abcdefgh12345678");
+        // The format follows the type's IdLogic, even if the old ID looks like
+        // a different format.
+        assert_eq!(
+            regenerate_top_level_id::<TestObjectRootTimestamp>("TEST#abcdefgh12345678", &now),
+            format!("TEST#{}", timestamp_id_16_chars(&now))
+        );
+        let suffixed_id = regenerate_top_level_id::<TestObjectRootTimestampMillis>(
+            "TEST#0001700000000000aB3xYz",
+            &now,
+        );
+        assert_eq!(
+            suffixed_id[.."TEST#".len() + 16],
+            format!("TEST#{}", timestamp_id_16_chars(&now))
+        );
+        assert_eq!(suffixed_id.len(), "TEST#".len() + 22);
+        let ulid_id =
+            regenerate_top_level_id::<TestObjectRootUlid>("TEST#01HF7YAT00ABCDEFGHJKMNPQRS", &now);
+        assert_eq!(ulid_id.len(), "TEST#".len() + 26);
+        assert_eq!(
+            ulid_id[.."TEST#".len() + 10],
+            format!("TEST#{}", &ulid_bounds_26_chars(&now).0[..10])
+        );
+        let ksuid_id = regenerate_top_level_id::<TestObjectRootKsuid>(
+            "TEST#2WGJ9Ay7mJXMvkQxpjGH4U3cFTb",
+            &now,
+        );
+        assert_eq!(ksuid_id.len(), "TEST#".len() + 27);
+        assert_ne!(ksuid_id, "TEST#2WGJ9Ay7mJXMvkQxpjGH4U3cFTb");
+        assert!(ksuid_id["TEST#".len()..] >= *ksuid_bounds_27_chars(&now).0);
+        let custom_id = regenerate_top_level_id::<TestObjectCustom>("ARTICLE#my-slug", &now);
+        assert_eq!(custom_id.len(), "ARTICLE#".len() + 16);
+        assert_eq!(
+            regenerate_top_level_id::<TestObjectSingleton>("@SINGLETON", &now),
+            "@SINGLETON"
+        );
+    }
+
     #[test]
     fn test_get_object_type() {
         assert!(get_object_type("USER#123", "INVALID").is_err());
@@ -216,6 +729,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_get_own_obj_id() {
+        assert_eq!(get_own_obj_id("GROUP#123").unwrap(), "GROUP#123");
+        assert_eq!(
+            get_own_obj_id("GROUP#123#TASK#pending~456").unwrap(),
+            "TASK#pending~456"
+        );
+        assert_eq!(get_own_obj_id("GROUP#123#@CONFIG").unwrap(), "@CONFIG");
+        assert_eq!(get_own_obj_id("@CONFIG[a#b]").unwrap(), "@CONFIG[a#b]");
+        assert!(get_own_obj_id("ROOT").is_err());
+    }
+
     #[test]
     fn test_get_pk_sk_from_string() {
         // Valid:
@@ -288,6 +813,34 @@ mod tests {
         assert_eq!(result.1.len(), "TEST#".len() + 16);
     }
 
+    #[test]
+    fn test_generate_pk_sk_seeded() {
+        let data = TestObjectRootUuidData::default();
+        let seed = uuid::Uuid::new_v4();
+        let first =
+            generate_pk_sk_seeded::<TestObjectRootUuid>(&data, "any_pk", "any_sk", &seed).unwrap();
+        let second =
+            generate_pk_sk_seeded::<TestObjectRootUuid>(&data, "any_pk", "any_sk", &seed).unwrap();
+        assert_eq!(first, second);
+        assert!(first.1.starts_with("TEST#"));
+        assert_eq!(first.1.len(), "TEST#".len() + 16);
+
+        let other_seed = uuid::Uuid::new_v4();
+        let other =
+            generate_pk_sk_seeded::<TestObjectRootUuid>(&data, "any_pk", "any_sk", &other_seed)
+                .unwrap();
+        assert_ne!(first, other);
+
+        // Timestamp-based IDs can't be derived from a seed.
+        assert!(generate_pk_sk_seeded::<TestObjectRootTimestamp>(
+            &TestObjectRootTimestampData::default(),
+            "any_pk",
+            "any_sk",
+            &seed
+        )
+        .is_err());
+    }
+
     // Test case 2: NestingLogic::Root with IdLogic::Timestamp
     #[derive(Debug, Serialize, Deserialize, Default, Clone)]
     pub struct TestObjectRootTimestampData {}
@@ -315,6 +868,172 @@ mod tests {
         assert_eq!(result.1.len(), "TEST#".len() + 16);
     }
 
+    // Test case 2a: NestingLogic::Root with IdLogic::TimestampMillis
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    pub struct TestObjectRootTimestampMillisData {}
+    dynamo_object!(
+        TestObjectRootTimestampMillis,
+        TestObjectRootTimestampMillisData,
+        "TEST",
+        IdLogic::TimestampMillis,
+        NestingLogic::Root
+    );
+
+    #[test]
+    fn test_generate_pk_sk_root_timestamp_millis() {
+        let data = TestObjectRootTimestampMillisData::default();
+        let before = chrono::Utc::now();
+        let first =
+            generate_pk_sk::<TestObjectRootTimestampMillis>(&data, "any_pk", "any_sk").unwrap();
+        let second =
+            generate_pk_sk::<TestObjectRootTimestampMillis>(&data, "any_pk", "any_sk").unwrap();
+        let after = chrono::Utc::now();
+        assert_eq!(first.0, "ROOT");
+        assert!(first.1.starts_with("TEST#"));
+        assert_eq!(first.1.len(), "TEST#".len() + 22);
+        assert_ne!(first.1, second.1);
+        let (lowest, _) = timestamp_with_suffix_bounds_22_chars(&before);
+        let (_, highest) = timestamp_with_suffix_bounds_22_chars(&after);
+        for (_, sk) in [&first, &second] {
+            let id = &sk["TEST#".len()..];
+            assert!(id >= lowest.as_str() && id <= highest.as_str());
+        }
+    }
+
+    #[test]
+    fn test_timestamp_with_suffix_bounds_22_chars() {
+        let dt = chrono::DateTime::from_timestamp_millis(1630000000123).unwrap();
+        assert_eq!(
+            timestamp_with_suffix_bounds_22_chars(&dt),
+            (
+                "0001630000000123000000".to_string(),
+                "0001630000000123zzzzzz".to_string()
+            )
+        );
+    }
+
+    // Test case 2b: NestingLogic::Root with IdLogic::Ulid
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    pub struct TestObjectRootUlidData {}
+    dynamo_object!(
+        TestObjectRootUlid,
+        TestObjectRootUlidData,
+        "TEST",
+        IdLogic::Ulid,
+        NestingLogic::Root
+    );
+
+    #[test]
+    fn test_generate_pk_sk_root_ulid() {
+        let data = TestObjectRootUlidData::default();
+        let before = chrono::Utc::now();
+        let first = generate_pk_sk::<TestObjectRootUlid>(&data, "any_pk", "any_sk").unwrap();
+        let second = generate_pk_sk::<TestObjectRootUlid>(&data, "any_pk", "any_sk").unwrap();
+        let after = chrono::Utc::now();
+        assert_eq!(first.0, "ROOT");
+        assert!(first.1.starts_with("TEST#"));
+        assert_eq!(first.1.len(), "TEST#".len() + 26);
+        // Same-millisecond IDs should not collide.
+        assert_ne!(first.1, second.1);
+        // IDs should be within the bounds for their creation time.
+        let (lowest, _) = ulid_bounds_26_chars(&before);
+        let (_, highest) = ulid_bounds_26_chars(&after);
+        for (_, sk) in [&first, &second] {
+            let id = &sk["TEST#".len()..];
+            assert!(id >= lowest.as_str() && id <= highest.as_str());
+        }
+        assert!(generate_pk_sk_seeded::<TestObjectRootUlid>(
+            &data,
+            "any_pk",
+            "any_sk",
+            &uuid::Uuid::new_v4()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_ulid_bounds_26_chars() {
+        let earlier = chrono::DateTime::from_timestamp_millis(1_700_000_000_000).unwrap();
+        let later = chrono::DateTime::from_timestamp_millis(1_700_000_000_001).unwrap();
+        let (earlier_low, earlier_high) = ulid_bounds_26_chars(&earlier);
+        let (later_low, _) = ulid_bounds_26_chars(&later);
+        assert_eq!(earlier_low.len(), 26);
+        assert_eq!(earlier_low[..10], earlier_high[..10]);
+        assert!(earlier_high.ends_with("ZZZZZZZZZZZZZZZZ"));
+        assert!(earlier_low.ends_with("0000000000000000"));
+        assert!(earlier_high < later_low);
+    }
+
+    // Test case 2c: NestingLogic::Root with IdLogic::Ksuid
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    pub struct TestObjectRootKsuidData {}
+    dynamo_object!(
+        TestObjectRootKsuid,
+        TestObjectRootKsuidData,
+        "TEST",
+        IdLogic::Ksuid,
+        NestingLogic::Root
+    );
+
+    #[test]
+    fn test_generate_pk_sk_root_ksuid() {
+        let data = TestObjectRootKsuidData::default();
+        let before = chrono::Utc::now();
+        let first = generate_pk_sk::<TestObjectRootKsuid>(&data, "any_pk", "any_sk").unwrap();
+        let second = generate_pk_sk::<TestObjectRootKsuid>(&data, "any_pk", "any_sk").unwrap();
+        let after = chrono::Utc::now();
+        assert_eq!(first.0, "ROOT");
+        assert!(first.1.starts_with("TEST#"));
+        assert_eq!(first.1.len(), "TEST#".len() + 27);
+        assert_ne!(first.1, second.1);
+        let (lowest, _) = ksuid_bounds_27_chars(&before);
+        let (_, highest) = ksuid_bounds_27_chars(&after);
+        for (_, sk) in [&first, &second] {
+            let id = &sk["TEST#".len()..];
+            assert!(id >= lowest.as_str() && id <= highest.as_str());
+        }
+        assert!(generate_pk_sk_seeded::<TestObjectRootKsuid>(
+            &data,
+            "any_pk",
+            "any_sk",
+            &uuid::Uuid::new_v4()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_base62_encode_bytes() {
+        // Matches the u128 encoding for values that fit.
+        assert_eq!(
+            _base62_encode_bytes(&1234567890u128.to_be_bytes(), 16),
+            _base62_encode(1234567890, 16)
+        );
+        // Reference min / max KSUIDs.
+        assert_eq!(
+            _base62_encode_bytes(&[0u8; 20], 27),
+            "000000000000000000000000000"
+        );
+        assert_eq!(
+            _base62_encode_bytes(&[0xFFu8; 20], 27),
+            "aWgEPTl1tmebfsQzFP4bxwgy80V"
+        );
+    }
+
+    #[test]
+    fn test_ksuid_bounds_27_chars() {
+        let earlier = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let same_second = chrono::DateTime::from_timestamp_millis(1_700_000_000_999).unwrap();
+        let later = chrono::DateTime::from_timestamp(1_700_000_001, 0).unwrap();
+        let (earlier_low, earlier_high) = ksuid_bounds_27_chars(&earlier);
+        assert_eq!(earlier_low.len(), 27);
+        assert!(earlier_low < earlier_high);
+        assert_eq!(
+            ksuid_bounds_27_chars(&same_second),
+            (earlier_low, earlier_high.clone())
+        );
+        assert!(earlier_high < ksuid_bounds_27_chars(&later).0);
+    }
+
     // Test case 3: NestingLogic::TopLevelChildOfAny with IdLogic::Uuid
     #[derive(Debug, Serialize, Deserialize, Default, Clone)]
     pub struct TestObjectTopLevelChildUuidData {}
@@ -498,7 +1217,241 @@ mod tests {
         assert_eq!(result.1, "@FAMILY[key123]");
     }
 
-    // Test case 10: Invalid parent_sk format
+    // Test case 9b: IdLogic::Custom
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    pub struct TestObjectCustomData {
+        slug: String,
+    }
+    dynamo_object!(
+        TestObjectCustom,
+        TestObjectCustomData,
+        "ARTICLE",
+        IdLogic::Custom(Box::new(|obj: &TestObjectCustomData| obj.slug.clone())),
+        NestingLogic::TopLevelChildOfAny
+    );
+
+    #[test]
+    fn test_generate_pk_sk_custom() {
+        let data = |slug: &str| TestObjectCustomData {
+            slug: slug.to_string(),
+        };
+        assert_eq!(
+            generate_pk_sk::<TestObjectCustom>(&data("hello-world"), "ROOT", "BLOG#123").unwrap(),
+            ("BLOG#123".to_string(), "ARTICLE#hello-world".to_string())
+        );
+        for invalid in ["", "a#b", "a|b", "@ab"] {
+            assert!(
+                generate_pk_sk::<TestObjectCustom>(&data(invalid), "ROOT", "BLOG#123").is_err()
+            );
+        }
+        // The prefix doesn't depend on the data, so it works even though the
+        // default data would produce an invalid ID.
+        assert_eq!(
+            generate_pk_sk_prefix::<TestObjectCustom>("ROOT", "BLOG#123").unwrap(),
+            ("BLOG#123".to_string(), "ARTICLE#".to_string())
+        );
+    }
+
+    // Test case 9c: NestingLogic::ShardedRoot
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    pub struct TestObjectShardedRootData {}
+    dynamo_object!(
+        TestObjectShardedRoot,
+        TestObjectShardedRootData,
+        "TEST",
+        IdLogic::Uuid,
+        NestingLogic::ShardedRoot(4)
+    );
+    dynamo_object!(
+        TestObjectShardedSingleton,
+        TestObjectShardedRootData,
+        "SETTINGS",
+        IdLogic::Singleton,
+        NestingLogic::ShardedRoot(4)
+    );
+
+    #[test]
+    fn test_generate_pk_sk_sharded_root() {
+        let data = TestObjectShardedRootData::default();
+        let mut shards = std::collections::HashSet::new();
+        for _ in 0..100 {
+            let (pk, sk) =
+                generate_pk_sk::<TestObjectShardedRoot>(&data, "any_pk", "any_sk").unwrap();
+            assert!(sk.starts_with("TEST#"));
+            assert_eq!(pk, _root_shard_pk(_shard_for_id(&sk, 4)));
+            shards.insert(pk);
+        }
+        // Random IDs are spread across all shards.
+        assert_eq!(
+            shards,
+            ["ROOT#00", "ROOT#01", "ROOT#02", "ROOT#03"]
+                .into_iter()
+                .map(String::from)
+                .collect()
+        );
+        // Singletons always end up in the same shard.
+        assert_eq!(
+            generate_pk_sk::<TestObjectShardedSingleton>(&data, "any_pk", "any_sk").unwrap(),
+            generate_pk_sk::<TestObjectShardedSingleton>(&data, "other_pk", "other_sk").unwrap()
+        );
+        assert!(generate_pk_sk_prefix::<TestObjectShardedRoot>("ROOT", "ROOT").is_err());
+        assert_eq!(
+            generate_pk_sk_prefixes::<TestObjectShardedRoot>("ROOT", "ROOT").unwrap(),
+            vec![
+                ("ROOT#00".to_string(), "TEST#".to_string()),
+                ("ROOT#01".to_string(), "TEST#".to_string()),
+                ("ROOT#02".to_string(), "TEST#".to_string()),
+                ("ROOT#03".to_string(), "TEST#".to_string()),
+            ]
+        );
+        assert_eq!(
+            generate_pk_sk_prefixes::<TestObjectRootTimestamp>("ROOT", "ROOT").unwrap(),
+            vec![("ROOT".to_string(), "TEST#".to_string())]
+        );
+    }
+
+    // Test case 9d: NestingLogic::InlineChildOfPath / TopLevelChildOfPath
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    pub struct TestObjectPathData {}
+    dynamo_object!(
+        TestObjectInlinePath,
+        TestObjectPathData,
+        "TASK",
+        IdLogic::Uuid,
+        NestingLogic::InlineChildOfPath(&["GROUP", "LIST"])
+    );
+    dynamo_object!(
+        TestObjectTopLevelPath,
+        TestObjectPathData,
+        "COMMENT",
+        IdLogic::Uuid,
+        NestingLogic::TopLevelChildOfPath(&["LIST", "TASK"])
+    );
+
+    #[test]
+    fn test_generate_pk_sk_path() {
+        let data = TestObjectPathData::default();
+        // Inline under GROUP > LIST.
+        let (pk, sk) =
+            generate_pk_sk::<TestObjectInlinePath>(&data, "ROOT", "GROUP#1#LIST#2").unwrap();
+        assert_eq!(pk, "ROOT");
+        assert!(sk.starts_with("GROUP#1#LIST#2#TASK#"));
+        // Ancestry spanning the pk and sk (LIST as top-level child of GROUP).
+        assert!(generate_pk_sk::<TestObjectInlinePath>(&data, "GROUP#1", "LIST#2").is_ok());
+        // Right parent type, but wrong grandparent.
+        assert!(generate_pk_sk::<TestObjectInlinePath>(&data, "ROOT", "BOARD#1#LIST#2").is_err());
+        assert!(generate_pk_sk::<TestObjectInlinePath>(&data, "ROOT", "LIST#2").is_err());
+        // Wrong parent type.
+        assert!(generate_pk_sk::<TestObjectInlinePath>(&data, "ROOT", "GROUP#1").is_err());
+
+        let (pk, sk) =
+            generate_pk_sk::<TestObjectTopLevelPath>(&data, "GROUP#1", "LIST#2#TASK#3").unwrap();
+        assert_eq!(pk, "LIST#2#TASK#3");
+        assert!(sk.starts_with("COMMENT#"));
+        assert!(generate_pk_sk::<TestObjectTopLevelPath>(&data, "ROOT", "TASK#3").is_err());
+    }
+
+    #[test]
+    fn test_get_ancestry() {
+        assert_eq!(get_ancestry("ROOT", "ROOT"), Vec::<&str>::new());
+        assert_eq!(get_ancestry("ROOT", "GROUP#1"), vec!["GROUP"]);
+        assert_eq!(get_ancestry("ROOT#03", "GROUP#1"), vec!["GROUP"]);
+        assert_eq!(
+            get_ancestry("ROOT", "GROUP#1#LIST#2#TASK#3"),
+            vec!["GROUP", "LIST", "TASK"]
+        );
+        assert_eq!(
+            get_ancestry("GROUP#1", "LIST#2#TASK#3"),
+            vec!["GROUP", "LIST", "TASK"]
+        );
+    }
+
+    // Test case 9e: Data-derived sk component
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    pub struct TestObjectComponentData {
+        status: String,
+    }
+    dynamo_object!(
+        TestObjectComponent,
+        TestObjectComponentData,
+        "ORDER",
+        IdLogic::Uuid,
+        NestingLogic::InlineChildOfAny,
+        sk_component: |data: &TestObjectComponentData| data.status.clone()
+    );
+
+    #[test]
+    fn test_generate_pk_sk_sk_component() {
+        let data = |status: &str| TestObjectComponentData {
+            status: status.to_string(),
+        };
+        let (pk, sk) =
+            generate_pk_sk::<TestObjectComponent>(&data("pending"), "ROOT", "SHOP#1").unwrap();
+        assert_eq!(pk, "ROOT");
+        assert!(sk.starts_with("SHOP#1#ORDER#pending~"));
+        assert_eq!(sk.len(), "SHOP#1#ORDER#pending~".len() + 16);
+        assert_eq!(get_object_type(&pk, &sk).unwrap(), "ORDER");
+        for invalid in ["", "a#b", "a~b"] {
+            assert!(
+                generate_pk_sk::<TestObjectComponent>(&data(invalid), "ROOT", "SHOP#1").is_err()
+            );
+        }
+        assert_eq!(
+            generate_pk_sk_component_prefix::<TestObjectComponent>("ROOT", "SHOP#1", "pending")
+                .unwrap(),
+            ("ROOT".to_string(), "SHOP#1#ORDER#pending~".to_string())
+        );
+
+        // Re-keying only replaces the component.
+        let id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "SHOP#1#ORDER#pending~abc".to_string(),
+        };
+        assert_eq!(
+            regenerate_sk_component::<TestObjectComponent>(&id, &data("shipped")).unwrap(),
+            PkSk {
+                pk: "ROOT".to_string(),
+                sk: "SHOP#1#ORDER#shipped~abc".to_string(),
+            }
+        );
+        assert_eq!(
+            regenerate_sk_component::<TestObjectComponent>(&id, &data("pending")).unwrap(),
+            id
+        );
+
+        // Copies keep the component.
+        let copy = regenerate_top_level_id::<TestObjectComponent>(
+            "ORDER#pending~abcdefgh12345678",
+            &chrono::Utc::now(),
+        );
+        assert!(copy.starts_with("ORDER#pending~"));
+        assert_ne!(copy, "ORDER#pending~abcdefgh12345678");
+        assert_eq!(split_sk_component("pending~abc"), (Some("pending"), "abc"));
+        assert_eq!(split_sk_component("abc"), (None, "abc"));
+    }
+
+    // Test case 10: Prefix shared by all objects of a type
+    #[test]
+    fn test_generate_pk_sk_prefix() {
+        assert_eq!(
+            generate_pk_sk_prefix::<TestObjectInlineChildUuid>("parent_pk", "parent_sk").unwrap(),
+            ("parent_pk".to_string(), "parent_sk#TEST#".to_string())
+        );
+        assert_eq!(
+            generate_pk_sk_prefix::<TestObjectRootTimestamp>("any_pk", "any_sk").unwrap(),
+            ("ROOT".to_string(), "TEST#".to_string())
+        );
+        assert_eq!(
+            generate_pk_sk_prefix::<TestObjectSingleton>("any_pk", "any_sk").unwrap(),
+            ("ROOT".to_string(), "@SINGLETON".to_string())
+        );
+        assert_eq!(
+            generate_pk_sk_prefix::<TestObjectSingletonFamily>("any_pk", "any_sk").unwrap(),
+            ("ROOT".to_string(), "@FAMILY[".to_string())
+        );
+    }
+
+    // Test case 11: Invalid parent_sk format
     #[test]
     fn test_generate_pk_sk_invalid_parent_sk_format() {
         let obj = TestObjectTopLevelChildOfParent {
@@ -0,0 +1,157 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use fractic_server_error::ServerError;
+
+use crate::{
+    errors::DynamoItemParsingError,
+    util::{DynamoMap, AUTO_FIELDS_SCHEMA_VERSION},
+};
+
+use super::{DynamoObjectData, Migration, Migrations};
+
+impl<D: DynamoObjectData> Migrations<D> {
+    pub fn new() -> Self {
+        Self {
+            upgraders: Default::default(),
+            _data: Default::default(),
+        }
+    }
+
+    // Registers the upgrader from 'from_version' to 'from_version + 1'.
+    pub fn add(mut self, from_version: u32, upgrader: Migration) -> Self {
+        self.upgraders.insert(from_version, upgrader);
+        self
+    }
+
+    // The version new and updated items are stored with.
+    pub fn latest_version(&self) -> u32 {
+        self.upgraders
+            .keys()
+            .next_back()
+            .map(|version| version + 1)
+            .unwrap_or(0)
+    }
+
+    // Upgrades the item to the latest version, returning None if it is already
+    // up to date (or was written with a newer version than is known here, for
+    // example by a newer deployment, in which case it is parsed as-is).
+    pub(crate) fn migrate(&self, item: &DynamoMap) -> Result<Option<DynamoMap>, ServerError> {
+        let latest = self.latest_version();
+        let mut version = stored_schema_version(item)?;
+        if version >= latest {
+            return Ok(None);
+        }
+        let mut item = item.clone();
+        while version < latest {
+            let upgrader = self.upgraders.get(&version).ok_or_else(|| {
+                DynamoItemParsingError::new(&format!(
+                    "no migration registered from schema version {}",
+                    version
+                ))
+            })?;
+            item = upgrader(version, item);
+            version += 1;
+        }
+        item.insert(
+            AUTO_FIELDS_SCHEMA_VERSION.to_string(),
+            AttributeValue::N(latest.to_string()),
+        );
+        Ok(Some(item))
+    }
+}
+
+impl<D: DynamoObjectData> Default for Migrations<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub(crate) fn stored_schema_version(item: &DynamoMap) -> Result<u32, ServerError> {
+    match item.get(AUTO_FIELDS_SCHEMA_VERSION) {
+        Some(value) => value
+            .as_n()
+            .ok()
+            .and_then(|n| n.parse::<u32>().ok())
+            .ok_or_else(|| DynamoItemParsingError::new("invalid schema_version")),
+        None => Ok(0),
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fractic_core::collection;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Default, Clone)]
+    struct TestData {}
+    impl DynamoObjectData for TestData {}
+
+    fn rename_name_to_title(_: u32, mut item: DynamoMap) -> DynamoMap {
+        if let Some(name) = item.remove("name") {
+            item.insert("title".to_string(), name);
+        }
+        item
+    }
+
+    fn add_tags(_: u32, mut item: DynamoMap) -> DynamoMap {
+        item.entry("tags".to_string())
+            .or_insert(AttributeValue::L(Vec::new()));
+        item
+    }
+
+    fn migrations() -> Migrations<TestData> {
+        Migrations::new()
+            .add(0, rename_name_to_title)
+            .add(1, add_tags)
+    }
+
+    #[test]
+    fn test_latest_version() {
+        assert_eq!(Migrations::<TestData>::new().latest_version(), 0);
+        assert_eq!(migrations().latest_version(), 2);
+    }
+
+    #[test]
+    fn test_migrate() {
+        // Unversioned items are migrated from version 0.
+        let item: DynamoMap = collection! {
+            "name".to_string() => AttributeValue::S("Name".to_string()),
+        };
+        assert_eq!(
+            migrations().migrate(&item).unwrap(),
+            Some(collection! {
+                "title".to_string() => AttributeValue::S("Name".to_string()),
+                "tags".to_string() => AttributeValue::L(Vec::new()),
+                AUTO_FIELDS_SCHEMA_VERSION.to_string() => AttributeValue::N("2".to_string()),
+            })
+        );
+
+        // Only the remaining upgraders are applied.
+        let item: DynamoMap = collection! {
+            "name".to_string() => AttributeValue::S("Name".to_string()),
+            AUTO_FIELDS_SCHEMA_VERSION.to_string() => AttributeValue::N("1".to_string()),
+        };
+        let migrated = migrations().migrate(&item).unwrap().unwrap();
+        assert!(migrated.contains_key("name"));
+        assert!(migrated.contains_key("tags"));
+
+        // Up-to-date (or newer) items are left as-is.
+        let item: DynamoMap = collection! {
+            AUTO_FIELDS_SCHEMA_VERSION.to_string() => AttributeValue::N("2".to_string()),
+        };
+        assert_eq!(migrations().migrate(&item).unwrap(), None);
+        let item: DynamoMap = collection! {
+            AUTO_FIELDS_SCHEMA_VERSION.to_string() => AttributeValue::N("3".to_string()),
+        };
+        assert_eq!(migrations().migrate(&item).unwrap(), None);
+    }
+
+    #[test]
+    fn test_migrate_missing_upgrader() {
+        let migrations = Migrations::<TestData>::new().add(1, add_tags);
+        assert!(migrations.migrate(&DynamoMap::new()).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use fractic_server_error::ServerError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::errors::DynamoInvalidOperation;
+
+use super::{Decimal, Money};
+
+impl Money {
+    pub fn new(amount_minor: i64, currency: &str) -> Result<Self, ServerError> {
+        validate_currency(currency)?;
+        Ok(Self {
+            amount_minor,
+            currency: currency.to_string(),
+        })
+    }
+    pub fn zero(currency: &str) -> Result<Self, ServerError> {
+        Self::new(0, currency)
+    }
+    pub fn is_zero(&self) -> bool {
+        self.amount_minor == 0
+    }
+    pub fn is_negative(&self) -> bool {
+        self.amount_minor < 0
+    }
+
+    // Arithmetic. Amounts in different currencies can't be combined, and
+    // overflow is reported as an error rather than wrapping.
+    pub fn checked_add(&self, other: &Money) -> Result<Money, ServerError> {
+        self.combine(other, i64::checked_add)
+    }
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, ServerError> {
+        self.combine(other, i64::checked_sub)
+    }
+    pub fn checked_mul(&self, factor: i64) -> Result<Money, ServerError> {
+        self.with_amount(self.amount_minor.checked_mul(factor))
+    }
+    pub fn checked_neg(&self) -> Result<Money, ServerError> {
+        self.with_amount(self.amount_minor.checked_neg())
+    }
+    // Splits the amount into 'parts' amounts which differ by at most one minor
+    // unit and add up to the original amount (earlier parts get the
+    // remainder).
+    pub fn split(&self, parts: u32) -> Result<Vec<Money>, ServerError> {
+        if parts == 0 {
+            return Err(DynamoInvalidOperation::new(
+                "can't split money into 0 parts",
+            ));
+        }
+        let parts = parts as i64;
+        let (quotient, remainder) = (self.amount_minor / parts, self.amount_minor % parts);
+        Ok((0..parts)
+            .map(|i| Money {
+                amount_minor: quotient
+                    + if i < remainder.abs() {
+                        remainder.signum()
+                    } else {
+                        0
+                    },
+                currency: self.currency.clone(),
+            })
+            .collect())
+    }
+    // Sums the amounts, which must all be in 'currency'.
+    pub fn sum<'a>(
+        currency: &str,
+        amounts: impl IntoIterator<Item = &'a Money>,
+    ) -> Result<Money, ServerError> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency)?, |total, amount| {
+                total.checked_add(amount)
+            })
+    }
+
+    // The amount in major units, given the number of minor unit digits of the
+    // currency (for example 2 for USD, 0 for JPY).
+    pub fn to_decimal(&self, minor_unit_digits: u32) -> Result<Decimal, ServerError> {
+        format!("{}e-{}", self.amount_minor, minor_unit_digits).parse()
+    }
+
+    fn combine(
+        &self,
+        other: &Money,
+        op: fn(i64, i64) -> Option<i64>,
+    ) -> Result<Money, ServerError> {
+        if self.currency != other.currency {
+            return Err(DynamoInvalidOperation::new(&format!(
+                "can't combine amounts in different currencies ({} and {})",
+                self.currency, other.currency
+            )));
+        }
+        self.with_amount(op(self.amount_minor, other.amount_minor))
+    }
+
+    fn with_amount(&self, amount_minor: Option<i64>) -> Result<Money, ServerError> {
+        Ok(Money {
+            amount_minor: amount_minor
+                .ok_or_else(|| DynamoInvalidOperation::new("money amount overflowed"))?,
+            currency: self.currency.clone(),
+        })
+    }
+}
+
+fn validate_currency(currency: &str) -> Result<(), ServerError> {
+    match currency.len() == 3 && currency.bytes().all(|c| c.is_ascii_uppercase()) {
+        true => Ok(()),
+        false => Err(DynamoInvalidOperation::new(&format!(
+            "invalid currency code '{}'; expected an ISO 4217 code such as 'USD'",
+            currency
+        ))),
+    }
+}
+
+impl std::fmt::Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.amount_minor, self.currency)
+    }
+}
+
+impl std::str::FromStr for Money {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<Money, ServerError> {
+        let (amount, currency) = s
+            .split_once(',')
+            .ok_or_else(|| DynamoInvalidOperation::new("invalid money format"))?;
+        let amount_minor = amount
+            .parse()
+            .map_err(|e| DynamoInvalidOperation::with_debug("invalid money amount", &e))?;
+        Money::new(amount_minor, currency)
+    }
+}
+
+impl Serialize for Money {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.to_string().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D>(deserializer: D) -> Result<Money, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(MoneyVisitor)
+    }
+}
+
+// The main serialization format is "amount_minor,currency" as a string.
+// However, it was previously stored directly as the map. For now, support
+// either form of deserialization.
+struct MoneyVisitor;
+impl<'de> serde::de::Visitor<'de> for MoneyVisitor {
+    type Value = Money;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("money as a string or a map")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Money, E>
+    where
+        E: serde::de::Error,
+    {
+        value.parse().map_err(serde::de::Error::custom)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<Money, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        #[derive(Deserialize)]
+        struct LegacyMap {
+            amount_minor: i64,
+            currency: String,
+        }
+        let legacy_map: LegacyMap =
+            Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+        Money::new(legacy_map.amount_minor, &legacy_map.currency).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usd(amount_minor: i64) -> Money {
+        Money::new(amount_minor, "USD").unwrap()
+    }
+
+    #[test]
+    fn test_serialize() {
+        assert_eq!(
+            serde_json::to_value(usd(1999)).unwrap(),
+            serde_json::json!("1999,USD")
+        );
+        assert_eq!(
+            serde_json::to_value(usd(-5)).unwrap(),
+            serde_json::json!("-5,USD")
+        );
+    }
+
+    #[test]
+    fn test_deserialize() {
+        assert_eq!(
+            serde_json::from_value::<Money>(serde_json::json!("1999,USD")).unwrap(),
+            usd(1999)
+        );
+        assert_eq!(
+            serde_json::from_value::<Money>(serde_json::json!({
+                "amount_minor": 1999,
+                "currency": "USD",
+            }))
+            .unwrap(),
+            usd(1999)
+        );
+        for invalid in ["1999", "19.99,USD", "1999,usd", "1999,US", ",USD"] {
+            assert!(
+                serde_json::from_value::<Money>(serde_json::json!(invalid)).is_err(),
+                "input: {}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(usd(150).checked_add(&usd(50)).unwrap(), usd(200));
+        assert_eq!(usd(150).checked_sub(&usd(200)).unwrap(), usd(-50));
+        assert_eq!(usd(150).checked_mul(3).unwrap(), usd(450));
+        assert_eq!(usd(150).checked_neg().unwrap(), usd(-150));
+        assert!(usd(i64::MAX).checked_add(&usd(1)).is_err());
+        assert!(usd(1).checked_add(&Money::new(1, "EUR").unwrap()).is_err());
+        assert_eq!(
+            Money::sum("USD", &[usd(1), usd(2), usd(3)]).unwrap(),
+            usd(6)
+        );
+        assert!(Money::sum("EUR", &[usd(1)]).is_err());
+    }
+
+    #[test]
+    fn test_split() {
+        assert_eq!(usd(100).split(3).unwrap(), vec![usd(34), usd(33), usd(33)]);
+        assert_eq!(
+            usd(-100).split(3).unwrap(),
+            vec![usd(-34), usd(-33), usd(-33)]
+        );
+        assert_eq!(
+            usd(2).split(4).unwrap(),
+            vec![usd(1), usd(1), usd(0), usd(0)]
+        );
+        assert!(usd(100).split(0).is_err());
+    }
+
+    #[test]
+    fn test_to_decimal() {
+        assert_eq!(usd(1999).to_decimal(2).unwrap().as_str(), "19.99");
+        assert_eq!(usd(-5).to_decimal(2).unwrap().as_str(), "-0.05");
+        assert_eq!(
+            Money::new(500, "JPY")
+                .unwrap()
+                .to_decimal(0)
+                .unwrap()
+                .as_str(),
+            "500"
+        );
+    }
+}
@@ -1,10 +1,20 @@
-use std::collections::HashMap;
+use std::{cell::Cell, collections::HashMap};
 
 use aws_sdk_dynamodb::types::AttributeValue;
 use fractic_server_error::{CriticalError, ServerError};
 use serde::Serialize;
 
-use crate::{errors::DynamoItemParsingError, schema::DynamoObject, util::DynamoMap};
+use crate::{
+    errors::DynamoItemParsingError,
+    schema::{
+        binary::{binary_set_to_markers, binary_to_marker, marker_to_attribute_value},
+        decimal::{marker_to_number_attribute_value, number_to_serde_value},
+        id_calculations::{get_object_type, get_pk_sk_from_map},
+        set::marker_to_set_attribute_value,
+        DynamoObject,
+    },
+    util::DynamoMap,
+};
 
 // Converting between DynamoMap and DynamoObject.
 // --------------------------------------------------
@@ -55,7 +65,7 @@ fn build_dynamo_map_internal<T: Serialize>(
     let mut skipped_null_keys: Vec<String> = Vec::new();
 
     // DynamoObject -> Serde value.
-    let json_value = serde_json::to_value(&object)
+    let json_value = to_item_value(&object)
         .map_err(|e| DynamoItemParsingError::with_debug("failed to serialize object", &e))?;
 
     // Serde value -> DynamoMap.
@@ -94,7 +104,7 @@ fn build_dynamo_map_internal<T: Serialize>(
     // Set overrides.
     if let Some(overrides) = overrides {
         for (key, value) in overrides.into_iter() {
-            let json_value = serde_json::to_value(&value).map_err(|e| {
+            let json_value = to_item_value(&value).map_err(|e| {
                 DynamoItemParsingError::with_debug("failed to serialize override object", &e)
             })?;
             if let Some(v) = serde_value_to_attribute_value(json_value)? {
@@ -109,6 +119,11 @@ fn build_dynamo_map_internal<T: Serialize>(
 }
 
 pub fn parse_dynamo_map<T: DynamoObject>(map: &DynamoMap) -> Result<T, ServerError> {
+    // Upgrade items stored with an older schema version. The migrated item is
+    // only used for parsing; see DynamoUtil::migrate_item to persist it.
+    let migrated = T::migrations().migrate(map)?;
+    let map = migrated.as_ref().unwrap_or(map);
+
     // DynamoMap -> Serde value.
     let mut serde_map: serde_json::Map<String, serde_json::Value> = serde_json::Map::new();
     for (key, value) in map.iter() {
@@ -122,6 +137,14 @@ pub fn parse_dynamo_map<T: DynamoObject>(map: &DynamoMap) -> Result<T, ServerErr
         }
     }
 
+    // Read attributes stored under legacy names (see
+    // DynamoObject::attribute_aliases).
+    for (alias, name) in T::attribute_aliases() {
+        if let Some(v) = serde_map.remove(*alias) {
+            serde_map.entry(name.to_string()).or_insert(v);
+        }
+    }
+
     // Set ID key from pk/sk.
     serde_map.insert(
         "id".to_string(),
@@ -142,10 +165,56 @@ pub fn parse_dynamo_map<T: DynamoObject>(map: &DynamoMap) -> Result<T, ServerErr
         .map_err(|e| DynamoItemParsingError::with_debug("failed to convert from Serde value", &e))
 }
 
+// Parses the map as an object of type T if it is of type T. Otherwise (for
+// example, for inline children of a different type), returns None.
+pub fn parse_dynamo_map_if_type<T: DynamoObject>(
+    map: &DynamoMap,
+) -> Option<Result<T, ServerError>> {
+    let (pk, sk) = match get_pk_sk_from_map(map) {
+        Ok(id) => id,
+        Err(e) => return Some(Err(e)),
+    };
+    match get_object_type(pk, sk) {
+        Ok(label) if label == T::id_label() => Some(parse_dynamo_map::<T>(map)),
+        _ => None,
+    }
+}
+
+// Item serialization.
+// --------------------------------------------------
+//
+// Some types (such as Binary) are stored as attribute types which have no JSON
+// equivalent, so when serialized as part of an item, they produce marker
+// objects which serde_value_to_attribute_value converts to the corresponding
+// attributes. Otherwise (for example, in API responses), they serialize to
+// plain JSON.
+
+thread_local! {
+    static SERIALIZING_ITEM: Cell<bool> = const { Cell::new(false) };
+}
+
+// serde_json::to_value, for values which will be converted to attributes.
+pub(crate) fn to_item_value<T: Serialize + ?Sized>(
+    value: &T,
+) -> Result<serde_json::Value, serde_json::Error> {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            SERIALIZING_ITEM.with(|flag| flag.set(self.0));
+        }
+    }
+    let _restore = Restore(SERIALIZING_ITEM.with(|flag| flag.replace(true)));
+    serde_json::to_value(value)
+}
+
+pub(crate) fn is_serializing_item() -> bool {
+    SERIALIZING_ITEM.with(Cell::get)
+}
+
 // Inner recursive functions.
 // --------------------------------------------------
 
-fn serde_value_to_attribute_value(
+pub(crate) fn serde_value_to_attribute_value(
     value: serde_json::Value,
 ) -> Result<Option<AttributeValue>, ServerError> {
     match value {
@@ -153,15 +222,23 @@ fn serde_value_to_attribute_value(
         serde_json::Value::String(s) => Ok(Some(AttributeValue::S(s))),
         serde_json::Value::Number(n) => Ok(Some(AttributeValue::N(n.to_string()))),
         serde_json::Value::Bool(b) => Ok(Some(AttributeValue::Bool(b))),
-        serde_json::Value::Object(map) => Ok(Some(AttributeValue::M(
-            map.into_iter()
-                // Convert SerdeValue to AttributeValue for each key-value pair,
-                // filtering all pairs where value is None.
-                .filter_map(|(k, v)| Some((k, serde_value_to_attribute_value(v).transpose()?)))
-                // Catch any conversion errors.
-                .map(|(k, v)| Ok((k, v?)))
-                .collect::<Result<HashMap<String, AttributeValue>, ServerError>>()?,
-        ))),
+        serde_json::Value::Object(map) => {
+            if let Some(value) = marker_to_attribute_value(&map)
+                .or_else(|| marker_to_number_attribute_value(&map))
+                .or_else(|| marker_to_set_attribute_value(&map))
+            {
+                return value.map(Some);
+            }
+            Ok(Some(AttributeValue::M(
+                map.into_iter()
+                    // Convert SerdeValue to AttributeValue for each key-value
+                    // pair, filtering all pairs where value is None.
+                    .filter_map(|(k, v)| Some((k, serde_value_to_attribute_value(v).transpose()?)))
+                    // Catch any conversion errors.
+                    .map(|(k, v)| Ok((k, v?)))
+                    .collect::<Result<HashMap<String, AttributeValue>, ServerError>>()?,
+            )))
+        }
         serde_json::Value::Array(array) => {
             Ok(Some(AttributeValue::L(
                 array
@@ -179,17 +256,15 @@ fn serde_value_to_attribute_value(
     }
 }
 
-fn attribute_value_to_serde_value(
+pub(crate) fn attribute_value_to_serde_value(
     value: AttributeValue,
 ) -> Result<Option<serde_json::Value>, ServerError> {
     match value {
         AttributeValue::Null(_) => Ok(None),
         AttributeValue::S(s) => Ok(Some(serde_json::Value::String(s))),
-        AttributeValue::N(n) => {
-            Ok(Some(serde_json::Value::Number(n.parse().map_err(|e| {
-                DynamoItemParsingError::with_debug("failed to parse number", &e)
-            })?)))
-        }
+        // Numbers which can't be represented exactly are parsed as strings
+        // (see Decimal).
+        AttributeValue::N(n) => Ok(Some(number_to_serde_value(n)?)),
         AttributeValue::Bool(b) => Ok(Some(serde_json::Value::Bool(b))),
         AttributeValue::M(map) => Ok(Some(serde_json::Value::Object(
             map.into_iter()
@@ -212,6 +287,20 @@ fn attribute_value_to_serde_value(
                 })
                 .collect::<Result<Vec<_>, ServerError>>()?,
         ))),
+        // String and number sets are parsed as arrays, so they can be read into
+        // HashSet<String> / HashSet<i64> fields (see the set module).
+        AttributeValue::Ss(set) => Ok(Some(serde_json::Value::Array(
+            set.into_iter().map(serde_json::Value::String).collect(),
+        ))),
+        AttributeValue::Ns(set) => Ok(Some(serde_json::Value::Array(
+            set.into_iter()
+                .map(number_to_serde_value)
+                .collect::<Result<Vec<_>, ServerError>>()?,
+        ))),
+        // Binary values are parsed as marker objects, which can be read into
+        // Binary fields, or fields using the binary module's serde helpers.
+        AttributeValue::B(blob) => Ok(Some(binary_to_marker(&blob))),
+        AttributeValue::Bs(blobs) => Ok(Some(binary_set_to_markers(&blobs))),
         unsupported => Err(DynamoItemParsingError::new(&format!(
             "unsupported AttributeValue type '{:?}'",
             unsupported
@@ -230,13 +319,16 @@ mod tests {
         schema::{
             AutoFields, DynamoObject, DynamoObjectData, IdLogic, NestingLogic, PkSk, Timestamp,
         },
-        util::{AUTO_FIELDS_CREATED_AT, AUTO_FIELDS_SORT, AUTO_FIELDS_TTL, AUTO_FIELDS_UPDATED_AT},
+        util::{
+            AUTO_FIELDS_CREATED_AT, AUTO_FIELDS_SORT, AUTO_FIELDS_TTL, AUTO_FIELDS_UPDATED_AT,
+            AUTO_FIELDS_VERSION,
+        },
     };
     use aws_sdk_dynamodb::types::AttributeValue;
     use fractic_core::collection;
     use serde::{Deserialize, Serialize};
     use serde_json::Value;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
     #[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
     pub struct TestDynamoObjectData {
@@ -259,6 +351,21 @@ mod tests {
         NestingLogic::Root
     );
 
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Default, Clone)]
+    pub struct AliasedDynamoObjectData {
+        title: String,
+        count: u32,
+    }
+
+    dynamo_object!(
+        AliasedDynamoObject,
+        AliasedDynamoObjectData,
+        "ALIASED",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        aliases: &[("name", "title"), ("num", "count")]
+    );
+
     #[test]
     fn test_build_dynamo_map_for_new_obj() {
         let input = TestDynamoObject {
@@ -468,8 +575,12 @@ mod tests {
             auto_fields: AutoFields {
                 created_at: Some(sample_timestamp.clone()),
                 updated_at: Some(sample_timestamp.clone()),
+                created_by: Some("user_1".to_string()),
+                updated_by: Some("user_2".to_string()),
                 sort: Some(0.65),
                 ttl: Some(1234567890),
+                version: Some(3),
+                schema_version: None,
                 unknown_fields: collection!(
                     "unknown_field".to_string() => Value::String("unknown_value".to_string())
                 ),
@@ -593,6 +704,7 @@ mod tests {
             )),
             AUTO_FIELDS_SORT.to_string() => AttributeValue::N("1.2345".to_string()),
             AUTO_FIELDS_TTL.to_string() => AttributeValue::N("1234567890".to_string()),
+            AUTO_FIELDS_VERSION.to_string() => AttributeValue::N("3".to_string()),
             "unknown_field".to_string() => AttributeValue::S("unknown_value".to_string()),
         );
 
@@ -606,8 +718,12 @@ mod tests {
             auto_fields: AutoFields {
                 created_at: Some(sample_timestamp_1.clone()),
                 updated_at: Some(sample_timestamp_2.clone()),
+                created_by: None,
+                updated_by: None,
                 sort: Some(1.2345),
                 ttl: Some(1234567890),
+                version: Some(3),
+                schema_version: None,
                 unknown_fields: collection!(
                     "unknown_field".to_string() => Value::String("unknown_value".to_string())
                 ),
@@ -631,6 +747,36 @@ mod tests {
         assert_eq!(output.data, expected_output.data);
     }
 
+    #[test]
+    fn test_parse_dynamo_map_aliases() {
+        let input = collection!(
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("ALIASED#1".to_string()),
+            "name".to_string() => AttributeValue::S("Old".to_string()),
+            "num".to_string() => AttributeValue::N("1".to_string()),
+            "count".to_string() => AttributeValue::N("2".to_string()),
+        );
+        let output: AliasedDynamoObject = parse_dynamo_map(&input).unwrap();
+
+        // Aliased attributes are read as the current name, but the current name
+        // takes precedence if both are present.
+        assert_eq!(
+            output.data,
+            AliasedDynamoObjectData {
+                title: "Old".to_string(),
+                count: 2,
+            }
+        );
+        assert!(!output.has_unknown_fields());
+
+        // Only the current names are written.
+        let (map, _) =
+            build_dynamo_map_for_existing_obj(&output, IdKeys::CopyFromObject, None).unwrap();
+        assert_eq!(map.get("title").unwrap().as_s().unwrap(), "Old");
+        assert!(map.get("name").is_none());
+        assert!(map.get("num").is_none());
+    }
+
     // Null-values shouldn't really be encountered since they are skipped in
     // serialization. They should still work, however, so test it here.
     #[test]
@@ -672,6 +818,7 @@ mod tests {
             )),
             AUTO_FIELDS_SORT.to_string() => AttributeValue::N("1.2345".to_string()),
             AUTO_FIELDS_TTL.to_string() => AttributeValue::N("1234567890".to_string()),
+            AUTO_FIELDS_VERSION.to_string() => AttributeValue::N("3".to_string()),
             "unknown_field".to_string() => AttributeValue::S("unknown_value".to_string()),
         );
 
@@ -685,8 +832,12 @@ mod tests {
             auto_fields: AutoFields {
                 created_at: Some(sample_timestamp_1.clone()),
                 updated_at: Some(sample_timestamp_2.clone()),
+                created_by: None,
+                updated_by: None,
                 sort: Some(1.2345),
                 ttl: Some(1234567890),
+                version: Some(3),
+                schema_version: None,
                 unknown_fields: collection!(
                     "unknown_field".to_string() => Value::String("unknown_value".to_string())
                 ),
@@ -709,4 +860,28 @@ mod tests {
         assert_eq!(output.auto_fields, expected_output.auto_fields);
         assert_eq!(output.data, expected_output.data);
     }
+
+    #[test]
+    fn test_parse_sets() {
+        let strings = attribute_value_to_serde_value(AttributeValue::Ss(vec![
+            "a".to_string(),
+            "b".to_string(),
+        ]))
+        .unwrap()
+        .unwrap();
+        let numbers = attribute_value_to_serde_value(AttributeValue::Ns(vec![
+            "1".to_string(),
+            "-5".to_string(),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            serde_json::from_value::<HashSet<String>>(strings).unwrap(),
+            HashSet::from(["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            serde_json::from_value::<HashSet<i64>>(numbers).unwrap(),
+            HashSet::from([1, -5])
+        );
+    }
 }
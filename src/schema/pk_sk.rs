@@ -1,14 +1,9 @@
-use std::fmt;
+use std::{fmt, str::FromStr};
 
 use fractic_server_error::ServerError;
-use serde::{
-    de::{self},
-    Deserialize, Deserializer, Serialize, Serializer,
-};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::{
-    errors::DynamoInvalidId, schema::id_calculations::get_pk_sk_from_map, util::DynamoMap,
-};
+use crate::{schema::id_calculations::get_pk_sk_from_map, util::DynamoMap};
 
 use super::{
     id_calculations::{
@@ -34,8 +29,7 @@ impl PkSk {
     }
 
     pub fn from_string(s: &str) -> Result<PkSk, ServerError> {
-        serde_json::from_str(format!("\"{}\"", s).as_str())
-            .map_err(|e| DynamoInvalidId::with_debug("invalid PkSk string", &e))
+        s.parse()
     }
 
     pub fn from_map(map: &DynamoMap) -> Result<PkSk, ServerError> {
@@ -65,12 +59,32 @@ impl fmt::Display for PkSk {
     }
 }
 
+impl FromStr for PkSk {
+    type Err = ServerError;
+
+    fn from_str(s: &str) -> Result<PkSk, ServerError> {
+        let (pk, sk) = get_pk_sk_from_string(s)?;
+        Ok(PkSk {
+            pk: pk.to_string(),
+            sk: sk.to_string(),
+        })
+    }
+}
+
+impl TryFrom<&str> for PkSk {
+    type Error = ServerError;
+
+    fn try_from(s: &str) -> Result<PkSk, ServerError> {
+        s.parse()
+    }
+}
+
 impl Serialize for PkSk {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
     {
-        format!("{}|{}", self.pk, self.sk).serialize(serializer)
+        self.to_string().serialize(serializer)
     }
 }
 
@@ -79,11 +93,41 @@ impl<'de> Deserialize<'de> for PkSk {
     where
         D: Deserializer<'de>,
     {
-        let s = String::deserialize(deserializer)?;
-        let (pk, sk) = get_pk_sk_from_string(&s).map_err(|e| de::Error::custom(e))?;
+        deserializer.deserialize_any(PkSkVisitor)
+    }
+}
+
+// The main serialization format is "pk|sk" as a string, but the separate
+// {pk, sk} map is also accepted.
+struct PkSkVisitor;
+impl<'de> serde::de::Visitor<'de> for PkSkVisitor {
+    type Value = PkSk;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an ID as a \"pk|sk\" string or a map")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<PkSk, E>
+    where
+        E: serde::de::Error,
+    {
+        value.parse().map_err(serde::de::Error::custom)
+    }
+
+    fn visit_map<A>(self, map: A) -> Result<PkSk, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        #[derive(Deserialize)]
+        struct LegacyMap {
+            pk: String,
+            sk: String,
+        }
+        let legacy_map: LegacyMap =
+            Deserialize::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
         Ok(PkSk {
-            pk: pk.to_string(),
-            sk: sk.to_string(),
+            pk: legacy_map.pk,
+            sk: legacy_map.sk,
         })
     }
 }
@@ -140,6 +184,30 @@ mod tests {
         assert_eq!(serialized, r#""test_pk|test_sk""#); // Extra quotes.
     }
 
+    #[test]
+    fn test_from_str() {
+        let pksk: PkSk = "USER#123|ORDER#456".parse().unwrap();
+        assert_eq!(pksk.pk, "USER#123");
+        assert_eq!(pksk.sk, "ORDER#456");
+        assert_eq!(PkSk::try_from("USER#123|ORDER#456").unwrap(), pksk);
+        assert!("invalid_format".parse::<PkSk>().is_err());
+        // Characters that would need escaping in JSON are kept as-is.
+        let pksk: PkSk = r#"A"B|C\D"#.parse().unwrap();
+        assert_eq!(pksk.pk, r#"A"B"#);
+        assert_eq!(pksk.sk, r#"C\D"#);
+        assert_eq!(pksk.to_string().parse::<PkSk>().unwrap(), pksk);
+    }
+
+    #[test]
+    fn test_deserialize_legacy_map() {
+        let pksk: PkSk =
+            serde_json::from_value(serde_json::json!({ "pk": "test_pk", "sk": "test_sk" }))
+                .unwrap();
+        assert_eq!(pksk.pk, "test_pk");
+        assert_eq!(pksk.sk, "test_sk");
+        assert!(serde_json::from_value::<PkSk>(serde_json::json!({ "pk": "test_pk" })).is_err());
+    }
+
     #[test]
     fn test_object_type() {
         let pksk = PkSk {
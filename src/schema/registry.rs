@@ -0,0 +1,222 @@
+use std::any::{Any, TypeId};
+
+use fractic_server_error::{CriticalError, ServerError};
+
+use crate::util::DynamoMap;
+
+use super::{
+    id_calculations::{get_object_type, get_pk_sk_from_map, regenerate_top_level_id},
+    parsing::parse_dynamo_map,
+    DynamoObject, DynamoRegistry, NestingLogic, RegisteredType,
+};
+
+impl DynamoRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Registers type T under its id_label.
+    //
+    // Panics if a different type was already registered with the same label,
+    // since items of the two types could not be told apart.
+    pub fn register<T: DynamoObject + Send + 'static>(mut self) -> Self {
+        let registered = RegisteredType {
+            id_label: T::id_label(),
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            nesting_logic: T::nesting_logic,
+            regenerate_id: regenerate_top_level_id::<T>,
+            parse: |item| Ok(Box::new(parse_dynamo_map::<T>(item)?) as Box<dyn Any + Send>),
+            to_json: |item| {
+                serde_json::to_value(parse_dynamo_map::<T>(item)?).map_err(|e| {
+                    CriticalError::with_debug("failed to serialize registered object", &e)
+                })
+            },
+        };
+        if let Some(existing) = self.types.get(T::id_label()) {
+            if existing.type_id != registered.type_id {
+                panic!(
+                    "id_label '{}' is registered for both '{}' and '{}'",
+                    T::id_label(),
+                    existing.type_name,
+                    registered.type_name
+                );
+            }
+        }
+        self.types.insert(T::id_label(), registered);
+        self
+    }
+
+    pub fn get(&self, id_label: &str) -> Option<&RegisteredType> {
+        self.types.get(id_label)
+    }
+
+    // The registered type of the given item, based on the object label in its
+    // sk. Returns None if the label is not registered, or an error if the
+    // item's keys are invalid.
+    pub fn type_of(&self, item: &DynamoMap) -> Result<Option<&RegisteredType>, ServerError> {
+        let (pk, sk) = get_pk_sk_from_map(item)?;
+        Ok(self.get(get_object_type(pk, sk)?))
+    }
+
+    // The registered labels, in no particular order.
+    pub fn labels(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.types.keys().copied()
+    }
+}
+
+impl RegisteredType {
+    pub fn id_label(&self) -> &'static str {
+        self.id_label
+    }
+
+    // The Rust type name, for diagnostics only (the exact format is not
+    // guaranteed).
+    pub fn type_name(&self) -> &'static str {
+        self.type_name
+    }
+
+    pub fn nesting_logic(&self) -> NestingLogic {
+        (self.nesting_logic)()
+    }
+
+    // Generates a new ID for a copy of the top-level object with the given ID,
+    // based on the type's IdLogic (see DynamoUtil::copy_subtree).
+    pub(crate) fn regenerate_id(
+        &self,
+        id: &str,
+        timestamp: &chrono::DateTime<chrono::Utc>,
+    ) -> String {
+        (self.regenerate_id)(id, timestamp)
+    }
+
+    pub fn is<T: DynamoObject + 'static>(&self) -> bool {
+        self.type_id == TypeId::of::<T>()
+    }
+
+    // Parses the item as the registered type (applying migrations, aliases,
+    // etc. as in regular reads). The result can be downcast to the concrete
+    // type.
+    pub fn parse(&self, item: &DynamoMap) -> Result<Box<dyn Any + Send>, ServerError> {
+        (self.parse)(item)
+    }
+
+    // Parses the item as the registered type, and serializes the object to
+    // JSON (for example, for admin dumps).
+    pub fn to_json(&self, item: &DynamoMap) -> Result<serde_json::Value, ServerError> {
+        (self.to_json)(item)
+    }
+}
+
+impl std::fmt::Debug for RegisteredType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RegisteredType")
+            .field("id_label", &self.id_label)
+            .field("type_name", &self.type_name)
+            .finish()
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{AutoFields, DynamoObjectData, IdLogic, PkSk},
+    };
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use fractic_core::collection;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct TaskData {
+        name: String,
+    }
+    dynamo_object!(Task, TaskData, "TASK", IdLogic::Uuid, NestingLogic::Root);
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct NoteData {}
+    dynamo_object!(
+        Note,
+        NoteData,
+        "NOTE",
+        IdLogic::Uuid,
+        NestingLogic::InlineChildOf("TASK")
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct OtherTaskData {}
+    dynamo_object!(
+        OtherTask,
+        OtherTaskData,
+        "TASK",
+        IdLogic::Uuid,
+        NestingLogic::Root
+    );
+
+    fn task_item() -> DynamoMap {
+        collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("TASK#123".to_string()),
+            "name".to_string() => AttributeValue::S("Write report".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_registry_dispatch() {
+        let registry = DynamoRegistry::new().register::<Task>().register::<Note>();
+
+        let registered = registry.type_of(&task_item()).unwrap().unwrap();
+        assert_eq!(registered.id_label(), "TASK");
+        assert!(registered.is::<Task>());
+        assert!(!registered.is::<Note>());
+        assert!(matches!(registered.nesting_logic(), NestingLogic::Root));
+
+        let task = registered.parse(&task_item()).unwrap();
+        let task = task.downcast::<Task>().unwrap();
+        assert_eq!(
+            task.id(),
+            &PkSk {
+                pk: "ROOT".to_string(),
+                sk: "TASK#123".to_string(),
+            }
+        );
+        assert_eq!(task.data().name, "Write report");
+
+        let json = registered.to_json(&task_item()).unwrap();
+        assert_eq!(json["name"], "Write report");
+
+        let note_item: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("TASK#123#NOTE#456".to_string()),
+        };
+        assert!(registry.type_of(&note_item).unwrap().unwrap().is::<Note>());
+
+        let unknown_item: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("OTHER#123".to_string()),
+        };
+        assert!(registry.type_of(&unknown_item).unwrap().is_none());
+
+        let mut labels = registry.labels().collect::<Vec<_>>();
+        labels.sort();
+        assert_eq!(labels, vec!["NOTE", "TASK"]);
+    }
+
+    #[test]
+    fn test_registry_same_type_twice() {
+        let registry = DynamoRegistry::new().register::<Task>().register::<Task>();
+        assert_eq!(registry.labels().count(), 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_registry_conflicting_label() {
+        let _ = DynamoRegistry::new()
+            .register::<Task>()
+            .register::<OtherTask>();
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeSet;
+
+use aws_sdk_dynamodb::{primitives::Blob, types::AttributeValue};
+use fractic_server_error::ServerError;
+use serde::{ser::SerializeMap, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    errors::DynamoItemParsingError,
+    schema::parsing::{is_serializing_item, serde_value_to_attribute_value},
+};
+
+// String (SS) and number (NS) set attributes.
+// --------------------------------------------------
+//
+// By default, sets (such as HashSet<String>) are stored as lists. Fields can
+// opt in to being stored as native DynamoDB sets, which can then be modified
+// atomically with DynamoUtil::add_to_set / remove_from_set:
+//   #[serde(default, with = "fractic_aws_dynamo::schema::set")]
+//   pub tags: HashSet<String>,
+//
+// The set type is chosen from the serialized elements: strings are stored as
+// SS, numbers as NS, and binary values (see the binary module) as BS. Since
+// DynamoDB doesn't support empty sets, empty sets are not stored (so the field
+// should use #[serde(default)]). Outside of items (for example in API
+// responses), sets serialize as plain JSON arrays.
+
+const SET_MARKER: &str = "$dynamo_set";
+
+pub fn serialize<S, C, V>(values: &C, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    for<'a> &'a C: IntoIterator<Item = &'a V>,
+    V: Serialize,
+{
+    let values: Vec<&V> = values.into_iter().collect();
+    if !is_serializing_item() {
+        return values.serialize(serializer);
+    }
+    if values.is_empty() {
+        return serializer.serialize_none();
+    }
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(SET_MARKER, &values)?;
+    map.end()
+}
+
+pub fn deserialize<'de, D, C>(deserializer: D) -> Result<C, D::Error>
+where
+    D: Deserializer<'de>,
+    C: Deserialize<'de> + Default,
+{
+    // Sets are parsed as arrays (see attribute_value_to_serde_value), but an
+    // explicit null is also accepted as the empty set.
+    Ok(Option::<C>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+// Converts the marker object to a set attribute, used by
+// serde_value_to_attribute_value. Returns None for regular objects.
+pub(crate) fn marker_to_set_attribute_value(
+    map: &serde_json::Map<String, serde_json::Value>,
+) -> Option<Result<AttributeValue, ServerError>> {
+    match map.iter().next()? {
+        (key, serde_json::Value::Array(values)) if map.len() == 1 && key == SET_MARKER => {
+            Some(set_attribute_value(values))
+        }
+        _ => None,
+    }
+}
+
+fn set_attribute_value(values: &[serde_json::Value]) -> Result<AttributeValue, ServerError> {
+    let elements = values
+        .iter()
+        .map(|value| {
+            serde_value_to_attribute_value(value.clone())?
+                .ok_or_else(|| DynamoItemParsingError::new("set elements can't be null"))
+        })
+        .collect::<Result<Vec<AttributeValue>, ServerError>>()?;
+    // Duplicates (which DynamoDB rejects) are removed, since distinct elements
+    // can serialize to the same value.
+    if let Some(strings) = elements
+        .iter()
+        .map(|element| element.as_s().ok().cloned())
+        .collect::<Option<BTreeSet<String>>>()
+    {
+        return Ok(AttributeValue::Ss(strings.into_iter().collect()));
+    }
+    if let Some(numbers) = elements
+        .iter()
+        .map(|element| element.as_n().ok().cloned())
+        .collect::<Option<BTreeSet<String>>>()
+    {
+        return Ok(AttributeValue::Ns(numbers.into_iter().collect()));
+    }
+    if let Some(mut blobs) = elements
+        .iter()
+        .map(|element| element.as_b().ok().cloned())
+        .collect::<Option<Vec<Blob>>>()
+    {
+        blobs.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+        blobs.dedup();
+        return Ok(AttributeValue::Bs(blobs));
+    }
+    Err(DynamoItemParsingError::new(
+        "set elements must all be strings, numbers or binary values",
+    ))
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{
+            parsing::{build_dynamo_map_for_new_obj, parse_dynamo_map},
+            AutoFields, Binary, Decimal, DynamoObject, DynamoObjectData, IdLogic, NestingLogic,
+            PkSk,
+        },
+    };
+    use std::collections::{BTreeSet, HashSet};
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct ArticleData {
+        #[serde(default, with = "crate::schema::set")]
+        tags: HashSet<String>,
+        #[serde(default, with = "crate::schema::set")]
+        scores: BTreeSet<i64>,
+        #[serde(default, with = "crate::schema::set")]
+        hashes: HashSet<Binary>,
+        #[serde(default, with = "crate::schema::set")]
+        prices: HashSet<Decimal>,
+        // Not opted in, so stored as a list.
+        labels: HashSet<String>,
+    }
+    dynamo_object!(
+        Article,
+        ArticleData,
+        "ARTICLE",
+        IdLogic::Uuid,
+        NestingLogic::Root
+    );
+
+    fn build_map(data: &ArticleData) -> crate::util::DynamoMap {
+        build_dynamo_map_for_new_obj::<Article>(
+            data,
+            "ROOT".to_string(),
+            "ARTICLE#1".to_string(),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_set_round_trip() {
+        let data = ArticleData {
+            tags: HashSet::from(["b".to_string(), "a".to_string()]),
+            scores: BTreeSet::from([3, -1]),
+            hashes: HashSet::from([Binary(vec![1, 2])]),
+            prices: HashSet::from(["0.12345678901234567890".parse::<Decimal>().unwrap()]),
+            labels: HashSet::from(["x".to_string()]),
+        };
+        let map = build_map(&data);
+        assert_eq!(
+            map.get("tags").unwrap(),
+            &AttributeValue::Ss(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            map.get("scores").unwrap(),
+            &AttributeValue::Ns(vec!["-1".to_string(), "3".to_string()])
+        );
+        assert_eq!(
+            map.get("hashes").unwrap(),
+            &AttributeValue::Bs(vec![Blob::new(vec![1, 2])])
+        );
+        assert_eq!(
+            map.get("prices").unwrap(),
+            &AttributeValue::Ns(vec!["0.1234567890123456789".to_string()])
+        );
+        assert_eq!(
+            map.get("labels").unwrap(),
+            &AttributeValue::L(vec![AttributeValue::S("x".to_string())])
+        );
+
+        let parsed = parse_dynamo_map::<Article>(&map).unwrap();
+        assert_eq!(parsed.data, data);
+    }
+
+    #[test]
+    fn test_set_empty_not_stored() {
+        let map = build_map(&ArticleData::default());
+        assert!(map.get("tags").is_none());
+        assert!(map.get("scores").is_none());
+        assert!(map.get("hashes").is_none());
+        let parsed = parse_dynamo_map::<Article>(&map).unwrap();
+        assert_eq!(parsed.data, ArticleData::default());
+    }
+
+    #[test]
+    fn test_set_plain_json() {
+        let data = ArticleData {
+            scores: BTreeSet::from([2, 1]),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["scores"], serde_json::json!([1, 2]));
+        assert_eq!(json["tags"], serde_json::json!([]));
+        assert_eq!(serde_json::from_value::<ArticleData>(json).unwrap(), data);
+    }
+
+    #[test]
+    fn test_set_mixed_elements() {
+        let values = vec![serde_json::json!("a"), serde_json::json!(1)];
+        assert!(set_attribute_value(&values).is_err());
+    }
+}
@@ -24,6 +24,11 @@ impl Timestamp {
             )),
         }
     }
+    // The serialized form, "seconds.nanos" (zero-padded so that timestamps
+    // sort correctly as strings).
+    pub(crate) fn to_compact_string(&self) -> String {
+        format!("{:011}.{:09}", self.seconds, self.nanos)
+    }
     // Print as "YYYY-MM-DDTHH:MM:SSZ".
     pub fn to_iso_8601_string(&self) -> Result<String, ServerError> {
         Ok(self
@@ -49,7 +54,7 @@ impl Serialize for Timestamp {
     where
         S: Serializer,
     {
-        format!("{:011}.{:09}", self.seconds, self.nanos).serialize(serializer)
+        self.to_compact_string().serialize(serializer)
     }
 }
 
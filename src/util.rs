@@ -1,39 +1,119 @@
-use std::collections::HashMap;
+use std::{
+    collections::{hash_map::Entry, HashMap, VecDeque},
+    future::Future,
+    hash::Hash,
+    sync::Arc,
+};
 
 use aws_sdk_dynamodb::{
+    error::ProvideErrorMetadata,
     operation::{
-        batch_write_item::BatchWriteItemError, delete_item::DeleteItemError,
-        update_item::UpdateItemError,
+        batch_write_item::BatchWriteItemError, create_table::CreateTableError,
+        delete_item::DeleteItemError, delete_table::DeleteTableError,
+        describe_table::DescribeTableError, describe_time_to_live::DescribeTimeToLiveError,
+        put_item::PutItemError, query::QueryOutput, transact_write_items::TransactWriteItemsError,
+        update_item::UpdateItemError, update_table::UpdateTableError,
+        update_time_to_live::UpdateTimeToLiveError,
+    },
+    types::{
+        AttributeValue, BillingMode, ConsumedCapacity, GlobalSecondaryIndexDescription,
+        GlobalSecondaryIndexUpdate, IndexStatus, Put, ReturnValue, Select, TableDescription,
+        TableStatus, TimeToLiveSpecification, TimeToLiveStatus, TransactWriteItem,
     },
-    types::AttributeValue,
 };
 use backend::DynamoBackendImpl;
-use calculate_sort::calculate_sort_values;
+use cache::DynamoCache;
+use calculate_sort::{
+    calculate_sort_value_for_move, calculate_sort_values, rebalanced_sort_values,
+};
+use cancellation::CancellationToken;
 use chrono::{DateTime, Duration, Utc};
+use circuit_breaker::{is_unavailable_error, CircuitBreaker};
+use cursor::DynamoCursor;
+use filter::FilterExpression;
 use fractic_core::collection;
-use fractic_server_error::ServerError;
+use fractic_server_error::{CriticalError, ServerError};
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use item_size::{
+    size_limited_chunks, validate_item_size, DynamoMapExt, MAX_BATCH_GET_ITEMS,
+    MAX_BATCH_WRITE_ITEMS, MAX_BATCH_WRITE_SIZE,
+};
+use metrics::{report_consumed_capacity, DynamoMetrics};
+use multi_region::UPDATED_REGION_ATTRIBUTE;
+use rate_limit::{WriteRateLimit, WriteRateLimiter};
+use retry::{RetryPolicy, TimeoutConfig};
+use serde::Serialize;
+use table_router::{TableNaming, TableRouter};
+use table_schema::{
+    create_global_index_action, index_attribute_definitions, index_key_schema_matches, TableSchema,
+};
+use transaction::{DynamoTransaction, MAX_TRANSACTION_ITEMS};
 
 use crate::{
-    errors::{DynamoCalloutError, DynamoInvalidOperation, DynamoNotFound},
+    errors::{
+        DynamoAlreadyExists, DynamoCalloutError, DynamoConditionFailed, DynamoInvalidOperation,
+        DynamoNotFound, DynamoTableNotReady, DynamoTimeout, DynamoTransactionConflict,
+        DynamoUnprocessedItems, DynamoVersionConflict,
+    },
     schema::{
-        id_calculations::{generate_pk_sk, get_object_type, get_pk_sk_from_map},
+        id_calculations::{
+            generate_pk_sk, generate_pk_sk_component_prefix, generate_pk_sk_prefix,
+            generate_pk_sk_prefixes, generate_pk_sk_seeded, get_object_type, get_pk_sk_from_map,
+            ksuid_bounds_27_chars, regenerate_sk_component, split_sk_component, split_top_level_id,
+            timestamp_id_16_chars, timestamp_with_suffix_bounds_22_chars, ulid_bounds_26_chars,
+            SK_COMPONENT_SEPARATOR,
+        },
         parsing::{
             build_dynamo_map_for_existing_obj, build_dynamo_map_for_new_obj, parse_dynamo_map,
-            IdKeys,
+            parse_dynamo_map_if_type, serde_value_to_attribute_value, to_item_value, IdKeys,
         },
-        DynamoObject, IdLogic, PkSk, Timestamp,
+        DynamoObject, DynamoObjectFamily, DynamoRegistry, DynamoSetElement, IdLogic, NestingLogic,
+        PkSk, Timestamp,
     },
 };
 
 pub mod backend;
+pub mod backup;
+pub mod cache;
 mod calculate_sort;
+pub mod cancellation;
+pub mod circuit_breaker;
+pub mod cursor;
+pub mod export;
+pub mod filter;
+pub mod import;
+pub mod item_size;
+pub mod memory_backend;
+pub mod metrics;
+pub mod multi_region;
+pub mod rate_limit;
+pub mod retry;
+pub mod streams;
+pub mod table_router;
+pub mod table_schema;
 mod test;
+pub mod transaction;
+pub mod util_set;
 
 pub type DynamoMap = HashMap<String, AttributeValue>;
 pub const AUTO_FIELDS_CREATED_AT: &str = "created_at";
 pub const AUTO_FIELDS_UPDATED_AT: &str = "updated_at";
+pub const AUTO_FIELDS_CREATED_BY: &str = "created_by";
+pub const AUTO_FIELDS_UPDATED_BY: &str = "updated_by";
 pub const AUTO_FIELDS_SORT: &str = "sort";
 pub const AUTO_FIELDS_TTL: &str = "ttl";
+pub const AUTO_FIELDS_VERSION: &str = "version";
+pub const AUTO_FIELDS_SCHEMA_VERSION: &str = "schema_version";
+
+// Default for DynamoUtil::batch_concurrency.
+pub const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+// How long table management functions (such as create_table_for_schema) wait
+// for the change to be applied.
+pub const DEFAULT_TABLE_WAIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+// How often the table status is checked while waiting.
+const TABLE_STATUS_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
 
 #[derive(Debug, PartialEq)]
 pub enum DynamoQueryMatchType {
@@ -45,6 +125,9 @@ pub enum DynamoQueryMatchType {
     LessThanOrEquals,
     SuffixGreaterThanOrEquals(char),
     SuffixLessThanOrEquals(char),
+    // Matches sort keys between the given sk and the provided upper bound
+    // (both inclusive).
+    Between(String),
 }
 
 #[derive(Debug)]
@@ -52,6 +135,7 @@ pub enum DynamoInsertPosition {
     First,
     Last,
     After(PkSk),
+    Before(PkSk),
 }
 
 #[derive(Debug)]
@@ -63,11 +147,119 @@ pub enum TtlConfig {
     CustomDate(DateTime<Utc>),
 }
 
+/// Secondary index to query, created with IndexConfig::global or
+/// IndexConfig::local. For a global secondary index (GSI), both the partition
+/// and sort fields are custom attributes. For a local secondary index (LSI),
+/// the table's 'pk' is kept as the partition field and only the sort field is
+/// replaced.
 #[derive(Debug, Clone, Copy)]
 pub struct IndexConfig {
     pub name: &'static str,
     pub partition_field: &'static str,
     pub sort_field: &'static str,
+    // Only set by the constructors, so that the kind always matches the
+    // fields.
+    kind: IndexKind,
+}
+
+/// Whether an index is a GSI or an LSI. This is set explicitly rather than
+/// inferred from the partition field, since a GSI can also be keyed on 'pk'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexKind {
+    Global,
+    Local,
+}
+
+impl IndexConfig {
+    pub const fn global(
+        name: &'static str,
+        partition_field: &'static str,
+        sort_field: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            partition_field,
+            sort_field,
+            kind: IndexKind::Global,
+        }
+    }
+
+    pub const fn local(name: &'static str, sort_field: &'static str) -> Self {
+        Self {
+            name,
+            partition_field: "pk",
+            sort_field,
+            kind: IndexKind::Local,
+        }
+    }
+
+    pub fn kind(&self) -> IndexKind {
+        self.kind
+    }
+
+    pub fn is_local(&self) -> bool {
+        self.kind == IndexKind::Local
+    }
+}
+
+/// Eventually consistent reads are cheaper (half the read capacity) but may not
+/// reflect writes from the last second or so. Strongly consistent reads always
+/// reflect all successful writes, but are not supported on GSIs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReadConsistency {
+    #[default]
+    Eventual,
+    Strong,
+}
+
+impl ReadConsistency {
+    fn consistent_read(&self) -> Option<bool> {
+        match self {
+            ReadConsistency::Eventual => None,
+            ReadConsistency::Strong => Some(true),
+        }
+    }
+}
+
+/// Which version of the object to return from update_item_returning.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReturnedObject {
+    /// The object as it was before the update.
+    Old,
+    /// The object as it is after the update.
+    New,
+}
+
+impl ReturnedObject {
+    fn return_value(&self) -> ReturnValue {
+        match self {
+            ReturnedObject::Old => ReturnValue::AllOld,
+            ReturnedObject::New => ReturnValue::AllNew,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct QueryOptions {
+    /// Maximum number of items to evaluate. Since DynamoDB applies the limit
+    /// before any post-processing, items of other types (such as inline
+    /// children) count towards the limit, and results are only ordered by the
+    /// 'sort' field within the returned items.
+    ///
+    /// If not provided, all pages are fetched until the query is exhausted.
+    pub limit: Option<u32>,
+
+    /// Server-side filter, applied by DynamoDB after the key condition. Can be
+    /// built with the FilterExpression builder, or from a list of QueryFilter.
+    pub filter: Option<FilterExpression>,
+
+    /// Overrides the DynamoUtil's default read consistency for this query.
+    pub consistency: Option<ReadConsistency>,
+
+    /// If Some(false), results are returned in descending key order (for
+    /// example, newest-first for Timestamp IDs). The post-query ordering by the
+    /// 'sort' field is reversed accordingly.
+    pub scan_index_forward: Option<bool>,
 }
 
 #[derive(Debug, Default)]
@@ -77,7 +269,7 @@ pub struct CreateOptions {
     /// expiry time, usually within a day or two.
     ///
     /// IMPORTANT: This requires TTL to be enabled on the table, using attribute
-    /// name 'ttl'.
+    /// name 'ttl' (see DynamoUtil::ensure_ttl_enabled).
     pub ttl: Option<TtlConfig>,
 }
 
@@ -93,6 +285,676 @@ fn validate_id<T: DynamoObject>(id: &PkSk) -> Result<(), ServerError> {
     Ok(())
 }
 
+// Builds the key condition expression (and corresponding attribute names and
+// values) for a query, returning (index_name, condition, attribute_names,
+// attribute_values). Key fields are referenced through placeholders, since
+// index fields could be reserved words.
+fn build_key_condition(
+    index: Option<IndexConfig>,
+    id: PkSk,
+    match_type: DynamoQueryMatchType,
+) -> Result<(Option<String>, String, HashMap<String, String>, DynamoMap), ServerError> {
+    let (index_name, partition_field, sort_field) = match index {
+        Some(index) => (
+            Some(index.name.to_string()),
+            index.partition_field,
+            index.sort_field,
+        ),
+        None => (None, "pk", "sk"),
+    };
+    let mut attribute_names = HashMap::new();
+    attribute_names.insert("#pk_field".to_string(), partition_field.to_string());
+    let condition = match match_type {
+        DynamoQueryMatchType::BeginsWith if id.sk.is_empty() => "#pk_field = :pk_val",
+        DynamoQueryMatchType::BeginsWith => {
+            "#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)"
+        }
+        DynamoQueryMatchType::Equals => "#pk_field = :pk_val AND #sk_field = :sk_val",
+        DynamoQueryMatchType::GreaterThan => "#pk_field = :pk_val AND #sk_field > :sk_val",
+        DynamoQueryMatchType::GreaterThanOrEquals => "#pk_field = :pk_val AND #sk_field >= :sk_val",
+        DynamoQueryMatchType::LessThan => "#pk_field = :pk_val AND #sk_field < :sk_val",
+        DynamoQueryMatchType::LessThanOrEquals => "#pk_field = :pk_val AND #sk_field <= :sk_val",
+        DynamoQueryMatchType::SuffixGreaterThanOrEquals(_) => {
+            "#pk_field = :pk_val AND #sk_field BETWEEN :sk_val AND :sk_max"
+        }
+        DynamoQueryMatchType::SuffixLessThanOrEquals(_) => {
+            "#pk_field = :pk_val AND #sk_field BETWEEN :sk_min AND :sk_val"
+        }
+        DynamoQueryMatchType::Between(_) => {
+            "#pk_field = :pk_val AND #sk_field BETWEEN :sk_val AND :sk_max"
+        }
+    }
+    .to_string();
+    if condition.contains("#sk_field") {
+        attribute_names.insert("#sk_field".to_string(), sort_field.to_string());
+    }
+    let mut attribute_values = HashMap::new();
+    attribute_values.insert(":pk_val".to_string(), AttributeValue::S(id.pk));
+    match match_type {
+        DynamoQueryMatchType::SuffixGreaterThanOrEquals(delim) => {
+            // '~' is the last ASCII character, so we can use it as an upper
+            // bound to limit the query to a given prefix (similar to using
+            // begins_with). Since queries can only have one condition per
+            // key, this allows us to effectively do >= and begins_with at
+            // the same time, by using a BETWEEN condition.
+            attribute_values.insert(
+                ":sk_max".to_string(),
+                AttributeValue::S(format!(
+                    "{}~",
+                    id.sk
+                        .rsplit_once(delim)
+                        .ok_or_else(|| {
+                            DynamoInvalidOperation::with_debug(
+                                "sort field filter did not contain the delimiter char, so could not extract the prefix for matching",
+                                &id.sk,
+                            )
+                        })?
+                        .0
+                )),
+            );
+        }
+        DynamoQueryMatchType::SuffixLessThanOrEquals(delim) => {
+            attribute_values.insert(
+                ":sk_min".to_string(),
+                AttributeValue::S(format!(
+                    "{}",
+                    id.sk
+                        .rsplit_once(delim)
+                        .ok_or_else(|| {
+                            DynamoInvalidOperation::with_debug(
+                                "sort field filter did not contain the delimiter char, so could not extract the prefix for matching",
+                                &id.sk,
+                            )
+                        })?
+                        .0
+                )),
+            );
+        }
+        DynamoQueryMatchType::Between(max) => {
+            attribute_values.insert(":sk_max".to_string(), AttributeValue::S(max));
+        }
+        _ => {}
+    }
+    if !id.sk.is_empty() {
+        attribute_values.insert(":sk_val".to_string(), AttributeValue::S(id.sk));
+    }
+    Ok((index_name, condition, attribute_names, attribute_values))
+}
+
+// Key condition and options for a query, prepared once and reused across
+// pages.
+struct PreparedQuery {
+    table: String,
+    index_name: Option<String>,
+    condition: String,
+    attribute_values: DynamoMap,
+    filter_expression: Option<String>,
+    attribute_names: HashMap<String, String>,
+    consistent_read: Option<bool>,
+    scan_index_forward: Option<bool>,
+    limit: Option<usize>,
+}
+
+// Computes the key attributes of the indexes registered for T. Returns the
+// attributes to set, and the attributes to remove (for indexes the object
+// should not be included in).
+fn index_attributes<T: DynamoObject>(data: &T::Data) -> (DynamoMap, Vec<String>) {
+    let mut set = DynamoMap::new();
+    let mut remove = Vec::new();
+    for index in T::indexes() {
+        match (index.keys)(data) {
+            Some((partition_value, sort_value)) => {
+                set.insert(
+                    index.config.partition_field.to_string(),
+                    AttributeValue::S(partition_value),
+                );
+                set.insert(
+                    index.config.sort_field.to_string(),
+                    AttributeValue::S(sort_value),
+                );
+            }
+            None => {
+                remove.push(index.config.partition_field.to_string());
+                remove.push(index.config.sort_field.to_string());
+            }
+        }
+    }
+    (set, remove)
+}
+
+impl PreparedQuery {
+    // Returns (pk, query_key) if the results of this query can be cached. Only
+    // eventually consistent queries on the main table are cached, since
+    // writes can only be matched to the affected queries by the table's pk.
+    fn cache_key(&self) -> Option<(String, String)> {
+        if self.index_name.is_some() || self.consistent_read == Some(true) {
+            return None;
+        }
+        let pk = self.attribute_values.get(":pk_val")?.as_s().ok()?.clone();
+        let mut attribute_values = self.attribute_values.iter().collect::<Vec<_>>();
+        attribute_values.sort_by_key(|(k, _)| k.as_str());
+        let mut attribute_names = self.attribute_names.iter().collect::<Vec<_>>();
+        attribute_names.sort();
+        let query_key = format!(
+            "{:?}",
+            (
+                &self.table,
+                &self.condition,
+                attribute_values,
+                &self.filter_expression,
+                attribute_names,
+                self.scan_index_forward,
+                self.limit,
+            )
+        );
+        Some((pk, query_key))
+    }
+}
+
+// Update actions applied to a single field by update_field_atomic.
+enum FieldUpdateAction {
+    // Sets the field to the given expression.
+    Set(&'static str),
+    // Adds the value to a number (or set) field.
+    Add,
+    // Removes the values from a set field.
+    Delete,
+    // Removes the field entirely.
+    Remove,
+}
+
+// Returned (as Ok(Err(ConditionFailed))) when a write's condition expression
+// fails, to let the caller decide how to report or handle it.
+struct ConditionFailed;
+
+// Error for a failed update_item condition, where the only conditions are that
+// the object exists and (for versioned types) that its version matches.
+fn update_condition_error<T: DynamoObject>() -> ServerError {
+    match T::versioned() {
+        true => DynamoVersionConflict::new("object was modified or deleted concurrently"),
+        false => DynamoNotFound::new(),
+    }
+}
+
+// Components of an UpdateItem request, shared between update_item and
+// transactions.
+struct PreparedUpdate {
+    key: DynamoMap,
+    update_expression: String,
+    attribute_values: DynamoMap,
+    attribute_names: HashMap<String, String>,
+    condition_expression: String,
+}
+
+// Builds the update for an existing object, which SETs all non-null fields and
+// REMOVEs null fields (including index attributes) and aliased attributes.
+//
+// For versioned types, the update also increments the version, and requires
+// the stored version to equal 'expected_version' (or to not be set, if None).
+// If a principal is given, it is recorded as updated_by, and if a region is
+// given, it is recorded in the UPDATED_REGION_ATTRIBUTE.
+fn prepare_update<T: DynamoObject>(
+    object: &T,
+    expected_version: Option<u64>,
+    mut attribute_conditions: HashMap<String, AttributeValue>,
+    mut custom_conditions: Vec<String>,
+    principal: Option<&str>,
+    region: Option<&str>,
+) -> Result<PreparedUpdate, ServerError> {
+    validate_id::<T>(object.id())?;
+    let key = collection! {
+        "pk".to_string() => AttributeValue::S(object.pk().to_string()),
+        "sk".to_string() => AttributeValue::S(object.sk().to_string()),
+    };
+    let mut overrides: Vec<(&str, Box<dyn erased_serde::Serialize>)> =
+        vec![(AUTO_FIELDS_UPDATED_AT, Box::new(Timestamp::now()))];
+    if let Some(principal) = principal {
+        overrides.push((AUTO_FIELDS_UPDATED_BY, Box::new(principal.to_string())));
+    }
+    if let Some(schema_version) = schema_version::<T>() {
+        overrides.push((AUTO_FIELDS_SCHEMA_VERSION, Box::new(schema_version)));
+    }
+    if T::versioned() {
+        overrides.push((
+            AUTO_FIELDS_VERSION,
+            Box::new(expected_version.unwrap_or(0) + 1),
+        ));
+        match expected_version {
+            Some(version) => {
+                attribute_conditions.insert(
+                    AUTO_FIELDS_VERSION.to_string(),
+                    AttributeValue::N(version.to_string()),
+                );
+            }
+            None => custom_conditions.push("attribute_not_exists(#ver)".to_string()),
+        }
+    }
+    let (mut map, mut null_keys) =
+        build_dynamo_map_for_existing_obj::<T>(&object, IdKeys::None, Some(overrides))?;
+    let (index_set, index_remove) = index_attributes::<T>(object.data());
+    map.extend(index_set);
+    null_keys.extend(index_remove);
+    null_keys.extend(aliases_to_remove::<T>());
+    insert_updated_region(&mut map, region);
+    validate_item_size(key.estimated_size() + map.estimated_size())?;
+    let mut update = build_update(
+        key,
+        map,
+        null_keys,
+        attribute_conditions,
+        custom_conditions,
+        false,
+    );
+    if T::versioned() && expected_version.is_none() {
+        update
+            .attribute_names
+            .insert("#ver".to_string(), AUTO_FIELDS_VERSION.to_string());
+    }
+    Ok(update)
+}
+
+// For versioned types, returns the ADD action which increments the stored
+// version as part of an update which doesn't go through prepare_update (such as
+// patches and single-field updates), so that update_item calls based on the
+// previous version fail. The placeholders it uses are added to the given names
+// and values. ADD treats a missing version as 0.
+fn version_increment<T: DynamoObject>(
+    attribute_names: &mut HashMap<String, String>,
+    attribute_values: &mut DynamoMap,
+) -> Option<&'static str> {
+    if !T::versioned() {
+        return None;
+    }
+    attribute_names.insert("#ver".to_string(), AUTO_FIELDS_VERSION.to_string());
+    attribute_values.insert(":one".to_string(), AttributeValue::N("1".to_string()));
+    Some("#ver :one")
+}
+
+// Records the region (if any) in the UPDATED_REGION_ATTRIBUTE of an update's
+// SET attributes (see DynamoUtil::with_updated_region).
+fn insert_updated_region(map: &mut DynamoMap, region: Option<&str>) {
+    if let Some(region) = region {
+        map.insert(
+            UPDATED_REGION_ATTRIBUTE.to_string(),
+            AttributeValue::S(region.to_string()),
+        );
+    }
+}
+
+// Attributes stored under legacy names (see DynamoObject::attribute_aliases),
+// which are removed when the object is updated.
+fn aliases_to_remove<T: DynamoObject>() -> impl Iterator<Item = String> {
+    T::attribute_aliases()
+        .iter()
+        .map(|(alias, _)| alias.to_string())
+}
+
+// Builds the update for only the non-null fields of 'patch', leaving all other
+// fields untouched. Index attributes are not recomputed, and (for versioned
+// types) the version is incremented without being checked.
+fn prepare_patch<T: DynamoObject, P: Serialize>(
+    id: &PkSk,
+    patch: &P,
+    custom_conditions: Vec<String>,
+    principal: Option<&str>,
+    region: Option<&str>,
+) -> Result<PreparedUpdate, ServerError> {
+    validate_id::<T>(id)?;
+    let key = collection! {
+        "pk".to_string() => AttributeValue::S(id.pk.clone()),
+        "sk".to_string() => AttributeValue::S(id.sk.clone()),
+    };
+    let fields = match to_item_value(patch)
+        .map_err(|e| DynamoInvalidOperation::with_debug("failed to serialize patch", &e))?
+    {
+        serde_json::Value::Object(fields) => fields,
+        other => {
+            return Err(DynamoInvalidOperation::new(&format!(
+                "patch must serialize to an object, got '{:?}'",
+                other
+            )))
+        }
+    };
+    let mut map = DynamoMap::new();
+    for (field, value) in fields {
+        if ["id", "pk", "sk"].contains(&field.as_str()) {
+            return Err(DynamoInvalidOperation::new(&format!(
+                "patch can't modify ID field '{}'",
+                field
+            )));
+        }
+        if let Some(value) = serde_value_to_attribute_value(value)? {
+            map.insert(field, value);
+        }
+    }
+    if map.is_empty() {
+        return Err(DynamoInvalidOperation::new("patch contains no fields"));
+    }
+    map.insert(AUTO_FIELDS_UPDATED_AT.to_string(), now_attribute_value()?);
+    if let Some(principal) = principal {
+        map.insert(
+            AUTO_FIELDS_UPDATED_BY.to_string(),
+            AttributeValue::S(principal.to_string()),
+        );
+    }
+    insert_updated_region(&mut map, region);
+    Ok(build_update(
+        key,
+        map,
+        Vec::new(),
+        HashMap::default(),
+        custom_conditions,
+        T::versioned(),
+    ))
+}
+
+// Builds an update which SETs all attributes in 'map' and REMOVEs all
+// attributes in 'null_keys', and (if 'increment_version' is set) ADDs 1 to the
+// version. The condition expression requires all of the custom conditions, and
+// each attribute in 'attribute_conditions' to equal the given value.
+fn build_update(
+    key: DynamoMap,
+    map: DynamoMap,
+    null_keys: Vec<String>,
+    attribute_conditions: HashMap<String, AttributeValue>,
+    custom_conditions: Vec<String>,
+    increment_version: bool,
+) -> PreparedUpdate {
+    // Build update expression:
+    let mut expression_attribute_names = HashMap::new();
+    let mut expression_attribute_values = HashMap::new();
+    let set_expression = match map.is_empty() {
+        true => "".to_string(),
+        false => {
+            "SET ".to_string()
+                + &map
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, (key, value))| {
+                        let key_placeholder = format!("#k{}", idx + 1);
+                        let value_placeholder = format!(":v{}", idx + 1);
+                        expression_attribute_names.insert(key_placeholder.clone(), key);
+                        expression_attribute_values.insert(value_placeholder.clone(), value);
+                        format!("{} = {}", key_placeholder, value_placeholder)
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+        }
+    };
+    let remove_expression = match null_keys.is_empty() {
+        true => "".to_string(),
+        false => {
+            "REMOVE ".to_string()
+                + &null_keys
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, key)| {
+                        let key_placeholder = format!("#rmk{}", idx + 1);
+                        expression_attribute_names.insert(key_placeholder.clone(), key);
+                        key_placeholder
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ")
+        }
+    };
+    let add_expression = match increment_version {
+        true => {
+            expression_attribute_names.insert("#ver".to_string(), AUTO_FIELDS_VERSION.to_string());
+            expression_attribute_values
+                .insert(":one".to_string(), AttributeValue::N("1".to_string()));
+            "ADD #ver :one".to_string()
+        }
+        false => "".to_string(),
+    };
+    let update_expression = [set_expression, remove_expression, add_expression]
+        .into_iter()
+        .filter(|clause| !clause.is_empty())
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    // Ensure item exists, and any additional custom conditions:
+    let condition_expression = custom_conditions
+        .into_iter()
+        .chain(
+            attribute_conditions
+                .into_iter()
+                .enumerate()
+                .map(|(idx, (key, value))| {
+                    let key_placeholder = format!("#c{}", idx + 1);
+                    let value_placeholder = format!(":cv{}", idx + 1);
+                    expression_attribute_names.insert(key_placeholder.clone(), key);
+                    expression_attribute_values.insert(value_placeholder.clone(), value);
+                    format!("{} = {}", key_placeholder, value_placeholder)
+                }),
+        )
+        .collect::<Vec<String>>()
+        .join(" AND ");
+    PreparedUpdate {
+        key,
+        update_expression,
+        attribute_values: expression_attribute_values,
+        attribute_names: expression_attribute_names,
+        condition_expression,
+    }
+}
+
+// Generates the ID and builds the map for a new object of type T, including
+// auto fields and index attributes. If a principal is given, it is recorded as
+// created_by and updated_by.
+fn prepare_new_item<T: DynamoObject>(
+    parent_id: &PkSk,
+    data: &T::Data,
+    options: Option<&CreateOptions>,
+    principal: Option<&str>,
+) -> Result<(PkSk, DynamoMap), ServerError> {
+    let (pk, sk) = generate_pk_sk::<T>(data, &parent_id.pk, &parent_id.sk)?;
+    prepare_new_item_with_id::<T>(PkSk { pk, sk }, data, options, principal)
+}
+
+fn prepare_new_item_with_id<T: DynamoObject>(
+    id: PkSk,
+    data: &T::Data,
+    options: Option<&CreateOptions>,
+    principal: Option<&str>,
+) -> Result<(PkSk, DynamoMap), ServerError> {
+    let PkSk {
+        pk: new_pk,
+        sk: new_sk,
+    } = id;
+    let sort: Option<f64> = options.and_then(|o| o.custom_sort);
+    let ttl: Option<i64> = options
+        .and_then(|o| o.ttl.as_ref())
+        .map(|ttl| ttl.compute_timestamp());
+    let map: DynamoMap = build_dynamo_map_for_new_obj::<T>(
+        data,
+        new_pk.clone(),
+        new_sk.clone(),
+        Some(vec![
+            (AUTO_FIELDS_CREATED_AT, Box::new(Timestamp::now())),
+            (AUTO_FIELDS_UPDATED_AT, Box::new(Timestamp::now())),
+            (
+                AUTO_FIELDS_CREATED_BY,
+                Box::new(principal.map(str::to_string)),
+            ),
+            (
+                AUTO_FIELDS_UPDATED_BY,
+                Box::new(principal.map(str::to_string)),
+            ),
+            (AUTO_FIELDS_SORT, Box::new(sort)),
+            (AUTO_FIELDS_TTL, Box::new(ttl)),
+            (
+                AUTO_FIELDS_VERSION,
+                Box::new(T::versioned().then_some(1u64)),
+            ),
+            (AUTO_FIELDS_SCHEMA_VERSION, Box::new(schema_version::<T>())),
+        ]),
+    )?
+    .into_iter()
+    .chain(index_attributes::<T>(data).0)
+    .collect();
+    validate_item_size(map.estimated_size())?;
+    Ok((
+        PkSk {
+            pk: new_pk,
+            sk: new_sk,
+        },
+        map,
+    ))
+}
+
+// The schema version written with new and updated items, which are always
+// serialized from the latest data format. Omitted for types without
+// migrations.
+fn schema_version<T: DynamoObject>() -> Option<u32> {
+    Some(T::migrations().latest_version()).filter(|version| *version > 0)
+}
+
+// The current time, serialized as stored in the created_at / updated_at fields.
+fn now_attribute_value() -> Result<AttributeValue, ServerError> {
+    serde_json::to_value(Timestamp::now())
+        .map_err(|e| CriticalError::with_debug("failed to serialize timestamp", &e))
+        .and_then(serde_value_to_attribute_value)?
+        .ok_or_else(|| CriticalError::new("timestamp serialized to null"))
+}
+
+// Applies a JSON merge patch (RFC 7386) to 'target'.
+fn apply_merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    let target = target.as_object_mut().expect("target is an object");
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            apply_merge_patch(
+                target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                value,
+            );
+        }
+    }
+}
+
+// Prepares a copy of a raw item under a new ID. Since the copy is a new item,
+// its created_at and updated_at (and version, for versioned types) are reset,
+// and its created_by and updated_by are set to the given principal (or
+// removed, if None).
+fn copied_item(
+    mut item: DynamoMap,
+    id: PkSk,
+    principal: Option<&str>,
+) -> Result<DynamoMap, ServerError> {
+    id.write_to_map(&mut item);
+    let now = now_attribute_value()?;
+    item.insert(AUTO_FIELDS_CREATED_AT.to_string(), now.clone());
+    item.insert(AUTO_FIELDS_UPDATED_AT.to_string(), now);
+    for field in [AUTO_FIELDS_CREATED_BY, AUTO_FIELDS_UPDATED_BY] {
+        match principal {
+            Some(principal) => {
+                item.insert(field.to_string(), AttributeValue::S(principal.to_string()));
+            }
+            None => {
+                item.remove(field);
+            }
+        }
+    }
+    if item.contains_key(AUTO_FIELDS_VERSION) {
+        item.insert(
+            AUTO_FIELDS_VERSION.to_string(),
+            AttributeValue::N("1".to_string()),
+        );
+    }
+    Ok(item)
+}
+
+// Derives a client request token from the object type, parent and content, so
+// that retries of the same create (for example, when a Lambda invocation is
+// retried) send the same token. The token is a UUID, which fits the 36
+// character limit on DynamoDB client request tokens.
+fn idempotency_token<T: DynamoObject>(
+    parent_id: &PkSk,
+    data: &T::Data,
+) -> Result<uuid::Uuid, ServerError> {
+    // serde_json::Value objects are ordered by key, so the serialization is
+    // stable even if the data contains maps.
+    let content = serde_json::to_value(data)
+        .map_err(|e| CriticalError::with_debug("failed to serialize data", &e))?;
+    Ok(uuid::Uuid::new_v5(
+        &uuid::Uuid::NAMESPACE_OID,
+        format!("{}|{}|{}", T::id_label(), parent_id, content).as_bytes(),
+    ))
+}
+
+// Whether a failed create_item_idempotent write means that the object was
+// already created by a previous call, either within the token's idempotency
+// window (in which case the request differs only in its timestamps) or after it
+// (in which case the put's condition fails). Other cancellation reasons, such
+// as conflicts with concurrent transactions or throttling, are real failures.
+fn already_created(error: &TransactWriteItemsError) -> bool {
+    match error {
+        TransactWriteItemsError::IdempotentParameterMismatchException(_) => true,
+        TransactWriteItemsError::TransactionCanceledException(e) => {
+            e.cancellation_reasons().first().is_some_and(|reason| {
+                matches!(
+                    reason.code(),
+                    Some("ConditionalCheckFailed" | "IdempotentParameterMismatch")
+                )
+            })
+        }
+        _ => false,
+    }
+}
+
+// Parses query results into objects of type T. Items which are not of type T
+// are skipped.
+fn parse_items_of_type<T: DynamoObject>(items: Vec<DynamoMap>) -> Result<Vec<T>, ServerError> {
+    items
+        .iter()
+        .filter_map(parse_item_of_type::<T>)
+        .collect::<Result<Vec<T>, ServerError>>()
+}
+
+fn parse_item_of_type<T: DynamoObject>(item: &DynamoMap) -> Option<Result<T, ServerError>> {
+    // Items not of type T are inline children (of a different type), which
+    // are skipped. Use query_generic or query_family to access objects of type
+    // T together with their inline children.
+    parse_dynamo_map_if_type::<T>(item)
+}
+
+// Whether the GSI can be queried, and includes all items.
+fn is_index_active(index: &GlobalSecondaryIndexDescription) -> bool {
+    index.index_status() == Some(&IndexStatus::Active) && index.backfilling() != Some(true)
+}
+
+// Orders items by the 'sort' field. Items without a 'sort' value are placed
+// after ordered items. If not 'forward', the order is reversed.
+fn sort_by_sort_field(items: &mut [DynamoMap], forward: bool) {
+    items.sort_by(|a, b| {
+        let a_sort = a
+            .get(AUTO_FIELDS_SORT)
+            .and_then(|v| v.as_n().ok().map(|n| n.parse::<f64>().ok()))
+            .flatten();
+        let b_sort = b
+            .get(AUTO_FIELDS_SORT)
+            .and_then(|v| v.as_n().ok().map(|n| n.parse::<f64>().ok()))
+            .flatten();
+        let ordering = match (a_sort, b_sort) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap(),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            _ => std::cmp::Ordering::Equal,
+        };
+        match forward {
+            true => ordering,
+            false => ordering.reverse(),
+        }
+    });
+}
+
 impl TtlConfig {
     fn compute_timestamp(&self) -> i64 {
         match self {
@@ -108,197 +970,1099 @@ impl TtlConfig {
 #[derive(Debug, Clone)]
 pub struct DynamoUtil<B: DynamoBackendImpl> {
     pub backend: B,
+    /// Default table, used for all types without a table registered in
+    /// 'table_router', and by the untyped (generic / raw) functions.
     pub table: String,
+    pub table_router: TableRouter,
+    /// Prefix / suffix included in 'table' and the tables of 'table_router'
+    /// (see with_table_naming).
+    pub table_naming: TableNaming,
+    /// Consistency used for reads (get_item, item_exists, query), unless
+    /// overridden per-call.
+    pub read_consistency: ReadConsistency,
+    /// Retry behavior for failed requests (by default, throttled requests are
+    /// retried).
+    pub retry: RetryPolicy,
+    /// Time limits for requests (none by default).
+    pub timeout: TimeoutConfig,
+    /// If set, requests are rejected while DynamoDB appears to be down (see
+    /// CircuitBreaker).
+    pub circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// If set, get_item and query results are cached (see DynamoCache).
+    pub cache: Option<Arc<DynamoCache>>,
+    /// Max number of requests sent concurrently by batch operations (such as
+    /// 25-item chunks of batch writes, 100-key chunks of get_items, or
+    /// batch_update_item).
+    pub batch_concurrency: usize,
+    /// Principal (such as a user ID or service name) performing the writes. If
+    /// set, it is recorded in the created_by / updated_by fields of written
+    /// objects, in the same way as created_at / updated_at.
+    pub principal: Option<String>,
+    /// If set, the capacity consumed by each request is reported to this hook
+    /// (see DynamoMetrics).
+    pub metrics: Option<Arc<dyn DynamoMetrics>>,
+    /// If set, batch writes are limited to the given rate (see
+    /// WriteRateLimiter).
+    pub write_limiter: Option<Arc<WriteRateLimiter>>,
+    /// If set, requests are no longer sent once the token is cancelled (see
+    /// CancellationToken).
+    pub cancellation: Option<CancellationToken>,
+    /// If set, updates record the given region in the UPDATED_REGION_ATTRIBUTE
+    /// of the item (see with_updated_region).
+    pub updated_region: Option<String>,
 }
 impl<C: DynamoBackendImpl> DynamoUtil<C> {
     const ITEM_EXISTS_CONDITION: &'static str = "attribute_exists(pk)";
     const ITEM_DOES_NOT_EXIST_CONDITION: &'static str = "attribute_not_exists(pk)";
 
+    /// DynamoUtil using a custom backend (such as MemoryDynamoBackend), with
+    /// the default settings.
+    pub fn from_backend(backend: C, table: impl Into<String>) -> Self {
+        Self {
+            backend,
+            table: table.into(),
+            table_router: TableRouter::default(),
+            table_naming: TableNaming::default(),
+            read_consistency: ReadConsistency::default(),
+            retry: RetryPolicy::default(),
+            timeout: TimeoutConfig::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+    }
+
+    pub fn with_read_consistency(mut self, read_consistency: ReadConsistency) -> Self {
+        self.read_consistency = read_consistency;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Sets the time limits for requests. To use a different limit for a
+    /// single call, set it on a clone:
+    ///   util.clone().with_timeout(TimeoutConfig::new(duration)).get_item(...)
+    pub fn with_timeout(mut self, timeout: TimeoutConfig) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Stops long-running operations between requests once 'cancellation' is
+    /// cancelled or its deadline passes. Usually set on a clone for a single
+    /// call:
+    ///   util.clone().with_cancellation(token).scan_all::<T>()
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = Some(cancellation);
+        self
+    }
+
+    /// Enables a circuit breaker, which rejects requests with DynamoUnavailable
+    /// for 'cool_down' after 'failure_threshold' consecutive failures. The
+    /// breaker is shared between clones of this DynamoUtil.
+    pub fn with_circuit_breaker(
+        mut self,
+        failure_threshold: u32,
+        cool_down: std::time::Duration,
+    ) -> Self {
+        self.circuit_breaker = Some(Arc::new(CircuitBreaker::new(failure_threshold, cool_down)));
+        self
+    }
+
+    pub fn with_batch_concurrency(mut self, batch_concurrency: usize) -> Self {
+        self.batch_concurrency = batch_concurrency.max(1);
+        self
+    }
+
+    /// Limits the rate of batch writes, for example to avoid starving
+    /// production traffic during a backfill. The limit is shared between
+    /// clones of this DynamoUtil. Returns DynamoInvalidOperation if the rate is
+    /// not a positive, finite number.
+    pub fn with_write_rate_limit(mut self, limit: WriteRateLimit) -> Result<Self, ServerError> {
+        self.write_limiter = Some(Arc::new(WriteRateLimiter::new(limit)?));
+        Ok(self)
+    }
+
+    /// Enables caching of get_item and query results for the given duration.
+    /// The cache is shared between clones of this DynamoUtil.
+    pub fn with_cache(mut self, ttl: std::time::Duration) -> Self {
+        self.cache = Some(Arc::new(DynamoCache::new(ttl)));
+        self
+    }
+
+    /// Stores the types registered in 'table_router' in their own tables,
+    /// instead of the default table.
+    /// The table_naming is applied to the router's tables, so they should be
+    /// given as logical names.
+    pub fn with_table_router(mut self, table_router: TableRouter) -> Self {
+        self.table_router = table_router.map_tables(|table| self.table_naming.apply(table));
+        self
+    }
+
+    /// Adds the given prefix / suffix to the names of the default table and
+    /// the tables of the table_router, replacing any previous table_naming.
+    /// Done automatically by DynamoUtil::new, based on the environment.
+    pub fn with_table_naming(mut self, table_naming: TableNaming) -> Self {
+        let previous = std::mem::replace(&mut self.table_naming, table_naming);
+        let rename = |table: &str| self.table_naming.apply(previous.strip(table));
+        self.table = rename(&self.table);
+        self.table_router = std::mem::take(&mut self.table_router).map_tables(rename);
+        self
+    }
+
+    /// Records the given principal in the created_by / updated_by fields of
+    /// objects written through this DynamoUtil. Since the principal usually
+    /// differs per request, this is typically called on a clone of a shared
+    /// DynamoUtil.
+    pub fn with_principal(mut self, principal: impl Into<String>) -> Self {
+        self.principal = Some(principal.into());
+        self
+    }
+
+    /// Records the given region in the UPDATED_REGION_ATTRIBUTE of items
+    /// updated through this DynamoUtil, as part of the update expression. Set
+    /// by new_multi_region, where MultiRegionBackend tags put items itself.
+    pub fn with_updated_region(mut self, region: impl Into<String>) -> Self {
+        self.updated_region = Some(region.into());
+        self
+    }
+
+    /// Reports the capacity consumed by each request to 'metrics', for
+    /// example to track capacity usage per object type.
+    pub fn with_metrics(mut self, metrics: Arc<dyn DynamoMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// The table objects of type T are stored in.
+    pub fn table_for<T: DynamoObject>(&self) -> &str {
+        self.table_router.table_for::<T>().unwrap_or(&self.table)
+    }
+
+    // Subtree operations (such as copy_subtree, move_item and
+    // delete_item_recursive) find children from the key structure, which only
+    // works within a single table. With a table_router, children could be
+    // stored in any of the tables, so these operations are rejected rather than
+    // silently missing (or orphaning) children.
+    fn check_subtree_supported(&self) -> Result<(), ServerError> {
+        if self.table_router.is_empty() {
+            Ok(())
+        } else {
+            Err(DynamoInvalidOperation::new(
+                "subtree operations are not supported when types are routed to separate tables",
+            ))
+        }
+    }
+
+    /// Starts a transaction, to write several objects atomically.
+    pub fn transaction(&self) -> DynamoTransaction<'_, C> {
+        DynamoTransaction::new(self)
+    }
+
+    fn invalidate_cache(&self, id: &PkSk) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(id);
+        }
+    }
+
+    // Sends a request to the backend, retrying failed requests (see
+    // RetryPolicy) within the configured time limits (see TimeoutConfig). The
+    // outer error is DynamoTimeout (or DynamoUnavailable if the circuit breaker
+    // is open, or DynamoCancelled if the cancellation token is cancelled), and
+    // the inner one is the backend's error.
+    //
+    // Attempts which time out are retried, so this is only used for requests
+    // which can safely be repeated (reads, table management, and transactions
+    // with a client request token). Item writes use send_write.
+    async fn send<T, E, F, Fut>(&self, op: F) -> Result<Result<T, E>, ServerError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: ProvideErrorMetadata,
+    {
+        self.send_with(true, op).await
+    }
+
+    // Same as send, but attempts which time out are not retried, since they
+    // may have been applied (see TimeoutConfig).
+    async fn send_write<T, E, F, Fut>(&self, op: F) -> Result<Result<T, E>, ServerError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: ProvideErrorMetadata,
+    {
+        self.send_with(false, op).await
+    }
+
+    async fn send_with<T, E, F, Fut>(
+        &self,
+        retry_timeouts: bool,
+        op: F,
+    ) -> Result<Result<T, E>, ServerError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: ProvideErrorMetadata,
+    {
+        if let Some(cancellation) = &self.cancellation {
+            cancellation.check()?;
+        }
+        if let Some(breaker) = &self.circuit_breaker {
+            breaker.acquire()?;
+        }
+        let result = self.retry.run(&self.timeout, retry_timeouts, op).await;
+        if let Some(breaker) = &self.circuit_breaker {
+            match &result {
+                Ok(Err(e)) if is_unavailable_error(e.meta()) => breaker.record_failure(),
+                Err(_) => breaker.record_failure(),
+                Ok(_) => breaker.record_success(),
+            }
+        }
+        result.map_err(|_| {
+            DynamoTimeout::new(&format!(
+                "no response within the time limits ({:?} per attempt, {:?} overall)",
+                self.timeout.attempt, self.timeout.operation
+            ))
+        })
+    }
+
+    // Reports the capacity consumed by a request to the metrics hook, if set.
+    // 'items' are the keys or items read or written, used to determine the
+    // object label.
+    fn record_capacity<'a>(
+        &self,
+        operation: &'static str,
+        capacity: impl IntoIterator<Item = &'a ConsumedCapacity>,
+        items: impl IntoIterator<Item = &'a DynamoMap>,
+    ) {
+        if let Some(metrics) = &self.metrics {
+            report_consumed_capacity(metrics.as_ref(), operation, capacity, items);
+        }
+    }
+
     pub async fn query<T: DynamoObject>(
         &self,
         index: Option<IndexConfig>,
         id: PkSk,
         match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
     ) -> Result<Vec<T>, ServerError> {
-        self.query_generic(index, id, match_type)
+        parse_items_of_type::<T>(
+            self.query_generic_in(self.table_for::<T>(), index, id, match_type, options)
+                .await?,
+        )
+    }
+
+    /// Fetches a single page of results, for paging through large sets of
+    /// items without loading them all at once. The returned cursor is
+    /// Some(...) if more results are available, and can be passed back in to
+    /// fetch the next page.
+    ///
+    /// Since DynamoDB applies the limit before items of other types (such as
+    /// inline children) are filtered out, a page may contain fewer than 'limit'
+    /// items even if more results are available. Items are ordered by the
+    /// 'sort' field within each page, but not across pages.
+    pub async fn query_page<T: DynamoObject>(
+        &self,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        limit: u32,
+        cursor: Option<DynamoCursor>,
+    ) -> Result<(Vec<T>, Option<DynamoCursor>), ServerError> {
+        let (items, cursor) = self
+            .query_generic_page_in(self.table_for::<T>(), index, id, match_type, limit, cursor)
+            .await?;
+        Ok((parse_items_of_type::<T>(items)?, cursor))
+    }
+
+    /// Lazily streams query results, fetching the next page only once the
+    /// previous page has been consumed. Useful for processing large sets of
+    /// items without holding them all in memory.
+    ///
+    /// Items are ordered by the 'sort' field within each page, but not across
+    /// pages.
+    pub fn query_stream<'a, T: DynamoObject + 'a>(
+        &'a self,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<impl Stream<Item = Result<T, ServerError>> + 'a, ServerError> {
+        Ok(self
+            .query_generic_stream_in(self.table_for::<T>(), index, id, match_type, options)?
+            .try_filter_map(|item| async move { parse_item_of_type::<T>(&item).transpose() }))
+    }
+
+    /// Queries objects of several types at once, parsing each item into the
+    /// matching variant of the family F (see 'dynamo_object_family!'). Items
+    /// not belonging to any of the family's types are skipped.
+    ///
+    /// The query runs against the default table, so the family's types should
+    /// not be routed to other tables (see TableRouter).
+    pub async fn query_family<F: DynamoObjectFamily>(
+        &self,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<Vec<F>, ServerError> {
+        self.query_generic(index, id, match_type, options)
             .await?
-            .into_iter()
-            .filter_map(|item| {
-                let (pk, sk) =
-                    get_pk_sk_from_map(&item).expect("query result item did not have pk/sk.");
-                match get_object_type(pk, sk) {
-                    Ok(label) if label == T::id_label() => {
-                        // Item is of type T.
-                        Some(parse_dynamo_map::<T>(&item))
-                    }
-                    _ => {
-                        // Item is not of type T, but instead an inline child (of a
-                        // different type), which will be skipped. Use query_dynamic
-                        // to access objects of type T and their inline children.
-                        None
-                    }
+            .iter()
+            .filter_map(F::parse_dynamo_map)
+            .collect()
+    }
+
+    /// Fetches an object together with its inline children (see
+    /// NestingLogic::InlineChildOf) in a single query. Children are parsed into
+    /// the family F; children of types outside the family are skipped.
+    ///
+    /// Returns None if the parent object does not exist. Inline children are
+    /// stored alongside their parent, so they are read from P's table.
+    pub async fn query_with_inline_children<P: DynamoObject, F: DynamoObjectFamily>(
+        &self,
+        id: PkSk,
+    ) -> Result<Option<(P, Vec<F>)>, ServerError> {
+        validate_id::<P>(&id)?;
+        let child_prefix = format!("{}#", id.sk);
+        let items = self
+            .query_generic_in(
+                self.table_for::<P>(),
+                None,
+                id.clone(),
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
+            .await?;
+        let mut parent = None;
+        let mut children = Vec::new();
+        for item in items.iter() {
+            let (_, sk) = get_pk_sk_from_map(item)?;
+            if sk == id.sk {
+                parent = Some(parse_dynamo_map::<P>(item)?);
+            } else if sk.starts_with(&child_prefix) {
+                // Objects whose sk merely starts with the parent's sk (but are
+                // not nested under it) are excluded.
+                if let Some(child) = F::parse_dynamo_map(item) {
+                    children.push(child?);
                 }
-            })
-            .collect::<Result<Vec<T>, ServerError>>()
+            }
+        }
+        Ok(parent.map(|parent| (parent, children)))
     }
 
+    /// Queries the default table, returning the raw items.
     pub async fn query_generic(
         &self,
         index: Option<IndexConfig>,
         id: PkSk,
         match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
     ) -> Result<Vec<DynamoMap>, ServerError> {
-        let (index_name, partition_field, sort_field) = match index {
-            Some(index) => (
-                Some(index.name.to_string()),
-                index.partition_field,
-                index.sort_field,
-            ),
-            None => (None, "pk", "sk"),
-        };
-        let condition = match match_type {
-            DynamoQueryMatchType::BeginsWith if id.sk.is_empty() => {
-                format!("{} = :pk_val", partition_field)
-            }
-            DynamoQueryMatchType::BeginsWith => format!(
-                "{} = :pk_val AND begins_with({}, :sk_val)",
-                partition_field, sort_field
-            ),
-            DynamoQueryMatchType::Equals => {
-                format!("{} = :pk_val AND {} = :sk_val", partition_field, sort_field)
-            }
-            DynamoQueryMatchType::GreaterThan => {
-                format!("{} = :pk_val AND {} > :sk_val", partition_field, sort_field)
+        self.query_generic_in(&self.table, index, id, match_type, options)
+            .await
+    }
+
+    async fn query_generic_in(
+        &self,
+        table: &str,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<Vec<DynamoMap>, ServerError> {
+        let query = self.prepare_query(table, index, id, match_type, options)?;
+        let cache_key = self.cache.as_ref().and_then(|_| query.cache_key());
+        if let (Some(cache), Some((pk, query_key))) = (&self.cache, &cache_key) {
+            if let Some(items) = cache.get_query(pk, query_key) {
+                return Ok(items);
             }
-            DynamoQueryMatchType::GreaterThanOrEquals => {
-                format!(
-                    "{} = :pk_val AND {} >= :sk_val",
-                    partition_field, sort_field
+        }
+        let (mut items, _) = self.query_all_pages(&query, None).await?;
+        sort_by_sort_field(&mut items, query.scan_index_forward.unwrap_or(true));
+        if let (Some(cache), Some((pk, query_key))) = (&self.cache, cache_key) {
+            cache.put_query(pk, query_key, items.clone());
+        }
+        Ok(items)
+    }
+
+    pub async fn query_generic_page(
+        &self,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        limit: u32,
+        cursor: Option<DynamoCursor>,
+    ) -> Result<(Vec<DynamoMap>, Option<DynamoCursor>), ServerError> {
+        self.query_generic_page_in(&self.table, index, id, match_type, limit, cursor)
+            .await
+    }
+
+    async fn query_generic_page_in(
+        &self,
+        table: &str,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        limit: u32,
+        cursor: Option<DynamoCursor>,
+    ) -> Result<(Vec<DynamoMap>, Option<DynamoCursor>), ServerError> {
+        let query = self.prepare_query(table, index, id, match_type, None)?;
+        let response = self
+            .query_single_page(
+                &query,
+                Some(limit as usize),
+                cursor.map(|c| c.into_exclusive_start_key()).transpose()?,
+                None,
+            )
+            .await?;
+        let mut items = response.items.unwrap_or_default();
+        sort_by_sort_field(&mut items, true);
+        let next_cursor = response
+            .last_evaluated_key
+            .map(DynamoCursor::from_last_evaluated_key)
+            .transpose()?;
+        Ok((items, next_cursor))
+    }
+
+    pub fn query_generic_stream<'a>(
+        &'a self,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<impl Stream<Item = Result<DynamoMap, ServerError>> + 'a, ServerError> {
+        self.query_generic_stream_in(&self.table, index, id, match_type, options)
+    }
+
+    fn query_generic_stream_in<'a>(
+        &'a self,
+        table: &str,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<impl Stream<Item = Result<DynamoMap, ServerError>> + 'a, ServerError> {
+        let query = self.prepare_query(table, index, id, match_type, options)?;
+        let forward = query.scan_index_forward.unwrap_or(true);
+        // State: (query, next page's start key, items scanned so far). The start
+        // key is None once all pages have been fetched, or Some(None) for the
+        // first page.
+        let initial_state = (query, Some(None), 0);
+        Ok(stream::try_unfold(
+            initial_state,
+            move |(query, exclusive_start_key, scanned_count): (
+                PreparedQuery,
+                Option<Option<DynamoMap>>,
+                usize,
+            )| async move {
+                let Some(exclusive_start_key) = exclusive_start_key else {
+                    return Ok(None);
+                };
+                let remaining = query.limit.map(|l| l.saturating_sub(scanned_count));
+                if remaining == Some(0) {
+                    return Ok(None);
+                }
+                let response = self
+                    .query_single_page(&query, remaining, exclusive_start_key, None)
+                    .await?;
+                let scanned_count = scanned_count + response.scanned_count.max(0) as usize;
+                let next_start_key = response.last_evaluated_key.map(Some);
+                let mut items = response.items.unwrap_or_default();
+                sort_by_sort_field(&mut items, forward);
+                Ok::<_, ServerError>(Some((items, (query, next_start_key, scanned_count))))
+            },
+        )
+        .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+        .try_flatten())
+    }
+
+    /// Queries all objects of type T directly under the given parent. For
+    /// NestingLogic::ShardedRoot, all shards are queried concurrently and the
+    /// results merged, ordered by the 'sort' field (and by sk for objects
+    /// without a 'sort' value, matching the order of a single-partition
+    /// query).
+    pub async fn query_all<T: DynamoObject>(&self, parent_id: PkSk) -> Result<Vec<T>, ServerError> {
+        let partitions = generate_pk_sk_prefixes::<T>(&parent_id.pk, &parent_id.sk)?;
+        let mut items =
+            futures::future::try_join_all(partitions.into_iter().map(|(pk, sk_prefix)| {
+                self.query_generic_in(
+                    self.table_for::<T>(),
+                    None,
+                    PkSk { pk, sk: sk_prefix },
+                    DynamoQueryMatchType::BeginsWith,
+                    None,
                 )
+            }))
+            .await?
+            .concat();
+        items.sort_by(|a, b| {
+            let sk = |item: &DynamoMap| item.get("sk").and_then(|v| v.as_s().ok()).cloned();
+            sk(a).cmp(&sk(b))
+        });
+        sort_by_sort_field(&mut items, true);
+        parse_items_of_type::<T>(items)
+    }
+
+    /// Fetches the first object of type T under the given parent, by sk order
+    /// (for example, the oldest object for Timestamp IDs). The 'sort' field is
+    /// not taken into account.
+    pub async fn query_first<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+    ) -> Result<Option<T>, ServerError> {
+        self.query_edge::<T>(parent_id, true).await
+    }
+
+    /// Fetches the last object of type T under the given parent, by sk order
+    /// (for example, the most recent object for Timestamp IDs). The 'sort'
+    /// field is not taken into account.
+    pub async fn query_last<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+    ) -> Result<Option<T>, ServerError> {
+        self.query_edge::<T>(parent_id, false).await
+    }
+
+    async fn query_edge<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+        forward: bool,
+    ) -> Result<Option<T>, ServerError> {
+        let (pk, sk_prefix) = generate_pk_sk_prefix::<T>(&parent_id.pk, &parent_id.sk)?;
+        let query = self.prepare_query(
+            self.table_for::<T>(),
+            None,
+            PkSk { pk, sk: sk_prefix },
+            DynamoQueryMatchType::BeginsWith,
+            Some(QueryOptions {
+                scan_index_forward: Some(forward),
+                ..Default::default()
+            }),
+        )?;
+        // In ascending order, an object always comes before its inline
+        // children, so the first item is the one we are looking for. In
+        // descending order, however, the last object's inline children come
+        // first and need to be skipped. To handle this, only the first request
+        // uses Limit=1, and any further pages are fetched in full.
+        let mut limit = Some(1);
+        let mut exclusive_start_key = None;
+        loop {
+            let response = self
+                .query_single_page(&query, limit, exclusive_start_key, None)
+                .await?;
+            for item in response.items.unwrap_or_default() {
+                if let Some(object) = parse_item_of_type::<T>(&item) {
+                    return object.map(Some);
+                }
             }
-            DynamoQueryMatchType::LessThan => {
-                format!("{} = :pk_val AND {} < :sk_val", partition_field, sort_field)
+            match response.last_evaluated_key {
+                Some(key) => exclusive_start_key = Some(key),
+                None => return Ok(None),
             }
-            DynamoQueryMatchType::LessThanOrEquals => {
-                format!(
-                    "{} = :pk_val AND {} <= :sk_val",
-                    partition_field, sort_field
-                )
+            limit = None;
+        }
+    }
+
+    /// For objects using time-based IDs (IdLogic::Timestamp,
+    /// IdLogic::TimestampMillis, IdLogic::Ulid or IdLogic::Ksuid), queries
+    /// the objects of type T under the given parent which were created between
+    /// 'from' and 'to' (inclusive), based on the timestamp encoded in their
+    /// IDs. Note that Ksuid timestamps only have second precision.
+    pub async fn query_between<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+        options: Option<QueryOptions>,
+    ) -> Result<Vec<T>, ServerError> {
+        let (from_id, to_id) = match T::id_logic() {
+            IdLogic::Timestamp => (timestamp_id_16_chars(&from), timestamp_id_16_chars(&to)),
+            IdLogic::TimestampMillis => (
+                timestamp_with_suffix_bounds_22_chars(&from).0,
+                timestamp_with_suffix_bounds_22_chars(&to).1,
+            ),
+            IdLogic::Ulid => (ulid_bounds_26_chars(&from).0, ulid_bounds_26_chars(&to).1),
+            IdLogic::Ksuid => (ksuid_bounds_27_chars(&from).0, ksuid_bounds_27_chars(&to).1),
+            _ => {
+                return Err(DynamoInvalidOperation::new(
+                    "query_between is only supported for objects with timestamp-based IDs",
+                ))
             }
-            DynamoQueryMatchType::SuffixGreaterThanOrEquals(_) => {
-                format!(
-                    "{} = :pk_val AND {} BETWEEN :sk_val AND :sk_max",
-                    partition_field, sort_field
+        };
+        let (pk, sk_prefix) = generate_pk_sk_prefix::<T>(&parent_id.pk, &parent_id.sk)?;
+        self.query::<T>(
+            None,
+            PkSk {
+                pk,
+                sk: format!("{}{}", sk_prefix, from_id),
+            },
+            DynamoQueryMatchType::Between(format!("{}{}", sk_prefix, to_id)),
+            options,
+        )
+        .await
+    }
+
+    /// Queries objects of type T using one of the indexes registered for T
+    /// (see DynamoObject::indexes), by the index's partition and sort values.
+    pub async fn query_by_index<T: DynamoObject>(
+        &self,
+        index_name: &str,
+        partition_value: String,
+        sort_value: String,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<Vec<T>, ServerError> {
+        let index = T::indexes()
+            .into_iter()
+            .find(|index| index.config.name == index_name)
+            .ok_or_else(|| {
+                DynamoInvalidOperation::new(&format!(
+                    "index '{}' is not registered for object type '{}'",
+                    index_name,
+                    T::id_label()
+                ))
+            })?;
+        self.query::<T>(
+            Some(index.config),
+            PkSk {
+                pk: partition_value,
+                sk: sort_value,
+            },
+            match_type,
+            options,
+        )
+        .await
+    }
+
+    /// Counts the items stored under the given parent with T's sk prefix,
+    /// without fetching the items themselves: the objects of type T, together
+    /// with any objects inlined under them (see NestingLogic::InlineChildOf).
+    /// Inline children share the sk prefix of their parent, and DynamoDB
+    /// doesn't support filtering on key attributes, so they can't be excluded
+    /// from the count. For an exact count of T objects, use query::<T> (which
+    /// skips items of other types) instead.
+    pub async fn query_count_with_inline_children<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+    ) -> Result<usize, ServerError> {
+        let partitions = generate_pk_sk_prefixes::<T>(&parent_id.pk, &parent_id.sk)?;
+        let counts =
+            futures::future::try_join_all(partitions.into_iter().map(|(pk, sk_prefix)| {
+                self.query_count_generic_in(
+                    self.table_for::<T>(),
+                    None,
+                    PkSk { pk, sk: sk_prefix },
+                    DynamoQueryMatchType::BeginsWith,
+                    None,
                 )
+            }))
+            .await?;
+        Ok(counts.into_iter().sum())
+    }
+
+    /// Counts matching items using a Select=COUNT query (summing across pages),
+    /// so items are not transferred or parsed. If a filter is provided, only
+    /// items matching the filter are counted.
+    pub async fn query_count_generic(
+        &self,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<usize, ServerError> {
+        self.query_count_generic_in(&self.table, index, id, match_type, options)
+            .await
+    }
+
+    async fn query_count_generic_in(
+        &self,
+        table: &str,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<usize, ServerError> {
+        let query = self.prepare_query(table, index, id, match_type, options)?;
+        let (_, count) = self.query_all_pages(&query, Some(Select::Count)).await?;
+        Ok(count)
+    }
+
+    fn prepare_query(
+        &self,
+        table: &str,
+        index: Option<IndexConfig>,
+        id: PkSk,
+        match_type: DynamoQueryMatchType,
+        options: Option<QueryOptions>,
+    ) -> Result<PreparedQuery, ServerError> {
+        let QueryOptions {
+            limit,
+            filter,
+            consistency,
+            scan_index_forward,
+        } = options.unwrap_or_default();
+        // GSIs do not support strongly consistent reads, so explicitly
+        // requesting one is an error. The DynamoUtil's default consistency, on
+        // the other hand, is only applied where supported.
+        let consistency = match (index, consistency) {
+            (Some(index), Some(ReadConsistency::Strong)) if !index.is_local() => {
+                return Err(DynamoInvalidOperation::with_debug(
+                    "strongly consistent reads are not supported on global secondary indexes",
+                    &index.name,
+                ));
             }
-            DynamoQueryMatchType::SuffixLessThanOrEquals(_) => {
-                format!(
-                    "{} = :pk_val AND {} BETWEEN :sk_min AND :sk_val",
-                    partition_field, sort_field
-                )
+            (Some(index), None) if !index.is_local() => ReadConsistency::Eventual,
+            (_, consistency) => consistency.unwrap_or(self.read_consistency),
+        };
+        let (index_name, condition, mut attribute_names, mut attribute_values) =
+            build_key_condition(index, id, match_type)?;
+        let filter_expression = match filter {
+            Some(filter) if !filter.is_empty() => {
+                let (expression, names, values) = filter.build()?;
+                attribute_names.extend(names);
+                attribute_values.extend(values);
+                Some(expression)
             }
-        }
-        .to_string();
-        let mut attribute_values = HashMap::new();
-        attribute_values.insert(":pk_val".to_string(), AttributeValue::S(id.pk));
-        match match_type {
-            DynamoQueryMatchType::SuffixGreaterThanOrEquals(delim) => {
-                // '~' is the last ASCII character, so we can use it as an upper
-                // bound to limit the query to a given prefix (similar to using
-                // begins_with). Since queries can only have one condition per
-                // key, this allows us to effectively do >= and begins_with at
-                // the same time, by using a BETWEEN condition.
-                attribute_values.insert(
-                    ":sk_max".to_string(),
-                    AttributeValue::S(format!(
-                        "{}~",
-                        id.sk
-                            .rsplit_once(delim)
-                            .ok_or_else(|| {
-                                DynamoInvalidOperation::with_debug(
-                                    "sort field filter did not contain the delimiter char, so could not extract the prefix for matching",
-                                    &id.sk,
-                                )
-                            })?
-                            .0
-                    )),
-                );
+            _ => None,
+        };
+        Ok(PreparedQuery {
+            table: table.to_string(),
+            index_name,
+            condition,
+            attribute_values,
+            filter_expression,
+            attribute_names,
+            consistent_read: consistency.consistent_read(),
+            scan_index_forward,
+            limit: limit.map(|l| l as usize),
+        })
+    }
+
+    // Fetches pages until the query is exhausted (or the limit is reached),
+    // returning the combined items and the total count.
+    async fn query_all_pages(
+        &self,
+        query: &PreparedQuery,
+        select: Option<Select>,
+    ) -> Result<(Vec<DynamoMap>, usize), ServerError> {
+        let mut items = Vec::new();
+        let mut count = 0;
+        let mut scanned_count = 0;
+        let mut exclusive_start_key = None;
+        loop {
+            let remaining = query.limit.map(|l| l.saturating_sub(scanned_count));
+            if remaining == Some(0) {
+                break;
             }
-            DynamoQueryMatchType::SuffixLessThanOrEquals(delim) => {
-                attribute_values.insert(
-                    ":sk_min".to_string(),
-                    AttributeValue::S(format!(
-                        "{}",
-                        id.sk
-                            .rsplit_once(delim)
-                            .ok_or_else(|| {
-                                DynamoInvalidOperation::with_debug(
-                                    "sort field filter did not contain the delimiter char, so could not extract the prefix for matching",
-                                    &id.sk,
-                                )
-                            })?
-                            .0
-                    )),
-                );
+            let response = self
+                .query_single_page(query, remaining, exclusive_start_key, select.clone())
+                .await?;
+            count += response.count.max(0) as usize;
+            scanned_count += response.scanned_count.max(0) as usize;
+            items.extend(response.items.unwrap_or_default());
+            match response.last_evaluated_key {
+                Some(key) => exclusive_start_key = Some(key),
+                None => break,
             }
-            _ => {}
-        }
-        if !id.sk.is_empty() {
-            attribute_values.insert(":sk_val".to_string(), AttributeValue::S(id.sk));
         }
+        Ok((items, count))
+    }
+
+    async fn query_single_page(
+        &self,
+        query: &PreparedQuery,
+        limit: Option<usize>,
+        exclusive_start_key: Option<DynamoMap>,
+        select: Option<Select>,
+    ) -> Result<QueryOutput, ServerError> {
         let response = self
-            .backend
-            .query(self.table.clone(), index_name, condition, attribute_values)
-            .await
+            .send(|| {
+                self.backend.query(
+                    query.table.clone(),
+                    query.index_name.clone(),
+                    query.condition.clone(),
+                    query.attribute_values.clone(),
+                    limit.map(|l| l.try_into().unwrap_or(i32::MAX)),
+                    exclusive_start_key.clone(),
+                    query.filter_expression.clone(),
+                    Some(query.attribute_names.clone()),
+                    query.consistent_read,
+                    query.scan_index_forward,
+                    select.clone(),
+                )
+            })
+            .await?
             .map_err(|e| DynamoCalloutError::with_debug(&e))?;
-        let mut items = response.items().to_vec();
-        items.sort_by(|a, b| {
-            let a_sort = a
-                .get(AUTO_FIELDS_SORT)
-                .and_then(|v| v.as_n().ok().map(|n| n.parse::<f64>().ok()))
-                .flatten();
-            let b_sort = b
-                .get(AUTO_FIELDS_SORT)
-                .and_then(|v| v.as_n().ok().map(|n| n.parse::<f64>().ok()))
-                .flatten();
-            match (a_sort, b_sort) {
-                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap(),
-                (Some(_), None) => std::cmp::Ordering::Less,
-                (None, Some(_)) => std::cmp::Ordering::Greater,
-                _ => std::cmp::Ordering::Equal,
+        self.record_capacity(
+            "Query",
+            response.consumed_capacity.as_ref(),
+            response.items.iter().flatten(),
+        );
+        Ok(response)
+    }
+
+    /// Runs a PartiQL statement (such as a SELECT), fetching all pages and
+    /// parsing the results as objects of type T. Intended for ad-hoc admin and
+    /// reporting queries; regular access patterns should use the query
+    /// functions instead.
+    ///
+    /// Parameters are bound to the '?' placeholders in the statement, in order.
+    pub async fn partiql_query<T: DynamoObject>(
+        &self,
+        statement: &str,
+        params: Vec<AttributeValue>,
+    ) -> Result<Vec<T>, ServerError> {
+        let parameters = match params.is_empty() {
+            true => None,
+            false => Some(params),
+        };
+        let mut items = Vec::new();
+        let mut next_token = None;
+        loop {
+            let response = self
+                .send(|| {
+                    self.backend.execute_statement(
+                        statement.to_string(),
+                        parameters.clone(),
+                        self.read_consistency.consistent_read(),
+                        next_token.clone(),
+                    )
+                })
+                .await?
+                .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+            self.record_capacity(
+                "ExecuteStatement",
+                response.consumed_capacity.as_ref(),
+                response.items.iter().flatten(),
+            );
+            items.extend(response.items.unwrap_or_default());
+            match response.next_token {
+                Some(token) => next_token = Some(token),
+                None => break,
             }
-        });
-        Ok(items.to_vec())
+        }
+        items
+            .iter()
+            .map(parse_dynamo_map::<T>)
+            .collect::<Result<Vec<T>, ServerError>>()
+    }
+
+    /// Runs several PartiQL statements (with their parameters) in batches of
+    /// 25 (max supported by DynamoDB). Returns the item read by each statement
+    /// (if any), in order. If any of the statements fails, an error is
+    /// returned.
+    pub async fn partiql_batch_execute(
+        &self,
+        statements: Vec<(String, Vec<AttributeValue>)>,
+    ) -> Result<Vec<Option<DynamoMap>>, ServerError> {
+        let statements: Vec<(String, Option<Vec<AttributeValue>>)> = statements
+            .into_iter()
+            .map(|(statement, params)| match params.is_empty() {
+                true => (statement, None),
+                false => (statement, Some(params)),
+            })
+            .collect();
+        let mut results = Vec::with_capacity(statements.len());
+        for chunk in statements.chunks(25) {
+            let response = self
+                .send(|| self.backend.batch_execute_statement(chunk.to_vec()))
+                .await?
+                .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+            self.record_capacity(
+                "BatchExecuteStatement",
+                response.consumed_capacity.iter().flatten(),
+                response
+                    .responses
+                    .iter()
+                    .flatten()
+                    .filter_map(|statement_response| statement_response.item.as_ref()),
+            );
+            for statement_response in response.responses.unwrap_or_default() {
+                if let Some(error) = statement_response.error {
+                    return Err(DynamoCalloutError::with_debug(&error));
+                }
+                results.push(statement_response.item);
+            }
+        }
+        Ok(results)
+    }
+
+    /// Scans the full table for all objects of type T.
+    ///
+    /// A filter on the object label is pushed to DynamoDB to avoid transferring
+    /// unrelated items, but the full table is still read (and billed), so this
+    /// should only be used for infrequent maintenance tasks (such as
+    /// migrations), never in regular request handling.
+    pub async fn scan_all<T: DynamoObject>(&self) -> Result<Vec<T>, ServerError> {
+        // The label filter is a coarse pre-filter (it also matches the parents
+        // of inline children, for example), so the exact object type is still
+        // validated when parsing.
+        let label_filter = match T::id_logic() {
+            IdLogic::Singleton | IdLogic::SingletonFamily(_) => format!("@{}", T::id_label()),
+            IdLogic::Uuid
+            | IdLogic::Timestamp
+            | IdLogic::TimestampMillis
+            | IdLogic::Ulid
+            | IdLogic::Ksuid
+            | IdLogic::Custom(_) => {
+                format!("{}#", T::id_label())
+            }
+        };
+        let attribute_values = collection! {
+            ":label".to_string() => AttributeValue::S(label_filter),
+        };
+        let mut items = Vec::new();
+        let mut exclusive_start_key = None;
+        loop {
+            let response = self
+                .send(|| {
+                    self.backend.scan(
+                        self.table_for::<T>().to_string(),
+                        Some("contains(sk, :label)".to_string()),
+                        Some(attribute_values.clone()),
+                        exclusive_start_key.clone(),
+                    )
+                })
+                .await?
+                .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+            self.record_capacity(
+                "Scan",
+                response.consumed_capacity.as_ref(),
+                response.items.iter().flatten(),
+            );
+            items.extend(response.items.unwrap_or_default());
+            match response.last_evaluated_key {
+                Some(key) => exclusive_start_key = Some(key),
+                None => break,
+            }
+        }
+        parse_items_of_type::<T>(items)
     }
 
     pub async fn get_item<T: DynamoObject>(&self, id: PkSk) -> Result<Option<T>, ServerError> {
+        self.get_item_with_consistency::<T>(id, self.read_consistency)
+            .await
+    }
+
+    async fn get_item_with_consistency<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        consistency: ReadConsistency,
+    ) -> Result<Option<T>, ServerError> {
         validate_id::<T>(&id)?;
+        // Strongly consistent reads always bypass the cache.
+        let cache = match consistency {
+            ReadConsistency::Eventual => self.cache.as_ref(),
+            ReadConsistency::Strong => None,
+        };
+        if let Some(item) = cache.and_then(|cache| cache.get_item(&id)) {
+            return item.map(|item| parse_dynamo_map::<T>(&item)).transpose();
+        }
         let key = collection! {
-            "pk".to_string() => AttributeValue::S(id.pk),
-            "sk".to_string() => AttributeValue::S(id.sk),
+            "pk".to_string() => AttributeValue::S(id.pk.clone()),
+            "sk".to_string() => AttributeValue::S(id.sk.clone()),
         };
         let response = self
-            .backend
-            .get_item(self.table.clone(), key, None)
-            .await
+            .send(|| {
+                self.backend.get_item(
+                    self.table_for::<T>().to_string(),
+                    key.clone(),
+                    None,
+                    consistency.consistent_read(),
+                )
+            })
+            .await?
             .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+        self.record_capacity("GetItem", response.consumed_capacity.as_ref(), [&key]);
+        if let Some(cache) = cache {
+            cache.put_item(id, response.item.clone());
+        }
         response
             .item
             .map(|item| parse_dynamo_map::<T>(&item))
             .transpose()
     }
 
-    /// Efficiently checks if an item exists, without fetching item data.
-    pub async fn item_exists(&self, id: PkSk) -> Result<bool, ServerError> {
+    /// Fetches several items at once, using batches of 100 keys (max supported
+    /// by DynamoDB), with up to batch_concurrency batches in flight at a time.
+    /// Returns the items in the same order as the given IDs, with None for
+    /// items that do not exist.
+    pub async fn get_items<T: DynamoObject>(
+        &self,
+        ids: Vec<PkSk>,
+    ) -> Result<Vec<Option<T>>, ServerError> {
+        for id in &ids {
+            validate_id::<T>(id)?;
+        }
+        // DynamoDB rejects batches containing duplicate keys.
+        let mut unique_ids = ids.clone();
+        unique_ids.sort_by(|a, b| (&a.pk, &a.sk).cmp(&(&b.pk, &b.sk)));
+        unique_ids.dedup();
+        let keys = unique_ids
+            .into_iter()
+            .map(|id| {
+                collection! {
+                    "pk".to_string() => AttributeValue::S(id.pk),
+                    "sk".to_string() => AttributeValue::S(id.sk),
+                }
+            })
+            .collect::<Vec<DynamoMap>>();
+        let table = self.table_for::<T>();
+        let chunks = stream::iter(
+            keys.chunks(MAX_BATCH_GET_ITEMS)
+                .map(|chunk| self.batch_get_chunk(table, chunk.to_vec())),
+        )
+        .buffer_unordered(self.batch_concurrency)
+        .try_collect::<Vec<Vec<DynamoMap>>>()
+        .await?;
+        let mut found: HashMap<PkSk, DynamoMap> = HashMap::new();
+        for item in chunks.into_iter().flatten() {
+            let (pk, sk) = get_pk_sk_from_map(&item)?;
+            let id = PkSk {
+                pk: pk.to_string(),
+                sk: sk.to_string(),
+            };
+            found.insert(id, item);
+        }
+        ids.into_iter()
+            .map(|id| {
+                found
+                    .get(&id)
+                    .map(|item| parse_dynamo_map::<T>(item))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    /// Efficiently checks if an item exists, without fetching item data. T is
+    /// only used to select the table (see TableRouter).
+    pub async fn item_exists<T: DynamoObject>(&self, id: PkSk) -> Result<bool, ServerError> {
+        validate_id::<T>(&id)?;
         let key = collection! {
             "pk".to_string() => AttributeValue::S(id.pk),
             "sk".to_string() => AttributeValue::S(id.sk),
         };
         let response = self
-            .backend
-            .get_item(self.table.clone(), key, Some("pk".to_string()))
-            .await
+            .send(|| {
+                self.backend.get_item(
+                    self.table_for::<T>().to_string(),
+                    key.clone(),
+                    Some("pk".to_string()),
+                    self.read_consistency.consistent_read(),
+                )
+            })
+            .await?
             .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+        self.record_capacity("GetItem", response.consumed_capacity.as_ref(), [&key]);
         Ok(response.item.is_some())
     }
 
@@ -308,34 +2072,114 @@ impl<C: DynamoBackendImpl> DynamoUtil<C> {
         data: T::Data,
         options: Option<CreateOptions>,
     ) -> Result<T, ServerError> {
-        let (new_pk, new_sk) = generate_pk_sk::<T>(&data, &parent_id.pk, &parent_id.sk)?;
-        let sort: Option<f64> = options.as_ref().and_then(|o| o.custom_sort);
-        let ttl: Option<i64> = options
-            .as_ref()
-            .and_then(|o| o.ttl.as_ref())
-            .map(|ttl| ttl.compute_timestamp());
-        let map = build_dynamo_map_for_new_obj::<T>(
-            &data,
-            new_pk.clone(),
-            new_sk.clone(),
-            Some(vec![
-                (AUTO_FIELDS_CREATED_AT, Box::new(Timestamp::now())),
-                (AUTO_FIELDS_UPDATED_AT, Box::new(Timestamp::now())),
-                (AUTO_FIELDS_SORT, Box::new(sort)),
-                (AUTO_FIELDS_TTL, Box::new(ttl)),
-            ]),
-        )?;
-        self.backend
-            .put_item(self.table.clone(), map)
+        self.create_item_internal::<T>(parent_id, data, options, None)
             .await
-            .map_err(|e| DynamoCalloutError::with_debug(&e))?;
-        Ok(T::new(
-            PkSk {
-                pk: new_pk,
-                sk: new_sk,
-            },
+    }
+
+    /// Same as create_item, but returns DynamoAlreadyExists instead of
+    /// overwriting if an item with the same ID already exists. This is mainly
+    /// useful for types with deterministic IDs (such as singletons).
+    pub async fn create_item_if_not_exists<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+        data: T::Data,
+        options: Option<CreateOptions>,
+    ) -> Result<T, ServerError> {
+        self.create_item_internal::<T>(
+            parent_id,
             data,
-        ))
+            options,
+            Some(Self::ITEM_DOES_NOT_EXIST_CONDITION.to_string()),
+        )
+        .await
+    }
+
+    /// Same as create_item, but safe to call again if the caller is retried
+    /// (for example, after a Lambda timeout): the ID and the client request
+    /// token are derived from the object type, parent and data, so repeating
+    /// the call with the same arguments returns the object created by the
+    /// first call instead of creating a duplicate.
+    ///
+    /// Since the ID is derived from the content, creating an identical object
+    /// under the same parent always returns the existing object. Not supported
+    /// for objects with timestamp-based IDs.
+    pub async fn create_item_idempotent<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+        data: T::Data,
+        options: Option<CreateOptions>,
+    ) -> Result<T, ServerError> {
+        let token = idempotency_token::<T>(&parent_id, &data)?;
+        let (pk, sk) = generate_pk_sk_seeded::<T>(&data, &parent_id.pk, &parent_id.sk, &token)?;
+        let (id, map) = prepare_new_item_with_id::<T>(
+            PkSk { pk, sk },
+            &data,
+            options.as_ref(),
+            self.principal.as_deref(),
+        )?;
+        let put = Put::builder()
+            .table_name(self.table_for::<T>().to_string())
+            .set_item(Some(map))
+            .condition_expression(Self::ITEM_DOES_NOT_EXIST_CONDITION)
+            .build()
+            .expect("Invalid Put");
+        let result = self
+            .send(|| {
+                self.backend.transact_write_items(
+                    vec![TransactWriteItem::builder().put(put.clone()).build()],
+                    Some(token.to_string()),
+                )
+            })
+            .await?;
+        self.invalidate_cache(&id);
+        match result.map_err(|e| e.into_service_error()) {
+            Ok(output) => {
+                self.record_capacity(
+                    "TransactWriteItems",
+                    output.consumed_capacity.iter().flatten(),
+                    [&put.item],
+                );
+                Ok(T::new(id, data))
+            }
+            Err(e) if already_created(&e) => self
+                .get_item_with_consistency::<T>(id, ReadConsistency::Strong)
+                .await?
+                .ok_or_else(|| DynamoCalloutError::with_debug(&e)),
+            Err(other) => Err(DynamoCalloutError::with_debug(&other)),
+        }
+    }
+
+    async fn create_item_internal<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+        data: T::Data,
+        options: Option<CreateOptions>,
+        condition_expression: Option<String>,
+    ) -> Result<T, ServerError> {
+        let (id, map) = prepare_new_item::<T>(
+            &parent_id,
+            &data,
+            options.as_ref(),
+            self.principal.as_deref(),
+        )?;
+        let response = self
+            .send_write(|| {
+                self.backend.put_item(
+                    self.table_for::<T>().to_string(),
+                    map.clone(),
+                    condition_expression.clone(),
+                    None,
+                    None,
+                )
+            })
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                PutItemError::ConditionalCheckFailedException(_) => DynamoAlreadyExists::new(),
+                other => DynamoCalloutError::with_debug(&other),
+            })?;
+        self.record_capacity("PutItem", response.consumed_capacity.as_ref(), [&map]);
+        self.invalidate_cache(&id);
+        Ok(T::new(id, data))
     }
 
     pub async fn batch_create_item<T: DynamoObject>(
@@ -345,7 +2189,7 @@ impl<C: DynamoBackendImpl> DynamoUtil<C> {
     ) -> Result<Vec<T>, ServerError> {
         if matches!(T::id_logic(), IdLogic::Timestamp) {
             return Err(DynamoInvalidOperation::new(
-                "batch_create_item is not allowed with timestamp-based IDs, since all items would get the same ID and only one item would be written",
+                "batch_create_item is not allowed with timestamp-based IDs, since all items would get the same ID and only one item would be written (use IdLogic::TimestampMillis instead)",
             ));
         }
         if data_and_options.is_empty() {
@@ -354,39 +2198,29 @@ impl<C: DynamoBackendImpl> DynamoUtil<C> {
         let (items, ids): (Vec<DynamoMap>, Vec<PkSk>) = data_and_options
             .iter()
             .map(|(data, options)| {
-                let (new_pk, new_sk) = generate_pk_sk::<T>(data, &parent_id.pk, &parent_id.sk)?;
-                let sort: Option<f64> = options.as_ref().and_then(|o| o.custom_sort);
-                let ttl: Option<i64> = options
-                    .as_ref()
-                    .and_then(|o| o.ttl.as_ref())
-                    .map(|ttl| ttl.compute_timestamp());
-                Ok((
-                    build_dynamo_map_for_new_obj::<T>(
-                        data,
-                        new_pk.clone(),
-                        new_sk.clone(),
-                        Some(vec![
-                            (AUTO_FIELDS_CREATED_AT, Box::new(Timestamp::now())),
-                            (AUTO_FIELDS_UPDATED_AT, Box::new(Timestamp::now())),
-                            (AUTO_FIELDS_SORT, Box::new(sort)),
-                            (AUTO_FIELDS_TTL, Box::new(ttl)),
-                        ]),
-                    )?,
-                    PkSk {
-                        pk: new_pk,
-                        sk: new_sk,
-                    },
-                ))
+                let (id, map) = prepare_new_item::<T>(
+                    &parent_id,
+                    data,
+                    options.as_ref(),
+                    self.principal.as_deref(),
+                )?;
+                Ok((map, id))
             })
             .collect::<Result<Vec<(DynamoMap, PkSk)>, ServerError>>()?
             .into_iter()
             .unzip();
-        // Split into 25-item chunks (max supported by DynamoDB).
-        for chunk in items.chunks(25) {
-            self.backend
-                .batch_put_item(self.table.clone(), chunk.to_vec())
-                .await
-                .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+        // Split into chunks of at most 25 items and 16MB (max supported by
+        // DynamoDB).
+        stream::iter(
+            size_limited_chunks(&items, MAX_BATCH_WRITE_ITEMS, MAX_BATCH_WRITE_SIZE)
+                .into_iter()
+                .map(|chunk| self.batch_put_chunk(self.table_for::<T>(), chunk.to_vec())),
+        )
+        .buffer_unordered(self.batch_concurrency)
+        .try_collect::<Vec<()>>()
+        .await?;
+        for id in &ids {
+            self.invalidate_cache(id);
         }
         Ok(ids
             .into_iter()
@@ -471,152 +2305,1493 @@ impl<C: DynamoBackendImpl> DynamoUtil<C> {
         .await
     }
 
-    /// Updates fields of an existing item. Since this logic internally uses
-    /// update_item instead of put_item, unrecognized fields unaffected. If the
-    /// item does not exist, an error is returned. Fields with null values are
-    /// removed from the item.
-    pub async fn update_item<T: DynamoObject>(&self, object: &T) -> Result<(), ServerError> {
-        self.update_item_with_conditions(
-            object,
-            HashMap::default(),
-            vec![Self::ITEM_EXISTS_CONDITION.to_string()],
+    /// Replaces all objects of type T under the given parent with 'data', in
+    /// the given order (see create_item_ordered). Existing objects with the
+    /// same content are kept, so only the difference is written: new items are
+    /// created, items no longer in the list are deleted, and sort values are
+    /// only rewritten for items which changed position. The returned objects
+    /// are in the given order.
+    pub async fn batch_replace_all_ordered<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+        data: Vec<T::Data>,
+    ) -> Result<Vec<T>, ServerError> {
+        self.replace_all_ordered_internal::<T, String>(parent_id, data, |data| {
+            serde_json::to_value(data)
+                .map(|value| value.to_string())
+                .map_err(|e| CriticalError::with_debug("failed to serialize data", &e))
+        })
+        .await
+    }
+
+    /// Same as batch_replace_all_ordered, but matches existing objects by the
+    /// given key (for example, an external ID) instead of their full content.
+    /// Matched objects whose data changed are updated.
+    pub async fn batch_replace_all_ordered_by_key<T: DynamoObject, K: Eq + Hash>(
+        &self,
+        parent_id: PkSk,
+        data: Vec<T::Data>,
+        key: impl Fn(&T::Data) -> K,
+    ) -> Result<Vec<T>, ServerError> {
+        self.replace_all_ordered_internal::<T, K>(parent_id, data, |data| Ok(key(data)))
+            .await
+    }
+
+    async fn replace_all_ordered_internal<T: DynamoObject, K: Eq + Hash>(
+        &self,
+        parent_id: PkSk,
+        data: Vec<T::Data>,
+        key: impl Fn(&T::Data) -> Result<K, ServerError>,
+    ) -> Result<Vec<T>, ServerError> {
+        let (pk, sk_prefix) = generate_pk_sk_prefix::<T>(&parent_id.pk, &parent_id.sk)?;
+        let existing = self
+            .query::<T>(
+                None,
+                PkSk { pk, sk: sk_prefix },
+                DynamoQueryMatchType::BeginsWith,
+                Some(QueryOptions {
+                    consistency: Some(ReadConsistency::Strong),
+                    ..Default::default()
+                }),
+            )
+            .await?;
+        let mut existing_by_key: HashMap<K, VecDeque<T>> = HashMap::new();
+        for object in existing {
+            existing_by_key
+                .entry(key(object.data())?)
+                .or_default()
+                .push_back(object);
+        }
+
+        // Match each item with an existing object with the same key, if any.
+        let matched = data
+            .into_iter()
+            .map(|data| {
+                let existing = existing_by_key
+                    .get_mut(&key(&data)?)
+                    .and_then(VecDeque::pop_front);
+                Ok((data, existing))
+            })
+            .collect::<Result<Vec<(T::Data, Option<T>)>, ServerError>>()?;
+        let sort_values = reordered_sort_values(
+            &matched
+                .iter()
+                .map(|(_, existing)| existing.as_ref().and_then(|object| object.sort()))
+                .collect::<Vec<_>>(),
+        );
+        let removed = existing_by_key
+            .into_values()
+            .flatten()
+            .map(|object| object.id().clone())
+            .collect::<Vec<PkSk>>();
+
+        // Objects which are updated or reordered are replaced by the stored
+        // object after the write, so that the results reflect the new sort
+        // value (and version, for versioned types).
+        let mut results: Vec<Option<T>> = Vec::with_capacity(matched.len());
+        let mut created = Vec::new();
+        let mut updated = Vec::new();
+        let mut reordered = Vec::new();
+        for (index, ((data, existing), sort)) in matched.into_iter().zip(sort_values).enumerate() {
+            match existing {
+                Some(mut object) => {
+                    if serde_json::to_value(object.data()).ok() != serde_json::to_value(&data).ok()
+                    {
+                        *object.data_mut() = data;
+                        updated.push(index);
+                    }
+                    if object.sort() != Some(sort) {
+                        reordered.push((index, object.id().clone(), sort));
+                    }
+                    results.push(Some(object));
+                }
+                None => {
+                    created.push((index, data, sort));
+                    results.push(None);
+                }
+            }
+        }
+
+        // New items are written before old ones are deleted, so the list is
+        // never left empty.
+        let (created_indexes, created_data): (Vec<usize>, Vec<_>) = created
+            .into_iter()
+            .map(|(index, data, sort)| {
+                (
+                    index,
+                    (
+                        data,
+                        Some(CreateOptions {
+                            custom_sort: Some(sort),
+                            ..Default::default()
+                        }),
+                    ),
+                )
+            })
+            .unzip();
+        if !created_data.is_empty() {
+            let created_objects = self.batch_create_item::<T>(parent_id, created_data).await?;
+            for (index, object) in created_indexes.into_iter().zip(created_objects) {
+                results[index] = Some(object);
+            }
+        }
+        let updated_objects = stream::iter(updated.into_iter().filter_map(|index| {
+            results[index].as_ref().map(|object| async move {
+                let object = self
+                    .update_item_returning(object, ReturnedObject::New)
+                    .await?;
+                Ok::<_, ServerError>((index, object))
+            })
+        }))
+        .buffer_unordered(self.batch_concurrency)
+        .try_collect::<Vec<(usize, T)>>()
+        .await?;
+        for (index, object) in updated_objects {
+            results[index] = Some(object);
+        }
+        let reordered_objects =
+            stream::iter(reordered.into_iter().map(|(index, id, sort)| async move {
+                let attributes = self
+                    .update_field_atomic_returning::<T>(
+                        id,
+                        AUTO_FIELDS_SORT,
+                        FieldUpdateAction::Set(":v1"),
+                        collection! {
+                            ":v1".to_string() => AttributeValue::N(sort.to_string()),
+                        },
+                        Some(ReturnValue::AllNew),
+                    )
+                    .await?
+                    .ok_or_else(|| CriticalError::new("update did not return item attributes"))?;
+                Ok::<_, ServerError>((index, parse_dynamo_map::<T>(&attributes)?))
+            }))
+            .buffer_unordered(self.batch_concurrency)
+            .try_collect::<Vec<(usize, T)>>()
+            .await?;
+        for (index, object) in reordered_objects {
+            results[index] = Some(object);
+        }
+        self.batch_delete_item::<T>(removed).await?;
+        Ok(results.into_iter().flatten().collect())
+    }
+
+    /// Copies an object to a new ID under 'dest_parent' (which can be the
+    /// object's current parent, to duplicate it in place). The copy keeps the
+    /// object's data, sort value and TTL. Only the object itself is copied; use
+    /// copy_subtree to also copy its children.
+    pub async fn copy_item<T: DynamoObject>(
+        &self,
+        src: PkSk,
+        dest_parent: PkSk,
+    ) -> Result<T, ServerError> {
+        let object = self
+            .get_item_with_consistency::<T>(src, ReadConsistency::Strong)
+            .await?
+            .ok_or_else(DynamoNotFound::new)?;
+        let options = CreateOptions {
+            custom_sort: object.sort(),
+            ttl: object
+                .ttl()
+                .and_then(|ttl| DateTime::from_timestamp(ttl, 0))
+                .map(TtlConfig::CustomDate),
+        };
+        self.create_item::<T>(dest_parent, object.into_data(), Some(options))
+            .await
+    }
+
+    /// Same as copy_item, but also copies all of the object's children (inline
+    /// and top-level, at any depth), for example to duplicate a project. Each
+    /// copied top-level child gets a newly generated ID based on the IdLogic of
+    /// its type, which is looked up in 'registry', so the copied tree has the
+    /// same structure. Children of types missing from the registry are
+    /// rejected with DynamoInvalidOperation.
+    ///
+    /// WARNING: The whole subtree is read and written, which can be expensive
+    /// for large trees. The copy is not atomic: if it fails part of the way,
+    /// the partially written copy is left in place.
+    pub async fn copy_subtree<T: DynamoObject>(
+        &self,
+        src: PkSk,
+        dest_parent: PkSk,
+        registry: &DynamoRegistry,
+    ) -> Result<T, ServerError> {
+        self.check_subtree_supported()?;
+        let copy = self.copy_item::<T>(src.clone(), dest_parent).await?;
+        self.copy_descendants(self.table_for::<T>(), registry, &src, copy.id())
+            .await?;
+        Ok(copy)
+    }
+
+    // Copies all children of the object 'source' to the (already existing)
+    // object 'target', recursively. Children are read from and written to the
+    // given table.
+    async fn copy_descendants(
+        &self,
+        table: &str,
+        registry: &DynamoRegistry,
+        source: &PkSk,
+        target: &PkSk,
+    ) -> Result<(), ServerError> {
+        let strong = || {
+            Some(QueryOptions {
+                consistency: Some(ReadConsistency::Strong),
+                ..Default::default()
+            })
+        };
+        let mut new_items = Vec::new();
+        // Pairs of (source, target) sks of copied objects, whose top-level
+        // children (stored in the partition with the object's sk as pk) still
+        // need to be copied.
+        let mut pending = vec![(source.sk.clone(), target.sk.clone())];
+
+        // Inline children share the object's partition, with the object's sk as
+        // prefix, so only the prefix needs to be replaced.
+        let inline_children = self
+            .query_generic_in(
+                table,
+                None,
+                PkSk {
+                    pk: source.pk.clone(),
+                    sk: format!("{}#", source.sk),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                strong(),
+            )
+            .await?;
+        for item in inline_children {
+            let sk = get_pk_sk_from_map(&item)?.1.to_string();
+            let new_sk = format!("{}{}", target.sk, &sk[source.sk.len()..]);
+            pending.push((sk, new_sk.clone()));
+            new_items.push(copied_item(
+                item,
+                PkSk {
+                    pk: target.pk.clone(),
+                    sk: new_sk,
+                },
+                self.principal.as_deref(),
+            )?);
+        }
+
+        // Top-level children get new IDs. Timestamp-based IDs are assigned
+        // consecutive timestamps, in the order of the original IDs, so that
+        // copies stay unique and keep their order.
+        let now = Utc::now();
+        let mut num_new_ids = 0;
+        while let Some((source_partition, target_partition)) = pending.pop() {
+            let mut items = self
+                .query_generic_in(
+                    table,
+                    None,
+                    PkSk {
+                        pk: source_partition,
+                        sk: String::new(),
+                    },
+                    DynamoQueryMatchType::BeginsWith,
+                    strong(),
+                )
+                .await?
+                .into_iter()
+                .map(|item| Ok((get_pk_sk_from_map(&item)?.1.to_string(), item)))
+                .collect::<Result<Vec<_>, ServerError>>()?;
+            items.sort_by(|(a, _), (b, _)| a.cmp(b));
+            let mut new_ids: HashMap<String, String> = HashMap::new();
+            for (sk, item) in items {
+                let (id, suffix) = split_top_level_id(&sk);
+                let new_id = match new_ids.entry(id.to_string()) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        let label = get_object_type(&target_partition, id)?;
+                        let registered = registry.get(label).ok_or_else(|| {
+                            DynamoInvalidOperation::new(&format!(
+                                "can't copy child '{}', since its type is not registered",
+                                sk
+                            ))
+                        })?;
+                        num_new_ids += 1;
+                        entry.insert(
+                            registered
+                                .regenerate_id(id, &(now + Duration::milliseconds(num_new_ids))),
+                        )
+                    }
+                };
+                let new_sk = format!("{}{}", new_id, suffix);
+                new_items.push(copied_item(
+                    item,
+                    PkSk {
+                        pk: target_partition.clone(),
+                        sk: new_sk.clone(),
+                    },
+                    self.principal.as_deref(),
+                )?);
+                pending.push((sk, new_sk));
+            }
+        }
+        self.raw_batch_put_item_in(table, new_items).await
+    }
+
+    /// Moves an object (together with its inline children) under a different
+    /// parent, for example to move a task to another group. The object keeps
+    /// its own ID component, but its pk/sk are regenerated for the new parent,
+    /// so the returned object has a different PkSk.
+    ///
+    /// If the object's sk changes (for inline objects), its top-level children
+    /// are moved to the new partition as well. Moves of up to 50 items are
+    /// applied in a single transaction. Larger moves write all new items before
+    /// deleting the old ones, so a failure part of the way leaves duplicates
+    /// rather than losing data. Items are never overwritten: if an item already
+    /// exists at any of the new IDs, nothing is moved.
+    ///
+    /// Objects with NestingLogic::ShardedRoot can't be moved, since their
+    /// shard is derived from their ID and they have no parent to move under.
+    pub async fn move_item<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        new_parent: PkSk,
+    ) -> Result<T, ServerError> {
+        if matches!(T::nesting_logic(), NestingLogic::ShardedRoot(_)) {
+            return Err(DynamoInvalidOperation::new(
+                "objects with sharded nesting can't be moved",
+            ));
+        }
+        self.relocate_item::<T>(id, |id, object| {
+            // Keep the object's own randomly generated (or timestamp-based) ID,
+            // so that only the part of the key derived from the parent changes.
+            let (new_pk, generated_sk) =
+                generate_pk_sk::<T>(object.data(), &new_parent.pk, &new_parent.sk)?;
+            let new_sk = match (generated_sk.rsplit_once('#'), id.sk.rsplit_once('#')) {
+                (Some((prefix, generated_id)), Some((_, own_id)))
+                    if matches!(
+                        T::id_logic(),
+                        IdLogic::Uuid
+                            | IdLogic::Timestamp
+                            | IdLogic::TimestampMillis
+                            | IdLogic::Ulid
+                            | IdLogic::Ksuid
+                            | IdLogic::Custom(_)
+                    ) =>
+                {
+                    // The sk component is taken from the generated ID, since
+                    // it reflects the object's current data.
+                    match (
+                        split_sk_component(generated_id).0,
+                        split_sk_component(own_id).1,
+                    ) {
+                        (Some(component), unique_id) => format!(
+                            "{}#{}{}{}",
+                            prefix, component, SK_COMPONENT_SEPARATOR, unique_id
+                        ),
+                        (None, unique_id) => format!("{}#{}", prefix, unique_id),
+                    }
+                }
+                _ => generated_sk,
+            };
+            Ok(PkSk {
+                pk: new_pk,
+                sk: new_sk,
+            })
+        })
+        .await
+    }
+
+    /// For types with an sk component (see DynamoObject::sk_component), saves
+    /// the object (as update_item) and, if the sk component derived from its
+    /// data changed, re-keys it (together with its inline and top-level
+    /// children, as move_item). Returns the object with its new ID.
+    ///
+    /// The update and re-key are separate writes. If the re-key fails, the
+    /// updated object is left under its old ID, and calling this again
+    /// completes the re-key.
+    pub async fn update_sk_component<T: DynamoObject>(&self, object: &T) -> Result<T, ServerError> {
+        self.check_subtree_supported()?;
+        self.update_item(object).await?;
+        self.relocate_item::<T>(object.id().clone(), |id, object| {
+            regenerate_sk_component::<T>(id, object.data())
+        })
+        .await
+    }
+
+    /// Queries the objects of type T under the given parent which have the
+    /// given sk component (see DynamoObject::sk_component), using a
+    /// begins_with condition on the sk.
+    pub async fn query_by_sk_component<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+        component: &str,
+        options: Option<QueryOptions>,
+    ) -> Result<Vec<T>, ServerError> {
+        let (pk, sk_prefix) =
+            generate_pk_sk_component_prefix::<T>(&parent_id.pk, &parent_id.sk, component)?;
+        self.query::<T>(
+            None,
+            PkSk { pk, sk: sk_prefix },
+            DynamoQueryMatchType::BeginsWith,
+            options,
+        )
+        .await
+    }
+
+    // Moves an object (together with its inline and top-level children) to the
+    // ID computed by 'new_id' from its current ID and stored state.
+    async fn relocate_item<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        new_id: impl FnOnce(&PkSk, &T) -> Result<PkSk, ServerError>,
+    ) -> Result<T, ServerError> {
+        validate_id::<T>(&id)?;
+        self.check_subtree_supported()?;
+        let strong = || {
+            Some(QueryOptions {
+                consistency: Some(ReadConsistency::Strong),
+                ..Default::default()
+            })
+        };
+        let inline_prefix = format!("{}#", id.sk);
+        let table = self.table_for::<T>();
+        let items = self
+            .query_generic_in(
+                table,
+                None,
+                id.clone(),
+                DynamoQueryMatchType::BeginsWith,
+                strong(),
+            )
+            .await?
+            .into_iter()
+            .map(|item| Ok((PkSk::from_map(&item)?, item)))
+            .filter(|result| match result {
+                Ok((item_id, _)) => item_id.sk == id.sk || item_id.sk.starts_with(&inline_prefix),
+                Err(_) => true,
+            })
+            .collect::<Result<Vec<(PkSk, DynamoMap)>, ServerError>>()?;
+        let object = items
+            .iter()
+            .find(|(item_id, _)| *item_id == id)
+            .map(|(_, item)| parse_dynamo_map::<T>(item))
+            .ok_or_else(DynamoNotFound::new)??;
+
+        let new_id = new_id(&id, &object)?;
+        if new_id == id {
+            return Ok(object);
+        }
+
+        // Pairs of (new item, old ID).
+        let mut moves = Vec::new();
+        for (old_id, mut item) in items {
+            let item_new_id = PkSk {
+                pk: new_id.pk.clone(),
+                sk: format!("{}{}", new_id.sk, &old_id.sk[id.sk.len()..]),
+            };
+            item_new_id.write_to_map(&mut item);
+            if old_id == id {
+                item.insert(AUTO_FIELDS_UPDATED_AT.to_string(), now_attribute_value()?);
+                if let Some(principal) = &self.principal {
+                    item.insert(
+                        AUTO_FIELDS_UPDATED_BY.to_string(),
+                        AttributeValue::S(principal.clone()),
+                    );
+                }
+            }
+            moves.push((item, old_id));
+        }
+        let moved = moves
+            .iter()
+            .find(|(_, old_id)| *old_id == id)
+            .map(|(item, _)| parse_dynamo_map::<T>(item))
+            .ok_or_else(|| CriticalError::new("moved object not found"))??;
+
+        // Top-level children are stored with their parent's sk as pk, so they
+        // need to be moved if the sk changed.
+        if new_id.sk != id.sk {
+            let partitions = moves
+                .iter()
+                .map(|(item, old_id)| Ok((old_id.sk.clone(), PkSk::from_map(item)?.sk)))
+                .collect::<Result<Vec<(String, String)>, ServerError>>()?;
+            for (old_partition, new_partition) in partitions {
+                let children = self
+                    .query_generic_in(
+                        table,
+                        None,
+                        PkSk {
+                            pk: old_partition,
+                            sk: String::new(),
+                        },
+                        DynamoQueryMatchType::BeginsWith,
+                        strong(),
+                    )
+                    .await?;
+                for mut item in children {
+                    let old_id = PkSk::from_map(&item)?;
+                    PkSk {
+                        pk: new_partition.clone(),
+                        sk: old_id.sk.clone(),
+                    }
+                    .write_to_map(&mut item);
+                    moves.push((item, old_id));
+                }
+            }
+        }
+
+        // Each moved item requires both a put and a delete.
+        if moves.len() * 2 <= MAX_TRANSACTION_ITEMS {
+            let mut transaction = self.transaction();
+            for (item, old_id) in moves {
+                transaction.put_raw(table, item)?;
+                transaction.delete_raw(table, old_id);
+            }
+            transaction.commit().await?;
+        } else {
+            let (items, old_ids): (Vec<DynamoMap>, Vec<PkSk>) = moves.into_iter().unzip();
+            // Batch writes can't be conditional, so the new IDs are checked up
+            // front instead (unlike the transaction, this doesn't protect
+            // against items created concurrently).
+            let new_keys = items
+                .iter()
+                .map(|item| {
+                    let (pk, sk) = get_pk_sk_from_map(item)?;
+                    Ok(collection! {
+                        "pk".to_string() => AttributeValue::S(pk.to_string()),
+                        "sk".to_string() => AttributeValue::S(sk.to_string()),
+                    })
+                })
+                .collect::<Result<Vec<DynamoMap>, ServerError>>()?;
+            for chunk in new_keys.chunks(MAX_BATCH_GET_ITEMS) {
+                if !self
+                    .batch_get_chunk(table, chunk.to_vec())
+                    .await?
+                    .is_empty()
+                {
+                    return Err(DynamoAlreadyExists::new());
+                }
+            }
+            self.raw_batch_put_item_in(table, items).await?;
+            self.raw_batch_delete_ids_in(table, old_ids).await?;
+        }
+        Ok(moved)
+    }
+
+    /// Moves an existing ordered item (see create_item_ordered) to a new
+    /// position among its siblings, by updating only its 'sort' value. The
+    /// item's ID and data are unchanged.
+    ///
+    /// WARNING: Like create_item_ordered, this requires checking all existing
+    /// sort values, which can be expensive.
+    pub async fn move_item_ordered<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        insert_position: DynamoInsertPosition,
+    ) -> Result<(), ServerError> {
+        validate_id::<T>(&id)?;
+        let sort_val = calculate_sort_value_for_move::<T, _>(self, &id, insert_position).await?;
+        self.update_field_atomic::<T>(
+            id,
+            AUTO_FIELDS_SORT,
+            FieldUpdateAction::Set(":v1"),
+            collection! {
+                ":v1".to_string() => AttributeValue::N(sort_val.to_string()),
+            },
+        )
+        .await
+    }
+
+    /// Rewrites the sort values of all ordered objects of type T under the
+    /// given parent to evenly spaced integers, keeping their order. This
+    /// restores precision after many insertions between the same items (see
+    /// create_item_ordered). Returns the number of rewritten objects.
+    ///
+    /// Items are rewritten one by one in an order which keeps the list
+    /// correctly ordered at all times. If interrupted, calling this again
+    /// continues where the previous call stopped.
+    pub async fn rebalance_sort<T: DynamoObject>(
+        &self,
+        parent_id: PkSk,
+    ) -> Result<usize, ServerError> {
+        let (pk, sk_prefix) = generate_pk_sk_prefix::<T>(&parent_id.pk, &parent_id.sk)?;
+        let items = self
+            .query::<T>(
+                None,
+                PkSk { pk, sk: sk_prefix },
+                DynamoQueryMatchType::BeginsWith,
+                Some(QueryOptions {
+                    consistency: Some(ReadConsistency::Strong),
+                    ..Default::default()
+                }),
+            )
+            .await?
+            .into_iter()
+            .filter_map(|item| item.sort().map(|sort| (item.id().clone(), sort)))
+            .collect::<Vec<_>>();
+        let current = items.iter().map(|(_, sort)| *sort).collect::<Vec<_>>();
+        let mut rewritten = 0;
+        for ((id, sort), new_sort) in items
+            .into_iter()
+            .zip(rebalanced_sort_values(&current))
+            .rev()
+        {
+            if sort == new_sort {
+                continue;
+            }
+            self.update_field_atomic::<T>(
+                id,
+                AUTO_FIELDS_SORT,
+                FieldUpdateAction::Set(":v1"),
+                collection! {
+                    ":v1".to_string() => AttributeValue::N(new_sort.to_string()),
+                },
+            )
+            .await?;
+            rewritten += 1;
+        }
+        Ok(rewritten)
+    }
+
+    /// Updates fields of an existing item. Since this logic internally uses
+    /// update_item instead of put_item, unrecognized fields unaffected. If the
+    /// item does not exist, an error is returned. Fields with null values are
+    /// removed from the item.
+    ///
+    /// For versioned types (see DynamoObject::versioned), the update fails with
+    /// DynamoVersionConflict if the object was modified since it was read.
+    pub async fn update_item<T: DynamoObject>(&self, object: &T) -> Result<(), ServerError> {
+        self.update_item_with_conditions(
+            object,
+            object.version(),
+            HashMap::default(),
+            vec![Self::ITEM_EXISTS_CONDITION.to_string()],
+            None,
+        )
+        .await?
+        .map_err(|ConditionFailed| update_condition_error::<T>())?;
+        Ok(())
+    }
+
+    /// Same as update_item, but also returns the stored object as it was
+    /// before or after the update (without a separate get_item). For example,
+    /// this can be used to record an audit trail of changes.
+    pub async fn update_item_returning<T: DynamoObject>(
+        &self,
+        object: &T,
+        returned: ReturnedObject,
+    ) -> Result<T, ServerError> {
+        let attributes = self
+            .update_item_with_conditions(
+                object,
+                object.version(),
+                HashMap::default(),
+                vec![Self::ITEM_EXISTS_CONDITION.to_string()],
+                Some(returned.return_value()),
+            )
+            .await?
+            .map_err(|ConditionFailed| update_condition_error::<T>())?
+            .ok_or_else(|| CriticalError::new("update did not return item attributes"))?;
+        parse_dynamo_map::<T>(&attributes)
+    }
+
+    /// Updates several objects, with the same semantics as update_item. Since
+    /// DynamoDB has no native batch update, each object is updated by a
+    /// separate request, with up to batch_concurrency requests in flight at a
+    /// time.
+    ///
+    /// The updates are not atomic (see transaction() for that): if an error is
+    /// returned, some of the objects may still have been updated.
+    pub async fn batch_update_item<T: DynamoObject>(
+        &self,
+        objects: Vec<&T>,
+    ) -> Result<(), ServerError> {
+        stream::iter(objects.into_iter().map(|object| self.update_item(object)))
+            .buffer_unordered(self.batch_concurrency)
+            .try_collect::<Vec<()>>()
+            .await?;
+        Ok(())
+    }
+
+    /// Updates an object in an all-or-nothing transaction. If the object has
+    /// changed since it was fetched, the update is aborted and returns a
+    /// DynamoTransactionConflict error (see update_item_transaction_with_retry
+    /// to retry instead). If 'op' returns an error, the transaction is also
+    /// aborted. If the object does not exist, the result of 'op' will be
+    /// created as a new object, and the transaction condition will ensure
+    /// another object with the same ID wasn't created in the meantime.
+    pub async fn update_item_transaction<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        op: impl FnOnce(Option<T::Data>) -> Result<T::Data, ServerError>,
+    ) -> Result<T, ServerError> {
+        self.try_update_item_transaction::<T>(id, op)
+            .await?
+            .map_err(|ConditionFailed| {
+                DynamoTransactionConflict::new("object was modified concurrently")
+            })
+    }
+
+    /// Same as update_item_transaction, but if the object changes between the
+    /// read and the write, the object is read again and 'op' is re-applied
+    /// (after a backoff delay), up to retry.max_attempts attempts in total. A
+    /// DynamoTransactionConflict error is only returned once all attempts
+    /// conflicted.
+    pub async fn update_item_transaction_with_retry<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        retry: &RetryPolicy,
+        mut op: impl FnMut(Option<T::Data>) -> Result<T::Data, ServerError>,
+    ) -> Result<T, ServerError> {
+        let mut attempt = 1;
+        loop {
+            if let Ok(object) = self
+                .try_update_item_transaction::<T>(id.clone(), &mut op)
+                .await?
+            {
+                return Ok(object);
+            }
+            if attempt >= retry.max_attempts {
+                return Err(DynamoTransactionConflict::new(&format!(
+                    "object was modified concurrently during all {} attempts",
+                    attempt
+                )));
+            }
+            tokio::time::sleep(retry.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    async fn try_update_item_transaction<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        op: impl FnOnce(Option<T::Data>) -> Result<T::Data, ServerError>,
+    ) -> Result<Result<T, ConditionFailed>, ServerError> {
+        // Use a strongly consistent read, so that the condition check is made
+        // against the latest version of the object.
+        let object_before = self
+            .get_item_with_consistency::<T>(id.clone(), ReadConsistency::Strong)
+            .await?;
+        let (map_before, existance_condition) = match object_before {
+            Some(ref o) => (
+                build_dynamo_map_for_existing_obj::<T>(o, IdKeys::None, None)?.0,
+                Self::ITEM_EXISTS_CONDITION.to_string(),
+            ),
+            None => (
+                HashMap::default(),
+                Self::ITEM_DOES_NOT_EXIST_CONDITION.to_string(),
+            ),
+        };
+        let version_before = object_before.as_ref().and_then(|o| o.version());
+        let object_after = T::new(id, op(object_before.map(|o| o.into_data()))?);
+        Ok(self
+            .update_item_with_conditions::<T>(
+                &object_after,
+                version_before,
+                map_before,
+                vec![existance_condition],
+                None,
+            )
+            .await?
+            .map(|_| object_after))
+    }
+
+    /// Replaces the entire stored object with 'object' (using PutItem), instead
+    /// of merging fields into the existing item like update_item. Any fields of
+    /// the stored item not included in 'object' (including unrecognized fields)
+    /// are dropped, unless T preserves unknown fields (see
+    /// DynamoObject::preserve_unknown_fields). If the object does not exist, an
+    /// error is returned.
+    ///
+    /// The created_at, created_by, sort and ttl auto fields are preserved from
+    /// 'object' (created_at is set to the current time if unknown), and
+    /// updated_at (and updated_by, if a principal is set) is refreshed.
+    /// Versioned types are checked as in update_item.
+    pub async fn replace_item<T: DynamoObject>(&self, object: &T) -> Result<(), ServerError> {
+        validate_id::<T>(object.id())?;
+        let created_at = object.created_at().cloned().unwrap_or_else(Timestamp::now);
+        let mut overrides: Vec<(&str, Box<dyn erased_serde::Serialize>)> = vec![
+            (AUTO_FIELDS_CREATED_AT, Box::new(created_at)),
+            (AUTO_FIELDS_UPDATED_AT, Box::new(Timestamp::now())),
+            (
+                AUTO_FIELDS_CREATED_BY,
+                Box::new(object.created_by().map(str::to_string)),
+            ),
+            (
+                AUTO_FIELDS_UPDATED_BY,
+                Box::new(
+                    self.principal
+                        .as_deref()
+                        .or(object.updated_by())
+                        .map(str::to_string),
+                ),
+            ),
+            (AUTO_FIELDS_SORT, Box::new(object.sort())),
+            (AUTO_FIELDS_TTL, Box::new(object.ttl())),
+            (AUTO_FIELDS_SCHEMA_VERSION, Box::new(schema_version::<T>())),
+        ];
+        let mut conditions = vec![Self::ITEM_EXISTS_CONDITION.to_string()];
+        let mut attribute_names = HashMap::new();
+        let mut attribute_values = HashMap::new();
+        if T::versioned() {
+            let expected_version = object.version();
+            overrides.push((
+                AUTO_FIELDS_VERSION,
+                Box::new(expected_version.unwrap_or(0) + 1),
+            ));
+            attribute_names.insert("#ver".to_string(), AUTO_FIELDS_VERSION.to_string());
+            match expected_version {
+                Some(version) => {
+                    conditions.push("#ver = :ver".to_string());
+                    attribute_values
+                        .insert(":ver".to_string(), AttributeValue::N(version.to_string()));
+                }
+                None => conditions.push("attribute_not_exists(#ver)".to_string()),
+            }
+        }
+        if T::preserve_unknown_fields() {
+            // Written as an update which sets (or removes) all fields known to
+            // T, so that unknown fields are left untouched.
+            let (mut map, mut null_keys) =
+                build_dynamo_map_for_existing_obj::<T>(object, IdKeys::None, Some(overrides))?;
+            let (index_set, index_remove) = index_attributes::<T>(object.data());
+            map.extend(index_set);
+            null_keys.extend(index_remove);
+            null_keys.extend(aliases_to_remove::<T>());
+            insert_updated_region(&mut map, self.updated_region.as_deref());
+            let key = collection! {
+                "pk".to_string() => AttributeValue::S(object.pk().to_string()),
+                "sk".to_string() => AttributeValue::S(object.sk().to_string()),
+            };
+            validate_item_size(key.estimated_size() + map.estimated_size())?;
+            let mut update =
+                build_update(key, map, null_keys, HashMap::default(), conditions, false);
+            update.attribute_names.extend(attribute_names);
+            update.attribute_values.extend(attribute_values);
+            self.execute_update::<T>(object.id(), update, None)
+                .await?
+                .map_err(|ConditionFailed| update_condition_error::<T>())?;
+            return Ok(());
+        }
+        let map: DynamoMap = build_dynamo_map_for_existing_obj::<T>(
+            object,
+            IdKeys::CopyFromObject,
+            Some(overrides),
+        )?
+        .0
+        .into_iter()
+        .chain(index_attributes::<T>(object.data()).0)
+        .collect();
+        validate_item_size(map.estimated_size())?;
+        let condition_expression = conditions.join(" AND ");
+        let attribute_names = (!attribute_names.is_empty()).then_some(attribute_names);
+        let attribute_values = (!attribute_values.is_empty()).then_some(attribute_values);
+        let response = self
+            .send_write(|| {
+                self.backend.put_item(
+                    self.table_for::<T>().to_string(),
+                    map.clone(),
+                    Some(condition_expression.clone()),
+                    attribute_names.clone(),
+                    attribute_values.clone(),
+                )
+            })
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                PutItemError::ConditionalCheckFailedException(_) if T::versioned() => {
+                    DynamoVersionConflict::new("object was modified or deleted concurrently")
+                }
+                PutItemError::ConditionalCheckFailedException(_) => DynamoNotFound::new(),
+                other => DynamoCalloutError::with_debug(&other),
+            })?;
+        self.record_capacity("PutItem", response.consumed_capacity.as_ref(), [&map]);
+        self.invalidate_cache(object.id());
+        Ok(())
+    }
+
+    /// Persists the migrations of T (see DynamoObject::migrations) for the
+    /// stored item, so that it no longer needs to be upgraded on each read.
+    /// Returns false if the item was already at the latest schema version.
+    ///
+    /// The write is conditional on the stored schema_version being unchanged,
+    /// so a concurrent write with the latest format is never overwritten. The
+    /// item's other auto fields (including version) are left untouched.
+    pub async fn migrate_item<T: DynamoObject>(&self, id: PkSk) -> Result<bool, ServerError> {
+        validate_id::<T>(&id)?;
+        let key = collection! {
+            "pk".to_string() => AttributeValue::S(id.pk.clone()),
+            "sk".to_string() => AttributeValue::S(id.sk.clone()),
+        };
+        let response = self
+            .send(|| {
+                self.backend.get_item(
+                    self.table_for::<T>().to_string(),
+                    key.clone(),
+                    None,
+                    ReadConsistency::Strong.consistent_read(),
+                )
+            })
+            .await?
+            .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+        self.record_capacity("GetItem", response.consumed_capacity.as_ref(), [&key]);
+        let item = response.item.ok_or_else(DynamoNotFound::new)?;
+        let Some(migrated) = T::migrations().migrate(&item)? else {
+            return Ok(false);
+        };
+        let attribute_names: HashMap<String, String> =
+            collection! { "#sv".to_string() => AUTO_FIELDS_SCHEMA_VERSION.to_string() };
+        // Also fails if the item was deleted concurrently, rather than
+        // recreating it.
+        let (schema_condition, attribute_values) = match item.get(AUTO_FIELDS_SCHEMA_VERSION) {
+            Some(stored) => (
+                "#sv = :sv",
+                Some(collection! { ":sv".to_string() => stored.clone() }),
+            ),
+            None => ("attribute_not_exists(#sv)", None),
+        };
+        let condition_expression =
+            format!("{} AND {}", Self::ITEM_EXISTS_CONDITION, schema_condition);
+        let response = self
+            .send_write(|| {
+                self.backend.put_item(
+                    self.table_for::<T>().to_string(),
+                    migrated.clone(),
+                    Some(condition_expression.clone()),
+                    Some(attribute_names.clone()),
+                    attribute_values.clone(),
+                )
+            })
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                PutItemError::ConditionalCheckFailedException(_) => {
+                    DynamoVersionConflict::new("object was migrated or modified concurrently")
+                }
+                other => DynamoCalloutError::with_debug(&other),
+            })?;
+        self.record_capacity("PutItem", response.consumed_capacity.as_ref(), [&migrated]);
+        self.invalidate_cache(&id);
+        Ok(true)
+    }
+
+    /// Updates only the fields included in 'patch', leaving all other fields of
+    /// the object untouched. This avoids having to fetch and construct the full
+    /// object, and (unlike update_item) fields not included in the patch are
+    /// not cleared. Patches are typically structs mirroring T::Data with all
+    /// fields optional, where None fields are skipped:
+    ///   #[derive(Serialize)]
+    ///   struct UserPatch { name: Option<String>, email: Option<String> }
+    ///
+    /// Index attributes are not recomputed, so fields used by T::indexes()
+    /// should be modified using update_item instead.
+    pub async fn update_fields<T: DynamoObject, P: Serialize>(
+        &self,
+        id: PkSk,
+        patch: &P,
+    ) -> Result<(), ServerError> {
+        let update = prepare_patch::<T, P>(
+            &id,
+            patch,
+            vec![Self::ITEM_EXISTS_CONDITION.to_string()],
+            self.principal.as_deref(),
+            self.updated_region.as_deref(),
+        )?;
+        self.execute_update::<T>(&id, update, None)
+            .await?
+            .map_err(|ConditionFailed| DynamoNotFound::new())?;
+        Ok(())
+    }
+
+    /// Applies a JSON merge patch (RFC 7386) to the data of an existing object,
+    /// for example from an HTTP PATCH endpoint: fields set to null are removed,
+    /// nested objects are merged recursively, and other values replace the
+    /// stored value. The patched data must still be a valid T::Data.
+    ///
+    /// Since nested objects are merged with the stored data, the patch is
+    /// applied using update_item_transaction, and fails with
+    /// DynamoTransactionConflict if the object is modified concurrently.
+    pub async fn patch_item<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        patch: serde_json::Value,
+    ) -> Result<T, ServerError> {
+        let Some(fields) = patch.as_object() else {
+            return Err(DynamoInvalidOperation::new("patch must be a JSON object"));
+        };
+        if let Some(field) = fields
+            .keys()
+            .find(|field| ["id", "pk", "sk"].contains(&field.as_str()))
+        {
+            return Err(DynamoInvalidOperation::new(&format!(
+                "patch can't modify ID field '{}'",
+                field
+            )));
+        }
+        self.update_item_transaction::<T>(id, |data| {
+            let mut value = serde_json::to_value(data.ok_or_else(DynamoNotFound::new)?)
+                .map_err(|e| CriticalError::with_debug("failed to serialize data", &e))?;
+            apply_merge_patch(&mut value, &patch);
+            serde_json::from_value(value)
+                .map_err(|e| DynamoInvalidOperation::with_debug("patched data is not valid", &e))
+        })
+        .await
+    }
+
+    async fn update_item_with_conditions<T: DynamoObject>(
+        &self,
+        object: &T,
+        expected_version: Option<u64>,
+        attribute_conditions: HashMap<String, AttributeValue>,
+        custom_conditions: Vec<String>,
+        return_values: Option<ReturnValue>,
+    ) -> Result<Result<Option<DynamoMap>, ConditionFailed>, ServerError> {
+        let update = prepare_update(
+            object,
+            expected_version,
+            attribute_conditions,
+            custom_conditions,
+            self.principal.as_deref(),
+            self.updated_region.as_deref(),
+        )?;
+        self.execute_update::<T>(object.id(), update, return_values)
+            .await
+    }
+
+    // Returns the item attributes requested by 'return_values', if any. If the
+    // condition expression fails, returns Ok(Err(ConditionFailed)), so that the
+    // caller can report (or handle) the failure appropriately.
+    async fn execute_update<T: DynamoObject>(
+        &self,
+        id: &PkSk,
+        update: PreparedUpdate,
+        return_values: Option<ReturnValue>,
+    ) -> Result<Result<Option<DynamoMap>, ConditionFailed>, ServerError> {
+        let PreparedUpdate {
+            key,
+            update_expression,
+            attribute_values: expression_attribute_values,
+            attribute_names: expression_attribute_names,
+            condition_expression,
+        } = update;
+        let result = self
+            .send_write(|| {
+                self.backend.update_item(
+                    self.table_for::<T>().to_string(),
+                    key.clone(),
+                    update_expression.clone(),
+                    expression_attribute_values.clone(),
+                    expression_attribute_names.clone(),
+                    Some(condition_expression.clone()),
+                    return_values.clone(),
+                )
+            })
+            .await?;
+        let response = match result.map_err(|e| e.into_service_error()) {
+            Ok(response) => response,
+            Err(UpdateItemError::ConditionalCheckFailedException(_)) => {
+                return Ok(Err(ConditionFailed))
+            }
+            Err(UpdateItemError::ResourceNotFoundException(_)) => return Err(DynamoNotFound::new()),
+            Err(other) => return Err(DynamoCalloutError::with_debug(&other)),
+        };
+        self.record_capacity("UpdateItem", response.consumed_capacity.as_ref(), [&key]);
+        self.invalidate_cache(id);
+        Ok(Ok(response.attributes))
+    }
+
+    /// Atomically adds 'delta' (which can be negative) to a numeric field of an
+    /// existing object, without reading it first. If the field is not yet set,
+    /// it is treated as 0. See AtomicCounter.
+    pub async fn increment_field<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        field: &str,
+        delta: i64,
+    ) -> Result<(), ServerError> {
+        self.update_field_atomic::<T>(
+            id,
+            field,
+            FieldUpdateAction::Add,
+            collection! {
+                ":v1".to_string() => AttributeValue::N(delta.to_string()),
+            },
+        )
+        .await
+    }
+
+    /// Atomically appends 'values' to a list field of an existing object,
+    /// without reading it first. Unlike update_item (which rewrites the whole
+    /// list), this doesn't clobber elements appended concurrently by other
+    /// writers. If the field is not yet set, it is treated as an empty list.
+    pub async fn append_to_list<T: DynamoObject, V: Serialize>(
+        &self,
+        id: PkSk,
+        field: &str,
+        values: Vec<V>,
+    ) -> Result<(), ServerError> {
+        let values = to_item_value(&values)
+            .map_err(|e| DynamoInvalidOperation::with_debug("failed to serialize values", &e))
+            .and_then(serde_value_to_attribute_value)?
+            .ok_or_else(|| CriticalError::new("list serialized to null"))?;
+        self.update_field_atomic::<T>(
+            id,
+            field,
+            FieldUpdateAction::Set("list_append(if_not_exists(#k1, :v3), :v1)"),
+            collection! {
+                ":v1".to_string() => values,
+                ":v3".to_string() => AttributeValue::L(Vec::new()),
+            },
+        )
+        .await
+    }
+
+    /// Atomically adds 'values' to a string or number set field of an existing
+    /// object, without reading it first. If the field is not yet set, a new set
+    /// is created. See DynamoSetElement.
+    pub async fn add_to_set<T: DynamoObject, V: DynamoSetElement>(
+        &self,
+        id: PkSk,
+        field: &str,
+        values: Vec<V>,
+    ) -> Result<(), ServerError> {
+        self.update_set::<T, V>(id, field, values, FieldUpdateAction::Add)
+            .await
+    }
+
+    /// Atomically removes 'values' from a string or number set field of an
+    /// existing object. Values not in the set are ignored. If the set becomes
+    /// empty, DynamoDB removes the field.
+    pub async fn remove_from_set<T: DynamoObject, V: DynamoSetElement>(
+        &self,
+        id: PkSk,
+        field: &str,
+        values: Vec<V>,
+    ) -> Result<(), ServerError> {
+        self.update_set::<T, V>(id, field, values, FieldUpdateAction::Delete)
+            .await
+    }
+
+    async fn update_set<T: DynamoObject, V: DynamoSetElement>(
+        &self,
+        id: PkSk,
+        field: &str,
+        mut values: Vec<V>,
+        action: FieldUpdateAction,
+    ) -> Result<(), ServerError> {
+        // DynamoDB rejects empty sets, as well as sets with duplicates.
+        if values.is_empty() {
+            return Err(DynamoInvalidOperation::new(
+                "set operations require at least one value",
+            ));
+        }
+        values.sort();
+        values.dedup();
+        self.update_field_atomic::<T>(
+            id,
+            field,
+            action,
+            collection! {
+                ":v1".to_string() => V::to_set_attribute_value(values),
+            },
+        )
+        .await
+    }
+
+    /// Refreshes only the updated_at field (and updated_by, if a principal is
+    /// set) of an existing object (for example, for "last seen" tracking),
+    /// without reading or rewriting its data.
+    pub async fn touch<T: DynamoObject>(&self, id: PkSk) -> Result<(), ServerError> {
+        validate_id::<T>(&id)?;
+        let mut attribute_names: HashMap<String, String> = collection! {
+            "#k1".to_string() => AUTO_FIELDS_UPDATED_AT.to_string(),
+        };
+        let mut attribute_values: DynamoMap = collection! {
+            ":v1".to_string() => now_attribute_value()?,
+        };
+        let mut update_expression = "SET #k1 = :v1".to_string();
+        if let Some(principal) = &self.principal {
+            update_expression.push_str(", #by = :by");
+            attribute_names.insert("#by".to_string(), AUTO_FIELDS_UPDATED_BY.to_string());
+            attribute_values.insert(":by".to_string(), AttributeValue::S(principal.clone()));
+        }
+        if let Some(region) = &self.updated_region {
+            update_expression.push_str(", #region = :region");
+            attribute_names.insert("#region".to_string(), UPDATED_REGION_ATTRIBUTE.to_string());
+            attribute_values.insert(":region".to_string(), AttributeValue::S(region.clone()));
+        }
+        if let Some(increment) = version_increment::<T>(&mut attribute_names, &mut attribute_values)
+        {
+            update_expression.push_str(&format!(" ADD {}", increment));
+        }
+        let update = PreparedUpdate {
+            key: collection! {
+                "pk".to_string() => AttributeValue::S(id.pk.clone()),
+                "sk".to_string() => AttributeValue::S(id.sk.clone()),
+            },
+            update_expression,
+            attribute_values,
+            attribute_names,
+            condition_expression: Self::ITEM_EXISTS_CONDITION.to_string(),
+        };
+        self.execute_update::<T>(&id, update, None)
+            .await?
+            .map_err(|ConditionFailed| DynamoNotFound::new())?;
+        Ok(())
+    }
+
+    /// Sets (or extends) the expiry time of an existing object, without
+    /// rewriting its data. See CreateOptions::ttl.
+    pub async fn set_ttl<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        ttl: TtlConfig,
+    ) -> Result<(), ServerError> {
+        self.update_field_atomic::<T>(
+            id,
+            AUTO_FIELDS_TTL,
+            FieldUpdateAction::Set(":v1"),
+            collection! {
+                ":v1".to_string() => AttributeValue::N(ttl.compute_timestamp().to_string()),
+            },
+        )
+        .await
+    }
+
+    /// Removes the expiry time of an existing object, so that it is no longer
+    /// automatically deleted.
+    pub async fn clear_ttl<T: DynamoObject>(&self, id: PkSk) -> Result<(), ServerError> {
+        self.update_field_atomic::<T>(
+            id,
+            AUTO_FIELDS_TTL,
+            FieldUpdateAction::Remove,
+            DynamoMap::new(),
         )
         .await
     }
 
-    /// Updates an object in an all-or-nothing transaction. If the object has
-    /// changed since it was fetched, the update is aborted and returns an
-    /// error. If 'op' returns an error, the transaction is also aborted. If the
-    /// object does not exist, the result of 'op' will be created as a new
-    /// object, and the transaction condition will ensure another object with
-    /// the same ID wasn't created in the meantime.
-    pub async fn update_item_transaction<T: DynamoObject>(
+    // Applies a single-field update action to an existing object, without
+    // reading it first. In the action, '#k1' refers to the field, and ':v1'
+    // (and any further values other than ':v2', ':by', ':region' and ':one')
+    // to the provided values. The updated_at (and updated_by / region) fields
+    // are also refreshed, as in regular updates, and the version is
+    // incremented for versioned types. Fails with DynamoNotFound if the object
+    // doesn't exist.
+    async fn update_field_atomic<T: DynamoObject>(
         &self,
         id: PkSk,
-        op: impl FnOnce(Option<T::Data>) -> Result<T::Data, ServerError>,
-    ) -> Result<T, ServerError> {
-        let object_before = self.get_item::<T>(id.clone()).await?;
-        let (map_before, existance_condition) = match object_before {
-            Some(ref o) => (
-                build_dynamo_map_for_existing_obj::<T>(o, IdKeys::None, None)?.0,
-                Self::ITEM_EXISTS_CONDITION.to_string(),
-            ),
-            None => (
-                HashMap::default(),
-                Self::ITEM_DOES_NOT_EXIST_CONDITION.to_string(),
-            ),
-        };
-        let object_after = T::new(id, op(object_before.map(|o| o.into_data()))?);
-        self.update_item_with_conditions::<T>(&object_after, map_before, vec![existance_condition])
+        field: &str,
+        action: FieldUpdateAction,
+        attribute_values: DynamoMap,
+    ) -> Result<(), ServerError> {
+        self.update_field_atomic_returning::<T>(id, field, action, attribute_values, None)
             .await?;
-        Ok(object_after)
+        Ok(())
     }
 
-    async fn update_item_with_conditions<T: DynamoObject>(
+    // Same as update_field_atomic, but returns the item attributes requested by
+    // 'return_values', if any.
+    async fn update_field_atomic_returning<T: DynamoObject>(
         &self,
-        object: &T,
-        attribute_conditions: HashMap<String, AttributeValue>,
-        custom_conditions: Vec<String>,
-    ) -> Result<(), ServerError> {
-        validate_id::<T>(object.id())?;
+        id: PkSk,
+        field: &str,
+        action: FieldUpdateAction,
+        mut attribute_values: DynamoMap,
+        return_values: Option<ReturnValue>,
+    ) -> Result<Option<DynamoMap>, ServerError> {
+        validate_id::<T>(&id)?;
         let key = collection! {
-            "pk".to_string() => AttributeValue::S(object.pk().to_string()),
-            "sk".to_string() => AttributeValue::S(object.sk().to_string()),
+            "pk".to_string() => AttributeValue::S(id.pk.clone()),
+            "sk".to_string() => AttributeValue::S(id.sk.clone()),
         };
-        let (map, null_keys) = build_dynamo_map_for_existing_obj::<T>(
-            &object,
-            IdKeys::None,
-            Some(vec![(AUTO_FIELDS_UPDATED_AT, Box::new(Timestamp::now()))]),
-        )?;
-
-        // Build update expression:
-        let mut expression_attribute_names = HashMap::new();
-        let mut expression_attribute_values = HashMap::new();
-        let set_expression = match map.is_empty() {
-            true => "".to_string(),
-            false => {
-                "SET ".to_string()
-                    + &map
-                        .into_iter()
-                        .enumerate()
-                        .map(|(idx, (key, value))| {
-                            let key_placeholder = format!("#k{}", idx + 1);
-                            let value_placeholder = format!(":v{}", idx + 1);
-                            expression_attribute_names.insert(key_placeholder.clone(), key);
-                            expression_attribute_values.insert(value_placeholder.clone(), value);
-                            format!("{} = {}", key_placeholder, value_placeholder)
-                        })
-                        .collect::<Vec<String>>()
-                        .join(", ")
-            }
+        let mut attribute_names: HashMap<String, String> = collection! {
+            "#k1".to_string() => field.to_string(),
+            "#k2".to_string() => AUTO_FIELDS_UPDATED_AT.to_string(),
         };
-        let remove_expression = match null_keys.is_empty() {
-            true => "".to_string(),
-            false => {
-                "REMOVE ".to_string()
-                    + &null_keys
-                        .into_iter()
-                        .enumerate()
-                        .map(|(idx, key)| {
-                            let key_placeholder = format!("#rmk{}", idx + 1);
-                            expression_attribute_names.insert(key_placeholder.clone(), key);
-                            key_placeholder
-                        })
-                        .collect::<Vec<String>>()
-                        .join(", ")
-            }
+        attribute_values.insert(":v2".to_string(), now_attribute_value()?);
+        let mut set_actions = vec!["#k2 = :v2".to_string()];
+        if let Some(principal) = &self.principal {
+            set_actions.push("#by = :by".to_string());
+            attribute_names.insert("#by".to_string(), AUTO_FIELDS_UPDATED_BY.to_string());
+            attribute_values.insert(":by".to_string(), AttributeValue::S(principal.clone()));
+        }
+        if let Some(region) = &self.updated_region {
+            set_actions.push("#region = :region".to_string());
+            attribute_names.insert("#region".to_string(), UPDATED_REGION_ATTRIBUTE.to_string());
+            attribute_values.insert(":region".to_string(), AttributeValue::S(region.clone()));
+        }
+        // Each clause can only appear once, so the version increment (if any)
+        // is part of the same ADD clause as an Add action.
+        let mut add_actions = Vec::new();
+        let mut other_clause = None;
+        match action {
+            FieldUpdateAction::Set(value) => set_actions.push(format!("#k1 = {}", value)),
+            FieldUpdateAction::Add => add_actions.push("#k1 :v1"),
+            FieldUpdateAction::Delete => other_clause = Some("DELETE #k1 :v1"),
+            FieldUpdateAction::Remove => other_clause = Some("REMOVE #k1"),
+        }
+        add_actions.extend(version_increment::<T>(
+            &mut attribute_names,
+            &mut attribute_values,
+        ));
+        let mut update_expression = format!("SET {}", set_actions.join(", "));
+        if let Some(clause) = other_clause {
+            update_expression.push_str(&format!(" {}", clause));
+        }
+        if !add_actions.is_empty() {
+            update_expression.push_str(&format!(" ADD {}", add_actions.join(", ")));
+        }
+        let update = PreparedUpdate {
+            key,
+            update_expression,
+            attribute_values,
+            attribute_names,
+            condition_expression: Self::ITEM_EXISTS_CONDITION.to_string(),
         };
-        let update_expression = format!("{} {}", set_expression, remove_expression);
+        self.execute_update::<T>(&id, update, return_values)
+            .await?
+            .map_err(|ConditionFailed| DynamoNotFound::new())
+    }
 
-        // Ensure item exists, and any additional custom conditions:
-        let condition_expression = custom_conditions
-            .into_iter()
-            .chain(
-                attribute_conditions
-                    .into_iter()
-                    .enumerate()
-                    .map(|(idx, (key, value))| {
-                        let key_placeholder = format!("#c{}", idx + 1);
-                        let value_placeholder = format!(":cv{}", idx + 1);
-                        expression_attribute_names.insert(key_placeholder.clone(), key);
-                        expression_attribute_values.insert(value_placeholder.clone(), value);
-                        format!("{} = {}", key_placeholder, value_placeholder)
-                    }),
-            )
-            .collect::<Vec<String>>()
-            .join(" AND ");
-
-        self.backend
-            .update_item(
-                self.table.clone(),
-                key,
-                update_expression,
-                expression_attribute_values,
-                expression_attribute_names,
-                Some(condition_expression),
-            )
-            .await
-            .map_err(|e| match e.into_service_error() {
-                UpdateItemError::ResourceNotFoundException(_) => DynamoNotFound::new(),
-                other => DynamoCalloutError::with_debug(&other),
-            })?;
+    pub async fn delete_item<T: DynamoObject>(&self, id: PkSk) -> Result<(), ServerError> {
+        self.delete_item_internal::<T>(id, None, None).await?;
         Ok(())
     }
 
-    pub async fn delete_item<T: DynamoObject>(&self, id: PkSk) -> Result<(), ServerError> {
+    /// Same as delete_item, but returns the deleted object (or None if it
+    /// didn't exist), without a separate get_item.
+    pub async fn delete_item_returning<T: DynamoObject>(
+        &self,
+        id: PkSk,
+    ) -> Result<Option<T>, ServerError> {
+        self.delete_item_internal::<T>(id, None, Some(ReturnValue::AllOld))
+            .await?
+            .map(|attributes| parse_dynamo_map::<T>(&attributes))
+            .transpose()
+    }
+
+    /// Deletes the object only if all 'conditions' hold for the stored item
+    /// (for example, only delete drafts). Otherwise, nothing is deleted and
+    /// DynamoConditionFailed is returned.
+    pub async fn delete_item_with_conditions<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        conditions: impl Into<FilterExpression>,
+    ) -> Result<(), ServerError> {
+        let conditions = conditions.into();
+        if conditions.is_empty() {
+            return Err(DynamoInvalidOperation::new(
+                "conditional delete requires at least one condition",
+            ));
+        }
+        self.delete_item_internal::<T>(id, Some(conditions), None)
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes an object together with all of its children (inline and
+    /// top-level, at any depth, and of any type).
+    ///
+    /// Children are found from the key structure (inline children share the
+    /// object's sk as prefix, and top-level children are stored with their
+    /// parent's sk as pk), and deleted in batches from the deepest level up,
+    /// before the object itself. If this fails part of the way, no orphaned
+    /// children are left behind, and the delete can simply be retried.
+    pub async fn delete_item_recursive<T: DynamoObject>(
+        &self,
+        id: PkSk,
+    ) -> Result<(), ServerError> {
+        validate_id::<T>(&id)?;
+        self.check_subtree_supported()?;
+        let strong = || {
+            Some(QueryOptions {
+                consistency: Some(ReadConsistency::Strong),
+                ..Default::default()
+            })
+        };
+        // Each level contains the children of the items in the previous level,
+        // with the object and its inline children as the first level.
+        let table = self.table_for::<T>();
+        let inline_children = self
+            .query_generic_in(
+                table,
+                None,
+                PkSk {
+                    pk: id.pk.clone(),
+                    sk: format!("{}#", id.sk),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                strong(),
+            )
+            .await?
+            .iter()
+            .map(PkSk::from_map)
+            .collect::<Result<Vec<PkSk>, ServerError>>()?;
+        let mut levels = vec![inline_children];
+        let mut partitions = std::iter::once(id.sk.clone())
+            .chain(levels[0].iter().map(|child| child.sk.clone()))
+            .collect::<Vec<String>>();
+        while !partitions.is_empty() {
+            let mut level = Vec::new();
+            for partition in partitions {
+                let children = self
+                    .query_generic_in(
+                        table,
+                        None,
+                        PkSk {
+                            pk: partition,
+                            sk: String::new(),
+                        },
+                        DynamoQueryMatchType::BeginsWith,
+                        strong(),
+                    )
+                    .await?;
+                for child in &children {
+                    level.push(PkSk::from_map(child)?);
+                }
+            }
+            partitions = level.iter().map(|child| child.sk.clone()).collect();
+            levels.push(level);
+        }
+        for level in levels.into_iter().rev() {
+            self.raw_batch_delete_ids_in(table, level).await?;
+        }
+        self.delete_item::<T>(id).await
+    }
+
+    async fn delete_item_internal<T: DynamoObject>(
+        &self,
+        id: PkSk,
+        conditions: Option<FilterExpression>,
+        return_values: Option<ReturnValue>,
+    ) -> Result<Option<DynamoMap>, ServerError> {
         validate_id::<T>(&id)?;
         let key = collection! {
-            "pk".to_string() => AttributeValue::S(id.pk),
-            "sk".to_string() => AttributeValue::S(id.sk),
+            "pk".to_string() => AttributeValue::S(id.pk.clone()),
+            "sk".to_string() => AttributeValue::S(id.sk.clone()),
         };
-        self.backend
-            .delete_item(self.table.clone(), key)
-            .await
+        let (condition_expression, attribute_names, attribute_values) = match conditions {
+            Some(conditions) => {
+                let (expression, names, values) = conditions.build()?;
+                (
+                    Some(expression),
+                    Some(names),
+                    (!values.is_empty()).then_some(values),
+                )
+            }
+            None => (None, None, None),
+        };
+        let response = self
+            .send_write(|| {
+                self.backend.delete_item(
+                    self.table_for::<T>().to_string(),
+                    key.clone(),
+                    condition_expression.clone(),
+                    attribute_names.clone(),
+                    attribute_values.clone(),
+                    return_values.clone(),
+                )
+            })
+            .await?
             .map_err(|e| match e.into_service_error() {
                 DeleteItemError::ResourceNotFoundException(_) => DynamoNotFound::new(),
+                DeleteItemError::ConditionalCheckFailedException(_) => {
+                    DynamoConditionFailed::new("delete conditions not met")
+                }
                 other => DynamoCalloutError::with_debug(&other),
             })?;
-        Ok(())
+        self.record_capacity("DeleteItem", response.consumed_capacity.as_ref(), [&key]);
+        self.invalidate_cache(&id);
+        Ok(response.attributes)
     }
 
     pub async fn batch_delete_item<T: DynamoObject>(
@@ -626,55 +3801,513 @@ impl<C: DynamoBackendImpl> DynamoUtil<C> {
         for key in &keys {
             validate_id::<T>(key)?;
         }
-        self.raw_batch_delete_ids(keys).await
+        self.raw_batch_delete_ids_in(self.table_for::<T>(), keys)
+            .await
     }
 
-    /// Performs no checks and directly deletes the given IDs from the database.
+    /// Performs no checks and directly deletes the given IDs from the default
+    /// table.
     pub async fn raw_batch_delete_ids(&self, keys: Vec<PkSk>) -> Result<(), ServerError> {
+        self.raw_batch_delete_ids_in(&self.table, keys).await
+    }
+
+    async fn raw_batch_delete_ids_in(
+        &self,
+        table: &str,
+        keys: Vec<PkSk>,
+    ) -> Result<(), ServerError> {
         if keys.is_empty() {
             return Ok(());
         }
         let items = keys
-            .into_iter()
+            .iter()
             .map(|id| {
                 collection! {
-                    "pk".to_string() => AttributeValue::S(id.pk),
-                    "sk".to_string() => AttributeValue::S(id.sk),
+                    "pk".to_string() => AttributeValue::S(id.pk.clone()),
+                    "sk".to_string() => AttributeValue::S(id.sk.clone()),
                 }
             })
             .collect::<Vec<_>>();
-        // Split into 25-item chunks (max supported by DynamoDB).
-        for chunk in items.chunks(25) {
-            self.backend
-                .batch_delete_item(self.table.clone(), chunk.to_vec())
-                .await
-                .map_err(|e| match e.into_service_error() {
-                    BatchWriteItemError::ResourceNotFoundException(_) => DynamoNotFound::new(),
-                    other => DynamoCalloutError::with_debug(&other),
-                })?;
+        // Split into chunks of at most 25 items and 16MB (max supported by
+        // DynamoDB), as for puts.
+        stream::iter(
+            size_limited_chunks(&items, MAX_BATCH_WRITE_ITEMS, MAX_BATCH_WRITE_SIZE)
+                .into_iter()
+                .map(|chunk| self.batch_delete_chunk(table, chunk.to_vec())),
+        )
+        .buffer_unordered(self.batch_concurrency)
+        .try_collect::<Vec<()>>()
+        .await?;
+        for id in &keys {
+            self.invalidate_cache(id);
         }
         Ok(())
     }
 
     /// Performs no checks and directly writes the given DynamoMaps to the
-    /// database. If the item exists, it is updated. If it does not exist, it is
-    /// created.
+    /// default table. If the item exists, it is updated. If it does not exist,
+    /// it is created.
     ///
     /// This does not check or update auto fields (updated_at, sort, etc.). The
     /// map values are just directly written.
     ///
     /// Should only be used internally for efficient low-level DB actions.
+    /// Items larger than DynamoDB's 400KB limit are rejected with a
+    /// DynamoItemTooLarge error before anything is written.
     pub async fn raw_batch_put_item(&self, items: Vec<DynamoMap>) -> Result<(), ServerError> {
+        self.raw_batch_put_item_in(&self.table, items).await
+    }
+
+    async fn raw_batch_put_item_in(
+        &self,
+        table: &str,
+        items: Vec<DynamoMap>,
+    ) -> Result<(), ServerError> {
         if items.is_empty() {
             return Ok(());
         }
-        // Split into 25-item chunks (max supported by DynamoDB).
-        for chunk in items.chunks(25) {
-            self.backend
-                .batch_put_item(self.table.clone(), chunk.to_vec())
-                .await
+        for item in &items {
+            validate_item_size(item.estimated_size())?;
+        }
+        // Split into chunks of at most 25 items and 16MB (max supported by
+        // DynamoDB).
+        stream::iter(
+            size_limited_chunks(&items, MAX_BATCH_WRITE_ITEMS, MAX_BATCH_WRITE_SIZE)
+                .into_iter()
+                .map(|chunk| self.batch_put_chunk(table, chunk.to_vec())),
+        )
+        .buffer_unordered(self.batch_concurrency)
+        .try_collect::<Vec<()>>()
+        .await?;
+        for item in &items {
+            let (pk, sk) = get_pk_sk_from_map(item)?;
+            self.invalidate_cache(&PkSk {
+                pk: pk.to_string(),
+                sk: sk.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Creates a table with the key schema used by DynamoUtil (string 'pk' /
+    /// 'sk' keys) and the secondary indexes in 'schema', using on-demand
+    /// billing, and waits until it is active.
+    ///
+    /// Intended for integration tests and dev environments, where tables are
+    /// not provisioned separately. Returns DynamoAlreadyExists if the table
+    /// already exists.
+    pub async fn create_table_for_schema(
+        &self,
+        table: &str,
+        schema: &TableSchema,
+    ) -> Result<(), ServerError> {
+        let definition = schema.to_definition()?;
+        self.send(|| {
+            self.backend.create_table(
+                table.to_string(),
+                definition.key_schema.clone(),
+                definition.attribute_definitions.clone(),
+                Some(definition.global_secondary_indexes.clone())
+                    .filter(|indexes| !indexes.is_empty()),
+                Some(definition.local_secondary_indexes.clone())
+                    .filter(|indexes| !indexes.is_empty()),
+                BillingMode::PayPerRequest,
+            )
+        })
+        .await?
+        .map_err(|e| match e.into_service_error() {
+            CreateTableError::ResourceInUseException(_) => DynamoAlreadyExists::new(),
+            other => DynamoCalloutError::with_debug(&other),
+        })?;
+        self.wait_for_table_active(table, DEFAULT_TABLE_WAIT_TIMEOUT)
+            .await
+    }
+
+    /// Deletes a table, including all of its items, and waits until the
+    /// deletion is complete. Returns DynamoNotFound if the table does not
+    /// exist.
+    pub async fn delete_table(&self, table: &str) -> Result<(), ServerError> {
+        self.send(|| self.backend.delete_table(table.to_string()))
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                DeleteTableError::ResourceNotFoundException(_) => DynamoNotFound::new(),
+                other => DynamoCalloutError::with_debug(&other),
+            })?;
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        self.wait_for_table(table, DEFAULT_TABLE_WAIT_TIMEOUT, move || async move {
+            Ok(self.describe_table(table).await?.is_none())
+        })
+        .await
+    }
+
+    /// The table's current description (status, key schema, indexes, item
+    /// count, etc.), or None if the table does not exist.
+    pub async fn describe_table(
+        &self,
+        table: &str,
+    ) -> Result<Option<TableDescription>, ServerError> {
+        match self
+            .send(|| self.backend.describe_table(table.to_string()))
+            .await?
+        {
+            Ok(output) => Ok(output.table),
+            Err(e) => match e.into_service_error() {
+                DescribeTableError::ResourceNotFoundException(_) => Ok(None),
+                other => Err(DynamoCalloutError::with_debug(&other)),
+            },
+        }
+    }
+
+    /// Enables TTL on the table using the 'ttl' attribute, which is required
+    /// for CreateOptions::ttl and set_ttl to have any effect, and waits until
+    /// it is enabled. Does nothing if it is already enabled.
+    ///
+    /// Fails if TTL is enabled using a different attribute, or is still being
+    /// disabled (in which case DynamoDB doesn't allow re-enabling it until the
+    /// change completes, which can take up to an hour).
+    pub async fn ensure_ttl_enabled(&self, table: &str) -> Result<(), ServerError> {
+        let (status, attribute) = self.ttl_status(table).await?;
+        match status {
+            Some(TimeToLiveStatus::Enabled) | Some(TimeToLiveStatus::Enabling) => {
+                if attribute.as_deref() != Some(AUTO_FIELDS_TTL) {
+                    return Err(DynamoInvalidOperation::new(&format!(
+                        "TTL on table '{}' is enabled using attribute '{}' instead of '{}'",
+                        table,
+                        attribute.unwrap_or_default(),
+                        AUTO_FIELDS_TTL
+                    )));
+                }
+            }
+            Some(TimeToLiveStatus::Disabling) => {
+                return Err(DynamoInvalidOperation::new(&format!(
+                    "TTL on table '{}' is being disabled, and can't be enabled until that completes",
+                    table
+                )));
+            }
+            _ => {
+                let specification = TimeToLiveSpecification::builder()
+                    .attribute_name(AUTO_FIELDS_TTL)
+                    .enabled(true)
+                    .build()
+                    .expect("Invalid TimeToLiveSpecification");
+                self.send(|| {
+                    self.backend
+                        .update_time_to_live(table.to_string(), specification.clone())
+                })
+                .await?
+                .map_err(|e| match e.into_service_error() {
+                    UpdateTimeToLiveError::ResourceNotFoundException(_) => DynamoNotFound::new(),
+                    other => DynamoCalloutError::with_debug(&other),
+                })?;
+            }
+        }
+        self.wait_for_table(table, DEFAULT_TABLE_WAIT_TIMEOUT, move || async move {
+            Ok(self.ttl_status(table).await?.0 == Some(TimeToLiveStatus::Enabled))
+        })
+        .await
+    }
+
+    // The TTL status and attribute of the table.
+    async fn ttl_status(
+        &self,
+        table: &str,
+    ) -> Result<(Option<TimeToLiveStatus>, Option<String>), ServerError> {
+        let description = self
+            .send(|| self.backend.describe_time_to_live(table.to_string()))
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                DescribeTimeToLiveError::ResourceNotFoundException(_) => DynamoNotFound::new(),
+                other => DynamoCalloutError::with_debug(&other),
+            })?
+            .time_to_live_description;
+        Ok(description
+            .map(|d| (d.time_to_live_status, d.attribute_name))
+            .unwrap_or_default())
+    }
+
+    /// Creates a GSI on an existing table (for example, after adding an index
+    /// to a type's DynamoObject::indexes), and waits until it is active,
+    /// including backfilling existing items. Does nothing if the index already
+    /// exists and is active. The index projects all attributes, and the table
+    /// is expected to use on-demand billing.
+    ///
+    /// Fails if an index with the same name exists using different key
+    /// fields, or if a local index does not exist (since LSIs can only be
+    /// created together with the table, see create_table_for_schema). For
+    /// large tables, backfilling can take longer than DEFAULT_TABLE_WAIT_TIMEOUT,
+    /// in which case DynamoTableNotReady is returned while the index continues
+    /// to be created (use wait_for_table_active to keep waiting).
+    pub async fn ensure_index(&self, table: &str, index: IndexConfig) -> Result<(), ServerError> {
+        let description = self
+            .describe_table(table)
+            .await?
+            .ok_or_else(DynamoNotFound::new)?;
+        let mismatch = || {
+            DynamoInvalidOperation::new(&format!(
+                "index '{}' on table '{}' exists with different key fields",
+                index.name, table
+            ))
+        };
+        if let Some(existing) = description
+            .local_secondary_indexes()
+            .iter()
+            .find(|i| i.index_name() == Some(index.name))
+        {
+            if !index.is_local() || !index_key_schema_matches(&index, existing.key_schema()) {
+                return Err(mismatch());
+            }
+            return Ok(());
+        }
+        match description
+            .global_secondary_indexes()
+            .iter()
+            .find(|i| i.index_name() == Some(index.name))
+        {
+            Some(existing) => {
+                if index.is_local() || !index_key_schema_matches(&index, existing.key_schema()) {
+                    return Err(mismatch());
+                }
+            }
+            None if index.is_local() => {
+                return Err(DynamoInvalidOperation::new(&format!(
+                    "local index '{}' does not exist on table '{}', and can only be created together with the table",
+                    index.name, table
+                )));
+            }
+            None => {
+                let update = GlobalSecondaryIndexUpdate::builder()
+                    .create(create_global_index_action(&index))
+                    .build();
+                self.send(|| {
+                        self.backend.update_table(
+                            table.to_string(),
+                            index_attribute_definitions(&index),
+                            vec![update.clone()],
+                        )
+                    })
+                    .await?
+                    .map_err(|e| match e.into_service_error() {
+                        UpdateTableError::ResourceNotFoundException(_) => DynamoNotFound::new(),
+                        UpdateTableError::ResourceInUseException(_) => {
+                            DynamoInvalidOperation::new(&format!(
+                                "table '{}' is already being updated (DynamoDB only creates one index at a time)",
+                                table
+                            ))
+                        }
+                        other => DynamoCalloutError::with_debug(&other),
+                    })?;
+            }
+        }
+        self.wait_for_table(table, DEFAULT_TABLE_WAIT_TIMEOUT, move || async move {
+            Ok(self.describe_table(table).await?.is_some_and(|d| {
+                d.global_secondary_indexes()
+                    .iter()
+                    .any(|i| i.index_name() == Some(index.name) && is_index_active(i))
+            }))
+        })
+        .await
+    }
+
+    /// Waits until the table and all of its GSIs are active (including
+    /// backfilling of newly created GSIs), for example after creating a table
+    /// or index outside of this DynamoUtil. Returns DynamoTableNotReady if this
+    /// takes longer than 'timeout', or DynamoNotFound if the table does not
+    /// exist.
+    pub async fn wait_for_table_active(
+        &self,
+        table: &str,
+        timeout: std::time::Duration,
+    ) -> Result<(), ServerError> {
+        self.wait_for_table(table, timeout, move || async move {
+            let description = self
+                .describe_table(table)
+                .await?
+                .ok_or_else(DynamoNotFound::new)?;
+            Ok(description.table_status() == Some(&TableStatus::Active)
+                && description
+                    .global_secondary_indexes()
+                    .iter()
+                    .all(is_index_active))
+        })
+        .await
+    }
+
+    // Polls 'done' until it returns true, for changes to tables which DynamoDB
+    // applies asynchronously.
+    async fn wait_for_table<F, Fut>(
+        &self,
+        table: &str,
+        timeout: std::time::Duration,
+        done: F,
+    ) -> Result<(), ServerError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<bool, ServerError>>,
+    {
+        let deadline = std::time::Instant::now() + timeout;
+        loop {
+            if done().await? {
+                return Ok(());
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(DynamoTableNotReady::new(&format!(
+                    "table '{}' was still changing after {} seconds",
+                    table,
+                    timeout.as_secs()
+                )));
+            }
+            tokio::time::sleep(remaining.min(TABLE_STATUS_POLL_INTERVAL)).await;
+        }
+    }
+
+    // Writes a chunk of up to 25 items. Items can be left unprocessed by
+    // DynamoDB if the table is throttled, in which case they are written again
+    // after a delay.
+    async fn batch_put_chunk(&self, table: &str, items: Vec<DynamoMap>) -> Result<(), ServerError> {
+        let mut pending = items;
+        let mut attempt = 1;
+        loop {
+            if let Some(limiter) = &self.write_limiter {
+                limiter.acquire_puts(&pending).await;
+            }
+            let response = self
+                .send_write(|| {
+                    self.backend
+                        .batch_put_item(table.to_string(), pending.clone())
+                })
+                .await?
+                .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+            self.record_capacity(
+                "BatchWriteItem",
+                response.consumed_capacity.iter().flatten(),
+                &pending,
+            );
+            pending = response
+                .unprocessed_items
+                .and_then(|mut u| u.remove(table))
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|request| request.put_request.map(|put| put.item))
+                .collect();
+            if pending.is_empty() {
+                return Ok(());
+            }
+            self.backoff_unprocessed(pending.len(), &mut attempt)
+                .await?;
+        }
+    }
+
+    // Same as batch_put_chunk, for deletions.
+    async fn batch_delete_chunk(
+        &self,
+        table: &str,
+        keys: Vec<DynamoMap>,
+    ) -> Result<(), ServerError> {
+        let mut pending = keys;
+        let mut attempt = 1;
+        loop {
+            if let Some(limiter) = &self.write_limiter {
+                limiter.acquire_deletes(pending.len()).await;
+            }
+            let response = self
+                .send_write(|| {
+                    self.backend
+                        .batch_delete_item(table.to_string(), pending.clone())
+                })
+                .await?
+                .map_err(|e| match e.into_service_error() {
+                    BatchWriteItemError::ResourceNotFoundException(_) => DynamoNotFound::new(),
+                    other => DynamoCalloutError::with_debug(&other),
+                })?;
+            self.record_capacity(
+                "BatchWriteItem",
+                response.consumed_capacity.iter().flatten(),
+                &pending,
+            );
+            pending = response
+                .unprocessed_items
+                .and_then(|mut u| u.remove(table))
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|request| request.delete_request.map(|delete| delete.key))
+                .collect();
+            if pending.is_empty() {
+                return Ok(());
+            }
+            self.backoff_unprocessed(pending.len(), &mut attempt)
+                .await?;
+        }
+    }
+
+    // Fetches a chunk of up to 100 keys. Keys left unprocessed by DynamoDB are
+    // requested again. DynamoDB stops each response at 16MB, so unprocessed
+    // keys are routine for large items: as long as some items are returned,
+    // the remaining keys are requested immediately. Only rounds which return
+    // no items (for example, when throttled) back off and count towards
+    // retry.max_attempts, as in batch_put_chunk.
+    async fn batch_get_chunk(
+        &self,
+        table: &str,
+        keys: Vec<DynamoMap>,
+    ) -> Result<Vec<DynamoMap>, ServerError> {
+        let mut items = Vec::new();
+        let mut pending = keys;
+        let mut attempt = 1;
+        loop {
+            let response = self
+                .send(|| {
+                    self.backend.batch_get_item(
+                        table.to_string(),
+                        pending.clone(),
+                        self.read_consistency.consistent_read(),
+                    )
+                })
+                .await?
                 .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+            self.record_capacity(
+                "BatchGetItem",
+                response.consumed_capacity.iter().flatten(),
+                &pending,
+            );
+            let fetched = response
+                .responses
+                .and_then(|mut r| r.remove(table))
+                .unwrap_or_default();
+            let made_progress = !fetched.is_empty();
+            items.extend(fetched);
+            pending = response
+                .unprocessed_keys
+                .and_then(|mut u| u.remove(table))
+                .map(|keys_and_attributes| keys_and_attributes.keys().to_vec())
+                .unwrap_or_default();
+            if pending.is_empty() {
+                return Ok(items);
+            }
+            if made_progress {
+                attempt = 1;
+            } else {
+                self.backoff_unprocessed(pending.len(), &mut attempt)
+                    .await?;
+            }
+        }
+    }
+
+    async fn backoff_unprocessed(
+        &self,
+        unprocessed: usize,
+        attempt: &mut u32,
+    ) -> Result<(), ServerError> {
+        if *attempt >= self.retry.max_attempts {
+            return Err(DynamoUnprocessedItems::new(&format!(
+                "{} items were left unprocessed after {} attempts",
+                unprocessed, attempt
+            )));
         }
+        tokio::time::sleep(self.retry.backoff(*attempt)).await;
+        *attempt += 1;
         Ok(())
     }
 }
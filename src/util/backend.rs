@@ -1,18 +1,51 @@
 use std::collections::HashMap;
 
 use async_trait::async_trait;
-use aws_config::{BehaviorVersion, Region};
+use aws_config::{sts::AssumeRoleProvider, BehaviorVersion, Region};
 use aws_sdk_dynamodb::{
+    config::Credentials,
     error::SdkError,
     operation::{
+        batch_execute_statement::{BatchExecuteStatementError, BatchExecuteStatementOutput},
+        batch_get_item::{BatchGetItemError, BatchGetItemOutput},
         batch_write_item::{BatchWriteItemError, BatchWriteItemOutput},
+        create_backup::{CreateBackupError, CreateBackupOutput},
+        create_table::{CreateTableError, CreateTableOutput},
         delete_item::{DeleteItemError, DeleteItemOutput},
+        delete_table::{DeleteTableError, DeleteTableOutput},
+        describe_backup::{DescribeBackupError, DescribeBackupOutput},
+        describe_continuous_backups::{
+            DescribeContinuousBackupsError, DescribeContinuousBackupsOutput,
+        },
+        describe_export::{DescribeExportError, DescribeExportOutput},
+        describe_table::{DescribeTableError, DescribeTableOutput},
+        describe_time_to_live::{DescribeTimeToLiveError, DescribeTimeToLiveOutput},
+        execute_statement::{ExecuteStatementError, ExecuteStatementOutput},
+        export_table_to_point_in_time::{
+            ExportTableToPointInTimeError, ExportTableToPointInTimeOutput,
+        },
         get_item::{GetItemError, GetItemOutput},
         put_item::{PutItemError, PutItemOutput},
         query::{QueryError, QueryOutput},
+        restore_table_from_backup::{RestoreTableFromBackupError, RestoreTableFromBackupOutput},
+        restore_table_to_point_in_time::{
+            RestoreTableToPointInTimeError, RestoreTableToPointInTimeOutput,
+        },
+        scan::{ScanError, ScanOutput},
+        transact_write_items::{TransactWriteItemsError, TransactWriteItemsOutput},
+        update_continuous_backups::{UpdateContinuousBackupsError, UpdateContinuousBackupsOutput},
         update_item::{UpdateItemError, UpdateItemOutput},
+        update_table::{UpdateTableError, UpdateTableOutput},
+        update_time_to_live::{UpdateTimeToLiveError, UpdateTimeToLiveOutput},
+    },
+    primitives::DateTime,
+    types::{
+        AttributeDefinition, AttributeValue, BatchStatementRequest, BillingMode, DeleteRequest,
+        ExportFormat, GlobalSecondaryIndex, GlobalSecondaryIndexUpdate, KeySchemaElement,
+        KeysAndAttributes, LocalSecondaryIndex, PointInTimeRecoverySpecification, PutRequest,
+        ReturnConsumedCapacity, ReturnValue, Select, TimeToLiveSpecification, TransactWriteItem,
+        WriteRequest,
     },
-    types::{AttributeValue, DeleteRequest, PutRequest, WriteRequest},
 };
 use fractic_core::collection;
 use fractic_env_config::EnvVariables;
@@ -21,35 +54,72 @@ use mockall::automock;
 
 use crate::env::DynamoEnvConfig;
 
-use super::DynamoUtil;
+use super::{table_router::TableNaming, DynamoUtil};
 
 // Underlying backend, which performs the actual AWS operations. Kept generic so
 // that it can be swapped with a mock backend for testing.
 //
 // Should be kept as minimal and close as possible to the real
 // aws_sdk_dynamodb::Client, to minimize untestable code.
+//
+// Other backends (such as MemoryDynamoBackend) can be used through
+// DynamoUtil::from_backend. Note that DAX is not supported: AWS doesn't provide
+// a DAX client for Rust (aws-sdk-dax only covers cluster management), and DAX
+// doesn't accept the regular DynamoDB HTTP API, so a DAX backend would need a
+// client implementing its protocol first.
 #[automock]
 #[async_trait]
 pub trait DynamoBackendImpl {
+    // Fetches a single page of results. If the output includes a
+    // last_evaluated_key, further pages can be fetched by passing it back as
+    // the exclusive_start_key. Paging is left to DynamoUtil, so that limits and
+    // streaming only fetch the pages that are actually needed.
+    #[allow(clippy::too_many_arguments)]
     async fn query(
         &self,
         table_name: String,
         index: Option<String>,
         condition: String,
         attribute_values: HashMap<String, AttributeValue>,
+        limit: Option<i32>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+        filter_expression: Option<String>,
+        attribute_names: Option<HashMap<String, String>>,
+        consistent_read: Option<bool>,
+        scan_index_forward: Option<bool>,
+        select: Option<Select>,
     ) -> Result<QueryOutput, SdkError<QueryError>>;
 
+    async fn scan(
+        &self,
+        table_name: String,
+        filter_expression: Option<String>,
+        attribute_values: Option<HashMap<String, AttributeValue>>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+    ) -> Result<ScanOutput, SdkError<ScanError>>;
+
     async fn get_item(
         &self,
         table_name: String,
         key: HashMap<String, AttributeValue>,
         projection_expression: Option<String>,
+        consistent_read: Option<bool>,
     ) -> Result<GetItemOutput, SdkError<GetItemError>>;
 
+    async fn batch_get_item(
+        &self,
+        table_name: String,
+        keys: Vec<HashMap<String, AttributeValue>>,
+        consistent_read: Option<bool>,
+    ) -> Result<BatchGetItemOutput, SdkError<BatchGetItemError>>;
+
     async fn put_item(
         &self,
         table_name: String,
         item: HashMap<String, AttributeValue>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<HashMap<String, String>>,
+        expression_attribute_values: Option<HashMap<String, AttributeValue>>,
     ) -> Result<PutItemOutput, SdkError<PutItemError>>;
 
     async fn batch_put_item(
@@ -66,12 +136,17 @@ pub trait DynamoBackendImpl {
         expression_attribute_values: HashMap<String, AttributeValue>,
         expression_attribute_names: HashMap<String, String>,
         condition_expression: Option<String>,
+        return_values: Option<ReturnValue>,
     ) -> Result<UpdateItemOutput, SdkError<UpdateItemError>>;
 
     async fn delete_item(
         &self,
         table_name: String,
         key: HashMap<String, AttributeValue>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<HashMap<String, String>>,
+        expression_attribute_values: Option<HashMap<String, AttributeValue>>,
+        return_values: Option<ReturnValue>,
     ) -> Result<DeleteItemOutput, SdkError<DeleteItemError>>;
 
     async fn batch_delete_item(
@@ -79,12 +154,156 @@ pub trait DynamoBackendImpl {
         table_name: String,
         keys: Vec<HashMap<String, AttributeValue>>,
     ) -> Result<BatchWriteItemOutput, SdkError<BatchWriteItemError>>;
+
+    async fn transact_write_items(
+        &self,
+        items: Vec<TransactWriteItem>,
+        client_request_token: Option<String>,
+    ) -> Result<TransactWriteItemsOutput, SdkError<TransactWriteItemsError>>;
+
+    async fn execute_statement(
+        &self,
+        statement: String,
+        parameters: Option<Vec<AttributeValue>>,
+        consistent_read: Option<bool>,
+        next_token: Option<String>,
+    ) -> Result<ExecuteStatementOutput, SdkError<ExecuteStatementError>>;
+
+    async fn batch_execute_statement(
+        &self,
+        statements: Vec<(String, Option<Vec<AttributeValue>>)>,
+    ) -> Result<BatchExecuteStatementOutput, SdkError<BatchExecuteStatementError>>;
+
+    async fn create_table(
+        &self,
+        table_name: String,
+        key_schema: Vec<KeySchemaElement>,
+        attribute_definitions: Vec<AttributeDefinition>,
+        global_secondary_indexes: Option<Vec<GlobalSecondaryIndex>>,
+        local_secondary_indexes: Option<Vec<LocalSecondaryIndex>>,
+        billing_mode: BillingMode,
+    ) -> Result<CreateTableOutput, SdkError<CreateTableError>>;
+
+    async fn delete_table(
+        &self,
+        table_name: String,
+    ) -> Result<DeleteTableOutput, SdkError<DeleteTableError>>;
+
+    async fn describe_table(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeTableOutput, SdkError<DescribeTableError>>;
+
+    async fn update_table(
+        &self,
+        table_name: String,
+        attribute_definitions: Vec<AttributeDefinition>,
+        global_secondary_index_updates: Vec<GlobalSecondaryIndexUpdate>,
+    ) -> Result<UpdateTableOutput, SdkError<UpdateTableError>>;
+
+    async fn describe_time_to_live(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeTimeToLiveOutput, SdkError<DescribeTimeToLiveError>>;
+
+    async fn update_time_to_live(
+        &self,
+        table_name: String,
+        time_to_live_specification: TimeToLiveSpecification,
+    ) -> Result<UpdateTimeToLiveOutput, SdkError<UpdateTimeToLiveError>>;
+
+    async fn export_table_to_point_in_time(
+        &self,
+        table_arn: String,
+        s3_bucket: String,
+        s3_prefix: Option<String>,
+        export_format: ExportFormat,
+        export_time: Option<DateTime>,
+    ) -> Result<ExportTableToPointInTimeOutput, SdkError<ExportTableToPointInTimeError>>;
+
+    async fn describe_export(
+        &self,
+        export_arn: String,
+    ) -> Result<DescribeExportOutput, SdkError<DescribeExportError>>;
+
+    async fn create_backup(
+        &self,
+        table_name: String,
+        backup_name: String,
+    ) -> Result<CreateBackupOutput, SdkError<CreateBackupError>>;
+
+    async fn describe_backup(
+        &self,
+        backup_arn: String,
+    ) -> Result<DescribeBackupOutput, SdkError<DescribeBackupError>>;
+
+    async fn restore_table_from_backup(
+        &self,
+        target_table_name: String,
+        backup_arn: String,
+    ) -> Result<RestoreTableFromBackupOutput, SdkError<RestoreTableFromBackupError>>;
+
+    async fn describe_continuous_backups(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeContinuousBackupsOutput, SdkError<DescribeContinuousBackupsError>>;
+
+    async fn update_continuous_backups(
+        &self,
+        table_name: String,
+        point_in_time_recovery_specification: PointInTimeRecoverySpecification,
+    ) -> Result<UpdateContinuousBackupsOutput, SdkError<UpdateContinuousBackupsError>>;
+
+    // Restores to the latest restorable time if 'restore_date_time' is None.
+    async fn restore_table_to_point_in_time(
+        &self,
+        source_table_name: String,
+        target_table_name: String,
+        restore_date_time: Option<DateTime>,
+    ) -> Result<RestoreTableToPointInTimeOutput, SdkError<RestoreTableToPointInTimeError>>;
 }
 
 // Real implementation,
 // making actual calls to AWS.
 // --------------------------------------------------
 
+// Region and credentials used for local endpoints, which accept any values.
+const LOCAL_REGION: &str = "us-east-1";
+const LOCAL_ACCESS_KEY: &str = "local";
+
+// Session name recorded in CloudTrail for assumed roles, unless overridden.
+const DEFAULT_ROLE_SESSION_NAME: &str = "fractic-aws-dynamo";
+
+/// IAM role to assume for all requests (see DynamoUtil::new_with_role), for
+/// example to access a table in another account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssumeRoleConfig {
+    pub role_arn: String,
+    /// External ID required by the role's trust policy, if any.
+    pub external_id: Option<String>,
+    pub session_name: Option<String>,
+}
+
+impl AssumeRoleConfig {
+    pub fn new(role_arn: impl Into<String>) -> Self {
+        Self {
+            role_arn: role_arn.into(),
+            external_id: None,
+            session_name: None,
+        }
+    }
+
+    pub fn with_external_id(mut self, external_id: impl Into<String>) -> Self {
+        self.external_id = Some(external_id.into());
+        self
+    }
+
+    pub fn with_session_name(mut self, session_name: impl Into<String>) -> Self {
+        self.session_name = Some(session_name.into());
+        self
+    }
+}
+
 impl DynamoUtil<aws_sdk_dynamodb::Client> {
     pub async fn new(
         env: EnvVariables<DynamoEnvConfig>,
@@ -96,28 +315,135 @@ impl DynamoUtil<aws_sdk_dynamodb::Client> {
             .region(region)
             .load()
             .await;
-        let client = aws_sdk_dynamodb::Client::new(&shared_config);
-        Ok(Self {
-            backend: client,
-            table: table.into(),
-        })
+        Ok(
+            Self::from_backend(aws_sdk_dynamodb::Client::new(&shared_config), table)
+                .with_table_naming(TableNaming::from_env(&env)),
+        )
+    }
+
+    /// Same as new, but sends requests to the given endpoint URL instead of
+    /// the default AWS endpoint for the region (for example, a VPC endpoint or
+    /// a DynamoDB-compatible service).
+    pub async fn new_with_endpoint(
+        env: EnvVariables<DynamoEnvConfig>,
+        table: impl Into<String>,
+        endpoint_url: impl Into<String>,
+    ) -> Result<Self, ServerError> {
+        let region_str = env.get(&DynamoEnvConfig::DynamoRegion)?;
+        let region = Region::new(region_str.clone());
+        let shared_config = aws_config::defaults(BehaviorVersion::v2024_03_28())
+            .region(region)
+            .endpoint_url(endpoint_url)
+            .load()
+            .await;
+        Ok(
+            Self::from_backend(aws_sdk_dynamodb::Client::new(&shared_config), table)
+                .with_table_naming(TableNaming::from_env(&env)),
+        )
+    }
+
+    /// Same as new, but assumes the given IAM role (using the default
+    /// credentials) and sends all requests using the role's credentials. The
+    /// temporary credentials are refreshed automatically before they expire.
+    pub async fn new_with_role(
+        env: EnvVariables<DynamoEnvConfig>,
+        table: impl Into<String>,
+        role: AssumeRoleConfig,
+    ) -> Result<Self, ServerError> {
+        let region_str = env.get(&DynamoEnvConfig::DynamoRegion)?;
+        let region = Region::new(region_str.clone());
+        let base_config = aws_config::defaults(BehaviorVersion::v2024_03_28())
+            .region(region.clone())
+            .load()
+            .await;
+        let mut provider = AssumeRoleProvider::builder(role.role_arn)
+            .session_name(
+                role.session_name
+                    .unwrap_or_else(|| DEFAULT_ROLE_SESSION_NAME.to_string()),
+            )
+            .configure(&base_config);
+        if let Some(external_id) = role.external_id {
+            provider = provider.external_id(external_id);
+        }
+        let shared_config = aws_config::defaults(BehaviorVersion::v2024_03_28())
+            .region(region)
+            .credentials_provider(provider.build().await)
+            .load()
+            .await;
+        Ok(
+            Self::from_backend(aws_sdk_dynamodb::Client::new(&shared_config), table)
+                .with_table_naming(TableNaming::from_env(&env)),
+        )
+    }
+
+    /// Connects to a local DynamoDB endpoint, such as DynamoDB Local (for
+    /// example, "http://localhost:8000"), for tests and CI. Dummy region and
+    /// credentials are used, so no AWS account or environment configuration
+    /// is needed.
+    pub async fn new_local(endpoint_url: impl Into<String>, table: impl Into<String>) -> Self {
+        let shared_config = aws_config::defaults(BehaviorVersion::v2024_03_28())
+            .region(Region::new(LOCAL_REGION))
+            .credentials_provider(Credentials::new(
+                LOCAL_ACCESS_KEY,
+                LOCAL_ACCESS_KEY,
+                None,
+                None,
+                "fractic-aws-dynamo-local",
+            ))
+            .endpoint_url(endpoint_url)
+            .load()
+            .await;
+        Self::from_backend(aws_sdk_dynamodb::Client::new(&shared_config), table)
     }
 }
 
 #[async_trait]
 impl DynamoBackendImpl for aws_sdk_dynamodb::Client {
+    #[allow(clippy::too_many_arguments)]
     async fn query(
         &self,
         table_name: String,
         index: Option<String>,
         condition: String,
         attribute_values: HashMap<String, AttributeValue>,
+        limit: Option<i32>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+        filter_expression: Option<String>,
+        attribute_names: Option<HashMap<String, String>>,
+        consistent_read: Option<bool>,
+        scan_index_forward: Option<bool>,
+        select: Option<Select>,
     ) -> Result<QueryOutput, SdkError<QueryError>> {
         self.query()
             .set_table_name(Some(table_name))
             .set_index_name(index)
             .set_key_condition_expression(Some(condition))
             .set_expression_attribute_values(Some(attribute_values))
+            .set_limit(limit)
+            .set_exclusive_start_key(exclusive_start_key)
+            .set_filter_expression(filter_expression)
+            .set_expression_attribute_names(attribute_names)
+            .set_consistent_read(consistent_read)
+            .set_scan_index_forward(scan_index_forward)
+            .set_select(select)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await
+    }
+
+    async fn scan(
+        &self,
+        table_name: String,
+        filter_expression: Option<String>,
+        attribute_values: Option<HashMap<String, AttributeValue>>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+    ) -> Result<ScanOutput, SdkError<ScanError>> {
+        self.scan()
+            .set_table_name(Some(table_name))
+            .set_filter_expression(filter_expression)
+            .set_expression_attribute_values(attribute_values)
+            .set_exclusive_start_key(exclusive_start_key)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
             .send()
             .await
     }
@@ -127,11 +453,33 @@ impl DynamoBackendImpl for aws_sdk_dynamodb::Client {
         table_name: String,
         key: HashMap<String, AttributeValue>,
         projection_expression: Option<String>,
+        consistent_read: Option<bool>,
     ) -> Result<GetItemOutput, SdkError<GetItemError>> {
         self.get_item()
             .set_table_name(Some(table_name))
             .set_key(Some(key))
             .set_projection_expression(projection_expression)
+            .set_consistent_read(consistent_read)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await
+    }
+
+    async fn batch_get_item(
+        &self,
+        table_name: String,
+        keys: Vec<HashMap<String, AttributeValue>>,
+        consistent_read: Option<bool>,
+    ) -> Result<BatchGetItemOutput, SdkError<BatchGetItemError>> {
+        self.batch_get_item()
+            .set_request_items(Some(collection!(
+                table_name => KeysAndAttributes::builder()
+                    .set_keys(Some(keys))
+                    .set_consistent_read(consistent_read)
+                    .build()
+                    .expect("Invalid KeysAndAttributes")
+            )))
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
             .send()
             .await
     }
@@ -140,10 +488,17 @@ impl DynamoBackendImpl for aws_sdk_dynamodb::Client {
         &self,
         table_name: String,
         item: HashMap<String, AttributeValue>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<HashMap<String, String>>,
+        expression_attribute_values: Option<HashMap<String, AttributeValue>>,
     ) -> Result<PutItemOutput, SdkError<PutItemError>> {
         self.put_item()
             .set_table_name(Some(table_name))
             .set_item(Some(item))
+            .set_condition_expression(condition_expression)
+            .set_expression_attribute_names(expression_attribute_names)
+            .set_expression_attribute_values(expression_attribute_values)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
             .send()
             .await
     }
@@ -167,6 +522,7 @@ impl DynamoBackendImpl for aws_sdk_dynamodb::Client {
                     )
                     .collect()
             )))
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
             .send()
             .await
     }
@@ -179,6 +535,7 @@ impl DynamoBackendImpl for aws_sdk_dynamodb::Client {
         expression_attribute_values: HashMap<String, AttributeValue>,
         expression_attribute_names: HashMap<String, String>,
         condition_expression: Option<String>,
+        return_values: Option<ReturnValue>,
     ) -> Result<UpdateItemOutput, SdkError<UpdateItemError>> {
         self.update_item()
             .set_table_name(Some(table_name))
@@ -187,6 +544,8 @@ impl DynamoBackendImpl for aws_sdk_dynamodb::Client {
             .set_expression_attribute_values(Some(expression_attribute_values))
             .set_expression_attribute_names(Some(expression_attribute_names))
             .set_condition_expression(condition_expression)
+            .set_return_values(return_values)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
             .send()
             .await
     }
@@ -195,10 +554,19 @@ impl DynamoBackendImpl for aws_sdk_dynamodb::Client {
         &self,
         table_name: String,
         key: HashMap<String, AttributeValue>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<HashMap<String, String>>,
+        expression_attribute_values: Option<HashMap<String, AttributeValue>>,
+        return_values: Option<ReturnValue>,
     ) -> Result<DeleteItemOutput, SdkError<DeleteItemError>> {
         self.delete_item()
             .set_table_name(Some(table_name))
             .set_key(Some(key))
+            .set_condition_expression(condition_expression)
+            .set_expression_attribute_names(expression_attribute_names)
+            .set_expression_attribute_values(expression_attribute_values)
+            .set_return_values(return_values)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
             .send()
             .await
     }
@@ -222,6 +590,234 @@ impl DynamoBackendImpl for aws_sdk_dynamodb::Client {
                     )
                     .collect()
             )))
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await
+    }
+
+    async fn transact_write_items(
+        &self,
+        items: Vec<TransactWriteItem>,
+        client_request_token: Option<String>,
+    ) -> Result<TransactWriteItemsOutput, SdkError<TransactWriteItemsError>> {
+        self.transact_write_items()
+            .set_transact_items(Some(items))
+            .set_client_request_token(client_request_token)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await
+    }
+
+    async fn execute_statement(
+        &self,
+        statement: String,
+        parameters: Option<Vec<AttributeValue>>,
+        consistent_read: Option<bool>,
+        next_token: Option<String>,
+    ) -> Result<ExecuteStatementOutput, SdkError<ExecuteStatementError>> {
+        self.execute_statement()
+            .set_statement(Some(statement))
+            .set_parameters(parameters)
+            .set_consistent_read(consistent_read)
+            .set_next_token(next_token)
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await
+    }
+
+    async fn batch_execute_statement(
+        &self,
+        statements: Vec<(String, Option<Vec<AttributeValue>>)>,
+    ) -> Result<BatchExecuteStatementOutput, SdkError<BatchExecuteStatementError>> {
+        self.batch_execute_statement()
+            .set_statements(Some(
+                statements
+                    .into_iter()
+                    .map(|(statement, parameters)| {
+                        BatchStatementRequest::builder()
+                            .statement(statement)
+                            .set_parameters(parameters)
+                            .build()
+                            .expect("Invalid BatchStatementRequest")
+                    })
+                    .collect(),
+            ))
+            .return_consumed_capacity(ReturnConsumedCapacity::Total)
+            .send()
+            .await
+    }
+
+    async fn create_table(
+        &self,
+        table_name: String,
+        key_schema: Vec<KeySchemaElement>,
+        attribute_definitions: Vec<AttributeDefinition>,
+        global_secondary_indexes: Option<Vec<GlobalSecondaryIndex>>,
+        local_secondary_indexes: Option<Vec<LocalSecondaryIndex>>,
+        billing_mode: BillingMode,
+    ) -> Result<CreateTableOutput, SdkError<CreateTableError>> {
+        self.create_table()
+            .set_table_name(Some(table_name))
+            .set_key_schema(Some(key_schema))
+            .set_attribute_definitions(Some(attribute_definitions))
+            .set_global_secondary_indexes(global_secondary_indexes)
+            .set_local_secondary_indexes(local_secondary_indexes)
+            .billing_mode(billing_mode)
+            .send()
+            .await
+    }
+
+    async fn delete_table(
+        &self,
+        table_name: String,
+    ) -> Result<DeleteTableOutput, SdkError<DeleteTableError>> {
+        self.delete_table()
+            .set_table_name(Some(table_name))
+            .send()
+            .await
+    }
+
+    async fn describe_table(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeTableOutput, SdkError<DescribeTableError>> {
+        self.describe_table()
+            .set_table_name(Some(table_name))
+            .send()
+            .await
+    }
+
+    async fn update_table(
+        &self,
+        table_name: String,
+        attribute_definitions: Vec<AttributeDefinition>,
+        global_secondary_index_updates: Vec<GlobalSecondaryIndexUpdate>,
+    ) -> Result<UpdateTableOutput, SdkError<UpdateTableError>> {
+        self.update_table()
+            .set_table_name(Some(table_name))
+            .set_attribute_definitions(Some(attribute_definitions))
+            .set_global_secondary_index_updates(Some(global_secondary_index_updates))
+            .send()
+            .await
+    }
+
+    async fn describe_time_to_live(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeTimeToLiveOutput, SdkError<DescribeTimeToLiveError>> {
+        self.describe_time_to_live()
+            .set_table_name(Some(table_name))
+            .send()
+            .await
+    }
+
+    async fn update_time_to_live(
+        &self,
+        table_name: String,
+        time_to_live_specification: TimeToLiveSpecification,
+    ) -> Result<UpdateTimeToLiveOutput, SdkError<UpdateTimeToLiveError>> {
+        self.update_time_to_live()
+            .set_table_name(Some(table_name))
+            .set_time_to_live_specification(Some(time_to_live_specification))
+            .send()
+            .await
+    }
+
+    async fn export_table_to_point_in_time(
+        &self,
+        table_arn: String,
+        s3_bucket: String,
+        s3_prefix: Option<String>,
+        export_format: ExportFormat,
+        export_time: Option<DateTime>,
+    ) -> Result<ExportTableToPointInTimeOutput, SdkError<ExportTableToPointInTimeError>> {
+        self.export_table_to_point_in_time()
+            .set_table_arn(Some(table_arn))
+            .set_s3_bucket(Some(s3_bucket))
+            .set_s3_prefix(s3_prefix)
+            .set_export_format(Some(export_format))
+            .set_export_time(export_time)
+            .send()
+            .await
+    }
+
+    async fn describe_export(
+        &self,
+        export_arn: String,
+    ) -> Result<DescribeExportOutput, SdkError<DescribeExportError>> {
+        self.describe_export()
+            .set_export_arn(Some(export_arn))
+            .send()
+            .await
+    }
+
+    async fn create_backup(
+        &self,
+        table_name: String,
+        backup_name: String,
+    ) -> Result<CreateBackupOutput, SdkError<CreateBackupError>> {
+        self.create_backup()
+            .set_table_name(Some(table_name))
+            .set_backup_name(Some(backup_name))
+            .send()
+            .await
+    }
+
+    async fn describe_backup(
+        &self,
+        backup_arn: String,
+    ) -> Result<DescribeBackupOutput, SdkError<DescribeBackupError>> {
+        self.describe_backup()
+            .set_backup_arn(Some(backup_arn))
+            .send()
+            .await
+    }
+
+    async fn restore_table_from_backup(
+        &self,
+        target_table_name: String,
+        backup_arn: String,
+    ) -> Result<RestoreTableFromBackupOutput, SdkError<RestoreTableFromBackupError>> {
+        self.restore_table_from_backup()
+            .set_target_table_name(Some(target_table_name))
+            .set_backup_arn(Some(backup_arn))
+            .send()
+            .await
+    }
+
+    async fn describe_continuous_backups(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeContinuousBackupsOutput, SdkError<DescribeContinuousBackupsError>> {
+        self.describe_continuous_backups()
+            .set_table_name(Some(table_name))
+            .send()
+            .await
+    }
+
+    async fn update_continuous_backups(
+        &self,
+        table_name: String,
+        point_in_time_recovery_specification: PointInTimeRecoverySpecification,
+    ) -> Result<UpdateContinuousBackupsOutput, SdkError<UpdateContinuousBackupsError>> {
+        self.update_continuous_backups()
+            .set_table_name(Some(table_name))
+            .set_point_in_time_recovery_specification(Some(point_in_time_recovery_specification))
+            .send()
+            .await
+    }
+
+    async fn restore_table_to_point_in_time(
+        &self,
+        source_table_name: String,
+        target_table_name: String,
+        restore_date_time: Option<DateTime>,
+    ) -> Result<RestoreTableToPointInTimeOutput, SdkError<RestoreTableToPointInTimeError>> {
+        self.restore_table_to_point_in_time()
+            .set_source_table_name(Some(source_table_name))
+            .set_target_table_name(Some(target_table_name))
+            .set_use_latest_restorable_time(Some(restore_date_time.is_none()))
+            .set_restore_date_time(restore_date_time)
             .send()
             .await
     }
@@ -0,0 +1,353 @@
+use aws_sdk_dynamodb::{
+    operation::{
+        create_backup::CreateBackupError, describe_backup::DescribeBackupError,
+        describe_continuous_backups::DescribeContinuousBackupsError,
+        restore_table_from_backup::RestoreTableFromBackupError,
+        restore_table_to_point_in_time::RestoreTableToPointInTimeError,
+        update_continuous_backups::UpdateContinuousBackupsError,
+    },
+    primitives::DateTime as AwsDateTime,
+    types::{
+        BackupDetails, BackupStatus, ContinuousBackupsDescription,
+        PointInTimeRecoverySpecification, PointInTimeRecoveryStatus,
+    },
+};
+use chrono::{DateTime, Utc};
+use fractic_server_error::ServerError;
+
+use crate::errors::{
+    DynamoAlreadyExists, DynamoCalloutError, DynamoInvalidOperation, DynamoNotFound,
+};
+
+use super::{backend::DynamoBackendImpl, DynamoUtil};
+
+/// An on-demand backup of a table (see DynamoUtil::backup).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackupInfo {
+    pub backup_arn: String,
+    pub backup_name: String,
+    pub status: BackupStatus,
+    pub created_at: Option<DateTime<Utc>>,
+    /// Only reported once the backup is available.
+    pub size_bytes: Option<i64>,
+}
+
+impl BackupInfo {
+    pub fn is_available(&self) -> bool {
+        self.status == BackupStatus::Available
+    }
+
+    fn from_details(details: &BackupDetails) -> Self {
+        Self {
+            backup_arn: details.backup_arn().to_string(),
+            backup_name: details.backup_name().to_string(),
+            status: details.backup_status().clone(),
+            created_at: to_chrono(details.backup_creation_date_time()),
+            size_bytes: details.backup_size_bytes(),
+        }
+    }
+}
+
+/// Point-in-time recovery settings of a table (see DynamoUtil::pitr_status).
+#[derive(Debug, Clone, PartialEq)]
+pub struct PitrStatus {
+    pub enabled: bool,
+    /// Range of times the table can currently be restored to (only set when
+    /// enabled).
+    pub earliest_restorable: Option<DateTime<Utc>>,
+    pub latest_restorable: Option<DateTime<Utc>>,
+}
+
+impl PitrStatus {
+    fn from_description(description: Option<&ContinuousBackupsDescription>) -> Self {
+        let recovery =
+            description.and_then(|description| description.point_in_time_recovery_description());
+        Self {
+            enabled: recovery.and_then(|recovery| recovery.point_in_time_recovery_status())
+                == Some(&PointInTimeRecoveryStatus::Enabled),
+            earliest_restorable: recovery
+                .and_then(|recovery| recovery.earliest_restorable_date_time())
+                .and_then(to_chrono),
+            latest_restorable: recovery
+                .and_then(|recovery| recovery.latest_restorable_date_time())
+                .and_then(to_chrono),
+        }
+    }
+}
+
+impl<C: DynamoBackendImpl> DynamoUtil<C> {
+    /// Creates an on-demand backup of the table, for example to snapshot it
+    /// before a risky migration. The backup contains the table's data as of
+    /// this call, so writes can continue straight away, although it can only
+    /// be restored once it is available (usually within seconds).
+    ///
+    /// Backups are kept until deleted (from the console or the CLI), and don't
+    /// consume any read capacity.
+    pub async fn backup(&self, backup_name: &str) -> Result<BackupInfo, ServerError> {
+        self.backup_table(&self.table, backup_name).await
+    }
+
+    /// Same as backup, but for a different table (for example, one of the
+    /// tables of a TableRouter).
+    pub async fn backup_table(
+        &self,
+        table: &str,
+        backup_name: &str,
+    ) -> Result<BackupInfo, ServerError> {
+        let output = self
+            .send(|| {
+                self.backend
+                    .create_backup(table.to_string(), backup_name.to_string())
+            })
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                CreateBackupError::TableNotFoundException(_) => DynamoNotFound::new(),
+                CreateBackupError::TableInUseException(_) => DynamoInvalidOperation::new(&format!(
+                    "table '{}' is being created, updated or backed up, so can't be backed up",
+                    table
+                )),
+                other => DynamoCalloutError::with_debug(&other),
+            })?;
+        Ok(BackupInfo::from_details(
+            output
+                .backup_details()
+                .ok_or_else(|| DynamoCalloutError::with_debug(&"missing backup details"))?,
+        ))
+    }
+
+    /// Current status of a backup created with backup or backup_table.
+    pub async fn backup_info(&self, backup_arn: &str) -> Result<BackupInfo, ServerError> {
+        let output = self
+            .send(|| self.backend.describe_backup(backup_arn.to_string()))
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                DescribeBackupError::BackupNotFoundException(_) => DynamoNotFound::new(),
+                other => DynamoCalloutError::with_debug(&other),
+            })?;
+        Ok(BackupInfo::from_details(
+            output
+                .backup_description()
+                .and_then(|description| description.backup_details())
+                .ok_or_else(|| DynamoCalloutError::with_debug(&"missing backup details"))?,
+        ))
+    }
+
+    /// Restores a backup into a new table named 'table', and waits until the
+    /// table is active. Returns DynamoAlreadyExists if the table already
+    /// exists (DynamoDB can't restore into an existing table), or
+    /// DynamoTableNotReady if the restore is still in progress after
+    /// 'timeout' (in which case it keeps running).
+    pub async fn restore_to(
+        &self,
+        backup_arn: &str,
+        table: &str,
+        timeout: std::time::Duration,
+    ) -> Result<(), ServerError> {
+        self.send(|| {
+            self.backend
+                .restore_table_from_backup(table.to_string(), backup_arn.to_string())
+        })
+        .await?
+        .map_err(|e| match e.into_service_error() {
+            RestoreTableFromBackupError::TableAlreadyExistsException(_) => {
+                DynamoAlreadyExists::new()
+            }
+            RestoreTableFromBackupError::BackupNotFoundException(_) => DynamoNotFound::new(),
+            RestoreTableFromBackupError::BackupInUseException(_) => {
+                DynamoInvalidOperation::new(&format!(
+                    "backup '{}' is still being created, so can't be restored yet",
+                    backup_arn
+                ))
+            }
+            other => DynamoCalloutError::with_debug(&other),
+        })?;
+        self.wait_for_table_active(table, timeout).await
+    }
+
+    /// Enables point-in-time recovery on the table, which continuously backs
+    /// it up so that it can be restored to any second of the last 35 days
+    /// (see restore_to_point_in_time). Does nothing if it is already enabled.
+    pub async fn enable_pitr(&self, table: &str) -> Result<(), ServerError> {
+        self.send(|| {
+            self.backend.update_continuous_backups(
+                table.to_string(),
+                PointInTimeRecoverySpecification::builder()
+                    .point_in_time_recovery_enabled(true)
+                    .build()
+                    .expect("point-in-time recovery specification should be complete"),
+            )
+        })
+        .await?
+        .map_err(|e| match e.into_service_error() {
+            UpdateContinuousBackupsError::TableNotFoundException(_) => DynamoNotFound::new(),
+            UpdateContinuousBackupsError::ContinuousBackupsUnavailableException(_) => {
+                DynamoInvalidOperation::new(&format!(
+                    "backups are still being enabled on table '{}' (for example, if it was just created)",
+                    table
+                ))
+            }
+            other => DynamoCalloutError::with_debug(&other),
+        })?;
+        Ok(())
+    }
+
+    /// Whether point-in-time recovery is enabled on the table, and the range
+    /// of times it can be restored to.
+    pub async fn pitr_status(&self, table: &str) -> Result<PitrStatus, ServerError> {
+        let output = self
+            .send(|| self.backend.describe_continuous_backups(table.to_string()))
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                DescribeContinuousBackupsError::TableNotFoundException(_) => DynamoNotFound::new(),
+                other => DynamoCalloutError::with_debug(&other),
+            })?;
+        Ok(PitrStatus::from_description(
+            output.continuous_backups_description(),
+        ))
+    }
+
+    /// Restores the table's data as of 'time' (or the latest restorable time,
+    /// usually about 5 minutes ago, if None) into a new table named
+    /// 'new_table', and waits until the table is active. Point-in-time
+    /// recovery must be enabled on the table (see enable_pitr).
+    ///
+    /// As with restore_to, returns DynamoAlreadyExists if the new table
+    /// already exists, or DynamoTableNotReady if the restore is still in
+    /// progress after 'timeout'.
+    pub async fn restore_to_point_in_time(
+        &self,
+        table: &str,
+        time: Option<DateTime<Utc>>,
+        new_table: &str,
+        timeout: std::time::Duration,
+    ) -> Result<(), ServerError> {
+        self.send(|| {
+            self.backend.restore_table_to_point_in_time(
+                table.to_string(),
+                new_table.to_string(),
+                time.map(|time| AwsDateTime::from_millis(time.timestamp_millis())),
+            )
+        })
+        .await?
+        .map_err(|e| match e.into_service_error() {
+            RestoreTableToPointInTimeError::SourceTableNotFoundException(_) => {
+                DynamoNotFound::new()
+            }
+            RestoreTableToPointInTimeError::TableAlreadyExistsException(_) => {
+                DynamoAlreadyExists::new()
+            }
+            RestoreTableToPointInTimeError::PointInTimeRecoveryUnavailableException(_) => {
+                DynamoInvalidOperation::new(&format!(
+                    "point-in-time recovery must be enabled on table '{}' to restore it",
+                    table
+                ))
+            }
+            RestoreTableToPointInTimeError::InvalidRestoreTimeException(_) => {
+                DynamoInvalidOperation::new(
+                    "restore time is outside of the point-in-time recovery window",
+                )
+            }
+            other => DynamoCalloutError::with_debug(&other),
+        })?;
+        self.wait_for_table_active(new_table, timeout).await
+    }
+}
+
+fn to_chrono(time: &AwsDateTime) -> Option<DateTime<Utc>> {
+    time.to_millis()
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use fractic_core::collection;
+
+    use super::*;
+    use crate::util::DynamoMap;
+
+    fn item(sk: &str) -> DynamoMap {
+        collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S(sk.to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        util.raw_batch_put_item(vec![item("TASK#1"), item("TASK#2")])
+            .await
+            .unwrap();
+
+        let backup = util.backup("before-migration").await.unwrap();
+        assert_eq!(backup.backup_name, "before-migration");
+        assert!(backup.is_available());
+        assert!(backup.created_at.is_some());
+        assert_eq!(util.backup_info(&backup.backup_arn).await.unwrap(), backup);
+
+        // Later writes aren't included in the backup.
+        util.raw_batch_put_item(vec![item("TASK#3")]).await.unwrap();
+        util.restore_to(
+            &backup.backup_arn,
+            "my_table_restored",
+            std::time::Duration::from_secs(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(util.backend.items("my_table_restored").len(), 2);
+        assert_eq!(util.backend.items("my_table").len(), 3);
+
+        // Restoring into an existing table fails.
+        assert!(util
+            .restore_to(
+                &backup.backup_arn,
+                "my_table",
+                std::time::Duration::from_secs(1)
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_point_in_time_recovery() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        util.raw_batch_put_item(vec![item("TASK#1")]).await.unwrap();
+
+        let status = util.pitr_status("my_table").await.unwrap();
+        assert!(!status.enabled);
+        assert!(status.earliest_restorable.is_none());
+
+        // Restoring requires point-in-time recovery to be enabled.
+        let timeout = std::time::Duration::from_secs(1);
+        assert!(util
+            .restore_to_point_in_time("my_table", None, "my_table_restored", timeout)
+            .await
+            .is_err());
+
+        util.enable_pitr("my_table").await.unwrap();
+        util.enable_pitr("my_table").await.unwrap();
+        let status = util.pitr_status("my_table").await.unwrap();
+        assert!(status.enabled);
+        assert!(status.earliest_restorable <= status.latest_restorable);
+
+        util.restore_to_point_in_time("my_table", None, "my_table_restored", timeout)
+            .await
+            .unwrap();
+        assert_eq!(util.backend.items("my_table_restored").len(), 1);
+        // Restored tables don't inherit the setting.
+        assert!(!util.pitr_status("my_table_restored").await.unwrap().enabled);
+    }
+
+    #[tokio::test]
+    async fn test_backup_not_found() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        assert!(util.backup_table("other_table", "backup").await.is_err());
+        assert!(util.backup_info("arn:missing").await.is_err());
+        assert!(util.enable_pitr("other_table").await.is_err());
+    }
+}
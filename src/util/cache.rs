@@ -0,0 +1,152 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::schema::PkSk;
+
+use super::DynamoMap;
+
+/// In-memory read-through cache for get_item and query results, shared between
+/// clones of a DynamoUtil. Entries expire after the configured TTL, and are
+/// evicted when the DynamoUtil writes to the corresponding item or partition.
+///
+/// Writes made by other processes are not detected, so this should only be
+/// used for data where reads up to 'ttl' out of date are acceptable (such as
+/// hot singletons and config objects).
+#[derive(Debug)]
+pub struct DynamoCache {
+    ttl: Duration,
+    items: Mutex<HashMap<PkSk, (Instant, Option<DynamoMap>)>>,
+    // Query results are grouped by partition, so that all queries affected by
+    // a write can be evicted at once.
+    queries: Mutex<HashMap<String, HashMap<String, (Instant, Vec<DynamoMap>)>>>,
+}
+
+impl DynamoCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            items: Mutex::new(HashMap::new()),
+            queries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn clear(&self) {
+        self.items.lock().unwrap().clear();
+        self.queries.lock().unwrap().clear();
+    }
+
+    // Returns Some(item) on cache hit, where item is None if the item was
+    // cached as non-existent.
+    pub(crate) fn get_item(&self, id: &PkSk) -> Option<Option<DynamoMap>> {
+        let mut items = self.items.lock().unwrap();
+        match items.get(id) {
+            Some((inserted_at, item)) if inserted_at.elapsed() < self.ttl => Some(item.clone()),
+            Some(_) => {
+                items.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn put_item(&self, id: PkSk, item: Option<DynamoMap>) {
+        self.items
+            .lock()
+            .unwrap()
+            .insert(id, (Instant::now(), item));
+    }
+
+    pub(crate) fn get_query(&self, pk: &str, query_key: &str) -> Option<Vec<DynamoMap>> {
+        let mut queries = self.queries.lock().unwrap();
+        let partition = queries.get_mut(pk)?;
+        match partition.get(query_key) {
+            Some((inserted_at, items)) if inserted_at.elapsed() < self.ttl => Some(items.clone()),
+            Some(_) => {
+                partition.remove(query_key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn put_query(&self, pk: String, query_key: String, items: Vec<DynamoMap>) {
+        self.queries
+            .lock()
+            .unwrap()
+            .entry(pk)
+            .or_default()
+            .insert(query_key, (Instant::now(), items));
+    }
+
+    // Evicts the item, as well as all cached queries on its partition.
+    pub(crate) fn invalidate(&self, id: &PkSk) {
+        self.items.lock().unwrap().remove(id);
+        self.queries.lock().unwrap().remove(&id.pk);
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use fractic_core::collection;
+
+    use super::*;
+
+    fn id(pk: &str, sk: &str) -> PkSk {
+        PkSk {
+            pk: pk.to_string(),
+            sk: sk.to_string(),
+        }
+    }
+
+    fn item() -> DynamoMap {
+        collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("TEST#1".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_item_cache() {
+        let cache = DynamoCache::new(Duration::from_secs(60));
+        assert_eq!(cache.get_item(&id("ROOT", "TEST#1")), None);
+        cache.put_item(id("ROOT", "TEST#1"), Some(item()));
+        cache.put_item(id("ROOT", "TEST#2"), None);
+        assert_eq!(cache.get_item(&id("ROOT", "TEST#1")), Some(Some(item())));
+        assert_eq!(cache.get_item(&id("ROOT", "TEST#2")), Some(None));
+        cache.invalidate(&id("ROOT", "TEST#1"));
+        assert_eq!(cache.get_item(&id("ROOT", "TEST#1")), None);
+        assert_eq!(cache.get_item(&id("ROOT", "TEST#2")), Some(None));
+    }
+
+    #[test]
+    fn test_query_cache_invalidated_by_partition() {
+        let cache = DynamoCache::new(Duration::from_secs(60));
+        cache.put_query("ROOT".to_string(), "q1".to_string(), vec![item()]);
+        cache.put_query("OTHER".to_string(), "q1".to_string(), vec![]);
+        assert_eq!(cache.get_query("ROOT", "q1"), Some(vec![item()]));
+        assert_eq!(cache.get_query("ROOT", "q2"), None);
+        cache.invalidate(&id("ROOT", "TEST#5"));
+        assert_eq!(cache.get_query("ROOT", "q1"), None);
+        assert_eq!(cache.get_query("OTHER", "q1"), Some(vec![]));
+    }
+
+    #[test]
+    fn test_cache_expiry() {
+        let cache = DynamoCache::new(Duration::ZERO);
+        cache.put_item(id("ROOT", "TEST#1"), Some(item()));
+        cache.put_query("ROOT".to_string(), "q1".to_string(), vec![item()]);
+        assert_eq!(cache.get_item(&id("ROOT", "TEST#1")), None);
+        assert_eq!(cache.get_query("ROOT", "q1"), None);
+    }
+}
@@ -34,12 +34,14 @@ fn _sk_strip_uuid<T: DynamoObject>(
         IdLogic::Singleton => sk,
         // For SingletonFamily, strip the key.
         IdLogic::SingletonFamily(_) => sk.split('[').next().unwrap().to_string(),
-        // For Uuid and Timestamp, take ID until last '#' character.
-        IdLogic::Uuid | IdLogic::Timestamp => sk[..sk.rfind('#').ok_or_else(|| {
-            DynamoInvalidId::with_debug(
-                "can't strip Uuid/Timestamp since ID didn't contain '#'",
-                &sk,
-            )
+        // For LABEL#<id> IDs, take ID until last '#' character.
+        IdLogic::Uuid
+        | IdLogic::Timestamp
+        | IdLogic::TimestampMillis
+        | IdLogic::Ulid
+        | IdLogic::Ksuid
+        | IdLogic::Custom(_) => sk[..sk.rfind('#').ok_or_else(|| {
+            DynamoInvalidId::with_debug("can't strip ID since it didn't contain '#'", &sk)
         })?]
             .to_string(),
     })
@@ -52,11 +54,6 @@ pub(crate) async fn calculate_sort_values<T: DynamoObject, B: DynamoBackendImpl>
     insert_position: DynamoInsertPosition,
     num: usize,
 ) -> Result<Vec<f64>, ServerError> {
-    // Special 'sort' field is used to order elements. Use f64 so we can always
-    // insert in between any two elements.
-    let sort_value_init = NotNan::new(1.0).unwrap();
-    let sort_value_default_gap = NotNan::new(1.0).unwrap();
-
     // Search for all IDs for existing items of this type by creating an example
     // ID and stripping the ID UUID / timestamp off the end.
     let (example_pk, example_sk) = generate_pk_sk::<T>(data, &parent_id.pk, &parent_id.sk)?;
@@ -64,12 +61,123 @@ pub(crate) async fn calculate_sort_values<T: DynamoObject, B: DynamoBackendImpl>
         pk: example_pk,
         sk: _sk_strip_uuid::<T>(T::id_logic(), example_sk)?,
     };
+    _calculate_sort_values::<T, B>(util, search_id, insert_position, num, None).await
+}
+
+// Calculates the new sort value for an existing item, to move it to
+// 'insert_position' among its siblings. The item's current position is ignored,
+// so it doesn't affect the calculated value.
+pub(crate) async fn calculate_sort_value_for_move<T: DynamoObject, B: DynamoBackendImpl>(
+    util: &DynamoUtil<B>,
+    id: &PkSk,
+    insert_position: DynamoInsertPosition,
+) -> Result<f64, ServerError> {
+    let search_id = PkSk {
+        pk: id.pk.clone(),
+        sk: _sk_strip_uuid::<T>(T::id_logic(), id.sk.clone())?,
+    };
+    _calculate_sort_values::<T, B>(util, search_id, insert_position, 1, Some(id))
+        .await?
+        .pop()
+        .ok_or(DynamoInvalidOperation::new(
+            "failed to calculate sort value",
+        ))
+}
+
+// Calculates evenly spaced integer sort values for items currently ordered by
+// 'current' (ascending). The values are chosen so that rewriting the items one
+// by one, from last to first, keeps them in the same order after every write.
+// Since items already at their new value produce the same result, an
+// interrupted rebalance can be resumed by recalculating and skipping those.
+pub(crate) fn rebalanced_sort_values(current: &[f64]) -> Vec<f64> {
+    // Item i is written while items before it still have their current value,
+    // so its new value (base + i) must be greater than current[i - 1].
+    let base = current
+        .iter()
+        .enumerate()
+        .map(|(i, sort)| (sort - (i as f64 + 1.0)).floor() + 1.0)
+        .fold(1.0, f64::max);
+    (0..current.len()).map(|i| base + i as f64).collect()
+}
+
+// Calculates sort values for items in a new order, where 'current' holds each
+// item's existing sort value (None for new items). To minimize writes, the
+// largest set of items which are already in increasing order keep their values,
+// and the remaining items are placed evenly in between them.
+pub(crate) fn reordered_sort_values(current: &[Option<f64>]) -> Vec<f64> {
+    // Find the longest strictly increasing subsequence of existing values.
+    // tails[n] is the index of the smallest value ending an increasing
+    // subsequence of length n + 1, and prev links each item to its predecessor
+    // in such a subsequence.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev: Vec<Option<usize>> = vec![None; current.len()];
+    for (i, value) in current.iter().enumerate() {
+        let Some(value) = value else {
+            continue;
+        };
+        let len = tails.partition_point(|&t| current[t].is_some_and(|t| t < *value));
+        if len > 0 {
+            prev[i] = Some(tails[len - 1]);
+        }
+        match tails.get_mut(len) {
+            Some(tail) => *tail = i,
+            None => tails.push(i),
+        }
+    }
+    let mut keep = vec![false; current.len()];
+    let mut next = tails.last().copied();
+    while let Some(i) = next {
+        keep[i] = true;
+        next = prev[i];
+    }
+
+    // Place the remaining items in the gaps between kept values.
+    let mut result = vec![0.0; current.len()];
+    let mut gap_start = 0;
+    let mut lower: Option<f64> = None;
+    for i in 0..=current.len() {
+        if i < current.len() && !keep[i] {
+            continue;
+        }
+        let upper = current.get(i).copied().flatten();
+        let gap = (i - gap_start) as f64;
+        for (j, index) in (gap_start..i).enumerate() {
+            let j = j as f64 + 1.0;
+            result[index] = match (lower, upper) {
+                (Some(lower), Some(upper)) => lower + (upper - lower) * j / (gap + 1.0),
+                (Some(lower), None) => lower + j,
+                (None, Some(upper)) => upper - (gap + 1.0 - j),
+                (None, None) => j,
+            };
+        }
+        if let Some(upper) = upper {
+            result[i] = upper;
+        }
+        lower = upper;
+        gap_start = i + 1;
+    }
+    result
+}
+
+async fn _calculate_sort_values<T: DynamoObject, B: DynamoBackendImpl>(
+    util: &DynamoUtil<B>,
+    search_id: PkSk,
+    insert_position: DynamoInsertPosition,
+    num: usize,
+    exclude: Option<&PkSk>,
+) -> Result<Vec<f64>, ServerError> {
+    // Special 'sort' field is used to order elements. Use f64 so we can always
+    // insert in between any two elements.
+    let sort_value_init = NotNan::new(1.0).unwrap();
+    let sort_value_default_gap = NotNan::new(1.0).unwrap();
+
     let query = util
-        .query::<T>(None, search_id, DynamoQueryMatchType::BeginsWith)
+        .query::<T>(None, search_id, DynamoQueryMatchType::BeginsWith, None)
         .await?;
     let existing_vals = {
         let mut v = query
             .iter()
+            .filter(|item| Some(item.id()) != exclude)
             .filter_map(|item| {
                 if let Some(Ok(sort)) = item.sort().map(NotNan::new) {
                     Some(OrderedItem {
@@ -133,6 +241,35 @@ pub(crate) async fn calculate_sort_values<T: DynamoObject, B: DynamoBackendImpl>
                     .collect(),
             }
         }
+        DynamoInsertPosition::Before(id) => {
+            let insert_before_index = existing_vals
+                .iter()
+                .position(|item| item.id == id)
+                .ok_or(DynamoInvalidOperation::new(
+                    "the ID provided in DynamoInsertPosition::Before(id) does not exist as a sorted item of type T in the database",
+                ))?;
+            let insert_before = existing_vals.get(insert_before_index).unwrap();
+            let insert_after = insert_before_index
+                .checked_sub(1)
+                .and_then(|index| existing_vals.get(index));
+            match insert_after {
+                // Insert in between two items by calculating evenly spaced
+                // values in between insert_after and insert_before.
+                Some(insert_after) => {
+                    let gap = (insert_before.sort - insert_after.sort) / (num as f64 + 1.0);
+                    (0..num)
+                        .map(|i| insert_after.sort + gap * (i as f64 + 1.0))
+                        .map(f64::from)
+                        .collect()
+                }
+                // No items before, simple insert same as ::First.
+                None => (0..num)
+                    .map(|i| insert_before.sort - sort_value_default_gap * (i as f64 + 1.0))
+                    .map(f64::from)
+                    .rev()
+                    .collect(),
+            }
+        }
     })
 }
 
@@ -196,8 +333,8 @@ mod tests {
         let mut backend = MockDynamoBackendImpl::new();
         backend
             .expect_query()
-            .withf(|_, _, _, _| true)
-            .returning(|_, _, _, _| {
+            .withf(|_, _, _, _, _, _, _, _, _, _, _| true)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
                 Ok(QueryOutput::builder()
                     .set_items(Some(vec![
                         build_dynamo_item("ROOT", "GROUP#123#TEST#1", Some(0.5)),
@@ -209,6 +346,19 @@ mod tests {
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
         let parent_id = PkSk {
@@ -239,8 +389,8 @@ mod tests {
         let mut backend = MockDynamoBackendImpl::new();
         backend
             .expect_query()
-            .withf(|_, _, _, _| true)
-            .returning(|_, _, _, _| {
+            .withf(|_, _, _, _, _, _, _, _, _, _, _| true)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
                 Ok(QueryOutput::builder()
                     .set_items(Some(vec![
                         build_dynamo_item("ROOT", "GROUP#123#TEST#1", Some(0.5)),
@@ -252,6 +402,19 @@ mod tests {
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
         let parent_id = PkSk {
@@ -282,8 +445,8 @@ mod tests {
         let mut backend = MockDynamoBackendImpl::new();
         backend
             .expect_query()
-            .withf(|_, _, _, _| true)
-            .returning(|_, _, _, _| {
+            .withf(|_, _, _, _, _, _, _, _, _, _, _| true)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
                 Ok(QueryOutput::builder()
                     .set_items(Some(vec![
                         build_dynamo_item("ROOT", "GROUP#123#TEST#1", Some(0.5)),
@@ -295,6 +458,19 @@ mod tests {
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
         let parent_id = PkSk {
@@ -330,8 +506,8 @@ mod tests {
         let mut backend = MockDynamoBackendImpl::new();
         backend
             .expect_query()
-            .withf(|_, _, _, _| true)
-            .returning(|_, _, _, _| {
+            .withf(|_, _, _, _, _, _, _, _, _, _, _| true)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
                 Ok(QueryOutput::builder()
                     .set_items(Some(vec![
                         build_dynamo_item("ROOT", "GROUP#123#TEST#1", Some(0.5)),
@@ -343,6 +519,19 @@ mod tests {
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
         let parent_id = PkSk {
@@ -373,17 +562,154 @@ mod tests {
         assert!(sort_values[0] < sort_values[1]);
     }
 
+    #[tokio::test]
+    async fn test_calculate_sort_values_before() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, _, _, _, _, _, _| true)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_dynamo_item("ROOT", "GROUP#123#TEST#1", Some(0.5)),
+                        build_dynamo_item("ROOT", "GROUP#123#TEST#2", Some(1.5)),
+                    ]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let parent_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+
+        let object = build_test_item("ROOT", "GROUP#123#TEST#3", None);
+
+        let before_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123#TEST#2".to_string(),
+        };
+
+        let sort_values = calculate_sort_values::<TestDynamoObject, _>(
+            &util,
+            parent_id,
+            &object.data,
+            DynamoInsertPosition::Before(before_id),
+            2,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(sort_values.len(), 2);
+        assert!(sort_values[0] > 0.5 && sort_values[0] < 1.5);
+        assert!(sort_values[1] > 0.5 && sort_values[1] < 1.5);
+        assert!(sort_values[0] < sort_values[1]);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_sort_values_before_first_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, _, _, _, _, _, _| true)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_dynamo_item("ROOT", "GROUP#123#TEST#1", Some(0.5)),
+                        build_dynamo_item("ROOT", "GROUP#123#TEST#2", Some(1.5)),
+                    ]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let parent_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+
+        let object = build_test_item("ROOT", "GROUP#123#TEST#3", None);
+
+        let before_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123#TEST#1".to_string(),
+        };
+
+        let sort_values = calculate_sort_values::<TestDynamoObject, _>(
+            &util,
+            parent_id,
+            &object.data,
+            DynamoInsertPosition::Before(before_id),
+            2,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(sort_values.len(), 2);
+        assert!(sort_values[0] < 0.5);
+        assert!(sort_values[1] < 0.5);
+        assert!(sort_values[0] < sort_values[1]);
+    }
+
     #[tokio::test]
     async fn test_calculate_sort_values_empty_existing_items() {
         let mut backend = MockDynamoBackendImpl::new();
         backend
             .expect_query()
-            .withf(|_, _, _, _| true)
-            .returning(|_, _, _, _| Ok(QueryOutput::builder().set_items(Some(vec![])).build()));
+            .withf(|_, _, _, _, _, _, _, _, _, _, _| true)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder().set_items(Some(vec![])).build())
+            });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
         let parent_id = PkSk {
@@ -407,6 +733,129 @@ mod tests {
         assert!(sort_values[0] < sort_values[1]);
     }
 
+    #[tokio::test]
+    async fn test_calculate_sort_value_for_move() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, _, _, _, _, _, _| true)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_dynamo_item("GROUP#123", "TEST#1", Some(0.5)),
+                        build_dynamo_item("GROUP#123", "TEST#2", Some(1.5)),
+                        build_dynamo_item("GROUP#123", "TEST#3", Some(2.5)),
+                    ]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let id = PkSk {
+            pk: "GROUP#123".to_string(),
+            sk: "TEST#2".to_string(),
+        };
+
+        // The moved item itself is ignored, so moving it after its predecessor
+        // places it between its predecessor and its successor.
+        let sort_value = calculate_sort_value_for_move::<TestDynamoObject, _>(
+            &util,
+            &id,
+            DynamoInsertPosition::After(PkSk {
+                pk: "GROUP#123".to_string(),
+                sk: "TEST#1".to_string(),
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(sort_value > 0.5 && sort_value < 2.5);
+
+        let sort_value = calculate_sort_value_for_move::<TestDynamoObject, _>(
+            &util,
+            &id,
+            DynamoInsertPosition::Last,
+        )
+        .await
+        .unwrap();
+        assert!(sort_value > 2.5);
+
+        // Can't be moved relative to itself.
+        assert!(calculate_sort_value_for_move::<TestDynamoObject, _>(
+            &util,
+            &id,
+            DynamoInsertPosition::After(id.clone()),
+        )
+        .await
+        .is_err());
+    }
+
+    #[test]
+    fn test_rebalanced_sort_values() {
+        // Values which converged after many midpoint insertions.
+        assert_eq!(
+            rebalanced_sort_values(&[0.5, 0.5000001, 0.5000002]),
+            vec![1.0, 2.0, 3.0]
+        );
+        // New values stay above the preceding item's current value, so the
+        // order is kept while the items are rewritten from last to first.
+        let current = [3.0, 7.5, 7.6, 8.0];
+        let rebalanced = rebalanced_sort_values(&current);
+        assert_eq!(rebalanced, vec![6.0, 7.0, 8.0, 9.0]);
+        for i in 1..current.len() {
+            assert!(rebalanced[i] > current[i - 1]);
+        }
+        // Resuming a partially rewritten list gives the same values, so the
+        // already rewritten items are unchanged.
+        assert_eq!(
+            rebalanced_sort_values(&[3.0, 7.5, 8.0, 9.0]),
+            vec![6.0, 7.0, 8.0, 9.0]
+        );
+        // Already balanced values are unchanged.
+        assert_eq!(
+            rebalanced_sort_values(&[1.0, 2.0, 3.0]),
+            vec![1.0, 2.0, 3.0]
+        );
+        assert!(rebalanced_sort_values(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_reordered_sort_values() {
+        // Unchanged order keeps all values.
+        assert_eq!(
+            reordered_sort_values(&[Some(1.0), Some(2.0), Some(3.0)]),
+            vec![1.0, 2.0, 3.0]
+        );
+        // Only new and out-of-order items get new values.
+        let values = reordered_sort_values(&[Some(1.0), None, Some(3.0), Some(2.0)]);
+        assert_eq!(values[0], 1.0);
+        assert_eq!(values[3], 2.0);
+        assert!(values[0] < values[1] && values[1] < values[2] && values[2] < values[3]);
+        // New items before the first and after the last kept item.
+        assert_eq!(
+            reordered_sort_values(&[None, None, Some(5.0), None]),
+            vec![3.0, 4.0, 5.0, 6.0]
+        );
+        // Only new items.
+        assert_eq!(reordered_sort_values(&[None, None]), vec![1.0, 2.0]);
+        assert!(reordered_sort_values(&[]).is_empty());
+    }
+
     #[test]
     fn test_sk_strip_uuid() {
         // We just use TestDynamoObject for all these, even though technically
@@ -0,0 +1,109 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use fractic_server_error::ServerError;
+
+use crate::errors::DynamoCancelled;
+
+/// Cooperative cancellation of long-running operations (such as scan_all,
+/// batch writes, batch_replace_all_ordered or export_table), for example to
+/// stop cleanly before a Lambda times out (see DynamoUtil::with_cancellation).
+///
+/// The token is cancelled either explicitly (with cancel, from any clone) or
+/// once its deadline passes. It is checked before each request to DynamoDB,
+/// so operations stop between pages or chunks with DynamoCancelled, rather
+/// than being interrupted mid-request. Requests already sent are completed,
+/// so multi-step operations can be left partially applied, as with any other
+/// error.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels once 'deadline' passes (in addition to explicit cancellation).
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Cancels once 'time_limit' has passed from now, for example the Lambda's
+    /// remaining time minus a safety margin.
+    pub fn with_time_limit(self, time_limit: Duration) -> Self {
+        self.with_deadline(Instant::now() + time_limit)
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Time left until the deadline, if any.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.deadline
+            .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    pub(crate) fn check(&self) -> Result<(), ServerError> {
+        if self.cancelled.load(Ordering::SeqCst) {
+            return Err(DynamoCancelled::new("operation was cancelled"));
+        }
+        if self.remaining() == Some(Duration::ZERO) {
+            return Err(DynamoCancelled::new("deadline was reached"));
+        }
+        Ok(())
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        assert!(token.check().is_ok());
+        assert_eq!(token.remaining(), None);
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert!(token.check().is_err());
+    }
+
+    #[test]
+    fn test_deadline() {
+        let token = CancellationToken::new().with_time_limit(Duration::from_secs(60));
+        assert!(!token.is_cancelled());
+        assert!(token.remaining().unwrap() > Duration::from_secs(59));
+
+        let token = CancellationToken::new().with_deadline(Instant::now());
+        assert!(token.is_cancelled());
+        assert!(token.check().is_err());
+        assert_eq!(token.remaining(), Some(Duration::ZERO));
+    }
+}
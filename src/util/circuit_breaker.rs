@@ -0,0 +1,156 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use aws_sdk_dynamodb::error::ErrorMetadata;
+use fractic_server_error::ServerError;
+
+use crate::errors::DynamoUnavailable;
+
+// Error codes returned by DynamoDB when the service itself is failing (as
+// opposed to errors caused by the request).
+const UNAVAILABLE_ERROR_CODES: [&str; 2] = ["InternalServerError", "ServiceUnavailable"];
+
+/// Stops sending requests to DynamoDB while it appears to be down, so that
+/// callers fail fast with DynamoUnavailable instead of each waiting for
+/// timeouts and retries. Shared between clones of a DynamoUtil.
+///
+/// The breaker opens after 'failure_threshold' consecutive failed requests
+/// (after retries). While open, requests are rejected without being sent.
+/// Once 'cool_down' has passed, a single request is let through: if it
+/// succeeds the breaker closes again, otherwise it stays open for another
+/// cool-down period.
+///
+/// Only timeouts, connection errors and server-side errors count as failures.
+/// Other errors (such as failed conditions or validation errors) show that
+/// DynamoDB is reachable, and reset the count.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cool_down: Duration,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    // Set while the breaker is open. Reset to the current time when a trial
+    // request is let through, so that other requests keep being rejected
+    // until it completes.
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cool_down,
+            state: Mutex::new(BreakerState::default()),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().opened_at.is_some()
+    }
+
+    // Returns DynamoUnavailable if the request should not be sent.
+    pub(crate) fn acquire(&self) -> Result<(), ServerError> {
+        let mut state = self.state.lock().unwrap();
+        match state.opened_at {
+            Some(opened_at) if opened_at.elapsed() < self.cool_down => {
+                Err(DynamoUnavailable::new(&format!(
+                    "circuit breaker is open after {} consecutive failures",
+                    state.consecutive_failures
+                )))
+            }
+            Some(_) => {
+                state.opened_at = Some(Instant::now());
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.opened_at = None;
+    }
+
+    pub(crate) fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.consecutive_failures >= self.failure_threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Whether the error indicates that DynamoDB is unavailable. Errors without a
+/// code are those where no valid response was received (such as connection
+/// failures).
+pub(crate) fn is_unavailable_error(error: &ErrorMetadata) -> bool {
+    error
+        .code()
+        .is_none_or(|code| UNAVAILABLE_ERROR_CODES.contains(&code))
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        breaker.record_failure();
+        breaker.record_failure();
+        // A success resets the count.
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+        assert!(breaker.acquire().is_ok());
+
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        assert!(breaker.acquire().is_err());
+    }
+
+    #[test]
+    fn test_trial_request_after_cool_down() {
+        let breaker = CircuitBreaker::new(1, Duration::from_millis(10));
+        breaker.record_failure();
+        assert!(breaker.acquire().is_err());
+        std::thread::sleep(Duration::from_millis(20));
+
+        // Only a single request is let through, and if it fails the breaker
+        // stays open.
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.acquire().is_err());
+        breaker.record_failure();
+        assert!(breaker.acquire().is_err());
+        std::thread::sleep(Duration::from_millis(20));
+
+        // If it succeeds, the breaker closes.
+        assert!(breaker.acquire().is_ok());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_is_unavailable_error() {
+        let error = |code: &str| ErrorMetadata::builder().code(code).build();
+        assert!(is_unavailable_error(&error("InternalServerError")));
+        assert!(is_unavailable_error(&ErrorMetadata::builder().build()));
+        assert!(!is_unavailable_error(&error(
+            "ConditionalCheckFailedException"
+        )));
+        assert!(!is_unavailable_error(&error("ThrottlingException")));
+    }
+}
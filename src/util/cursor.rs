@@ -0,0 +1,84 @@
+use fractic_server_error::ServerError;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    errors::{DynamoInvalidOperation, DynamoItemParsingError},
+    schema::parsing::{attribute_value_to_serde_value, serde_value_to_attribute_value},
+};
+
+use super::DynamoMap;
+
+/// Opaque pagination cursor, wrapping DynamoDB's LastEvaluatedKey.
+///
+/// Serializes as a plain string, so it can be handed to API clients and sent
+/// back as-is to fetch the next page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct DynamoCursor(String);
+
+impl DynamoCursor {
+    pub fn from_string(s: impl Into<String>) -> Self {
+        Self(s.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn from_last_evaluated_key(key: DynamoMap) -> Result<Self, ServerError> {
+        let json_map = key
+            .into_iter()
+            .filter_map(|(k, v)| Some((k, attribute_value_to_serde_value(v).transpose()?)))
+            .map(|(k, v)| Ok((k, v?)))
+            .collect::<Result<serde_json::Map<String, serde_json::Value>, ServerError>>()?;
+        serde_json::to_string(&json_map)
+            .map(Self)
+            .map_err(|e| DynamoItemParsingError::with_debug("failed to serialize cursor", &e))
+    }
+
+    pub(crate) fn into_exclusive_start_key(self) -> Result<DynamoMap, ServerError> {
+        let json_map: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&self.0)
+            .map_err(|e| DynamoInvalidOperation::with_debug("invalid pagination cursor", &e))?;
+        json_map
+            .into_iter()
+            .filter_map(|(k, v)| Some((k, serde_value_to_attribute_value(v).transpose()?)))
+            .map(|(k, v)| Ok((k, v?)))
+            .collect::<Result<DynamoMap, ServerError>>()
+    }
+}
+
+impl std::fmt::Display for DynamoCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use fractic_core::collection;
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let key: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("GROUP#123#TEST#1".to_string()),
+            "gsi_sort".to_string() => AttributeValue::N("42".to_string()),
+        };
+        let cursor = DynamoCursor::from_last_evaluated_key(key.clone()).unwrap();
+        let serialized = serde_json::to_string(&cursor).unwrap();
+        let deserialized: DynamoCursor = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, cursor);
+        assert_eq!(deserialized.into_exclusive_start_key().unwrap(), key);
+    }
+
+    #[test]
+    fn test_cursor_invalid() {
+        let cursor = DynamoCursor::from_string("not a cursor");
+        assert!(cursor.into_exclusive_start_key().is_err());
+    }
+}
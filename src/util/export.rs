@@ -0,0 +1,216 @@
+use aws_sdk_dynamodb::{
+    operation::export_table_to_point_in_time::ExportTableToPointInTimeError,
+    primitives::DateTime as AwsDateTime,
+    types::{ExportDescription, ExportFormat, ExportStatus},
+};
+use chrono::{DateTime, Utc};
+use fractic_server_error::ServerError;
+
+use crate::errors::{
+    DynamoCalloutError, DynamoExportFailed, DynamoInvalidOperation, DynamoNotFound, DynamoTimeout,
+};
+
+use super::{backend::DynamoBackendImpl, cancellation::CancellationToken, DynamoUtil};
+
+// Exports usually take several minutes (or hours for large tables), so there
+// is no need to poll their status often.
+const EXPORT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Destination and format of a table export (see DynamoUtil::export_table).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub s3_bucket: String,
+    /// Prefix of the exported files' keys in the bucket.
+    pub s3_prefix: Option<String>,
+    /// DynamoDB JSON by default. Amazon Ion is also supported.
+    pub format: ExportFormat,
+    /// Point in time to export, within the point-in-time recovery window.
+    /// Defaults to the current time.
+    pub export_time: Option<DateTime<Utc>>,
+}
+
+impl ExportOptions {
+    pub fn new(s3_bucket: impl Into<String>) -> Self {
+        Self {
+            s3_bucket: s3_bucket.into(),
+            s3_prefix: None,
+            format: ExportFormat::DynamodbJson,
+            export_time: None,
+        }
+    }
+
+    pub fn with_prefix(mut self, s3_prefix: impl Into<String>) -> Self {
+        self.s3_prefix = Some(s3_prefix.into());
+        self
+    }
+
+    pub fn with_format(mut self, format: ExportFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn at(mut self, export_time: DateTime<Utc>) -> Self {
+        self.export_time = Some(export_time);
+        self
+    }
+}
+
+/// Status of a table export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportProgress {
+    pub export_arn: String,
+    pub status: ExportStatus,
+    /// Number of items exported so far (only reported once completed).
+    pub item_count: Option<i64>,
+    pub billed_size_bytes: Option<i64>,
+    /// S3 key of the export's manifest file, which lists the data files.
+    pub manifest: Option<String>,
+    pub failure_message: Option<String>,
+}
+
+impl ExportProgress {
+    pub fn is_in_progress(&self) -> bool {
+        self.status == ExportStatus::InProgress
+    }
+
+    fn from_description(description: &ExportDescription) -> Result<Self, ServerError> {
+        Ok(Self {
+            export_arn: description
+                .export_arn()
+                .ok_or_else(|| DynamoCalloutError::with_debug(&"export has no ARN"))?
+                .to_string(),
+            status: description
+                .export_status()
+                .cloned()
+                .unwrap_or(ExportStatus::InProgress),
+            item_count: description.item_count(),
+            billed_size_bytes: description.billed_size_bytes(),
+            manifest: description.export_manifest().map(str::to_string),
+            failure_message: description.failure_message().map(str::to_string),
+        })
+    }
+}
+
+impl<C: DynamoBackendImpl> DynamoUtil<C> {
+    /// Exports the table to S3 using DynamoDB's native export, which reads
+    /// from the point-in-time recovery backup and so doesn't consume any read
+    /// capacity. Point-in-time recovery must be enabled on the table.
+    ///
+    /// Waits until the export completes, calling 'on_progress' with its
+    /// status after each check. Returns DynamoExportFailed if the export
+    /// fails, or DynamoTimeout if it is still in progress after 'timeout' (in
+    /// which case it keeps running, and can be checked with export_progress).
+    /// Similarly, if the DynamoUtil's cancellation token is cancelled, waiting
+    /// stops with DynamoCancelled but the export keeps running.
+    pub async fn export_table(
+        &self,
+        table: &str,
+        options: ExportOptions,
+        timeout: std::time::Duration,
+        mut on_progress: impl FnMut(&ExportProgress),
+    ) -> Result<ExportProgress, ServerError> {
+        let deadline = std::time::Instant::now() + timeout;
+        let mut progress = self.start_export(table, options).await?;
+        loop {
+            on_progress(&progress);
+            match progress.status {
+                ExportStatus::InProgress => {}
+                ExportStatus::Completed => return Ok(progress),
+                _ => {
+                    return Err(DynamoExportFailed::new(&format!(
+                        "export '{}' of table '{}' failed ({})",
+                        progress.export_arn,
+                        table,
+                        progress
+                            .failure_message
+                            .as_deref()
+                            .unwrap_or("unknown reason")
+                    )))
+                }
+            }
+            let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(DynamoTimeout::new(&format!(
+                    "export '{}' of table '{}' was still in progress after {} seconds",
+                    progress.export_arn,
+                    table,
+                    timeout.as_secs()
+                )));
+            }
+            // Wake up in time to stop cleanly if the cancellation deadline
+            // comes before the next check.
+            let cancellation_remaining = self
+                .cancellation
+                .as_ref()
+                .and_then(CancellationToken::remaining)
+                .unwrap_or(EXPORT_POLL_INTERVAL);
+            tokio::time::sleep(
+                remaining
+                    .min(EXPORT_POLL_INTERVAL)
+                    .min(cancellation_remaining),
+            )
+            .await;
+            progress = self.export_progress(&progress.export_arn).await?;
+        }
+    }
+
+    /// Starts exporting the table to S3 (see export_table), without waiting
+    /// for the export to complete.
+    pub async fn start_export(
+        &self,
+        table: &str,
+        options: ExportOptions,
+    ) -> Result<ExportProgress, ServerError> {
+        let table_arn = self
+            .describe_table(table)
+            .await?
+            .ok_or_else(DynamoNotFound::new)?
+            .table_arn
+            .ok_or_else(|| DynamoCalloutError::with_debug(&"table has no ARN"))?;
+        let output = self
+            .send(|| {
+                self.backend.export_table_to_point_in_time(
+                    table_arn.clone(),
+                    options.s3_bucket.clone(),
+                    options.s3_prefix.clone(),
+                    options.format.clone(),
+                    options
+                        .export_time
+                        .map(|time| AwsDateTime::from_millis(time.timestamp_millis())),
+                )
+            })
+            .await?
+            .map_err(|e| match e.into_service_error() {
+                ExportTableToPointInTimeError::PointInTimeRecoveryUnavailableException(_) => {
+                    DynamoInvalidOperation::new(&format!(
+                        "point-in-time recovery must be enabled on table '{}' to export it",
+                        table
+                    ))
+                }
+                ExportTableToPointInTimeError::InvalidExportTimeException(_) => {
+                    DynamoInvalidOperation::new(
+                        "export time is outside of the point-in-time recovery window",
+                    )
+                }
+                other => DynamoCalloutError::with_debug(&other),
+            })?;
+        ExportProgress::from_description(
+            output
+                .export_description()
+                .ok_or_else(|| DynamoCalloutError::with_debug(&"missing export description"))?,
+        )
+    }
+
+    /// Current status of an export started with export_table or start_export.
+    pub async fn export_progress(&self, export_arn: &str) -> Result<ExportProgress, ServerError> {
+        let output = self
+            .send(|| self.backend.describe_export(export_arn.to_string()))
+            .await?
+            .map_err(|e| DynamoCalloutError::with_debug(&e))?;
+        ExportProgress::from_description(
+            output
+                .export_description()
+                .ok_or_else(|| DynamoCalloutError::with_debug(&"missing export description"))?,
+        )
+    }
+}
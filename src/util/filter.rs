@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::types::AttributeValue;
+use fractic_server_error::ServerError;
+
+use crate::errors::DynamoInvalidOperation;
+
+use super::DynamoMap;
+
+// Max number of values in an IN condition, as supported by DynamoDB.
+const MAX_IN_VALUES: usize = 100;
+
+/// Builder for server-side query filters, applied by DynamoDB after the key
+/// condition. All conditions are joined with AND.
+///
+/// Note that filtered-out items still count towards the query limit and
+/// consumed read capacity, since DynamoDB applies filters after reading.
+///
+/// Example:
+///   FilterExpression::new()
+///       .attribute_exists("due_date")
+///       .equals("status", AttributeValue::S("OPEN".to_string()))
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FilterExpression {
+    conditions: Vec<String>,
+    attribute_names: HashMap<String, String>,
+    attribute_values: DynamoMap,
+    // Set if a condition was invalid, so that the builder methods can be
+    // chained, while the operation using the filter fails.
+    invalid: Option<String>,
+}
+
+impl FilterExpression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn attribute_exists(self, field: &str) -> Self {
+        self.push(field, None, |k, _| format!("attribute_exists({})", k))
+    }
+
+    pub fn attribute_not_exists(self, field: &str) -> Self {
+        self.push(field, None, |k, _| format!("attribute_not_exists({})", k))
+    }
+
+    pub fn equals(self, field: &str, value: AttributeValue) -> Self {
+        self.push(field, Some(value), |k, v| format!("{} = {}", k, v))
+    }
+
+    pub fn not_equals(self, field: &str, value: AttributeValue) -> Self {
+        self.push(field, Some(value), |k, v| format!("{} <> {}", k, v))
+    }
+
+    pub fn less_than(self, field: &str, value: AttributeValue) -> Self {
+        self.push(field, Some(value), |k, v| format!("{} < {}", k, v))
+    }
+
+    pub fn greater_than(self, field: &str, value: AttributeValue) -> Self {
+        self.push(field, Some(value), |k, v| format!("{} > {}", k, v))
+    }
+
+    /// For string fields, checks for a substring. For list or set fields,
+    /// checks for an element.
+    pub fn contains(self, field: &str, value: AttributeValue) -> Self {
+        self.push(field, Some(value), |k, v| format!("contains({}, {})", k, v))
+    }
+
+    pub fn begins_with(self, field: &str, value: AttributeValue) -> Self {
+        self.push(field, Some(value), |k, v| {
+            format!("begins_with({}, {})", k, v)
+        })
+    }
+
+    /// Inclusive on both ends.
+    pub fn between(self, field: &str, low: AttributeValue, high: AttributeValue) -> Self {
+        self.push_multi(field, vec![low, high], |k, v| {
+            format!("{} BETWEEN {} AND {}", k, v[0], v[1])
+        })
+    }
+
+    /// Checks that the field is equal to any of the given values. Between 1 and
+    /// 100 values are supported; otherwise, the operation using the filter
+    /// fails with DynamoInvalidOperation.
+    pub fn is_in(mut self, field: &str, values: Vec<AttributeValue>) -> Self {
+        if values.is_empty() || values.len() > MAX_IN_VALUES {
+            self.invalid.get_or_insert(format!(
+                "IN condition on '{}' must have between 1 and {} values, got {}",
+                field,
+                MAX_IN_VALUES,
+                values.len()
+            ));
+            return self;
+        }
+        self.push_multi(field, values, |k, v| format!("{} IN ({})", k, v.join(", ")))
+    }
+
+    pub fn filter(self, filter: QueryFilter) -> Self {
+        match filter {
+            QueryFilter::Eq(field, value) => self.equals(&field, value),
+            QueryFilter::Ne(field, value) => self.not_equals(&field, value),
+            QueryFilter::Contains(field, value) => self.contains(&field, value),
+            QueryFilter::AttributeExists(field) => self.attribute_exists(&field),
+            QueryFilter::AttributeNotExists(field) => self.attribute_not_exists(&field),
+            QueryFilter::Between(field, low, high) => self.between(&field, low, high),
+            QueryFilter::In(field, values) => self.is_in(&field, values),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.conditions.is_empty() && self.invalid.is_none()
+    }
+
+    // Placeholders are prefixed with 'f' to avoid conflicting with the
+    // placeholders used in the key condition.
+    fn push(
+        mut self,
+        field: &str,
+        value: Option<AttributeValue>,
+        build: impl FnOnce(&str, &str) -> String,
+    ) -> Self {
+        let idx = self.conditions.len() + 1;
+        let key_placeholder = format!("#f{}", idx);
+        let value_placeholder = format!(":f{}", idx);
+        self.attribute_names
+            .insert(key_placeholder.clone(), field.to_string());
+        if let Some(value) = value {
+            self.attribute_values
+                .insert(value_placeholder.clone(), value);
+        }
+        self.conditions
+            .push(build(&key_placeholder, &value_placeholder));
+        self
+    }
+
+    // For conditions with several values, placeholders are suffixed with the
+    // value index (ex. ':f1_1', ':f1_2').
+    fn push_multi(
+        mut self,
+        field: &str,
+        values: Vec<AttributeValue>,
+        build: impl FnOnce(&str, &[String]) -> String,
+    ) -> Self {
+        let idx = self.conditions.len() + 1;
+        let key_placeholder = format!("#f{}", idx);
+        self.attribute_names
+            .insert(key_placeholder.clone(), field.to_string());
+        let value_placeholders = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let value_placeholder = format!(":f{}_{}", idx, i + 1);
+                self.attribute_values
+                    .insert(value_placeholder.clone(), value);
+                value_placeholder
+            })
+            .collect::<Vec<_>>();
+        self.conditions
+            .push(build(&key_placeholder, &value_placeholders));
+        self
+    }
+
+    // Returns (expression, attribute_names, attribute_values), or an error if
+    // any of the conditions was invalid.
+    pub(crate) fn build(self) -> Result<(String, HashMap<String, String>, DynamoMap), ServerError> {
+        if let Some(invalid) = self.invalid {
+            return Err(DynamoInvalidOperation::new(&invalid));
+        }
+        Ok((
+            self.conditions.join(" AND "),
+            self.attribute_names,
+            self.attribute_values,
+        ))
+    }
+}
+
+/// Typed filter condition, as an alternative to the FilterExpression builder
+/// methods. A list of filters can be converted into a FilterExpression (joined
+/// with AND):
+///
+///   let filter: FilterExpression = vec![
+///       QueryFilter::AttributeExists("due_date".to_string()),
+///       QueryFilter::Ne("status".to_string(), AttributeValue::S("DONE".to_string())),
+///   ]
+///   .into();
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryFilter {
+    Eq(String, AttributeValue),
+    Ne(String, AttributeValue),
+    Contains(String, AttributeValue),
+    AttributeExists(String),
+    AttributeNotExists(String),
+    Between(String, AttributeValue, AttributeValue),
+    In(String, Vec<AttributeValue>),
+}
+
+impl From<QueryFilter> for FilterExpression {
+    fn from(filter: QueryFilter) -> Self {
+        FilterExpression::new().filter(filter)
+    }
+}
+
+impl From<Vec<QueryFilter>> for FilterExpression {
+    fn from(filters: Vec<QueryFilter>) -> Self {
+        filters.into_iter().collect()
+    }
+}
+
+impl FromIterator<QueryFilter> for FilterExpression {
+    fn from_iter<I: IntoIterator<Item = QueryFilter>>(iter: I) -> Self {
+        iter.into_iter()
+            .fold(FilterExpression::new(), FilterExpression::filter)
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fractic_core::collection;
+
+    #[test]
+    fn test_build_filter_expression() {
+        let (expression, names, values) = FilterExpression::new()
+            .attribute_exists("due_date")
+            .equals("status", AttributeValue::S("OPEN".to_string()))
+            .contains("tags", AttributeValue::S("urgent".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            expression,
+            "attribute_exists(#f1) AND #f2 = :f2 AND contains(#f3, :f3)"
+        );
+        assert_eq!(
+            names,
+            collection! {
+                "#f1".to_string() => "due_date".to_string(),
+                "#f2".to_string() => "status".to_string(),
+                "#f3".to_string() => "tags".to_string(),
+            }
+        );
+        assert_eq!(
+            values,
+            collection! {
+                ":f2".to_string() => AttributeValue::S("OPEN".to_string()),
+                ":f3".to_string() => AttributeValue::S("urgent".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let filter: FilterExpression = vec![
+            QueryFilter::Ne("status".to_string(), AttributeValue::S("DONE".to_string())),
+            QueryFilter::Between(
+                "priority".to_string(),
+                AttributeValue::N("1".to_string()),
+                AttributeValue::N("3".to_string()),
+            ),
+            QueryFilter::In(
+                "owner".to_string(),
+                vec![
+                    AttributeValue::S("a".to_string()),
+                    AttributeValue::S("b".to_string()),
+                ],
+            ),
+            QueryFilter::AttributeNotExists("deleted_at".to_string()),
+        ]
+        .into();
+        let (expression, names, values) = filter.build().unwrap();
+        assert_eq!(
+            expression,
+            "#f1 <> :f1 AND #f2 BETWEEN :f2_1 AND :f2_2 AND #f3 IN (:f3_1, :f3_2) AND attribute_not_exists(#f4)"
+        );
+        assert_eq!(names.len(), 4);
+        assert_eq!(names.get("#f3").unwrap(), "owner");
+        assert_eq!(
+            values,
+            collection! {
+                ":f1".to_string() => AttributeValue::S("DONE".to_string()),
+                ":f2_1".to_string() => AttributeValue::N("1".to_string()),
+                ":f2_2".to_string() => AttributeValue::N("3".to_string()),
+                ":f3_1".to_string() => AttributeValue::S("a".to_string()),
+                ":f3_2".to_string() => AttributeValue::S("b".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn test_empty_filter_expression() {
+        assert!(FilterExpression::new().is_empty());
+        assert!(!FilterExpression::new().attribute_exists("x").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_in() {
+        let filter = FilterExpression::new().is_in("owner", vec![]);
+        assert!(!filter.is_empty());
+        assert!(filter.build().is_err());
+        let values = (0..101)
+            .map(|i| AttributeValue::N(i.to_string()))
+            .collect::<Vec<_>>();
+        let filter = FilterExpression::new()
+            .is_in("owner", values)
+            .attribute_exists("due_date");
+        assert!(filter.build().is_err());
+    }
+}
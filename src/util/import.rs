@@ -0,0 +1,494 @@
+use std::collections::{HashMap, VecDeque};
+
+use aws_sdk_dynamodb::{primitives::Blob, types::AttributeValue};
+use fractic_server_error::ServerError;
+use futures::{Stream, StreamExt};
+
+use crate::{
+    errors::{DynamoInvalidOperation, DynamoItemParsingError},
+    schema::{binary::base64_decode, parsing::serde_value_to_attribute_value},
+};
+
+use super::{backend::DynamoBackendImpl, DynamoMap, DynamoUtil};
+
+/// Format of each line of an import (see DynamoUtil::import_items).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImportFormat {
+    /// DynamoDB JSON, as written by DynamoDB's native export to S3 (one
+    /// '{"Item": {...}}' object per line). Lines without the "Item" wrapper
+    /// are also accepted.
+    #[default]
+    DynamoDbJson,
+    /// Plain JSON objects (for example, a JSONL dump of items), converted the
+    /// same way as serialized objects. Null values are skipped.
+    PlainJson,
+}
+
+/// Options of an import (see DynamoUtil::import_items).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportOptions {
+    pub format: ImportFormat,
+    /// Number of lines to skip, to resume an interrupted import from the
+    /// last reported ImportProgress::lines_done.
+    pub resume_from: u64,
+    /// Number of items buffered and written between progress reports.
+    pub chunk_size: usize,
+    /// Limits the write rate, to avoid consuming all of the table's write
+    /// capacity (or being throttled).
+    pub max_items_per_second: Option<u32>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            format: ImportFormat::default(),
+            resume_from: 0,
+            chunk_size: 1000,
+            max_items_per_second: None,
+        }
+    }
+}
+
+impl ImportOptions {
+    pub fn with_format(mut self, format: ImportFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn resume_from(mut self, lines_done: u64) -> Self {
+        self.resume_from = lines_done;
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn with_max_items_per_second(mut self, max_items_per_second: u32) -> Self {
+        self.max_items_per_second = Some(max_items_per_second.max(1));
+        self
+    }
+}
+
+/// Progress of an import. Only reported once all items of the lines done so
+/// far have been written, so 'lines_done' can be used to resume the import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImportProgress {
+    /// Number of lines processed, including any lines skipped when resuming.
+    pub lines_done: u64,
+    /// Number of items written by this import (excluding skipped lines).
+    pub items_written: u64,
+}
+
+impl<C: DynamoBackendImpl> DynamoUtil<C> {
+    /// Bulk-writes items read from 'lines' into the table, for example to
+    /// restore a DynamoDB export (see export_table) or load a JSONL dump. Each
+    /// line holds a single item; blank lines are ignored.
+    ///
+    /// The lines can come from any source; to import an export from S3, read
+    /// the data files listed in the export's manifest (decompressing them if
+    /// needed) and stream their lines here.
+    ///
+    /// Items are written in chunks using raw_batch_put_item, interleaving the
+    /// items of each chunk by partition key so that consecutive batches are
+    /// spread across partitions rather than hitting a single hot partition.
+    /// 'on_progress' is called after each chunk is written. Items are written
+    /// as-is, so existing items with the same keys are overwritten.
+    pub async fn import_items(
+        &self,
+        lines: impl Stream<Item = std::io::Result<String>>,
+        options: ImportOptions,
+        mut on_progress: impl FnMut(&ImportProgress),
+    ) -> Result<ImportProgress, ServerError> {
+        let mut lines = std::pin::pin!(lines);
+        let start = std::time::Instant::now();
+        let mut progress = ImportProgress::default();
+        let mut chunk = Vec::with_capacity(options.chunk_size);
+        loop {
+            let line = lines.next().await.transpose().map_err(|e| {
+                DynamoInvalidOperation::with_debug("failed to read import line", &e)
+            })?;
+            let is_end = line.is_none();
+            if let Some(line) = line {
+                progress.lines_done += 1;
+                if progress.lines_done <= options.resume_from {
+                    continue;
+                }
+                if let Some(item) = parse_import_line(&line, options.format)
+                    .map_err(|e| e.with_context(progress.lines_done))?
+                {
+                    chunk.push(item);
+                }
+                if chunk.len() < options.chunk_size {
+                    continue;
+                }
+            }
+            if !chunk.is_empty() {
+                let items = interleave_by_partition(std::mem::take(&mut chunk));
+                progress.items_written += items.len() as u64;
+                self.raw_batch_put_item(items).await?;
+                on_progress(&progress);
+                if let Some(rate) = options.max_items_per_second {
+                    let target = std::time::Duration::from_secs_f64(
+                        progress.items_written as f64 / rate as f64,
+                    );
+                    tokio::time::sleep(target.saturating_sub(start.elapsed())).await;
+                }
+            }
+            if is_end {
+                return Ok(progress);
+            }
+        }
+    }
+}
+
+// Parsing.
+// --------------------------------------------------
+
+// Parsing error of a single line, before the line number is known.
+struct LineError(String);
+
+impl LineError {
+    fn with_context(self, line_number: u64) -> ServerError {
+        DynamoItemParsingError::new(&format!("invalid import line {}: {}", line_number, self.0))
+    }
+}
+
+// Returns None for blank lines.
+fn parse_import_line(line: &str, format: ImportFormat) -> Result<Option<DynamoMap>, LineError> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let value: serde_json::Value =
+        serde_json::from_str(line).map_err(|e| LineError(e.to_string()))?;
+    let serde_json::Value::Object(mut object) = value else {
+        return Err(LineError("expected a JSON object".to_string()));
+    };
+    let item = match format {
+        ImportFormat::DynamoDbJson => {
+            let object = match object.remove("Item") {
+                Some(serde_json::Value::Object(item)) if object.is_empty() => item,
+                Some(other) if object.is_empty() => {
+                    return Err(LineError(format!(
+                        "expected 'Item' to be an object: {}",
+                        other
+                    )))
+                }
+                Some(item) => {
+                    // Not the export wrapper, but an attribute named 'Item'.
+                    object.insert("Item".to_string(), item);
+                    object
+                }
+                None => object,
+            };
+            object
+                .into_iter()
+                .map(|(key, value)| Ok((key, dynamo_json_to_attribute_value(value)?)))
+                .collect::<Result<DynamoMap, LineError>>()?
+        }
+        ImportFormat::PlainJson => object
+            .into_iter()
+            .filter_map(|(key, value)| {
+                serde_value_to_attribute_value(value)
+                    .map_err(|e| LineError(e.to_string()))
+                    .transpose()
+                    .map(|value| Ok((key, value?)))
+            })
+            .collect::<Result<DynamoMap, LineError>>()?,
+    };
+    if !matches!(item.get("pk"), Some(AttributeValue::S(_)))
+        || !matches!(item.get("sk"), Some(AttributeValue::S(_)))
+    {
+        return Err(LineError("item has no 'pk' and 'sk' strings".to_string()));
+    }
+    Ok(Some(item))
+}
+
+// Converts a value in DynamoDB JSON (for example, '{"S": "text"}').
+fn dynamo_json_to_attribute_value(value: serde_json::Value) -> Result<AttributeValue, LineError> {
+    let invalid = |value: &serde_json::Value| {
+        LineError(format!("invalid DynamoDB JSON attribute value: {}", value))
+    };
+    let serde_json::Value::Object(object) = &value else {
+        return Err(invalid(&value));
+    };
+    let mut entries = object.iter();
+    let (Some((tag, inner)), None) = (entries.next(), entries.next()) else {
+        return Err(invalid(&value));
+    };
+    let string = |inner: &serde_json::Value| inner.as_str().map(str::to_string);
+    let blob = |inner: &serde_json::Value| inner.as_str().and_then(base64_decode).map(Blob::new);
+    let strings = |inner: &serde_json::Value| {
+        inner
+            .as_array()?
+            .iter()
+            .map(|s| s.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()
+    };
+    let parsed = match tag.as_str() {
+        "S" => string(inner).map(AttributeValue::S),
+        "N" => string(inner).map(AttributeValue::N),
+        "B" => blob(inner).map(AttributeValue::B),
+        "BOOL" => inner.as_bool().map(AttributeValue::Bool),
+        "NULL" => inner.as_bool().map(AttributeValue::Null),
+        "SS" => strings(inner).map(AttributeValue::Ss),
+        "NS" => strings(inner).map(AttributeValue::Ns),
+        "BS" => inner
+            .as_array()
+            .and_then(|array| array.iter().map(blob).collect::<Option<Vec<_>>>())
+            .map(AttributeValue::Bs),
+        "L" => match inner {
+            serde_json::Value::Array(array) => {
+                return Ok(AttributeValue::L(
+                    array
+                        .iter()
+                        .cloned()
+                        .map(dynamo_json_to_attribute_value)
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+            _ => None,
+        },
+        "M" => match inner {
+            serde_json::Value::Object(map) => {
+                return Ok(AttributeValue::M(
+                    map.iter()
+                        .map(|(key, value)| {
+                            Ok((key.clone(), dynamo_json_to_attribute_value(value.clone())?))
+                        })
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    parsed.ok_or_else(|| invalid(&value))
+}
+
+// Sharding.
+// --------------------------------------------------
+
+// Reorders the items round-robin across partition keys, so that each batch
+// write contains items from as many partitions as possible. Items of the same
+// partition key keep their relative order.
+fn interleave_by_partition(items: Vec<DynamoMap>) -> Vec<DynamoMap> {
+    let total = items.len();
+    let mut order: Vec<String> = Vec::new();
+    let mut partitions: HashMap<String, VecDeque<DynamoMap>> = HashMap::new();
+    for item in items {
+        let pk = match item.get("pk") {
+            Some(AttributeValue::S(pk)) => pk.clone(),
+            _ => String::new(),
+        };
+        partitions
+            .entry(pk.clone())
+            .or_insert_with(|| {
+                order.push(pk);
+                VecDeque::new()
+            })
+            .push_back(item);
+    }
+    let mut interleaved = Vec::with_capacity(total);
+    while interleaved.len() < total {
+        for pk in &order {
+            if let Some(item) = partitions.get_mut(pk).and_then(VecDeque::pop_front) {
+                interleaved.push(item);
+            }
+        }
+    }
+    interleaved
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> impl Stream<Item = std::io::Result<String>> {
+        futures::stream::iter(
+            lines
+                .iter()
+                .map(|line| Ok(line.to_string()))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    fn export_line(pk: &str, sk: &str) -> String {
+        format!(
+            r#"{{"Item":{{"pk":{{"S":"{}"}},"sk":{{"S":"{}"}},"count":{{"N":"3"}}}}}}"#,
+            pk, sk
+        )
+    }
+
+    #[test]
+    fn test_parse_dynamo_json() {
+        let item = parse_import_line(
+            r#"{"Item":{"pk":{"S":"ROOT"},"sk":{"S":"TASK#1"},"n":{"N":"1.5"},"b":{"B":"aGk="},"flag":{"BOOL":true},"none":{"NULL":true},"tags":{"SS":["a","b"]},"list":{"L":[{"S":"x"},{"M":{"k":{"N":"2"}}}]}}}"#,
+            ImportFormat::DynamoDbJson,
+        )
+        .ok()
+        .flatten()
+        .unwrap();
+        assert_eq!(item["n"], AttributeValue::N("1.5".to_string()));
+        assert_eq!(item["b"], AttributeValue::B(Blob::new(b"hi".to_vec())));
+        assert_eq!(item["flag"], AttributeValue::Bool(true));
+        assert_eq!(item["none"], AttributeValue::Null(true));
+        assert_eq!(
+            item["tags"],
+            AttributeValue::Ss(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(
+            item["list"],
+            AttributeValue::L(vec![
+                AttributeValue::S("x".to_string()),
+                AttributeValue::M(HashMap::from([(
+                    "k".to_string(),
+                    AttributeValue::N("2".to_string())
+                )])),
+            ])
+        );
+
+        // Blank lines are skipped, invalid lines are errors.
+        assert!(matches!(
+            parse_import_line("  ", ImportFormat::DynamoDbJson),
+            Ok(None)
+        ));
+        assert!(parse_import_line(r#"{"pk":"ROOT"}"#, ImportFormat::DynamoDbJson).is_err());
+        assert!(parse_import_line(r#"{"pk":{"S":"ROOT"}}"#, ImportFormat::DynamoDbJson).is_err());
+    }
+
+    #[test]
+    fn test_parse_plain_json() {
+        let item = parse_import_line(
+            r#"{"pk":"ROOT","sk":"TASK#1","count":3,"missing":null}"#,
+            ImportFormat::PlainJson,
+        )
+        .ok()
+        .flatten()
+        .unwrap();
+        assert_eq!(item["count"], AttributeValue::N("3".to_string()));
+        assert!(!item.contains_key("missing"));
+    }
+
+    #[test]
+    fn test_interleave_by_partition() {
+        let items = ["A", "A", "A", "B", "C", "C"]
+            .iter()
+            .enumerate()
+            .map(|(i, pk)| {
+                HashMap::from([
+                    ("pk".to_string(), AttributeValue::S(pk.to_string())),
+                    ("sk".to_string(), AttributeValue::S(i.to_string())),
+                ])
+            })
+            .collect();
+        let order = interleave_by_partition(items)
+            .iter()
+            .map(|item| item["sk"].as_s().unwrap().clone())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["0", "3", "4", "1", "5", "2"]);
+    }
+
+    #[tokio::test]
+    async fn test_import_items() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let input = (0..5)
+            .map(|i| export_line(&format!("P{}", i % 2), &format!("TASK#{}", i)))
+            .collect::<Vec<_>>();
+        let input = input.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let mut reports = Vec::new();
+        let progress = util
+            .import_items(
+                lines(&input),
+                ImportOptions::default().with_chunk_size(2),
+                |progress| reports.push(*progress),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            progress,
+            ImportProgress {
+                lines_done: 5,
+                items_written: 5,
+            }
+        );
+        assert_eq!(
+            reports.iter().map(|p| p.lines_done).collect::<Vec<_>>(),
+            vec![2, 4, 5]
+        );
+        assert_eq!(util.backend.items("my_table").len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_import_resume() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let input = [
+            export_line("ROOT", "TASK#1"),
+            String::new(),
+            export_line("ROOT", "TASK#2"),
+            "not json".to_string(),
+            export_line("ROOT", "TASK#3"),
+        ];
+        let input = input.iter().map(String::as_str).collect::<Vec<_>>();
+
+        // The invalid line fails the import, after the earlier lines were
+        // written.
+        let mut last = ImportProgress::default();
+        let result = util
+            .import_items(
+                lines(&input),
+                ImportOptions::default().with_chunk_size(1),
+                |progress| last = *progress,
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(last.lines_done, 3);
+        assert_eq!(util.backend.items("my_table").len(), 2);
+
+        // Resuming after the invalid line imports the rest.
+        let progress = util
+            .import_items(
+                lines(&input),
+                ImportOptions::default().resume_from(last.lines_done + 1),
+                |_| {},
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            progress,
+            ImportProgress {
+                lines_done: 5,
+                items_written: 1,
+            }
+        );
+        assert_eq!(util.backend.items("my_table").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_import_rate_limit() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let input = (0..4)
+            .map(|i| export_line("ROOT", &format!("TASK#{}", i)))
+            .collect::<Vec<_>>();
+        let input = input.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let start = std::time::Instant::now();
+        util.import_items(
+            lines(&input),
+            ImportOptions::default()
+                .with_chunk_size(2)
+                .with_max_items_per_second(40),
+            |_| {},
+        )
+        .await
+        .unwrap();
+        // 4 items at 40 items per second take at least 100ms.
+        assert!(start.elapsed() >= std::time::Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,208 @@
+use aws_sdk_dynamodb::types::AttributeValue;
+use fractic_server_error::ServerError;
+
+use crate::errors::DynamoItemTooLarge;
+
+use super::DynamoMap;
+
+/// Maximum size of a single item supported by DynamoDB (400KB), including
+/// attribute names.
+pub const MAX_ITEM_SIZE: usize = 400 * 1024;
+
+/// Maximum total size of a BatchWriteItem request (16MB).
+pub(crate) const MAX_BATCH_WRITE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Maximum number of items in a BatchWriteItem request.
+pub(crate) const MAX_BATCH_WRITE_ITEMS: usize = 25;
+
+/// Maximum number of keys in a BatchGetItem request.
+pub(crate) const MAX_BATCH_GET_ITEMS: usize = 100;
+
+/// Size estimation for DynamoDB items, following the rules in the DynamoDB
+/// developer guide ("Item sizes and formats").
+///
+/// The estimate is exact for strings, binaries, booleans and nulls, and close
+/// (within a byte per number) for numbers, so it can be used to reject items
+/// which would exceed the 400KB limit before they are sent to AWS.
+pub trait DynamoMapExt {
+    /// Estimated stored size of the item, in bytes.
+    fn estimated_size(&self) -> usize;
+}
+
+impl DynamoMapExt for DynamoMap {
+    fn estimated_size(&self) -> usize {
+        self.iter()
+            .map(|(name, value)| name.len() + attribute_value_size(value))
+            .sum()
+    }
+}
+
+fn attribute_value_size(value: &AttributeValue) -> usize {
+    match value {
+        AttributeValue::S(s) => s.len(),
+        AttributeValue::N(n) => number_size(n),
+        AttributeValue::B(b) => b.as_ref().len(),
+        AttributeValue::Bool(_) | AttributeValue::Null(_) => 1,
+        AttributeValue::Ss(values) => values.iter().map(String::len).sum(),
+        AttributeValue::Ns(values) => values.iter().map(|n| number_size(n)).sum(),
+        AttributeValue::Bs(values) => values.iter().map(|b| b.as_ref().len()).sum(),
+        AttributeValue::L(values) => {
+            3 + values
+                .iter()
+                .map(|value| 1 + attribute_value_size(value))
+                .sum::<usize>()
+        }
+        AttributeValue::M(map) => {
+            3 + map
+                .iter()
+                .map(|(name, value)| 1 + name.len() + attribute_value_size(value))
+                .sum::<usize>()
+        }
+        // Unknown variants (added in future SDK versions) are counted by their
+        // debug representation, which over-estimates rather than ignores them.
+        other => format!("{:?}", other).len(),
+    }
+}
+
+// Numbers are stored as one byte per two significant digits, plus one byte.
+// Leading and trailing zeros are not significant.
+fn number_size(n: &str) -> usize {
+    let mantissa = n.split(['e', 'E']).next().unwrap_or_default();
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let significant = digits.trim_start_matches('0').trim_end_matches('0').len();
+    significant.div_ceil(2) + 1
+}
+
+/// Returns a DynamoItemTooLarge error if the estimated item size exceeds
+/// MAX_ITEM_SIZE.
+pub(crate) fn validate_item_size(size: usize) -> Result<(), ServerError> {
+    if size > MAX_ITEM_SIZE {
+        return Err(DynamoItemTooLarge::new(&format!(
+            "estimated size is {} bytes, but at most {} bytes are supported",
+            size, MAX_ITEM_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// Splits items into chunks of at most 'max_items' items and (unless a single
+/// item is larger) 'max_size' bytes in total.
+///
+/// Used with MAX_BATCH_WRITE_ITEMS and MAX_BATCH_WRITE_SIZE to split batch
+/// writes into requests which DynamoDB accepts.
+pub(crate) fn size_limited_chunks(
+    items: &[DynamoMap],
+    max_items: usize,
+    max_size: usize,
+) -> Vec<&[DynamoMap]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut chunk_size = 0;
+    for (index, item) in items.iter().enumerate() {
+        let size = item.estimated_size();
+        if index > start && (index - start >= max_items || chunk_size + size > max_size) {
+            chunks.push(&items[start..index]);
+            start = index;
+            chunk_size = 0;
+        }
+        chunk_size += size;
+    }
+    if start < items.len() {
+        chunks.push(&items[start..]);
+    }
+    chunks
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_dynamodb::primitives::Blob;
+    use fractic_core::collection;
+
+    #[test]
+    fn test_estimated_size_scalars() {
+        let item: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "data".to_string() => AttributeValue::B(Blob::new(vec![0u8; 10])),
+            "flag".to_string() => AttributeValue::Bool(true),
+            "none".to_string() => AttributeValue::Null(true),
+        };
+        assert_eq!(
+            item.estimated_size(),
+            (2 + 4) + (4 + 10) + (4 + 1) + (4 + 1)
+        );
+    }
+
+    #[test]
+    fn test_estimated_size_numbers() {
+        assert_eq!(number_size("0"), 1);
+        assert_eq!(number_size("7"), 2);
+        assert_eq!(number_size("12"), 2);
+        assert_eq!(number_size("123"), 3);
+        assert_eq!(number_size("-1200"), 2);
+        assert_eq!(number_size("0.0015"), 2);
+        assert_eq!(number_size("1.5E10"), 2);
+    }
+
+    #[test]
+    fn test_estimated_size_nested() {
+        let item: DynamoMap = collection! {
+            "l".to_string() => AttributeValue::L(vec![
+                AttributeValue::S("ab".to_string()),
+                AttributeValue::Bool(false),
+            ]),
+            "m".to_string() => AttributeValue::M(collection! {
+                "key".to_string() => AttributeValue::S("value".to_string()),
+            }),
+            "ss".to_string() => AttributeValue::Ss(vec!["a".to_string(), "bc".to_string()]),
+        };
+        assert_eq!(
+            item.estimated_size(),
+            (1 + 3 + (1 + 2) + (1 + 1)) + (1 + 3 + (1 + 3 + 5)) + (2 + 3)
+        );
+    }
+
+    #[test]
+    fn test_validate_item_size() {
+        let item: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("x".repeat(MAX_ITEM_SIZE - 2)),
+        };
+        assert!(validate_item_size(item.estimated_size()).is_ok());
+        let item: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("x".repeat(MAX_ITEM_SIZE - 1)),
+        };
+        assert!(validate_item_size(item.estimated_size()).is_err());
+    }
+
+    #[test]
+    fn test_size_limited_chunks() {
+        let item = |len: usize| -> DynamoMap {
+            collection! {
+                "pk".to_string() => AttributeValue::S("x".repeat(len - 2)),
+            }
+        };
+        let lengths = |items: &[DynamoMap], max_items, max_size| -> Vec<usize> {
+            size_limited_chunks(items, max_items, max_size)
+                .iter()
+                .map(|chunk| chunk.len())
+                .collect()
+        };
+
+        // Split by item count.
+        let items = vec![item(10); 60];
+        assert_eq!(lengths(&items, 25, 1000), vec![25, 25, 10]);
+
+        // Split by total size.
+        let items = vec![item(100), item(300), item(700), item(100), item(100)];
+        assert_eq!(lengths(&items, 25, 1000), vec![2, 3]);
+
+        // Items larger than the limit are sent on their own.
+        let items = vec![item(100), item(2000), item(100)];
+        assert_eq!(lengths(&items, 25, 1000), vec![1, 1, 1]);
+
+        assert!(size_limited_chunks(&[], 25, 1000).is_empty());
+    }
+}
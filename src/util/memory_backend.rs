@@ -0,0 +1,1498 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+use aws_sdk_dynamodb::{
+    config::http::HttpResponse,
+    error::{ErrorMetadata, SdkError},
+    operation::{
+        batch_execute_statement::{BatchExecuteStatementError, BatchExecuteStatementOutput},
+        batch_get_item::{BatchGetItemError, BatchGetItemOutput},
+        batch_write_item::{BatchWriteItemError, BatchWriteItemOutput},
+        create_backup::{CreateBackupError, CreateBackupOutput},
+        create_table::{CreateTableError, CreateTableOutput},
+        delete_item::{DeleteItemError, DeleteItemOutput},
+        delete_table::{DeleteTableError, DeleteTableOutput},
+        describe_backup::{DescribeBackupError, DescribeBackupOutput},
+        describe_continuous_backups::{
+            DescribeContinuousBackupsError, DescribeContinuousBackupsOutput,
+        },
+        describe_export::{DescribeExportError, DescribeExportOutput},
+        describe_table::{DescribeTableError, DescribeTableOutput},
+        describe_time_to_live::{DescribeTimeToLiveError, DescribeTimeToLiveOutput},
+        execute_statement::{ExecuteStatementError, ExecuteStatementOutput},
+        export_table_to_point_in_time::{
+            ExportTableToPointInTimeError, ExportTableToPointInTimeOutput,
+        },
+        get_item::{GetItemError, GetItemOutput},
+        put_item::{PutItemError, PutItemOutput},
+        query::{QueryError, QueryOutput},
+        restore_table_from_backup::{RestoreTableFromBackupError, RestoreTableFromBackupOutput},
+        restore_table_to_point_in_time::{
+            RestoreTableToPointInTimeError, RestoreTableToPointInTimeOutput,
+        },
+        scan::{ScanError, ScanOutput},
+        transact_write_items::{TransactWriteItemsError, TransactWriteItemsOutput},
+        update_continuous_backups::{UpdateContinuousBackupsError, UpdateContinuousBackupsOutput},
+        update_item::{UpdateItemError, UpdateItemOutput},
+        update_table::{UpdateTableError, UpdateTableOutput},
+        update_time_to_live::{UpdateTimeToLiveError, UpdateTimeToLiveOutput},
+    },
+    primitives::DateTime,
+    types::{
+        error::{
+            BackupNotFoundException, ConditionalCheckFailedException,
+            PointInTimeRecoveryUnavailableException, ResourceInUseException,
+            ResourceNotFoundException, SourceTableNotFoundException, TableAlreadyExistsException,
+            TableNotFoundException, TransactionCanceledException,
+        },
+        AttributeDefinition, AttributeValue, BackupDescription, BackupDetails, BackupStatus,
+        BackupType, BillingMode, CancellationReason, ContinuousBackupsDescription,
+        ContinuousBackupsStatus, ExportFormat, GlobalSecondaryIndex,
+        GlobalSecondaryIndexDescription, GlobalSecondaryIndexUpdate, IndexStatus, KeySchemaElement,
+        KeyType, LocalSecondaryIndex, LocalSecondaryIndexDescription,
+        PointInTimeRecoveryDescription, PointInTimeRecoverySpecification,
+        PointInTimeRecoveryStatus, ReturnValue, Select, TableDescription, TableStatus,
+        TimeToLiveDescription, TimeToLiveSpecification, TimeToLiveStatus, TransactWriteItem,
+    },
+};
+
+use super::{backend::DynamoBackendImpl, DynamoMap, DynamoUtil};
+
+mod expression;
+
+use expression::{compare_values, parse_condition, parse_update, Condition, ExprResult};
+
+/// In-process backend storing items in memory, for tests which should
+/// exercise DynamoUtil end-to-end without AWS (or DynamoDB Local):
+///
+///   let dynamo_util = DynamoUtil::new_in_memory("my_table");
+///
+/// Key conditions, filter / condition expressions, update expressions,
+/// pagination, secondary indexes and transactions behave as in DynamoDB. As
+/// with DynamoUtil, all tables use string 'pk' / 'sk' keys. Tables are created
+/// automatically on first use, but indexes must be created explicitly (for
+/// example, with DynamoUtil::create_table_for_schema). PartiQL statements are
+/// not supported, and TTL settings are recorded but items never expire.
+/// On-demand backups are kept in memory, and are available immediately.
+/// Point-in-time recovery settings are recorded, but tables can only be
+/// restored to the latest restorable time (the current data).
+///
+/// Clones share the same data.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryDynamoBackend {
+    state: Arc<Mutex<MemoryState>>,
+    page_size: Option<usize>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    tables: HashMap<String, MemoryTable>,
+    transaction_tokens: HashSet<String>,
+    backups: Vec<MemoryBackup>,
+}
+
+#[derive(Debug, Clone)]
+struct MemoryBackup {
+    details: BackupDetails,
+    table: MemoryTable,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MemoryTable {
+    items: BTreeMap<(String, String), DynamoMap>,
+    global_indexes: Vec<MemoryIndex>,
+    local_indexes: Vec<MemoryIndex>,
+    ttl_attribute: Option<String>,
+    // Time point-in-time recovery was enabled at, if enabled.
+    pitr_enabled_at: Option<DateTime>,
+}
+
+#[derive(Debug, Clone)]
+struct MemoryIndex {
+    name: String,
+    key_schema: Vec<KeySchemaElement>,
+}
+
+impl MemoryDynamoBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the number of items evaluated per query / scan page (as
+    /// DynamoDB does based on the 1 MB page size), to exercise pagination in
+    /// tests.
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size.max(1));
+        self
+    }
+
+    /// All items currently stored in the table, ordered by pk and sk.
+    pub fn items(&self, table: &str) -> Vec<DynamoMap> {
+        self.state()
+            .tables
+            .get(table)
+            .map(|table| table.items.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Removes all tables and items.
+    pub fn clear(&self) {
+        *self.state() = MemoryState::default();
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state
+            .lock()
+            .expect("MemoryDynamoBackend state poisoned")
+    }
+}
+
+impl DynamoUtil<MemoryDynamoBackend> {
+    /// DynamoUtil backed by a new, empty MemoryDynamoBackend.
+    pub fn new_in_memory(table: impl Into<String>) -> Self {
+        Self::from_backend(MemoryDynamoBackend::new(), table)
+    }
+}
+
+impl MemoryState {
+    // Tables are created automatically on first use by item operations.
+    fn table(&mut self, name: &str) -> &mut MemoryTable {
+        self.tables.entry(name.to_string()).or_default()
+    }
+
+    fn backup(&self, arn: &str) -> Option<&MemoryBackup> {
+        self.backups
+            .iter()
+            .find(|backup| backup.details.backup_arn() == arn)
+    }
+}
+
+impl MemoryTable {
+    fn index(&self, name: &str) -> Option<&MemoryIndex> {
+        self.global_indexes
+            .iter()
+            .chain(&self.local_indexes)
+            .find(|index| index.name == name)
+    }
+
+    // Copy of the table's data and indexes for a restore. Restored tables
+    // don't inherit the point-in-time recovery setting.
+    fn restored(&self) -> MemoryTable {
+        MemoryTable {
+            pitr_enabled_at: None,
+            ..self.clone()
+        }
+    }
+
+    fn describe_continuous_backups(&self) -> ContinuousBackupsDescription {
+        let recovery = match &self.pitr_enabled_at {
+            Some(enabled_at) => PointInTimeRecoveryDescription::builder()
+                .point_in_time_recovery_status(PointInTimeRecoveryStatus::Enabled)
+                .earliest_restorable_date_time(*enabled_at)
+                .latest_restorable_date_time(DateTime::from(std::time::SystemTime::now())),
+            None => PointInTimeRecoveryDescription::builder()
+                .point_in_time_recovery_status(PointInTimeRecoveryStatus::Disabled),
+        };
+        ContinuousBackupsDescription::builder()
+            .continuous_backups_status(ContinuousBackupsStatus::Enabled)
+            .point_in_time_recovery_description(recovery.build())
+            .build()
+            .expect("continuous backups description should have all required fields")
+    }
+
+    fn describe(&self, name: &str) -> TableDescription {
+        TableDescription::builder()
+            .table_name(name)
+            .table_status(TableStatus::Active)
+            .set_key_schema(Some(table_key_schema()))
+            .item_count(self.items.len() as i64)
+            .set_global_secondary_indexes(Some(
+                self.global_indexes
+                    .iter()
+                    .map(|index| {
+                        GlobalSecondaryIndexDescription::builder()
+                            .index_name(&index.name)
+                            .set_key_schema(Some(index.key_schema.clone()))
+                            .index_status(IndexStatus::Active)
+                            .backfilling(false)
+                            .build()
+                    })
+                    .collect(),
+            ))
+            .set_local_secondary_indexes(Some(
+                self.local_indexes
+                    .iter()
+                    .map(|index| {
+                        LocalSecondaryIndexDescription::builder()
+                            .index_name(&index.name)
+                            .set_key_schema(Some(index.key_schema.clone()))
+                            .build()
+                    })
+                    .collect(),
+            ))
+            .build()
+    }
+}
+
+impl MemoryIndex {
+    fn key_field(&self, key_type: KeyType) -> Option<&str> {
+        self.key_schema
+            .iter()
+            .find(|element| element.key_type == key_type)
+            .map(|element| element.attribute_name.as_str())
+    }
+}
+
+// Query / scan pagination.
+// --------------------------------------------------
+
+struct Page {
+    items: Vec<DynamoMap>,
+    scanned_count: usize,
+    last_evaluated_key: Option<DynamoMap>,
+}
+
+fn compare_keys(a: &[AttributeValue], b: &[AttributeValue]) -> Ordering {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| compare_values(a, b).unwrap_or(Ordering::Equal))
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn key_values(item: &DynamoMap, key_fields: &[&str]) -> Vec<AttributeValue> {
+    key_fields
+        .iter()
+        .map(|field| {
+            item.get(*field)
+                .cloned()
+                .unwrap_or(AttributeValue::Null(true))
+        })
+        .collect()
+}
+
+// Reads a page of 'candidates', ordered by 'key_fields' (the index keys
+// followed by the table keys). As in DynamoDB, the limit applies to the number
+// of items read, before the filter is applied.
+fn read_page(
+    mut candidates: Vec<DynamoMap>,
+    key_fields: &[&str],
+    forward: bool,
+    exclusive_start_key: Option<&DynamoMap>,
+    limit: Option<usize>,
+    filter: Option<&Condition>,
+) -> ExprResult<Page> {
+    candidates.sort_by(|a, b| {
+        let ordering = compare_keys(&key_values(a, key_fields), &key_values(b, key_fields));
+        if forward {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    });
+    let start = exclusive_start_key.map(|key| key_values(key, key_fields));
+    let mut remaining = candidates.into_iter().filter(|item| match &start {
+        Some(start) => {
+            let ordering = compare_keys(&key_values(item, key_fields), start);
+            if forward {
+                ordering == Ordering::Greater
+            } else {
+                ordering == Ordering::Less
+            }
+        }
+        None => true,
+    });
+    let mut page = Page {
+        items: Vec::new(),
+        scanned_count: 0,
+        last_evaluated_key: None,
+    };
+    let mut last_item = None;
+    for item in remaining.by_ref() {
+        if filter.map_or(Ok(true), |filter| filter.evaluate(&item))? {
+            page.items.push(item.clone());
+        }
+        page.scanned_count += 1;
+        last_item = Some(item);
+        if limit.is_some_and(|limit| page.scanned_count >= limit) {
+            break;
+        }
+    }
+    if remaining.next().is_some() {
+        page.last_evaluated_key = last_item.map(|item| {
+            key_fields
+                .iter()
+                .filter_map(|field| Some((field.to_string(), item.get(*field)?.clone())))
+                .collect()
+        });
+    }
+    Ok(page)
+}
+
+// Items.
+// --------------------------------------------------
+
+fn item_key(item: &DynamoMap) -> ExprResult<(String, String)> {
+    match (item.get("pk"), item.get("sk")) {
+        (Some(AttributeValue::S(pk)), Some(AttributeValue::S(sk))) => Ok((pk.clone(), sk.clone())),
+        _ => Err("the item must have string 'pk' and 'sk' attributes".to_string()),
+    }
+}
+
+fn check_condition(
+    item: Option<&DynamoMap>,
+    condition: Option<&str>,
+    names: Option<&HashMap<String, String>>,
+    values: Option<&DynamoMap>,
+) -> ExprResult<bool> {
+    let Some(condition) = condition else {
+        return Ok(true);
+    };
+    let empty_names = HashMap::new();
+    let empty_values = DynamoMap::new();
+    let empty_item = DynamoMap::new();
+    parse_condition(
+        condition,
+        names.unwrap_or(&empty_names),
+        values.unwrap_or(&empty_values),
+    )?
+    .evaluate(item.unwrap_or(&empty_item))
+}
+
+// Applies the update expression to the item with the given key (creating it
+// if it doesn't exist), returning the old and new item.
+fn apply_update(
+    table: &mut MemoryTable,
+    key: &DynamoMap,
+    update_expression: &str,
+    names: &HashMap<String, String>,
+    values: &DynamoMap,
+    return_values: Option<&ReturnValue>,
+) -> ExprResult<Option<DynamoMap>> {
+    let key_tuple = item_key(key)?;
+    let update = parse_update(update_expression, names, values)?;
+    let old = table.items.get(&key_tuple).cloned();
+    let mut new = old.clone().unwrap_or_else(|| key.clone());
+    update.apply(&mut new)?;
+    if item_key(&new)? != key_tuple {
+        return Err("key attributes can't be updated".to_string());
+    }
+    table.items.insert(key_tuple, new.clone());
+
+    let updated = |item: DynamoMap| -> DynamoMap {
+        let attributes = update.updated_attributes();
+        item.into_iter()
+            .filter(|(name, _)| attributes.contains(&name.as_str()))
+            .collect()
+    };
+    Ok(match return_values {
+        Some(ReturnValue::AllOld) => old,
+        Some(ReturnValue::AllNew) => Some(new),
+        Some(ReturnValue::UpdatedOld) => old.map(updated),
+        Some(ReturnValue::UpdatedNew) => Some(updated(new)),
+        _ => None,
+    })
+}
+
+// Checks the condition of a transaction item against the current state.
+fn transaction_condition(state: &mut MemoryState, item: &TransactWriteItem) -> ExprResult<bool> {
+    if let Some(check) = &item.condition_check {
+        let table = state.table(&check.table_name);
+        check_condition(
+            table.items.get(&item_key(&check.key)?),
+            Some(&check.condition_expression),
+            check.expression_attribute_names.as_ref(),
+            check.expression_attribute_values.as_ref(),
+        )
+    } else if let Some(put) = &item.put {
+        let table = state.table(&put.table_name);
+        check_condition(
+            table.items.get(&item_key(&put.item)?),
+            put.condition_expression.as_deref(),
+            put.expression_attribute_names.as_ref(),
+            put.expression_attribute_values.as_ref(),
+        )
+    } else if let Some(update) = &item.update {
+        let table = state.table(&update.table_name);
+        check_condition(
+            table.items.get(&item_key(&update.key)?),
+            update.condition_expression.as_deref(),
+            update.expression_attribute_names.as_ref(),
+            update.expression_attribute_values.as_ref(),
+        )
+    } else if let Some(delete) = &item.delete {
+        let table = state.table(&delete.table_name);
+        check_condition(
+            table.items.get(&item_key(&delete.key)?),
+            delete.condition_expression.as_deref(),
+            delete.expression_attribute_names.as_ref(),
+            delete.expression_attribute_values.as_ref(),
+        )
+    } else {
+        Err("transaction item has no operation".to_string())
+    }
+}
+
+fn table_key_schema() -> Vec<KeySchemaElement> {
+    [("pk", KeyType::Hash), ("sk", KeyType::Range)]
+        .into_iter()
+        .map(|(name, key_type)| {
+            KeySchemaElement::builder()
+                .attribute_name(name)
+                .key_type(key_type)
+                .build()
+                .expect("Invalid KeySchemaElement")
+        })
+        .collect()
+}
+
+// Errors.
+// --------------------------------------------------
+
+fn service_error<E>(error: E) -> SdkError<E> {
+    SdkError::service_error(
+        error,
+        HttpResponse::new(400u16.try_into().expect("Invalid status code"), "".into()),
+    )
+}
+
+fn validation_error(message: impl Into<String>) -> ErrorMetadata {
+    ErrorMetadata::builder()
+        .code("ValidationException")
+        .message(message)
+        .build()
+}
+
+fn unsupported_error(message: &str) -> ErrorMetadata {
+    ErrorMetadata::builder()
+        .code("UnsupportedOperation")
+        .message(message)
+        .build()
+}
+
+fn condition_failed() -> ConditionalCheckFailedException {
+    ConditionalCheckFailedException::builder()
+        .message("The conditional request failed")
+        .build()
+}
+
+fn table_not_found(table: &str) -> ResourceNotFoundException {
+    ResourceNotFoundException::builder()
+        .message(format!(
+            "Requested resource not found: Table: {} not found",
+            table
+        ))
+        .build()
+}
+
+fn table_already_exists(table: &str) -> TableAlreadyExistsException {
+    TableAlreadyExistsException::builder()
+        .message(format!("Table already exists: {}", table))
+        .build()
+}
+
+fn backup_not_found(arn: &str) -> BackupNotFoundException {
+    BackupNotFoundException::builder()
+        .message(format!("Backup not found: {}", arn))
+        .build()
+}
+
+// Backend implementation.
+// --------------------------------------------------
+
+#[async_trait]
+impl DynamoBackendImpl for MemoryDynamoBackend {
+    #[allow(clippy::too_many_arguments)]
+    async fn query(
+        &self,
+        table_name: String,
+        index: Option<String>,
+        condition: String,
+        attribute_values: HashMap<String, AttributeValue>,
+        limit: Option<i32>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+        filter_expression: Option<String>,
+        attribute_names: Option<HashMap<String, String>>,
+        _consistent_read: Option<bool>,
+        scan_index_forward: Option<bool>,
+        select: Option<Select>,
+    ) -> Result<QueryOutput, SdkError<QueryError>> {
+        let mut state = self.state();
+        let table = state.table(&table_name);
+        let names = attribute_names.unwrap_or_default();
+        let page = (|| {
+            let mut key_fields = Vec::new();
+            if let Some(index) = &index {
+                let index = table
+                    .index(index)
+                    .ok_or_else(|| format!("the table does not have the index '{}'", index))?;
+                key_fields.extend(
+                    [KeyType::Hash, KeyType::Range]
+                        .into_iter()
+                        .filter_map(|key_type| index.key_field(key_type)),
+                );
+            }
+            key_fields.extend(["pk", "sk"]);
+            let key_condition = parse_condition(&condition, &names, &attribute_values)?;
+            let filter = filter_expression
+                .as_deref()
+                .map(|filter| parse_condition(filter, &names, &attribute_values))
+                .transpose()?;
+            let mut candidates = Vec::new();
+            for item in table.items.values() {
+                if key_fields.iter().all(|field| item.contains_key(*field))
+                    && key_condition.evaluate(item)?
+                {
+                    candidates.push(item.clone());
+                }
+            }
+            let limit = limit.map(|limit| limit.max(0) as usize);
+            read_page(
+                candidates,
+                &key_fields,
+                scan_index_forward.unwrap_or(true),
+                exclusive_start_key.as_ref(),
+                limit.into_iter().chain(self.page_size).min(),
+                filter.as_ref(),
+            )
+        })()
+        .map_err(|e| service_error(QueryError::generic(validation_error(e))))?;
+
+        let count = page.items.len() as i32;
+        Ok(QueryOutput::builder()
+            .set_items((select != Some(Select::Count)).then_some(page.items))
+            .count(count)
+            .scanned_count(page.scanned_count as i32)
+            .set_last_evaluated_key(page.last_evaluated_key)
+            .build())
+    }
+
+    async fn scan(
+        &self,
+        table_name: String,
+        filter_expression: Option<String>,
+        attribute_values: Option<HashMap<String, AttributeValue>>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+    ) -> Result<ScanOutput, SdkError<ScanError>> {
+        let mut state = self.state();
+        let table = state.table(&table_name);
+        let page = (|| {
+            let filter = filter_expression
+                .as_deref()
+                .map(|filter| {
+                    parse_condition(
+                        filter,
+                        &HashMap::new(),
+                        attribute_values.as_ref().unwrap_or(&DynamoMap::new()),
+                    )
+                })
+                .transpose()?;
+            read_page(
+                table.items.values().cloned().collect(),
+                &["pk", "sk"],
+                true,
+                exclusive_start_key.as_ref(),
+                self.page_size,
+                filter.as_ref(),
+            )
+        })()
+        .map_err(|e| service_error(ScanError::generic(validation_error(e))))?;
+
+        Ok(ScanOutput::builder()
+            .count(page.items.len() as i32)
+            .scanned_count(page.scanned_count as i32)
+            .set_items(Some(page.items))
+            .set_last_evaluated_key(page.last_evaluated_key)
+            .build())
+    }
+
+    async fn get_item(
+        &self,
+        table_name: String,
+        key: HashMap<String, AttributeValue>,
+        projection_expression: Option<String>,
+        _consistent_read: Option<bool>,
+    ) -> Result<GetItemOutput, SdkError<GetItemError>> {
+        let key = item_key(&key)
+            .map_err(|e| service_error(GetItemError::generic(validation_error(e))))?;
+        let mut state = self.state();
+        let item = state.table(&table_name).items.get(&key).map(|item| {
+            match &projection_expression {
+                // Only top-level attribute names are supported.
+                Some(projection) => {
+                    let attributes: Vec<&str> = projection.split(',').map(str::trim).collect();
+                    item.iter()
+                        .filter(|(name, _)| attributes.contains(&name.as_str()))
+                        .map(|(name, value)| (name.clone(), value.clone()))
+                        .collect()
+                }
+                None => item.clone(),
+            }
+        });
+        Ok(GetItemOutput::builder().set_item(item).build())
+    }
+
+    async fn batch_get_item(
+        &self,
+        table_name: String,
+        keys: Vec<HashMap<String, AttributeValue>>,
+        _consistent_read: Option<bool>,
+    ) -> Result<BatchGetItemOutput, SdkError<BatchGetItemError>> {
+        let mut state = self.state();
+        let table = state.table(&table_name);
+        let mut items = Vec::new();
+        for key in keys {
+            let key = item_key(&key)
+                .map_err(|e| service_error(BatchGetItemError::generic(validation_error(e))))?;
+            items.extend(table.items.get(&key).cloned());
+        }
+        Ok(BatchGetItemOutput::builder()
+            .set_responses(Some(HashMap::from([(table_name, items)])))
+            .build())
+    }
+
+    async fn put_item(
+        &self,
+        table_name: String,
+        item: HashMap<String, AttributeValue>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<HashMap<String, String>>,
+        expression_attribute_values: Option<HashMap<String, AttributeValue>>,
+    ) -> Result<PutItemOutput, SdkError<PutItemError>> {
+        let mut state = self.state();
+        let table = state.table(&table_name);
+        let key = item_key(&item)
+            .map_err(|e| service_error(PutItemError::generic(validation_error(e))))?;
+        let passed = check_condition(
+            table.items.get(&key),
+            condition_expression.as_deref(),
+            expression_attribute_names.as_ref(),
+            expression_attribute_values.as_ref(),
+        )
+        .map_err(|e| service_error(PutItemError::generic(validation_error(e))))?;
+        if !passed {
+            return Err(service_error(
+                PutItemError::ConditionalCheckFailedException(condition_failed()),
+            ));
+        }
+        table.items.insert(key, item);
+        Ok(PutItemOutput::builder().build())
+    }
+
+    async fn batch_put_item(
+        &self,
+        table_name: String,
+        items: Vec<HashMap<String, AttributeValue>>,
+    ) -> Result<BatchWriteItemOutput, SdkError<BatchWriteItemError>> {
+        let mut state = self.state();
+        let table = state.table(&table_name);
+        for item in items {
+            let key = item_key(&item)
+                .map_err(|e| service_error(BatchWriteItemError::generic(validation_error(e))))?;
+            table.items.insert(key, item);
+        }
+        Ok(BatchWriteItemOutput::builder().build())
+    }
+
+    async fn update_item(
+        &self,
+        table_name: String,
+        key: HashMap<String, AttributeValue>,
+        update_expression: String,
+        expression_attribute_values: HashMap<String, AttributeValue>,
+        expression_attribute_names: HashMap<String, String>,
+        condition_expression: Option<String>,
+        return_values: Option<ReturnValue>,
+    ) -> Result<UpdateItemOutput, SdkError<UpdateItemError>> {
+        let mut state = self.state();
+        let table = state.table(&table_name);
+        let validation = |e: String| service_error(UpdateItemError::generic(validation_error(e)));
+        let existing = table.items.get(&item_key(&key).map_err(validation)?);
+        let passed = check_condition(
+            existing,
+            condition_expression.as_deref(),
+            Some(&expression_attribute_names),
+            Some(&expression_attribute_values),
+        )
+        .map_err(validation)?;
+        if !passed {
+            return Err(service_error(
+                UpdateItemError::ConditionalCheckFailedException(condition_failed()),
+            ));
+        }
+        let attributes = apply_update(
+            table,
+            &key,
+            &update_expression,
+            &expression_attribute_names,
+            &expression_attribute_values,
+            return_values.as_ref(),
+        )
+        .map_err(validation)?;
+        Ok(UpdateItemOutput::builder()
+            .set_attributes(attributes)
+            .build())
+    }
+
+    async fn delete_item(
+        &self,
+        table_name: String,
+        key: HashMap<String, AttributeValue>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<HashMap<String, String>>,
+        expression_attribute_values: Option<HashMap<String, AttributeValue>>,
+        return_values: Option<ReturnValue>,
+    ) -> Result<DeleteItemOutput, SdkError<DeleteItemError>> {
+        let mut state = self.state();
+        let table = state.table(&table_name);
+        let validation = |e: String| service_error(DeleteItemError::generic(validation_error(e)));
+        let key = item_key(&key).map_err(validation)?;
+        let passed = check_condition(
+            table.items.get(&key),
+            condition_expression.as_deref(),
+            expression_attribute_names.as_ref(),
+            expression_attribute_values.as_ref(),
+        )
+        .map_err(validation)?;
+        if !passed {
+            return Err(service_error(
+                DeleteItemError::ConditionalCheckFailedException(condition_failed()),
+            ));
+        }
+        let old = table.items.remove(&key);
+        Ok(DeleteItemOutput::builder()
+            .set_attributes(old.filter(|_| return_values == Some(ReturnValue::AllOld)))
+            .build())
+    }
+
+    async fn batch_delete_item(
+        &self,
+        table_name: String,
+        keys: Vec<HashMap<String, AttributeValue>>,
+    ) -> Result<BatchWriteItemOutput, SdkError<BatchWriteItemError>> {
+        let mut state = self.state();
+        let table = state.table(&table_name);
+        for key in keys {
+            let key = item_key(&key)
+                .map_err(|e| service_error(BatchWriteItemError::generic(validation_error(e))))?;
+            table.items.remove(&key);
+        }
+        Ok(BatchWriteItemOutput::builder().build())
+    }
+
+    async fn transact_write_items(
+        &self,
+        items: Vec<TransactWriteItem>,
+        client_request_token: Option<String>,
+    ) -> Result<TransactWriteItemsOutput, SdkError<TransactWriteItemsError>> {
+        let mut state = self.state();
+        // Retries of a successful transaction (with the same token) are no-ops.
+        if let Some(token) = &client_request_token {
+            if state.transaction_tokens.contains(token) {
+                return Ok(TransactWriteItemsOutput::builder().build());
+            }
+        }
+        let validation =
+            |e: String| service_error(TransactWriteItemsError::generic(validation_error(e)));
+
+        // All conditions are checked against the state before the transaction.
+        let mut reasons = Vec::new();
+        for item in &items {
+            let passed = transaction_condition(&mut state, item).map_err(validation)?;
+            reasons.push(
+                CancellationReason::builder()
+                    .code(if passed {
+                        "None"
+                    } else {
+                        "ConditionalCheckFailed"
+                    })
+                    .build(),
+            );
+        }
+        if reasons
+            .iter()
+            .any(|reason| reason.code() == Some("ConditionalCheckFailed"))
+        {
+            return Err(service_error(
+                TransactWriteItemsError::TransactionCanceledException(
+                    TransactionCanceledException::builder()
+                        .message("Transaction cancelled")
+                        .set_cancellation_reasons(Some(reasons))
+                        .build(),
+                ),
+            ));
+        }
+
+        // Writes are applied to a copy, so that nothing is written if any of
+        // them fails.
+        let mut tables = state.tables.clone();
+        for item in items {
+            if let Some(put) = item.put {
+                let key = item_key(&put.item).map_err(validation)?;
+                tables
+                    .entry(put.table_name)
+                    .or_default()
+                    .items
+                    .insert(key, put.item);
+            } else if let Some(update) = item.update {
+                apply_update(
+                    tables.entry(update.table_name).or_default(),
+                    &update.key,
+                    &update.update_expression,
+                    &update.expression_attribute_names.unwrap_or_default(),
+                    &update.expression_attribute_values.unwrap_or_default(),
+                    None,
+                )
+                .map_err(validation)?;
+            } else if let Some(delete) = item.delete {
+                let key = item_key(&delete.key).map_err(validation)?;
+                tables
+                    .entry(delete.table_name)
+                    .or_default()
+                    .items
+                    .remove(&key);
+            }
+        }
+        state.tables = tables;
+        if let Some(token) = client_request_token {
+            state.transaction_tokens.insert(token);
+        }
+        Ok(TransactWriteItemsOutput::builder().build())
+    }
+
+    async fn execute_statement(
+        &self,
+        _statement: String,
+        _parameters: Option<Vec<AttributeValue>>,
+        _consistent_read: Option<bool>,
+        _next_token: Option<String>,
+    ) -> Result<ExecuteStatementOutput, SdkError<ExecuteStatementError>> {
+        Err(service_error(ExecuteStatementError::generic(
+            unsupported_error("PartiQL statements are not supported by MemoryDynamoBackend"),
+        )))
+    }
+
+    async fn batch_execute_statement(
+        &self,
+        _statements: Vec<(String, Option<Vec<AttributeValue>>)>,
+    ) -> Result<BatchExecuteStatementOutput, SdkError<BatchExecuteStatementError>> {
+        Err(service_error(BatchExecuteStatementError::generic(
+            unsupported_error("PartiQL statements are not supported by MemoryDynamoBackend"),
+        )))
+    }
+
+    async fn create_table(
+        &self,
+        table_name: String,
+        _key_schema: Vec<KeySchemaElement>,
+        _attribute_definitions: Vec<AttributeDefinition>,
+        global_secondary_indexes: Option<Vec<GlobalSecondaryIndex>>,
+        local_secondary_indexes: Option<Vec<LocalSecondaryIndex>>,
+        _billing_mode: BillingMode,
+    ) -> Result<CreateTableOutput, SdkError<CreateTableError>> {
+        let mut state = self.state();
+        if state.tables.contains_key(&table_name) {
+            return Err(service_error(CreateTableError::ResourceInUseException(
+                ResourceInUseException::builder()
+                    .message(format!("Table already exists: {}", table_name))
+                    .build(),
+            )));
+        }
+        let table = MemoryTable {
+            global_indexes: global_secondary_indexes
+                .unwrap_or_default()
+                .into_iter()
+                .map(|index| MemoryIndex {
+                    name: index.index_name,
+                    key_schema: index.key_schema,
+                })
+                .collect(),
+            local_indexes: local_secondary_indexes
+                .unwrap_or_default()
+                .into_iter()
+                .map(|index| MemoryIndex {
+                    name: index.index_name,
+                    key_schema: index.key_schema,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let description = table.describe(&table_name);
+        state.tables.insert(table_name, table);
+        Ok(CreateTableOutput::builder()
+            .table_description(description)
+            .build())
+    }
+
+    async fn delete_table(
+        &self,
+        table_name: String,
+    ) -> Result<DeleteTableOutput, SdkError<DeleteTableError>> {
+        match self.state().tables.remove(&table_name) {
+            Some(table) => Ok(DeleteTableOutput::builder()
+                .table_description(table.describe(&table_name))
+                .build()),
+            None => Err(service_error(DeleteTableError::ResourceNotFoundException(
+                table_not_found(&table_name),
+            ))),
+        }
+    }
+
+    async fn describe_table(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeTableOutput, SdkError<DescribeTableError>> {
+        match self.state().tables.get(&table_name) {
+            Some(table) => Ok(DescribeTableOutput::builder()
+                .table(table.describe(&table_name))
+                .build()),
+            None => Err(service_error(
+                DescribeTableError::ResourceNotFoundException(table_not_found(&table_name)),
+            )),
+        }
+    }
+
+    async fn update_table(
+        &self,
+        table_name: String,
+        _attribute_definitions: Vec<AttributeDefinition>,
+        global_secondary_index_updates: Vec<GlobalSecondaryIndexUpdate>,
+    ) -> Result<UpdateTableOutput, SdkError<UpdateTableError>> {
+        let mut state = self.state();
+        let Some(table) = state.tables.get_mut(&table_name) else {
+            return Err(service_error(UpdateTableError::ResourceNotFoundException(
+                table_not_found(&table_name),
+            )));
+        };
+        for update in global_secondary_index_updates {
+            if let Some(create) = update.create {
+                if table.index(&create.index_name).is_some() {
+                    return Err(service_error(UpdateTableError::generic(validation_error(
+                        format!("index '{}' already exists", create.index_name),
+                    ))));
+                }
+                table.global_indexes.push(MemoryIndex {
+                    name: create.index_name,
+                    key_schema: create.key_schema,
+                });
+            }
+            if let Some(delete) = update.delete {
+                table
+                    .global_indexes
+                    .retain(|index| index.name != delete.index_name);
+            }
+        }
+        Ok(UpdateTableOutput::builder()
+            .table_description(table.describe(&table_name))
+            .build())
+    }
+
+    async fn describe_time_to_live(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeTimeToLiveOutput, SdkError<DescribeTimeToLiveError>> {
+        let state = self.state();
+        let Some(table) = state.tables.get(&table_name) else {
+            return Err(service_error(
+                DescribeTimeToLiveError::ResourceNotFoundException(table_not_found(&table_name)),
+            ));
+        };
+        Ok(DescribeTimeToLiveOutput::builder()
+            .time_to_live_description(
+                TimeToLiveDescription::builder()
+                    .time_to_live_status(match table.ttl_attribute {
+                        Some(_) => TimeToLiveStatus::Enabled,
+                        None => TimeToLiveStatus::Disabled,
+                    })
+                    .set_attribute_name(table.ttl_attribute.clone())
+                    .build(),
+            )
+            .build())
+    }
+
+    async fn update_time_to_live(
+        &self,
+        table_name: String,
+        time_to_live_specification: TimeToLiveSpecification,
+    ) -> Result<UpdateTimeToLiveOutput, SdkError<UpdateTimeToLiveError>> {
+        let mut state = self.state();
+        let Some(table) = state.tables.get_mut(&table_name) else {
+            return Err(service_error(
+                UpdateTimeToLiveError::ResourceNotFoundException(table_not_found(&table_name)),
+            ));
+        };
+        if time_to_live_specification.enabled == table.ttl_attribute.is_some() {
+            return Err(service_error(UpdateTimeToLiveError::generic(
+                validation_error("TimeToLive is already in the requested state"),
+            )));
+        }
+        table.ttl_attribute = time_to_live_specification
+            .enabled
+            .then(|| time_to_live_specification.attribute_name.clone());
+        Ok(UpdateTimeToLiveOutput::builder()
+            .time_to_live_specification(time_to_live_specification)
+            .build())
+    }
+
+    async fn export_table_to_point_in_time(
+        &self,
+        _table_arn: String,
+        _s3_bucket: String,
+        _s3_prefix: Option<String>,
+        _export_format: ExportFormat,
+        _export_time: Option<DateTime>,
+    ) -> Result<ExportTableToPointInTimeOutput, SdkError<ExportTableToPointInTimeError>> {
+        Err(service_error(ExportTableToPointInTimeError::generic(
+            unsupported_error("Exports are not supported by MemoryDynamoBackend"),
+        )))
+    }
+
+    async fn describe_export(
+        &self,
+        _export_arn: String,
+    ) -> Result<DescribeExportOutput, SdkError<DescribeExportError>> {
+        Err(service_error(DescribeExportError::generic(
+            unsupported_error("Exports are not supported by MemoryDynamoBackend"),
+        )))
+    }
+
+    async fn create_backup(
+        &self,
+        table_name: String,
+        backup_name: String,
+    ) -> Result<CreateBackupOutput, SdkError<CreateBackupError>> {
+        let mut state = self.state();
+        let Some(table) = state.tables.get(&table_name).cloned() else {
+            return Err(service_error(CreateBackupError::TableNotFoundException(
+                TableNotFoundException::builder()
+                    .message(format!("Table not found: {}", table_name))
+                    .build(),
+            )));
+        };
+        let details = BackupDetails::builder()
+            .backup_arn(format!(
+                "arn:aws:dynamodb:memory:000000000000:table/{}/backup/{}",
+                table_name,
+                state.backups.len()
+            ))
+            .backup_name(backup_name)
+            .backup_status(BackupStatus::Available)
+            .backup_type(BackupType::User)
+            .backup_creation_date_time(DateTime::from(std::time::SystemTime::now()))
+            .build()
+            .expect("backup details should have all required fields");
+        state.backups.push(MemoryBackup {
+            details: details.clone(),
+            table,
+        });
+        Ok(CreateBackupOutput::builder()
+            .backup_details(details)
+            .build())
+    }
+
+    async fn describe_backup(
+        &self,
+        backup_arn: String,
+    ) -> Result<DescribeBackupOutput, SdkError<DescribeBackupError>> {
+        match self.state().backup(&backup_arn) {
+            Some(backup) => Ok(DescribeBackupOutput::builder()
+                .backup_description(
+                    BackupDescription::builder()
+                        .backup_details(backup.details.clone())
+                        .build(),
+                )
+                .build()),
+            None => Err(service_error(DescribeBackupError::BackupNotFoundException(
+                backup_not_found(&backup_arn),
+            ))),
+        }
+    }
+
+    async fn restore_table_from_backup(
+        &self,
+        target_table_name: String,
+        backup_arn: String,
+    ) -> Result<RestoreTableFromBackupOutput, SdkError<RestoreTableFromBackupError>> {
+        let mut state = self.state();
+        let Some(backup) = state.backup(&backup_arn).cloned() else {
+            return Err(service_error(
+                RestoreTableFromBackupError::BackupNotFoundException(backup_not_found(&backup_arn)),
+            ));
+        };
+        if state.tables.contains_key(&target_table_name) {
+            return Err(service_error(
+                RestoreTableFromBackupError::TableAlreadyExistsException(table_already_exists(
+                    &target_table_name,
+                )),
+            ));
+        }
+        let table = backup.table.restored();
+        let description = table.describe(&target_table_name);
+        state.tables.insert(target_table_name, table);
+        Ok(RestoreTableFromBackupOutput::builder()
+            .table_description(description)
+            .build())
+    }
+
+    async fn describe_continuous_backups(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeContinuousBackupsOutput, SdkError<DescribeContinuousBackupsError>> {
+        match self.state().tables.get(&table_name) {
+            Some(table) => Ok(DescribeContinuousBackupsOutput::builder()
+                .continuous_backups_description(table.describe_continuous_backups())
+                .build()),
+            None => Err(service_error(
+                DescribeContinuousBackupsError::TableNotFoundException(
+                    TableNotFoundException::builder()
+                        .message(format!("Table not found: {}", table_name))
+                        .build(),
+                ),
+            )),
+        }
+    }
+
+    async fn update_continuous_backups(
+        &self,
+        table_name: String,
+        point_in_time_recovery_specification: PointInTimeRecoverySpecification,
+    ) -> Result<UpdateContinuousBackupsOutput, SdkError<UpdateContinuousBackupsError>> {
+        let mut state = self.state();
+        let Some(table) = state.tables.get_mut(&table_name) else {
+            return Err(service_error(
+                UpdateContinuousBackupsError::TableNotFoundException(
+                    TableNotFoundException::builder()
+                        .message(format!("Table not found: {}", table_name))
+                        .build(),
+                ),
+            ));
+        };
+        if !point_in_time_recovery_specification.point_in_time_recovery_enabled {
+            table.pitr_enabled_at = None;
+        } else if table.pitr_enabled_at.is_none() {
+            table.pitr_enabled_at = Some(DateTime::from(std::time::SystemTime::now()));
+        }
+        Ok(UpdateContinuousBackupsOutput::builder()
+            .continuous_backups_description(table.describe_continuous_backups())
+            .build())
+    }
+
+    async fn restore_table_to_point_in_time(
+        &self,
+        source_table_name: String,
+        target_table_name: String,
+        restore_date_time: Option<DateTime>,
+    ) -> Result<RestoreTableToPointInTimeOutput, SdkError<RestoreTableToPointInTimeError>> {
+        let mut state = self.state();
+        let Some(source) = state.tables.get(&source_table_name).cloned() else {
+            return Err(service_error(
+                RestoreTableToPointInTimeError::SourceTableNotFoundException(
+                    SourceTableNotFoundException::builder()
+                        .message(format!("Source table not found: {}", source_table_name))
+                        .build(),
+                ),
+            ));
+        };
+        if source.pitr_enabled_at.is_none() {
+            return Err(service_error(
+                RestoreTableToPointInTimeError::PointInTimeRecoveryUnavailableException(
+                    PointInTimeRecoveryUnavailableException::builder()
+                        .message(format!(
+                            "Point in time recovery is not enabled for table '{}'",
+                            source_table_name
+                        ))
+                        .build(),
+                ),
+            ));
+        }
+        if restore_date_time.is_some() {
+            // Past versions of items aren't kept, so only the current data
+            // can be restored.
+            return Err(service_error(RestoreTableToPointInTimeError::generic(
+                unsupported_error(
+                    "Restoring to a specific time is not supported by MemoryDynamoBackend",
+                ),
+            )));
+        }
+        if state.tables.contains_key(&target_table_name) {
+            return Err(service_error(
+                RestoreTableToPointInTimeError::TableAlreadyExistsException(table_already_exists(
+                    &target_table_name,
+                )),
+            ));
+        }
+        let table = source.restored();
+        let description = table.describe(&target_table_name);
+        state.tables.insert(target_table_name, table);
+        Ok(RestoreTableToPointInTimeOutput::builder()
+            .table_description(description)
+            .build())
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{
+            AutoFields, DynamoObject, DynamoObjectData, IdLogic, IndexDefinition, NestingLogic,
+            PkSk,
+        },
+        util::{table_schema::TableSchema, DynamoQueryMatchType, IndexConfig},
+    };
+    use fractic_core::collection;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct NoteData {
+        owner: String,
+        text: String,
+    }
+    dynamo_object!(
+        Note,
+        NoteData,
+        "NOTE",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        indexes: vec![IndexDefinition {
+            config: IndexConfig::global("owner_index", "gsi1pk", "gsi1sk"),
+            keys: Box::new(|data: &NoteData| Some((data.owner.clone(), data.text.clone()))),
+        }]
+    );
+
+    fn note(owner: &str, text: &str) -> NoteData {
+        NoteData {
+            owner: owner.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    fn notes_prefix() -> PkSk {
+        PkSk {
+            pk: "ROOT".to_string(),
+            sk: "NOTE".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend_crud() {
+        let util = DynamoUtil::new_in_memory("my_table");
+
+        let mut created = util
+            .create_item::<Note>(PkSk::root(), note("alice", "first"), None)
+            .await
+            .unwrap();
+        let fetched = util
+            .get_item::<Note>(created.id().clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched.data(), &note("alice", "first"));
+        assert!(util
+            .item_exists::<Note>(created.id().clone())
+            .await
+            .unwrap());
+
+        created.data_mut().text = "updated".to_string();
+        util.update_item(&created).await.unwrap();
+        let fetched = util
+            .get_item::<Note>(created.id().clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(fetched.data().text, "updated");
+
+        util.delete_item::<Note>(created.id().clone())
+            .await
+            .unwrap();
+        assert!(util
+            .get_item::<Note>(created.id().clone())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(util.backend.items("my_table").is_empty());
+
+        // Updating a deleted item fails its condition.
+        assert!(util.update_item(&created).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend_query_pages() {
+        let util =
+            DynamoUtil::from_backend(MemoryDynamoBackend::new().with_page_size(2), "my_table");
+        for text in ["a", "b", "c", "d", "e"] {
+            util.create_item::<Note>(PkSk::root(), note("alice", text), None)
+                .await
+                .unwrap();
+        }
+        // Items which don't match the key condition are ignored.
+        util.backend
+            .put_item(
+                "my_table".to_string(),
+                collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S("OTHER#1".to_string()),
+                },
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        // All pages are fetched by query.
+        let notes = util
+            .query::<Note>(None, notes_prefix(), DynamoQueryMatchType::BeginsWith, None)
+            .await
+            .unwrap();
+        assert_eq!(notes.len(), 5);
+
+        let (page_1, cursor) = util
+            .query_page::<Note>(
+                None,
+                notes_prefix(),
+                DynamoQueryMatchType::BeginsWith,
+                3,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(page_1.len(), 3);
+        let (page_2, cursor) = util
+            .query_page::<Note>(
+                None,
+                notes_prefix(),
+                DynamoQueryMatchType::BeginsWith,
+                3,
+                cursor,
+            )
+            .await
+            .unwrap();
+        assert_eq!(page_2.len(), 2);
+        assert!(cursor.is_none());
+        assert!(page_2
+            .iter()
+            .all(|note| page_1.iter().all(|other| other.id() != note.id())));
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend_index_query() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        util.create_table_for_schema("my_table", &TableSchema::new().with_type::<Note>())
+            .await
+            .unwrap();
+        for (owner, text) in [("alice", "b"), ("bob", "c"), ("alice", "a")] {
+            util.create_item::<Note>(PkSk::root(), note(owner, text), None)
+                .await
+                .unwrap();
+        }
+
+        let index = IndexConfig::global("owner_index", "gsi1pk", "gsi1sk");
+        let notes = util
+            .query::<Note>(
+                Some(index),
+                PkSk {
+                    pk: "alice".to_string(),
+                    sk: String::new(),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
+            .await
+            .unwrap();
+        // Ordered by the index sort key.
+        let texts: Vec<&str> = notes.iter().map(|note| note.data().text.as_str()).collect();
+        assert_eq!(texts, vec!["a", "b"]);
+
+        // Creating the table again fails.
+        assert!(util
+            .create_table_for_schema("my_table", &TableSchema::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_backend_conditions_and_updates() {
+        let backend = MemoryDynamoBackend::new();
+        let key: DynamoMap = collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("COUNTER#1".to_string()),
+        };
+
+        // Updates create the item if it doesn't exist.
+        let output = backend
+            .update_item(
+                "my_table".to_string(),
+                key.clone(),
+                "SET #c = if_not_exists(#c, :zero) + :one".to_string(),
+                collection! {
+                    ":zero".to_string() => AttributeValue::N("0".to_string()),
+                    ":one".to_string() => AttributeValue::N("1".to_string()),
+                },
+                collection! { "#c".to_string() => "count".to_string() },
+                None,
+                Some(ReturnValue::UpdatedNew),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            output.attributes,
+            Some(collection! { "count".to_string() => AttributeValue::N("1".to_string()) })
+        );
+
+        // Failed conditions return ConditionalCheckFailedException.
+        let error = backend
+            .put_item(
+                "my_table".to_string(),
+                key.clone(),
+                Some("attribute_not_exists(pk)".to_string()),
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.into_service_error(),
+            PutItemError::ConditionalCheckFailedException(_)
+        ));
+
+        // Key attributes can't be updated.
+        assert!(backend
+            .update_item(
+                "my_table".to_string(),
+                key.clone(),
+                "SET sk = :sk".to_string(),
+                collection! { ":sk".to_string() => AttributeValue::S("OTHER".to_string()) },
+                HashMap::new(),
+                None,
+                None,
+            )
+            .await
+            .is_err());
+
+        let output = backend
+            .delete_item(
+                "my_table".to_string(),
+                key.clone(),
+                Some("#c = :one".to_string()),
+                Some(collection! { "#c".to_string() => "count".to_string() }),
+                Some(collection! { ":one".to_string() => AttributeValue::N("1".to_string()) }),
+                Some(ReturnValue::AllOld),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            output.attributes.unwrap().get("count"),
+            Some(&AttributeValue::N("1".to_string()))
+        );
+        assert!(backend.items("my_table").is_empty());
+    }
+}
@@ -0,0 +1,981 @@
+use std::{cmp::Ordering, collections::HashMap};
+
+use aws_sdk_dynamodb::types::AttributeValue;
+
+use crate::util::DynamoMap;
+
+// Parsing and evaluation of DynamoDB expressions (key conditions, condition /
+// filter expressions and update expressions), as used by MemoryDynamoBackend.
+//
+// Placeholders are resolved while parsing, so errors for missing names or
+// values are reported even if the expression would not otherwise be
+// evaluated. Errors are returned as messages, which the backend reports as
+// ValidationExceptions (like DynamoDB does).
+
+pub(super) type ExprResult<T> = Result<T, String>;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Name(String),
+    Value(String),
+    Number(usize),
+    Symbol(&'static str),
+}
+
+fn tokenize(expression: &str) -> ExprResult<Vec<Token>> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let word = |start: usize| {
+        let mut end = start;
+        while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+            end += 1;
+        }
+        (chars[start..end].iter().collect::<String>(), end)
+    };
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        if let Some(symbol) = ["<>", "<=", ">="].into_iter().find(|s| *s == two) {
+            tokens.push(Token::Symbol(symbol));
+            i += 2;
+            continue;
+        }
+        if let Some(symbol) = ["(", ")", ",", ".", "[", "]", "=", "<", ">", "+", "-"]
+            .into_iter()
+            .find(|s| s.starts_with(c))
+        {
+            tokens.push(Token::Symbol(symbol));
+            i += 1;
+            continue;
+        }
+        match c {
+            '#' | ':' => {
+                let (name, end) = word(i + 1);
+                if name.is_empty() {
+                    return Err(format!("invalid placeholder at position {}", i));
+                }
+                tokens.push(if c == '#' {
+                    Token::Name(format!("#{}", name))
+                } else {
+                    Token::Value(format!(":{}", name))
+                });
+                i = end;
+            }
+            c if c.is_ascii_digit() => {
+                let (digits, end) = word(i);
+                tokens.push(Token::Number(
+                    digits
+                        .parse()
+                        .map_err(|_| format!("invalid number '{}'", digits))?,
+                ));
+                i = end;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let (ident, end) = word(i);
+                tokens.push(Token::Ident(ident));
+                i = end;
+            }
+            other => return Err(format!("unexpected character '{}'", other)),
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum PathElement {
+    Attribute(String),
+    Index(usize),
+}
+
+pub(super) type Path = Vec<PathElement>;
+
+#[derive(Debug, Clone)]
+pub(super) enum Operand {
+    Path(Path),
+    Value(AttributeValue),
+    Size(Path),
+    IfNotExists(Path, Box<Operand>),
+    ListAppend(Box<Operand>, Box<Operand>),
+    Plus(Box<Operand>, Box<Operand>),
+    Minus(Box<Operand>, Box<Operand>),
+}
+
+#[derive(Debug, Clone)]
+pub(super) enum Condition {
+    Compare(Operand, &'static str, Operand),
+    Between(Operand, Operand, Operand),
+    In(Operand, Vec<Operand>),
+    Exists(Path),
+    NotExists(Path),
+    AttributeType(Path, Operand),
+    BeginsWith(Operand, Operand),
+    Contains(Operand, Operand),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct Update {
+    set: Vec<(Path, Operand)>,
+    remove: Vec<Path>,
+    add: Vec<(Path, Operand)>,
+    delete: Vec<(Path, Operand)>,
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    names: &'a HashMap<String, String>,
+    values: &'a DynamoMap,
+}
+
+impl<'a> Parser<'a> {
+    fn new(
+        expression: &str,
+        names: &'a HashMap<String, String>,
+        values: &'a DynamoMap,
+    ) -> ExprResult<Self> {
+        Ok(Self {
+            tokens: tokenize(expression)?,
+            position: 0,
+            names,
+            values,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.position + offset)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i.eq_ignore_ascii_case(keyword))
+    }
+
+    fn is_function(&self, function: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(i)) if i == function)
+            && matches!(self.peek_at(1), Some(Token::Symbol("(")))
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> ExprResult<()> {
+        match self.next() {
+            Some(Token::Symbol(s)) if s == symbol => Ok(()),
+            other => Err(format!("expected '{}', found {:?}", symbol, other)),
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> ExprResult<()> {
+        match self.next() {
+            Some(Token::Ident(i)) if i.eq_ignore_ascii_case(keyword) => Ok(()),
+            other => Err(format!("expected '{}', found {:?}", keyword, other)),
+        }
+    }
+
+    fn expect_end(&self) -> ExprResult<()> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    fn attribute_name(&mut self) -> ExprResult<String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            Some(Token::Name(placeholder)) => self
+                .names
+                .get(&placeholder)
+                .cloned()
+                .ok_or_else(|| format!("undefined attribute name '{}'", placeholder)),
+            other => Err(format!("expected attribute name, found {:?}", other)),
+        }
+    }
+
+    fn path(&mut self) -> ExprResult<Path> {
+        let mut path = vec![PathElement::Attribute(self.attribute_name()?)];
+        loop {
+            if self.is_symbol(".") {
+                self.next();
+                path.push(PathElement::Attribute(self.attribute_name()?));
+            } else if self.is_symbol("[") {
+                self.next();
+                match self.next() {
+                    Some(Token::Number(index)) => path.push(PathElement::Index(index)),
+                    other => return Err(format!("expected list index, found {:?}", other)),
+                }
+                self.expect_symbol("]")?;
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn value(&mut self) -> ExprResult<AttributeValue> {
+        match self.next() {
+            Some(Token::Value(placeholder)) => self
+                .values
+                .get(&placeholder)
+                .cloned()
+                .ok_or_else(|| format!("undefined attribute value '{}'", placeholder)),
+            other => Err(format!("expected attribute value, found {:?}", other)),
+        }
+    }
+
+    // Operand in a condition: a path, a value, or size(path).
+    fn operand(&mut self) -> ExprResult<Operand> {
+        if self.is_function("size") {
+            self.next();
+            self.expect_symbol("(")?;
+            let path = self.path()?;
+            self.expect_symbol(")")?;
+            return Ok(Operand::Size(path));
+        }
+        match self.peek() {
+            Some(Token::Value(_)) => Ok(Operand::Value(self.value()?)),
+            _ => Ok(Operand::Path(self.path()?)),
+        }
+    }
+
+    // Value in a SET action: an operand, optionally followed by + or -.
+    fn set_value(&mut self) -> ExprResult<Operand> {
+        let left = self.set_operand()?;
+        if self.is_symbol("+") {
+            self.next();
+            Ok(Operand::Plus(Box::new(left), Box::new(self.set_operand()?)))
+        } else if self.is_symbol("-") {
+            self.next();
+            Ok(Operand::Minus(
+                Box::new(left),
+                Box::new(self.set_operand()?),
+            ))
+        } else {
+            Ok(left)
+        }
+    }
+
+    fn set_operand(&mut self) -> ExprResult<Operand> {
+        if self.is_function("if_not_exists") {
+            self.next();
+            self.expect_symbol("(")?;
+            let path = self.path()?;
+            self.expect_symbol(",")?;
+            let default = self.set_value()?;
+            self.expect_symbol(")")?;
+            return Ok(Operand::IfNotExists(path, Box::new(default)));
+        }
+        if self.is_function("list_append") {
+            self.next();
+            self.expect_symbol("(")?;
+            let first = self.set_value()?;
+            self.expect_symbol(",")?;
+            let second = self.set_value()?;
+            self.expect_symbol(")")?;
+            return Ok(Operand::ListAppend(Box::new(first), Box::new(second)));
+        }
+        self.operand()
+    }
+
+    fn condition(&mut self) -> ExprResult<Condition> {
+        let mut condition = self.and_condition()?;
+        while self.is_keyword("OR") {
+            self.next();
+            condition = Condition::Or(Box::new(condition), Box::new(self.and_condition()?));
+        }
+        Ok(condition)
+    }
+
+    fn and_condition(&mut self) -> ExprResult<Condition> {
+        let mut condition = self.not_condition()?;
+        while self.is_keyword("AND") {
+            self.next();
+            condition = Condition::And(Box::new(condition), Box::new(self.not_condition()?));
+        }
+        Ok(condition)
+    }
+
+    fn not_condition(&mut self) -> ExprResult<Condition> {
+        if self.is_keyword("NOT") {
+            self.next();
+            return Ok(Condition::Not(Box::new(self.not_condition()?)));
+        }
+        self.primary_condition()
+    }
+
+    fn primary_condition(&mut self) -> ExprResult<Condition> {
+        if self.is_symbol("(") {
+            self.next();
+            let condition = self.condition()?;
+            self.expect_symbol(")")?;
+            return Ok(condition);
+        }
+        for function in [
+            "attribute_exists",
+            "attribute_not_exists",
+            "attribute_type",
+            "begins_with",
+            "contains",
+        ] {
+            if !self.is_function(function) {
+                continue;
+            }
+            self.next();
+            self.expect_symbol("(")?;
+            let condition = match function {
+                "attribute_exists" => Condition::Exists(self.path()?),
+                "attribute_not_exists" => Condition::NotExists(self.path()?),
+                "attribute_type" => {
+                    let path = self.path()?;
+                    self.expect_symbol(",")?;
+                    Condition::AttributeType(path, self.operand()?)
+                }
+                _ => {
+                    let first = self.operand()?;
+                    self.expect_symbol(",")?;
+                    let second = self.operand()?;
+                    if function == "begins_with" {
+                        Condition::BeginsWith(first, second)
+                    } else {
+                        Condition::Contains(first, second)
+                    }
+                }
+            };
+            self.expect_symbol(")")?;
+            return Ok(condition);
+        }
+        let left = self.operand()?;
+        if self.is_keyword("BETWEEN") {
+            self.next();
+            let low = self.operand()?;
+            self.expect_keyword("AND")?;
+            let high = self.operand()?;
+            return Ok(Condition::Between(left, low, high));
+        }
+        if self.is_keyword("IN") {
+            self.next();
+            self.expect_symbol("(")?;
+            let mut options = vec![self.operand()?];
+            while self.is_symbol(",") {
+                self.next();
+                options.push(self.operand()?);
+            }
+            self.expect_symbol(")")?;
+            return Ok(Condition::In(left, options));
+        }
+        match self.next() {
+            Some(Token::Symbol(comparator @ ("=" | "<>" | "<" | "<=" | ">" | ">="))) => {
+                Ok(Condition::Compare(left, comparator, self.operand()?))
+            }
+            other => Err(format!("expected comparator, found {:?}", other)),
+        }
+    }
+
+    fn update(&mut self) -> ExprResult<Update> {
+        let mut update = Update::default();
+        if self.peek().is_none() {
+            return Err("empty update expression".to_string());
+        }
+        while let Some(token) = self.next() {
+            let clause = match token {
+                Token::Ident(clause) => clause.to_ascii_uppercase(),
+                other => return Err(format!("expected update clause, found {:?}", other)),
+            };
+            loop {
+                match clause.as_str() {
+                    "SET" => {
+                        let path = self.path()?;
+                        self.expect_symbol("=")?;
+                        update.set.push((path, self.set_value()?));
+                    }
+                    "REMOVE" => update.remove.push(self.path()?),
+                    "ADD" => {
+                        let path = self.path()?;
+                        update.add.push((path, self.operand()?));
+                    }
+                    "DELETE" => {
+                        let path = self.path()?;
+                        update.delete.push((path, self.operand()?));
+                    }
+                    other => return Err(format!("unknown update clause '{}'", other)),
+                }
+                if !self.is_symbol(",") {
+                    break;
+                }
+                self.next();
+            }
+        }
+        Ok(update)
+    }
+}
+
+pub(super) fn parse_condition(
+    expression: &str,
+    names: &HashMap<String, String>,
+    values: &DynamoMap,
+) -> ExprResult<Condition> {
+    let mut parser = Parser::new(expression, names, values)?;
+    let condition = parser.condition()?;
+    parser.expect_end()?;
+    Ok(condition)
+}
+
+pub(super) fn parse_update(
+    expression: &str,
+    names: &HashMap<String, String>,
+    values: &DynamoMap,
+) -> ExprResult<Update> {
+    Parser::new(expression, names, values)?.update()
+}
+
+// Evaluation.
+// --------------------------------------------------
+
+impl Condition {
+    pub(super) fn evaluate(&self, item: &DynamoMap) -> ExprResult<bool> {
+        Ok(match self {
+            Condition::Compare(left, comparator, right) => {
+                let (left, right) = (left.evaluate(item)?, right.evaluate(item)?);
+                match (left, right, *comparator) {
+                    // Attributes which don't exist are never equal to anything.
+                    (Some(l), Some(r), "=") => values_equal(&l, &r),
+                    (Some(l), Some(r), "<>") => !values_equal(&l, &r),
+                    (_, _, "<>") => true,
+                    (Some(l), Some(r), comparator) => match compare_values(&l, &r) {
+                        Some(ordering) => match comparator {
+                            "<" => ordering == Ordering::Less,
+                            "<=" => ordering != Ordering::Greater,
+                            ">" => ordering == Ordering::Greater,
+                            _ => ordering != Ordering::Less,
+                        },
+                        None => false,
+                    },
+                    _ => false,
+                }
+            }
+            Condition::Between(value, low, high) => {
+                match (
+                    value.evaluate(item)?,
+                    low.evaluate(item)?,
+                    high.evaluate(item)?,
+                ) {
+                    (Some(v), Some(l), Some(h)) => {
+                        compare_values(&v, &l).is_some_and(|o| o != Ordering::Less)
+                            && compare_values(&v, &h).is_some_and(|o| o != Ordering::Greater)
+                    }
+                    _ => false,
+                }
+            }
+            Condition::In(value, options) => match value.evaluate(item)? {
+                Some(v) => {
+                    let mut found = false;
+                    for option in options {
+                        if option
+                            .evaluate(item)?
+                            .is_some_and(|option| values_equal(&v, &option))
+                        {
+                            found = true;
+                        }
+                    }
+                    found
+                }
+                None => false,
+            },
+            Condition::Exists(path) => resolve_path(item, path).is_some(),
+            Condition::NotExists(path) => resolve_path(item, path).is_none(),
+            Condition::AttributeType(path, type_name) => {
+                match (resolve_path(item, path), type_name.evaluate(item)?) {
+                    (Some(value), Some(AttributeValue::S(type_name))) => {
+                        type_code(value) == type_name
+                    }
+                    _ => false,
+                }
+            }
+            Condition::BeginsWith(value, prefix) => {
+                match (value.evaluate(item)?, prefix.evaluate(item)?) {
+                    (Some(AttributeValue::S(v)), Some(AttributeValue::S(p))) => v.starts_with(&p),
+                    (Some(AttributeValue::B(v)), Some(AttributeValue::B(p))) => {
+                        v.as_ref().starts_with(p.as_ref())
+                    }
+                    _ => false,
+                }
+            }
+            Condition::Contains(value, operand) => {
+                match (value.evaluate(item)?, operand.evaluate(item)?) {
+                    (Some(AttributeValue::S(v)), Some(AttributeValue::S(o))) => v.contains(&o),
+                    (Some(AttributeValue::Ss(set)), Some(AttributeValue::S(o))) => set.contains(&o),
+                    (Some(AttributeValue::Ns(set)), Some(o @ AttributeValue::N(_))) => set
+                        .iter()
+                        .any(|n| values_equal(&AttributeValue::N(n.clone()), &o)),
+                    (Some(AttributeValue::Bs(set)), Some(AttributeValue::B(o))) => set.contains(&o),
+                    (Some(AttributeValue::L(list)), Some(o)) => {
+                        list.iter().any(|element| values_equal(element, &o))
+                    }
+                    _ => false,
+                }
+            }
+            Condition::And(left, right) => left.evaluate(item)? && right.evaluate(item)?,
+            Condition::Or(left, right) => left.evaluate(item)? || right.evaluate(item)?,
+            Condition::Not(condition) => !condition.evaluate(item)?,
+        })
+    }
+}
+
+impl Operand {
+    // Evaluates the operand, returning None if it refers to a missing
+    // attribute.
+    fn evaluate(&self, item: &DynamoMap) -> ExprResult<Option<AttributeValue>> {
+        Ok(match self {
+            Operand::Path(path) => resolve_path(item, path).cloned(),
+            Operand::Value(value) => Some(value.clone()),
+            Operand::Size(path) => match resolve_path(item, path) {
+                Some(value) => Some(AttributeValue::N(value_size(value)?.to_string())),
+                None => None,
+            },
+            Operand::IfNotExists(path, default) => match resolve_path(item, path) {
+                Some(value) => Some(value.clone()),
+                None => default.evaluate(item)?,
+            },
+            Operand::ListAppend(first, second) => {
+                match (first.evaluate(item)?, second.evaluate(item)?) {
+                    (Some(AttributeValue::L(mut first)), Some(AttributeValue::L(second))) => {
+                        first.extend(second);
+                        Some(AttributeValue::L(first))
+                    }
+                    _ => return Err("list_append requires two lists".to_string()),
+                }
+            }
+            Operand::Plus(left, right) | Operand::Minus(left, right) => {
+                let (left, right) = match (left.evaluate(item)?, right.evaluate(item)?) {
+                    (Some(AttributeValue::N(l)), Some(AttributeValue::N(r))) => (l, r),
+                    _ => return Err("arithmetic requires two existing numbers".to_string()),
+                };
+                let negate = matches!(self, Operand::Minus(..));
+                Some(AttributeValue::N(add_numbers(&left, &right, negate)?))
+            }
+        })
+    }
+}
+
+impl Update {
+    // Applies the update to 'item'. All values are computed from the item as it
+    // was before the update.
+    pub(super) fn apply(&self, item: &mut DynamoMap) -> ExprResult<()> {
+        let original = item.clone();
+        for (path, value) in &self.set {
+            let value = value
+                .evaluate(&original)?
+                .ok_or_else(|| "SET value refers to a missing attribute".to_string())?;
+            set_path(item, path, value)?;
+        }
+        for path in &self.remove {
+            remove_path(item, path);
+        }
+        for (path, value) in &self.add {
+            let value = value
+                .evaluate(&original)?
+                .ok_or_else(|| "ADD value refers to a missing attribute".to_string())?;
+            let new_value = match (resolve_path(&original, path), value) {
+                (
+                    None,
+                    value @ (AttributeValue::N(_)
+                    | AttributeValue::Ss(_)
+                    | AttributeValue::Ns(_)
+                    | AttributeValue::Bs(_)),
+                ) => value,
+                (Some(AttributeValue::N(current)), AttributeValue::N(delta)) => {
+                    AttributeValue::N(add_numbers(current, &delta, false)?)
+                }
+                (Some(AttributeValue::Ss(current)), AttributeValue::Ss(values)) => {
+                    AttributeValue::Ss(set_union(current, values))
+                }
+                (Some(AttributeValue::Ns(current)), AttributeValue::Ns(values)) => {
+                    AttributeValue::Ns(set_union(current, values))
+                }
+                (Some(AttributeValue::Bs(current)), AttributeValue::Bs(values)) => {
+                    AttributeValue::Bs(set_union(current, values))
+                }
+                _ => return Err("ADD requires a number or a set of the same type".to_string()),
+            };
+            set_path(item, path, new_value)?;
+        }
+        for (path, value) in &self.delete {
+            let value = value
+                .evaluate(&original)?
+                .ok_or_else(|| "DELETE value refers to a missing attribute".to_string())?;
+            let remaining = match (resolve_path(&original, path), value) {
+                (None, _) => continue,
+                (Some(AttributeValue::Ss(current)), AttributeValue::Ss(values)) => {
+                    set_difference(current, &values).map(AttributeValue::Ss)
+                }
+                (Some(AttributeValue::Ns(current)), AttributeValue::Ns(values)) => {
+                    set_difference(current, &values).map(AttributeValue::Ns)
+                }
+                (Some(AttributeValue::Bs(current)), AttributeValue::Bs(values)) => {
+                    set_difference(current, &values).map(AttributeValue::Bs)
+                }
+                _ => return Err("DELETE requires a set of the same type".to_string()),
+            };
+            match remaining {
+                Some(remaining) => set_path(item, path, remaining)?,
+                None => remove_path(item, path),
+            }
+        }
+        Ok(())
+    }
+
+    // Top-level attributes written by the update (for UPDATED_* return values).
+    pub(super) fn updated_attributes(&self) -> Vec<&str> {
+        self.set
+            .iter()
+            .map(|(path, _)| path)
+            .chain(&self.remove)
+            .chain(self.add.iter().map(|(path, _)| path))
+            .chain(self.delete.iter().map(|(path, _)| path))
+            .filter_map(|path| match path.first() {
+                Some(PathElement::Attribute(name)) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn resolve_path<'a>(item: &'a DynamoMap, path: &Path) -> Option<&'a AttributeValue> {
+    let mut elements = path.iter();
+    let mut current = match elements.next()? {
+        PathElement::Attribute(name) => item.get(name)?,
+        PathElement::Index(_) => return None,
+    };
+    for element in elements {
+        current = match (element, current) {
+            (PathElement::Attribute(name), AttributeValue::M(map)) => map.get(name)?,
+            (PathElement::Index(index), AttributeValue::L(list)) => list.get(*index)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn resolve_path_mut<'a>(
+    item: &'a mut DynamoMap,
+    path: &[PathElement],
+) -> Option<&'a mut AttributeValue> {
+    let (first, rest) = path.split_first()?;
+    let mut current = match first {
+        PathElement::Attribute(name) => item.get_mut(name)?,
+        PathElement::Index(_) => return None,
+    };
+    for element in rest {
+        current = match (element, current) {
+            (PathElement::Attribute(name), AttributeValue::M(map)) => map.get_mut(name)?,
+            (PathElement::Index(index), AttributeValue::L(list)) => list.get_mut(*index)?,
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn set_path(item: &mut DynamoMap, path: &Path, value: AttributeValue) -> ExprResult<()> {
+    let (last, parent) = path.split_last().ok_or("empty path")?;
+    if parent.is_empty() {
+        return match last {
+            PathElement::Attribute(name) => {
+                item.insert(name.clone(), value);
+                Ok(())
+            }
+            PathElement::Index(_) => Err("path must start with an attribute name".to_string()),
+        };
+    }
+    match (resolve_path_mut(item, parent), last) {
+        (Some(AttributeValue::M(map)), PathElement::Attribute(name)) => {
+            map.insert(name.clone(), value);
+            Ok(())
+        }
+        (Some(AttributeValue::L(list)), PathElement::Index(index)) => {
+            // Indexes past the end of the list append to it.
+            match list.get_mut(*index) {
+                Some(element) => *element = value,
+                None => list.push(value),
+            }
+            Ok(())
+        }
+        _ => Err("the document path provided in the update expression is invalid".to_string()),
+    }
+}
+
+fn remove_path(item: &mut DynamoMap, path: &Path) {
+    let Some((last, parent)) = path.split_last() else {
+        return;
+    };
+    if parent.is_empty() {
+        if let PathElement::Attribute(name) = last {
+            item.remove(name);
+        }
+        return;
+    }
+    match (resolve_path_mut(item, parent), last) {
+        (Some(AttributeValue::M(map)), PathElement::Attribute(name)) => {
+            map.remove(name);
+        }
+        (Some(AttributeValue::L(list)), PathElement::Index(index)) if *index < list.len() => {
+            list.remove(*index);
+        }
+        _ => {}
+    }
+}
+
+fn value_size(value: &AttributeValue) -> ExprResult<usize> {
+    Ok(match value {
+        AttributeValue::S(s) => s.len(),
+        AttributeValue::B(b) => b.as_ref().len(),
+        AttributeValue::Ss(set) => set.len(),
+        AttributeValue::Ns(set) => set.len(),
+        AttributeValue::Bs(set) => set.len(),
+        AttributeValue::L(list) => list.len(),
+        AttributeValue::M(map) => map.len(),
+        _ => return Err("size() is not supported for this attribute type".to_string()),
+    })
+}
+
+fn type_code(value: &AttributeValue) -> &'static str {
+    match value {
+        AttributeValue::S(_) => "S",
+        AttributeValue::N(_) => "N",
+        AttributeValue::B(_) => "B",
+        AttributeValue::Bool(_) => "BOOL",
+        AttributeValue::Null(_) => "NULL",
+        AttributeValue::Ss(_) => "SS",
+        AttributeValue::Ns(_) => "NS",
+        AttributeValue::Bs(_) => "BS",
+        AttributeValue::L(_) => "L",
+        AttributeValue::M(_) => "M",
+        _ => "",
+    }
+}
+
+// Numbers are compared exactly if both are integers, and as floats otherwise.
+fn compare_numbers(a: &str, b: &str) -> Option<Ordering> {
+    match (a.parse::<i128>(), b.parse::<i128>()) {
+        (Ok(a), Ok(b)) => Some(a.cmp(&b)),
+        _ => a.parse::<f64>().ok()?.partial_cmp(&b.parse::<f64>().ok()?),
+    }
+}
+
+fn add_numbers(a: &str, b: &str, negate: bool) -> ExprResult<String> {
+    let invalid = || format!("invalid number operands '{}' and '{}'", a, b);
+    match (a.parse::<i128>(), b.parse::<i128>()) {
+        (Ok(a), Ok(b)) => Ok(if negate { a - b } else { a + b }.to_string()),
+        _ => {
+            let a = a.parse::<f64>().map_err(|_| invalid())?;
+            let b = b.parse::<f64>().map_err(|_| invalid())?;
+            Ok(if negate { a - b } else { a + b }.to_string())
+        }
+    }
+}
+
+// Orders two values of the same scalar type. Values of different (or
+// non-scalar) types can't be ordered.
+pub(super) fn compare_values(a: &AttributeValue, b: &AttributeValue) -> Option<Ordering> {
+    match (a, b) {
+        (AttributeValue::S(a), AttributeValue::S(b)) => Some(a.as_bytes().cmp(b.as_bytes())),
+        (AttributeValue::N(a), AttributeValue::N(b)) => compare_numbers(a, b),
+        (AttributeValue::B(a), AttributeValue::B(b)) => Some(a.as_ref().cmp(b.as_ref())),
+        _ => None,
+    }
+}
+
+fn values_equal(a: &AttributeValue, b: &AttributeValue) -> bool {
+    match (a, b) {
+        (AttributeValue::N(a), AttributeValue::N(b)) => {
+            compare_numbers(a, b) == Some(Ordering::Equal)
+        }
+        (AttributeValue::Ss(a), AttributeValue::Ss(b)) => same_elements(a, b),
+        (AttributeValue::Ns(a), AttributeValue::Ns(b)) => {
+            a.len() == b.len()
+                && a.iter().all(|x| {
+                    b.iter()
+                        .any(|y| compare_numbers(x, y) == Some(Ordering::Equal))
+                })
+        }
+        (AttributeValue::Bs(a), AttributeValue::Bs(b)) => same_elements(a, b),
+        (AttributeValue::L(a), AttributeValue::L(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(x, y)| values_equal(x, y))
+        }
+        (AttributeValue::M(a), AttributeValue::M(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, x)| b.get(key).is_some_and(|y| values_equal(x, y)))
+        }
+        (a, b) => a == b,
+    }
+}
+
+fn same_elements<T: PartialEq>(a: &[T], b: &[T]) -> bool {
+    a.len() == b.len() && a.iter().all(|x| b.contains(x))
+}
+
+fn set_union<T: PartialEq + Clone>(current: &[T], values: Vec<T>) -> Vec<T> {
+    let mut union = current.to_vec();
+    for value in values {
+        if !union.contains(&value) {
+            union.push(value);
+        }
+    }
+    union
+}
+
+// Returns None if no elements remain, since empty sets can't be stored.
+fn set_difference<T: PartialEq + Clone>(current: &[T], values: &[T]) -> Option<Vec<T>> {
+    let remaining: Vec<T> = current
+        .iter()
+        .filter(|value| !values.contains(value))
+        .cloned()
+        .collect();
+    (!remaining.is_empty()).then_some(remaining)
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fractic_core::collection;
+
+    fn s(value: &str) -> AttributeValue {
+        AttributeValue::S(value.to_string())
+    }
+
+    fn n(value: &str) -> AttributeValue {
+        AttributeValue::N(value.to_string())
+    }
+
+    fn item() -> DynamoMap {
+        collection! {
+            "pk".to_string() => s("ROOT"),
+            "sk".to_string() => s("TASK#123"),
+            "count".to_string() => n("5"),
+            "tags".to_string() => AttributeValue::Ss(vec!["a".to_string(), "b".to_string()]),
+            "nested".to_string() => AttributeValue::M(collection! {
+                "list".to_string() => AttributeValue::L(vec![s("x")]),
+            }),
+        }
+    }
+
+    fn evaluate(expression: &str, values: DynamoMap) -> bool {
+        let names = collection! {
+            "#c".to_string() => "count".to_string(),
+            "#sk".to_string() => "sk".to_string(),
+        };
+        parse_condition(expression, &names, &values)
+            .unwrap()
+            .evaluate(&item())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_conditions() {
+        assert!(evaluate("attribute_exists(pk)", DynamoMap::new()));
+        assert!(!evaluate("attribute_not_exists(pk)", DynamoMap::new()));
+        assert!(evaluate(
+            "attribute_exists(nested.list[0])",
+            DynamoMap::new()
+        ));
+        assert!(evaluate(
+            "pk = :pk AND begins_with(#sk, :prefix)",
+            collection! { ":pk".to_string() => s("ROOT"), ":prefix".to_string() => s("TASK#") }
+        ));
+        assert!(evaluate(
+            "#c BETWEEN :low AND :high",
+            collection! { ":low".to_string() => n("5"), ":high".to_string() => n("10") }
+        ));
+        assert!(!evaluate(
+            "#c > :v OR contains(tags, :tag)",
+            collection! { ":v".to_string() => n("5"), ":tag".to_string() => s("c") }
+        ));
+        assert!(evaluate(
+            "NOT (#c IN (:a, :b)) AND size(tags) = :two",
+            collection! {
+                ":a".to_string() => n("1"),
+                ":b".to_string() => n("2"),
+                ":two".to_string() => n("2"),
+            }
+        ));
+        // Missing attributes are not equal to anything.
+        assert!(evaluate(
+            "missing <> :v AND NOT missing = :v",
+            collection! { ":v".to_string() => n("1") }
+        ));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        let names = HashMap::new();
+        let values = DynamoMap::new();
+        assert!(parse_condition("pk = :missing", &names, &values).is_err());
+        assert!(parse_condition("#missing = pk", &names, &values).is_err());
+        assert!(parse_condition("pk = ", &names, &values).is_err());
+        assert!(parse_condition("pk = sk sk", &names, &values).is_err());
+        assert!(parse_update("", &names, &values).is_err());
+    }
+
+    #[test]
+    fn test_update() {
+        let names = collection! { "#c".to_string() => "count".to_string() };
+        let values = collection! {
+            ":one".to_string() => n("1"),
+            ":tags".to_string() => AttributeValue::Ss(vec!["a".to_string()]),
+            ":new".to_string() => AttributeValue::Ss(vec!["c".to_string()]),
+            ":list".to_string() => AttributeValue::L(vec![s("y")]),
+            ":empty".to_string() => AttributeValue::L(vec![]),
+        };
+        let update = parse_update(
+            "SET #c = #c + :one, nested.list = list_append(nested.list, :list), \
+             other = list_append(if_not_exists(other, :empty), :list) \
+             REMOVE sk DELETE tags :tags ADD tags :new, total :one",
+            &names,
+            &values,
+        )
+        .unwrap();
+        let mut item = item();
+        update.apply(&mut item).unwrap();
+
+        assert_eq!(item.get("count"), Some(&n("6")));
+        assert_eq!(
+            item.get("nested"),
+            Some(&AttributeValue::M(collection! {
+                "list".to_string() => AttributeValue::L(vec![s("x"), s("y")]),
+            }))
+        );
+        assert_eq!(item.get("other"), Some(&AttributeValue::L(vec![s("y")])));
+        assert_eq!(item.get("sk"), None);
+        assert_eq!(item.get("total"), Some(&n("1")));
+        // ADD and DELETE both apply to the original set.
+        assert_eq!(
+            item.get("tags"),
+            Some(&AttributeValue::Ss(vec![
+                "a".to_string(),
+                "b".to_string(),
+                "c".to_string()
+            ]))
+        );
+        assert!(update.updated_attributes().contains(&"count"));
+    }
+}
@@ -0,0 +1,165 @@
+use aws_sdk_dynamodb::types::ConsumedCapacity;
+
+use crate::schema::id_calculations::{get_object_type, get_pk_sk_from_map};
+
+use super::DynamoMap;
+
+/// Hook receiving the capacity consumed by DynamoUtil's requests (see
+/// DynamoUtil::with_metrics), for example to export it as metrics and see
+/// which object types use the most capacity.
+///
+/// Called synchronously after each request, so implementations should be
+/// cheap (such as incrementing counters).
+pub trait DynamoMetrics: std::fmt::Debug + Send + Sync {
+    fn record_consumed_capacity(&self, record: &ConsumedCapacityRecord);
+}
+
+/// Capacity consumed by a single request, for a single table.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsumedCapacityRecord {
+    /// DynamoDB operation, such as "Query" or "PutItem".
+    pub operation: &'static str,
+    pub table: String,
+    /// Object label (id_label) of the items read or written, or None if the
+    /// request involved several types of objects (or none at all).
+    pub label: Option<String>,
+    pub read_capacity_units: f64,
+    pub write_capacity_units: f64,
+}
+
+fn is_write_operation(operation: &str) -> bool {
+    matches!(
+        operation,
+        "PutItem"
+            | "UpdateItem"
+            | "DeleteItem"
+            | "BatchWriteItem"
+            | "TransactWriteItems"
+            | "BatchExecuteStatement"
+    )
+}
+
+// The label shared by all items, if any.
+fn common_label<'a>(items: impl IntoIterator<Item = &'a DynamoMap>) -> Option<String> {
+    let mut label = None;
+    for item in items {
+        let (pk, sk) = get_pk_sk_from_map(item).ok()?;
+        let item_label = get_object_type(pk, sk).ok()?;
+        match label {
+            None => label = Some(item_label),
+            Some(label) if label != item_label => return None,
+            Some(_) => {}
+        }
+    }
+    label.map(str::to_string)
+}
+
+/// Reports the capacity consumed by a request. 'items' are the keys or items
+/// read or written, used to determine the object label.
+///
+/// Depending on the table's billing mode, DynamoDB may only return the total
+/// capacity units, which are then counted as read or write units based on the
+/// operation.
+pub(crate) fn report_consumed_capacity<'a>(
+    metrics: &dyn DynamoMetrics,
+    operation: &'static str,
+    capacity: impl IntoIterator<Item = &'a ConsumedCapacity>,
+    items: impl IntoIterator<Item = &'a DynamoMap>,
+) {
+    let mut capacity = capacity.into_iter().peekable();
+    if capacity.peek().is_none() {
+        return;
+    }
+    let label = common_label(items);
+    for capacity in capacity {
+        let total = capacity.capacity_units.unwrap_or_default();
+        let (read, write) = match (capacity.read_capacity_units, capacity.write_capacity_units) {
+            (None, None) if is_write_operation(operation) => (0.0, total),
+            (None, None) => (total, 0.0),
+            (read, write) => (read.unwrap_or_default(), write.unwrap_or_default()),
+        };
+        metrics.record_consumed_capacity(&ConsumedCapacityRecord {
+            operation,
+            table: capacity.table_name.clone().unwrap_or_default(),
+            label: label.clone(),
+            read_capacity_units: read,
+            write_capacity_units: write,
+        });
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use fractic_core::collection;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct RecordingMetrics {
+        records: Mutex<Vec<ConsumedCapacityRecord>>,
+    }
+
+    impl DynamoMetrics for RecordingMetrics {
+        fn record_consumed_capacity(&self, record: &ConsumedCapacityRecord) {
+            self.records.lock().unwrap().push(record.clone());
+        }
+    }
+
+    fn key(sk: &str) -> DynamoMap {
+        collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S(sk.to_string()),
+        }
+    }
+
+    fn capacity(units: f64) -> ConsumedCapacity {
+        ConsumedCapacity::builder()
+            .table_name("my_table")
+            .capacity_units(units)
+            .build()
+    }
+
+    #[test]
+    fn test_report_consumed_capacity() {
+        let metrics = RecordingMetrics::default();
+        let keys = [key("TASK#1"), key("TASK#2")];
+        report_consumed_capacity(&metrics, "Query", Some(&capacity(0.5)), &keys);
+        report_consumed_capacity(&metrics, "PutItem", Some(&capacity(1.0)), &keys[..1]);
+        let mixed = [key("TASK#1"), key("NOTE#1")];
+        report_consumed_capacity(&metrics, "BatchGetItem", &[capacity(2.0)], &mixed);
+        // Nothing is reported if no capacity was returned.
+        report_consumed_capacity(&metrics, "GetItem", None::<&ConsumedCapacity>, &keys);
+
+        let records = metrics.records.lock().unwrap();
+        assert_eq!(
+            *records,
+            vec![
+                ConsumedCapacityRecord {
+                    operation: "Query",
+                    table: "my_table".to_string(),
+                    label: Some("TASK".to_string()),
+                    read_capacity_units: 0.5,
+                    write_capacity_units: 0.0,
+                },
+                ConsumedCapacityRecord {
+                    operation: "PutItem",
+                    table: "my_table".to_string(),
+                    label: Some("TASK".to_string()),
+                    read_capacity_units: 0.0,
+                    write_capacity_units: 1.0,
+                },
+                ConsumedCapacityRecord {
+                    operation: "BatchGetItem",
+                    table: "my_table".to_string(),
+                    label: None,
+                    read_capacity_units: 2.0,
+                    write_capacity_units: 0.0,
+                },
+            ]
+        );
+    }
+}
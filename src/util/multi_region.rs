@@ -0,0 +1,601 @@
+use std::{collections::HashMap, future::Future};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_dynamodb::{
+    error::{ProvideErrorMetadata, SdkError},
+    operation::{
+        batch_execute_statement::{BatchExecuteStatementError, BatchExecuteStatementOutput},
+        batch_get_item::{BatchGetItemError, BatchGetItemOutput},
+        batch_write_item::{BatchWriteItemError, BatchWriteItemOutput},
+        create_backup::{CreateBackupError, CreateBackupOutput},
+        create_table::{CreateTableError, CreateTableOutput},
+        delete_item::{DeleteItemError, DeleteItemOutput},
+        delete_table::{DeleteTableError, DeleteTableOutput},
+        describe_backup::{DescribeBackupError, DescribeBackupOutput},
+        describe_continuous_backups::{
+            DescribeContinuousBackupsError, DescribeContinuousBackupsOutput,
+        },
+        describe_export::{DescribeExportError, DescribeExportOutput},
+        describe_table::{DescribeTableError, DescribeTableOutput},
+        describe_time_to_live::{DescribeTimeToLiveError, DescribeTimeToLiveOutput},
+        execute_statement::{ExecuteStatementError, ExecuteStatementOutput},
+        export_table_to_point_in_time::{
+            ExportTableToPointInTimeError, ExportTableToPointInTimeOutput,
+        },
+        get_item::{GetItemError, GetItemOutput},
+        put_item::{PutItemError, PutItemOutput},
+        query::{QueryError, QueryOutput},
+        restore_table_from_backup::{RestoreTableFromBackupError, RestoreTableFromBackupOutput},
+        restore_table_to_point_in_time::{
+            RestoreTableToPointInTimeError, RestoreTableToPointInTimeOutput,
+        },
+        scan::{ScanError, ScanOutput},
+        transact_write_items::{TransactWriteItemsError, TransactWriteItemsOutput},
+        update_continuous_backups::{UpdateContinuousBackupsError, UpdateContinuousBackupsOutput},
+        update_item::{UpdateItemError, UpdateItemOutput},
+        update_table::{UpdateTableError, UpdateTableOutput},
+        update_time_to_live::{UpdateTimeToLiveError, UpdateTimeToLiveOutput},
+    },
+    primitives::DateTime,
+    types::{
+        AttributeDefinition, AttributeValue, BillingMode, ExportFormat, GlobalSecondaryIndex,
+        GlobalSecondaryIndexUpdate, KeySchemaElement, LocalSecondaryIndex,
+        PointInTimeRecoverySpecification, ReturnValue, Select, TimeToLiveSpecification,
+        TransactWriteItem,
+    },
+};
+use fractic_env_config::EnvVariables;
+use fractic_server_error::ServerError;
+
+use crate::env::DynamoEnvConfig;
+
+use super::{
+    backend::DynamoBackendImpl, circuit_breaker::is_unavailable_error, table_router::TableNaming,
+    DynamoMap, DynamoUtil,
+};
+
+/// Attribute recording the region an item was last written from. With global
+/// tables, concurrent writes in different regions are resolved by keeping the
+/// last write, so this shows which region's write was kept.
+pub const UPDATED_REGION_ATTRIBUTE: &str = "updated_region";
+
+/// Backend for global tables, which sends requests to a primary region, and
+/// falls back to a replica region for reads if the primary region fails (with
+/// a timeout, connection error or server-side error). Since replication is
+/// asynchronous, reads from the replica may not reflect the latest writes,
+/// even if they are strongly consistent.
+///
+/// Writes are always sent to the primary region, and are tagged with the
+/// primary region's name (see UPDATED_REGION_ATTRIBUTE): put items are tagged
+/// by this backend, while updates are tagged by DynamoUtil as part of the update
+/// expression (see DynamoUtil::with_updated_region, which new_multi_region
+/// sets). PartiQL statements are not tagged, and table management operations
+/// (create_table, etc.) only apply to the primary region.
+///
+///   let dynamo_util = DynamoUtil::new_multi_region(env, "my_table", "eu-west-1").await?;
+#[derive(Debug, Clone)]
+pub struct MultiRegionBackend<B: DynamoBackendImpl> {
+    primary: B,
+    primary_region: String,
+    fallback: B,
+    fallback_region: String,
+    read_failover: bool,
+}
+
+impl<B: DynamoBackendImpl + Send + Sync> MultiRegionBackend<B> {
+    pub fn new(
+        primary: B,
+        primary_region: impl Into<String>,
+        fallback: B,
+        fallback_region: impl Into<String>,
+    ) -> Self {
+        Self {
+            primary,
+            primary_region: primary_region.into(),
+            fallback,
+            fallback_region: fallback_region.into(),
+            read_failover: true,
+        }
+    }
+
+    /// Enables or disables falling back to the replica region for reads
+    /// (enabled by default). If disabled, the fallback region is unused, but
+    /// writes are still tagged with the primary region.
+    pub fn with_read_failover(mut self, read_failover: bool) -> Self {
+        self.read_failover = read_failover;
+        self
+    }
+
+    pub fn primary_region(&self) -> &str {
+        &self.primary_region
+    }
+
+    pub fn fallback_region(&self) -> &str {
+        &self.fallback_region
+    }
+
+    // Runs 'op' against the primary region, and retries it against the
+    // fallback region if the primary region failed.
+    async fn read<'a, T, E, F, Fut>(&'a self, op: F) -> Result<T, SdkError<E>>
+    where
+        F: Fn(&'a B) -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E>>>,
+        E: ProvideErrorMetadata,
+    {
+        match op(&self.primary).await {
+            Err(e) if self.read_failover && is_unavailable_error(e.meta()) => {
+                op(&self.fallback).await
+            }
+            result => result,
+        }
+    }
+
+    fn tag_item(&self, item: &mut DynamoMap) {
+        item.insert(
+            UPDATED_REGION_ATTRIBUTE.to_string(),
+            AttributeValue::S(self.primary_region.clone()),
+        );
+    }
+}
+
+impl DynamoUtil<MultiRegionBackend<aws_sdk_dynamodb::Client>> {
+    /// Connects to a global table, using the region from the environment as
+    /// the primary region, and 'fallback_region' as the replica to read from
+    /// if the primary region fails (see MultiRegionBackend).
+    pub async fn new_multi_region(
+        env: EnvVariables<DynamoEnvConfig>,
+        table: impl Into<String>,
+        fallback_region: impl Into<String>,
+    ) -> Result<Self, ServerError> {
+        let primary_region = env.get(&DynamoEnvConfig::DynamoRegion)?.clone();
+        let fallback_region = fallback_region.into();
+        Ok(Self::from_backend(
+            MultiRegionBackend::new(
+                client_for_region(&primary_region).await,
+                primary_region.clone(),
+                client_for_region(&fallback_region).await,
+                fallback_region,
+            ),
+            table,
+        )
+        .with_table_naming(TableNaming::from_env(&env))
+        .with_updated_region(primary_region))
+    }
+}
+
+async fn client_for_region(region: &str) -> aws_sdk_dynamodb::Client {
+    let shared_config = aws_config::defaults(BehaviorVersion::v2024_03_28())
+        .region(Region::new(region.to_string()))
+        .load()
+        .await;
+    aws_sdk_dynamodb::Client::new(&shared_config)
+}
+
+#[async_trait]
+impl<B: DynamoBackendImpl + Send + Sync> DynamoBackendImpl for MultiRegionBackend<B> {
+    #[allow(clippy::too_many_arguments)]
+    async fn query(
+        &self,
+        table_name: String,
+        index: Option<String>,
+        condition: String,
+        attribute_values: HashMap<String, AttributeValue>,
+        limit: Option<i32>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+        filter_expression: Option<String>,
+        attribute_names: Option<HashMap<String, String>>,
+        consistent_read: Option<bool>,
+        scan_index_forward: Option<bool>,
+        select: Option<Select>,
+    ) -> Result<QueryOutput, SdkError<QueryError>> {
+        self.read(|backend| {
+            backend.query(
+                table_name.clone(),
+                index.clone(),
+                condition.clone(),
+                attribute_values.clone(),
+                limit,
+                exclusive_start_key.clone(),
+                filter_expression.clone(),
+                attribute_names.clone(),
+                consistent_read,
+                scan_index_forward,
+                select.clone(),
+            )
+        })
+        .await
+    }
+
+    async fn scan(
+        &self,
+        table_name: String,
+        filter_expression: Option<String>,
+        attribute_values: Option<HashMap<String, AttributeValue>>,
+        exclusive_start_key: Option<HashMap<String, AttributeValue>>,
+    ) -> Result<ScanOutput, SdkError<ScanError>> {
+        self.read(|backend| {
+            backend.scan(
+                table_name.clone(),
+                filter_expression.clone(),
+                attribute_values.clone(),
+                exclusive_start_key.clone(),
+            )
+        })
+        .await
+    }
+
+    async fn get_item(
+        &self,
+        table_name: String,
+        key: HashMap<String, AttributeValue>,
+        projection_expression: Option<String>,
+        consistent_read: Option<bool>,
+    ) -> Result<GetItemOutput, SdkError<GetItemError>> {
+        self.read(|backend| {
+            backend.get_item(
+                table_name.clone(),
+                key.clone(),
+                projection_expression.clone(),
+                consistent_read,
+            )
+        })
+        .await
+    }
+
+    async fn batch_get_item(
+        &self,
+        table_name: String,
+        keys: Vec<HashMap<String, AttributeValue>>,
+        consistent_read: Option<bool>,
+    ) -> Result<BatchGetItemOutput, SdkError<BatchGetItemError>> {
+        self.read(|backend| {
+            backend.batch_get_item(table_name.clone(), keys.clone(), consistent_read)
+        })
+        .await
+    }
+
+    async fn put_item(
+        &self,
+        table_name: String,
+        mut item: HashMap<String, AttributeValue>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<HashMap<String, String>>,
+        expression_attribute_values: Option<HashMap<String, AttributeValue>>,
+    ) -> Result<PutItemOutput, SdkError<PutItemError>> {
+        self.tag_item(&mut item);
+        self.primary
+            .put_item(
+                table_name,
+                item,
+                condition_expression,
+                expression_attribute_names,
+                expression_attribute_values,
+            )
+            .await
+    }
+
+    async fn batch_put_item(
+        &self,
+        table_name: String,
+        mut items: Vec<HashMap<String, AttributeValue>>,
+    ) -> Result<BatchWriteItemOutput, SdkError<BatchWriteItemError>> {
+        for item in &mut items {
+            self.tag_item(item);
+        }
+        self.primary.batch_put_item(table_name, items).await
+    }
+
+    async fn update_item(
+        &self,
+        table_name: String,
+        key: HashMap<String, AttributeValue>,
+        update_expression: String,
+        expression_attribute_values: HashMap<String, AttributeValue>,
+        expression_attribute_names: HashMap<String, String>,
+        condition_expression: Option<String>,
+        return_values: Option<ReturnValue>,
+    ) -> Result<UpdateItemOutput, SdkError<UpdateItemError>> {
+        self.primary
+            .update_item(
+                table_name,
+                key,
+                update_expression,
+                expression_attribute_values,
+                expression_attribute_names,
+                condition_expression,
+                return_values,
+            )
+            .await
+    }
+
+    async fn delete_item(
+        &self,
+        table_name: String,
+        key: HashMap<String, AttributeValue>,
+        condition_expression: Option<String>,
+        expression_attribute_names: Option<HashMap<String, String>>,
+        expression_attribute_values: Option<HashMap<String, AttributeValue>>,
+        return_values: Option<ReturnValue>,
+    ) -> Result<DeleteItemOutput, SdkError<DeleteItemError>> {
+        self.primary
+            .delete_item(
+                table_name,
+                key,
+                condition_expression,
+                expression_attribute_names,
+                expression_attribute_values,
+                return_values,
+            )
+            .await
+    }
+
+    async fn batch_delete_item(
+        &self,
+        table_name: String,
+        keys: Vec<HashMap<String, AttributeValue>>,
+    ) -> Result<BatchWriteItemOutput, SdkError<BatchWriteItemError>> {
+        self.primary.batch_delete_item(table_name, keys).await
+    }
+
+    async fn transact_write_items(
+        &self,
+        mut items: Vec<TransactWriteItem>,
+        client_request_token: Option<String>,
+    ) -> Result<TransactWriteItemsOutput, SdkError<TransactWriteItemsError>> {
+        for item in &mut items {
+            if let Some(put) = &mut item.put {
+                self.tag_item(&mut put.item);
+            }
+        }
+        self.primary
+            .transact_write_items(items, client_request_token)
+            .await
+    }
+
+    async fn execute_statement(
+        &self,
+        statement: String,
+        parameters: Option<Vec<AttributeValue>>,
+        consistent_read: Option<bool>,
+        next_token: Option<String>,
+    ) -> Result<ExecuteStatementOutput, SdkError<ExecuteStatementError>> {
+        self.primary
+            .execute_statement(statement, parameters, consistent_read, next_token)
+            .await
+    }
+
+    async fn batch_execute_statement(
+        &self,
+        statements: Vec<(String, Option<Vec<AttributeValue>>)>,
+    ) -> Result<BatchExecuteStatementOutput, SdkError<BatchExecuteStatementError>> {
+        self.primary.batch_execute_statement(statements).await
+    }
+
+    async fn create_table(
+        &self,
+        table_name: String,
+        key_schema: Vec<KeySchemaElement>,
+        attribute_definitions: Vec<AttributeDefinition>,
+        global_secondary_indexes: Option<Vec<GlobalSecondaryIndex>>,
+        local_secondary_indexes: Option<Vec<LocalSecondaryIndex>>,
+        billing_mode: BillingMode,
+    ) -> Result<CreateTableOutput, SdkError<CreateTableError>> {
+        self.primary
+            .create_table(
+                table_name,
+                key_schema,
+                attribute_definitions,
+                global_secondary_indexes,
+                local_secondary_indexes,
+                billing_mode,
+            )
+            .await
+    }
+
+    async fn delete_table(
+        &self,
+        table_name: String,
+    ) -> Result<DeleteTableOutput, SdkError<DeleteTableError>> {
+        self.primary.delete_table(table_name).await
+    }
+
+    async fn describe_table(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeTableOutput, SdkError<DescribeTableError>> {
+        self.primary.describe_table(table_name).await
+    }
+
+    async fn update_table(
+        &self,
+        table_name: String,
+        attribute_definitions: Vec<AttributeDefinition>,
+        global_secondary_index_updates: Vec<GlobalSecondaryIndexUpdate>,
+    ) -> Result<UpdateTableOutput, SdkError<UpdateTableError>> {
+        self.primary
+            .update_table(
+                table_name,
+                attribute_definitions,
+                global_secondary_index_updates,
+            )
+            .await
+    }
+
+    async fn describe_time_to_live(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeTimeToLiveOutput, SdkError<DescribeTimeToLiveError>> {
+        self.primary.describe_time_to_live(table_name).await
+    }
+
+    async fn update_time_to_live(
+        &self,
+        table_name: String,
+        time_to_live_specification: TimeToLiveSpecification,
+    ) -> Result<UpdateTimeToLiveOutput, SdkError<UpdateTimeToLiveError>> {
+        self.primary
+            .update_time_to_live(table_name, time_to_live_specification)
+            .await
+    }
+
+    async fn export_table_to_point_in_time(
+        &self,
+        table_arn: String,
+        s3_bucket: String,
+        s3_prefix: Option<String>,
+        export_format: ExportFormat,
+        export_time: Option<DateTime>,
+    ) -> Result<ExportTableToPointInTimeOutput, SdkError<ExportTableToPointInTimeError>> {
+        self.primary
+            .export_table_to_point_in_time(
+                table_arn,
+                s3_bucket,
+                s3_prefix,
+                export_format,
+                export_time,
+            )
+            .await
+    }
+
+    async fn describe_export(
+        &self,
+        export_arn: String,
+    ) -> Result<DescribeExportOutput, SdkError<DescribeExportError>> {
+        self.primary.describe_export(export_arn).await
+    }
+
+    async fn create_backup(
+        &self,
+        table_name: String,
+        backup_name: String,
+    ) -> Result<CreateBackupOutput, SdkError<CreateBackupError>> {
+        self.primary.create_backup(table_name, backup_name).await
+    }
+
+    async fn describe_backup(
+        &self,
+        backup_arn: String,
+    ) -> Result<DescribeBackupOutput, SdkError<DescribeBackupError>> {
+        self.primary.describe_backup(backup_arn).await
+    }
+
+    async fn restore_table_from_backup(
+        &self,
+        target_table_name: String,
+        backup_arn: String,
+    ) -> Result<RestoreTableFromBackupOutput, SdkError<RestoreTableFromBackupError>> {
+        self.primary
+            .restore_table_from_backup(target_table_name, backup_arn)
+            .await
+    }
+
+    async fn describe_continuous_backups(
+        &self,
+        table_name: String,
+    ) -> Result<DescribeContinuousBackupsOutput, SdkError<DescribeContinuousBackupsError>> {
+        self.primary.describe_continuous_backups(table_name).await
+    }
+
+    async fn update_continuous_backups(
+        &self,
+        table_name: String,
+        point_in_time_recovery_specification: PointInTimeRecoverySpecification,
+    ) -> Result<UpdateContinuousBackupsOutput, SdkError<UpdateContinuousBackupsError>> {
+        self.primary
+            .update_continuous_backups(table_name, point_in_time_recovery_specification)
+            .await
+    }
+
+    async fn restore_table_to_point_in_time(
+        &self,
+        source_table_name: String,
+        target_table_name: String,
+        restore_date_time: Option<DateTime>,
+    ) -> Result<RestoreTableToPointInTimeOutput, SdkError<RestoreTableToPointInTimeError>> {
+        self.primary
+            .restore_table_to_point_in_time(source_table_name, target_table_name, restore_date_time)
+            .await
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodb::types::Put;
+    use fractic_core::collection;
+
+    use super::super::{backend::MockDynamoBackendImpl, memory_backend::MemoryDynamoBackend};
+    use super::*;
+
+    fn key() -> DynamoMap {
+        collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("TASK#1".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_failover() {
+        let mut primary = MockDynamoBackendImpl::new();
+        primary
+            .expect_get_item()
+            .times(2)
+            .returning(|_, _, _, _| Err(SdkError::timeout_error("connection timed out")));
+        let mut fallback = MockDynamoBackendImpl::new();
+        fallback
+            .expect_get_item()
+            .times(1)
+            .returning(|_, _, _, _| Ok(GetItemOutput::builder().set_item(Some(key())).build()));
+
+        let backend = MultiRegionBackend::new(primary, "us-east-1", fallback, "eu-west-1");
+        let output = backend
+            .get_item("my_table".to_string(), key(), None, None)
+            .await
+            .unwrap();
+        assert_eq!(output.item, Some(key()));
+
+        // Without failover, the primary region's error is returned.
+        let backend = backend.with_read_failover(false);
+        let result = backend
+            .get_item("my_table".to_string(), key(), None, None)
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_puts_tagged_with_region() {
+        // Updates are tagged by DynamoUtil instead (see with_updated_region).
+        let primary = MemoryDynamoBackend::new();
+        let backend = MultiRegionBackend::new(
+            primary.clone(),
+            "us-east-1",
+            MemoryDynamoBackend::new(),
+            "eu-west-1",
+        );
+        backend
+            .put_item("my_table".to_string(), key(), None, None, None)
+            .await
+            .unwrap();
+        let put = Put::builder()
+            .table_name("other_table")
+            .set_item(Some(key()))
+            .build()
+            .unwrap();
+        backend
+            .transact_write_items(vec![TransactWriteItem::builder().put(put).build()], None)
+            .await
+            .unwrap();
+
+        for table in ["my_table", "other_table"] {
+            let items = primary.items(table);
+            assert_eq!(items.len(), 1);
+            assert_eq!(
+                items[0].get(UPDATED_REGION_ATTRIBUTE),
+                Some(&AttributeValue::S("us-east-1".to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::{sync::Mutex, time::Instant};
+
+use fractic_server_error::ServerError;
+
+use crate::errors::DynamoInvalidOperation;
+
+use super::{item_size::DynamoMapExt, DynamoMap};
+
+// Size of a write capacity unit, in bytes.
+const WRITE_UNIT_SIZE: usize = 1024;
+
+/// Rate at which batch writes are sent (see DynamoUtil::with_write_rate_limit).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteRateLimit {
+    ItemsPerSecond(f64),
+    /// Write capacity units per second, where writing an item consumes one
+    /// unit per started KB of its size, and deleting an item one unit.
+    WriteUnitsPerSecond(f64),
+}
+
+/// Token bucket limiting the rate of batch writes (raw_batch_put_item,
+/// batch_create_item, batch_delete_item, imports, etc.), to avoid starving
+/// production traffic of write capacity during backfills. Shared between
+/// clones of a DynamoUtil.
+///
+/// The bucket holds up to one second's worth of writes, so short bursts are
+/// sent immediately. Once it is empty, writes wait until enough capacity has
+/// been refilled, in the order they were requested. Single-item writes (such
+/// as create_item) are not limited.
+#[derive(Debug)]
+pub struct WriteRateLimiter {
+    limit: WriteRateLimit,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    // Can be negative, when writes are waiting for capacity.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl WriteRateLimiter {
+    /// Returns DynamoInvalidOperation if the rate is not a positive, finite
+    /// number.
+    pub fn new(limit: WriteRateLimit) -> Result<Self, ServerError> {
+        let rate = limit.rate();
+        if !rate.is_finite() || rate <= 0.0 {
+            return Err(DynamoInvalidOperation::new(&format!(
+                "write rate limit must be positive, but was {}",
+                rate
+            )));
+        }
+        Ok(Self {
+            limit,
+            state: Mutex::new(BucketState {
+                tokens: rate,
+                refilled_at: Instant::now(),
+            }),
+        })
+    }
+
+    pub fn limit(&self) -> WriteRateLimit {
+        self.limit
+    }
+
+    // Waits until the given items can be written.
+    pub(crate) async fn acquire_puts(&self, items: &[DynamoMap]) {
+        let cost = match self.limit {
+            WriteRateLimit::ItemsPerSecond(_) => items.len() as f64,
+            WriteRateLimit::WriteUnitsPerSecond(_) => items
+                .iter()
+                .map(|item| item.estimated_size().div_ceil(WRITE_UNIT_SIZE).max(1) as f64)
+                .sum(),
+        };
+        self.acquire(cost).await;
+    }
+
+    // Waits until the given number of items can be deleted.
+    pub(crate) async fn acquire_deletes(&self, count: usize) {
+        self.acquire(count as f64).await;
+    }
+
+    async fn acquire(&self, cost: f64) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let rate = self.limit.rate();
+            let now = Instant::now();
+            let refill = now.duration_since(state.refilled_at).as_secs_f64() * rate;
+            state.tokens = (state.tokens + refill).min(rate);
+            state.refilled_at = now;
+            // Taking the tokens up front (going into debt if needed) reserves
+            // the capacity, so that later writes wait for this one.
+            state.tokens -= cost;
+            (-state.tokens / rate).max(0.0)
+        };
+        if wait > 0.0 {
+            tokio::time::sleep(std::time::Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+impl WriteRateLimit {
+    fn rate(&self) -> f64 {
+        match self {
+            WriteRateLimit::ItemsPerSecond(rate) | WriteRateLimit::WriteUnitsPerSecond(rate) => {
+                *rate
+            }
+        }
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use fractic_core::collection;
+
+    use super::*;
+
+    fn item(size: usize) -> DynamoMap {
+        collection! {
+            "pk".to_string() => AttributeValue::S("x".repeat(size.saturating_sub(2))),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_burst_then_wait() {
+        let limiter = WriteRateLimiter::new(WriteRateLimit::ItemsPerSecond(20.0)).unwrap();
+
+        // A full bucket allows a second's worth of writes immediately.
+        let start = Instant::now();
+        limiter.acquire_puts(&vec![item(10); 20]).await;
+        assert!(start.elapsed() < Duration::from_millis(40));
+
+        // Further writes wait for the bucket to refill.
+        limiter.acquire_puts(&vec![item(10); 2]).await;
+        assert!(start.elapsed() >= Duration::from_millis(90));
+        limiter.acquire_deletes(2).await;
+        assert!(start.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[tokio::test]
+    async fn test_write_units() {
+        let limiter = WriteRateLimiter::new(WriteRateLimit::WriteUnitsPerSecond(10.0)).unwrap();
+        let start = Instant::now();
+        // 3 units for an item of 2.5KB, plus 10 units for 10 small items.
+        limiter.acquire_puts(&[item(2560)]).await;
+        limiter.acquire_puts(&vec![item(10); 10]).await;
+        assert!(start.elapsed() >= Duration::from_millis(290));
+    }
+
+    #[test]
+    fn test_invalid_rate() {
+        assert!(WriteRateLimiter::new(WriteRateLimit::ItemsPerSecond(0.0)).is_err());
+        assert!(WriteRateLimiter::new(WriteRateLimit::WriteUnitsPerSecond(-5.0)).is_err());
+        assert!(WriteRateLimiter::new(WriteRateLimit::ItemsPerSecond(f64::NAN)).is_err());
+        assert!(WriteRateLimiter::new(WriteRateLimit::ItemsPerSecond(f64::INFINITY)).is_err());
+    }
+}
@@ -0,0 +1,408 @@
+use std::{fmt::Debug, future::Future, sync::Arc, time::Duration};
+
+use aws_sdk_dynamodb::error::{ErrorMetadata, ProvideErrorMetadata};
+use rand::Rng;
+use tokio::time::error::Elapsed;
+
+// Error codes returned by DynamoDB when requests are throttled.
+const THROTTLING_ERROR_CODES: [&str; 3] = [
+    "ProvisionedThroughputExceededException",
+    "ThrottlingException",
+    "RequestLimitExceeded",
+];
+
+/// Controls how failed requests are retried: which errors are retried (see
+/// RetryClassifier), how many times, and how long to wait between attempts.
+/// Applies to all requests sent to the backend, as well as to unprocessed
+/// batch items.
+///
+/// By default, throttled requests are retried up to 5 attempts in total, with
+/// exponential backoff. Note that the AWS SDK already retries some errors
+/// internally; this applies on top of that, to better tolerate sustained
+/// throttling.
+///
+/// Example:
+///   let policy = RetryPolicy::default()
+///       .with_max_attempts(3)
+///       .with_backoff(BackoffStrategy::Fixed(Duration::from_millis(100)))
+///       .with_classifier(ErrorCodeClassifier::throttling().with_code("InternalServerError"));
+///   let util = util.with_retry(policy);
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the initial request. A value of 1
+    /// disables retries.
+    pub max_attempts: u32,
+    pub backoff: BackoffStrategy,
+    pub classifier: Arc<dyn RetryClassifier>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            backoff: BackoffStrategy::default(),
+            classifier: Arc::new(ErrorCodeClassifier::throttling()),
+        }
+    }
+}
+
+/// Delay before each retry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackoffStrategy {
+    /// Retry immediately.
+    None,
+    /// Wait the same delay before each retry.
+    Fixed(Duration),
+    /// Wait a random delay of up to base * 2^(attempt - 1) (capped at max), so
+    /// that concurrent clients don't all retry at the same time.
+    Exponential { base: Duration, max: Duration },
+}
+
+impl Default for BackoffStrategy {
+    fn default() -> Self {
+        BackoffStrategy::Exponential {
+            base: Duration::from_millis(50),
+            max: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Decides whether a failed request should be retried, based on the error
+/// returned. Reads which time out (see TimeoutConfig) are always retried.
+pub trait RetryClassifier: Debug + Send + Sync {
+    fn is_retryable(&self, error: &ErrorMetadata) -> bool;
+}
+
+/// Retries errors with one of the given error codes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorCodeClassifier {
+    codes: Vec<String>,
+}
+
+impl ErrorCodeClassifier {
+    /// Retries throttling errors only (the default).
+    pub fn throttling() -> Self {
+        Self {
+            codes: THROTTLING_ERROR_CODES.map(str::to_string).to_vec(),
+        }
+    }
+
+    /// Doesn't retry any errors.
+    pub fn none() -> Self {
+        Self { codes: Vec::new() }
+    }
+
+    /// Also retries errors with the given code (such as "InternalServerError"
+    /// or "TransactionConflictException").
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.codes.push(code.into());
+        self
+    }
+}
+
+impl RetryClassifier for ErrorCodeClassifier {
+    fn is_retryable(&self, error: &ErrorMetadata) -> bool {
+        error
+            .code()
+            .is_some_and(|code| self.codes.iter().any(|c| c == code))
+    }
+}
+
+impl RetryPolicy {
+    pub fn disabled() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: BackoffStrategy) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    pub fn with_classifier(mut self, classifier: impl RetryClassifier + 'static) -> Self {
+        self.classifier = Arc::new(classifier);
+        self
+    }
+
+    // Runs 'op', retrying it if it fails with a retryable error or (if
+    // 'retry_timeouts' is set) if an attempt times out. The outer error is
+    // returned if the request timed out (see TimeoutConfig), and the inner one
+    // if 'op' failed.
+    //
+    // A timed out attempt may still have been applied, so 'retry_timeouts'
+    // should only be set for requests which can safely be repeated.
+    pub(crate) async fn run<T, E, F, Fut>(
+        &self,
+        timeout: &TimeoutConfig,
+        retry_timeouts: bool,
+        mut op: F,
+    ) -> Result<Result<T, E>, Elapsed>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: ProvideErrorMetadata,
+    {
+        let attempts = async {
+            let mut attempt = 1;
+            loop {
+                let result = match timeout.attempt {
+                    Some(limit) => tokio::time::timeout(limit, op()).await,
+                    None => Ok(op().await),
+                };
+                match result {
+                    Ok(Err(e))
+                        if attempt < self.max_attempts
+                            && self.classifier.is_retryable(e.meta()) => {}
+                    Err(_) if retry_timeouts && attempt < self.max_attempts => {}
+                    result => return result,
+                }
+                tokio::time::sleep(self.backoff(attempt)).await;
+                attempt += 1;
+            }
+        };
+        match timeout.operation {
+            Some(limit) => tokio::time::timeout(limit, attempts).await?,
+            None => attempts.await,
+        }
+    }
+
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        match &self.backoff {
+            BackoffStrategy::None => Duration::ZERO,
+            BackoffStrategy::Fixed(delay) => *delay,
+            BackoffStrategy::Exponential { base, max } => {
+                let max = base
+                    .saturating_mul(2u32.saturating_pow(attempt - 1))
+                    .min(*max);
+                max.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+            }
+        }
+    }
+}
+
+/// Limits how long requests can take, so that a slow or unresponsive DynamoDB
+/// call fails with DynamoTimeout instead of blocking the caller indefinitely
+/// (for example, until the Lambda running it times out). No limits are set by
+/// default.
+///
+/// Reads which time out are retried (as for throttled requests). Writes which
+/// time out are not, since the timed out attempt may still have been applied:
+/// retrying it could apply non-idempotent updates (such as increment_field or
+/// append_to_list) twice, and make conditional writes fail even though the
+/// first attempt succeeded. A timed out write therefore fails with
+/// DynamoTimeout, and may or may not have been applied. The exception is
+/// transactions with a client request token, which DynamoDB deduplicates.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimeoutConfig {
+    /// Max total time of an operation, including all retries and backoff
+    /// delays.
+    pub operation: Option<Duration>,
+    /// Max time of each individual attempt.
+    pub attempt: Option<Duration>,
+}
+
+impl TimeoutConfig {
+    pub fn new(operation: Duration) -> Self {
+        Self {
+            operation: Some(operation),
+            attempt: None,
+        }
+    }
+
+    pub fn with_attempt_timeout(mut self, attempt: Duration) -> Self {
+        self.attempt = Some(attempt);
+        self
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use super::*;
+
+    fn test_config(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy::default()
+            .with_max_attempts(max_attempts)
+            .with_backoff(BackoffStrategy::Exponential {
+                base: Duration::from_millis(1),
+                max: Duration::from_millis(2),
+            })
+    }
+
+    fn error(code: &str) -> ErrorMetadata {
+        ErrorMetadata::builder().code(code).build()
+    }
+
+    #[tokio::test]
+    async fn test_retries_throttling_errors() {
+        let attempts = AtomicU32::new(0);
+        let result = test_config(5)
+            .run(&TimeoutConfig::default(), true, || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(error("ProvisionedThroughputExceededException")),
+                    1 => Err(error("ThrottlingException")),
+                    _ => Ok(42),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = test_config(3)
+            .run(&TimeoutConfig::default(), true, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(error("ThrottlingException"))
+            })
+            .await
+            .unwrap();
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_other_errors() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = test_config(5)
+            .run(&TimeoutConfig::default(), true, || async {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err(error("ConditionalCheckFailedException"))
+            })
+            .await
+            .unwrap();
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_custom_classifier() {
+        let attempts = AtomicU32::new(0);
+        let result = test_config(5)
+            .with_classifier(ErrorCodeClassifier::none().with_code("InternalServerError"))
+            .run(&TimeoutConfig::default(), true, || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(error("InternalServerError")),
+                    _ => Err(error("ThrottlingException")),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.unwrap_err().code(), Some("ThrottlingException"));
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_timed_out_attempts() {
+        let attempts = AtomicU32::new(0);
+        let timeout = TimeoutConfig {
+            operation: None,
+            attempt: Some(Duration::from_millis(10)),
+        };
+        let result = test_config(5)
+            .run(&timeout, true, || async {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                }
+                Ok::<_, ErrorMetadata>(42)
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_timed_out_writes() {
+        let attempts = AtomicU32::new(0);
+        let timeout = TimeoutConfig {
+            operation: None,
+            attempt: Some(Duration::from_millis(10)),
+        };
+        let result = test_config(5)
+            .run(&timeout, false, || async {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                }
+                Ok::<_, ErrorMetadata>(42)
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // Throttled writes (which are rejected without being applied) are
+        // still retried.
+        let result = test_config(5)
+            .run(&timeout, false, || async {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    1 => Err(error("ThrottlingException")),
+                    _ => Ok(42),
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.unwrap(), 42);
+    }
+
+    #[tokio::test]
+    async fn test_operation_timeout() {
+        let attempts = AtomicU32::new(0);
+        let result = test_config(5)
+            .run(
+                &TimeoutConfig::new(Duration::from_millis(10)),
+                true,
+                || async {
+                    attempts.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                    Ok::<_, ErrorMetadata>(42)
+                },
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+
+        // Attempt timeouts are not retried beyond max_attempts.
+        let timeout = TimeoutConfig {
+            operation: None,
+            attempt: Some(Duration::from_millis(10)),
+        };
+        let result = test_config(2)
+            .run(&timeout, true, || async {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok::<_, ErrorMetadata>(42)
+            })
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let config = RetryPolicy::default().with_backoff(BackoffStrategy::Exponential {
+            base: Duration::from_millis(100),
+            max: Duration::from_millis(300),
+        });
+        for attempt in 1..10 {
+            assert!(config.backoff(attempt) <= Duration::from_millis(300));
+        }
+        assert!(config.backoff(1) <= Duration::from_millis(100));
+
+        let config =
+            RetryPolicy::default().with_backoff(BackoffStrategy::Fixed(Duration::from_millis(100)));
+        assert_eq!(config.backoff(1), Duration::from_millis(100));
+        assert_eq!(config.backoff(5), Duration::from_millis(100));
+    }
+}
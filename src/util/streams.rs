@@ -0,0 +1,611 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_sdk_dynamodbstreams::{
+    error::SdkError,
+    operation::{
+        describe_stream::{DescribeStreamError, DescribeStreamOutput},
+        get_records::{GetRecordsError, GetRecordsOutput},
+        get_shard_iterator::{GetShardIteratorError, GetShardIteratorOutput},
+    },
+    types::{Record, ShardIteratorType},
+};
+use fractic_env_config::EnvVariables;
+use fractic_server_error::ServerError;
+use mockall::automock;
+
+use crate::{env::DynamoEnvConfig, errors::DynamoCalloutError};
+
+use super::{backend::DynamoBackendImpl, DynamoUtil};
+
+pub mod change;
+
+// How often the stream is described again to discover new shards (which
+// DynamoDB creates roughly every 4 hours, or when a shard splits).
+const DEFAULT_SHARD_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+// Delay before polling again when no shard returned any records.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Underlying streams backend, kept generic (like DynamoBackendImpl) so that it
+// can be swapped with a mock backend for testing.
+#[automock]
+#[async_trait]
+pub trait DynamoStreamsBackendImpl {
+    async fn describe_stream(
+        &self,
+        stream_arn: String,
+        exclusive_start_shard_id: Option<String>,
+    ) -> Result<DescribeStreamOutput, SdkError<DescribeStreamError>>;
+
+    async fn get_shard_iterator(
+        &self,
+        stream_arn: String,
+        shard_id: String,
+        shard_iterator_type: ShardIteratorType,
+        sequence_number: Option<String>,
+    ) -> Result<GetShardIteratorOutput, SdkError<GetShardIteratorError>>;
+
+    async fn get_records(
+        &self,
+        shard_iterator: String,
+        limit: Option<i32>,
+    ) -> Result<GetRecordsOutput, SdkError<GetRecordsError>>;
+}
+
+/// Position of a consumer within a shard, saved once the records up to that
+/// position have been processed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StreamCheckpoint {
+    /// Records up to (and including) this sequence number were processed.
+    SequenceNumber(String),
+    /// The shard is closed, and all of its records were processed.
+    ShardEnd,
+}
+
+/// Stores the consumer's checkpoints, so that a restarted consumer resumes
+/// where the previous one stopped. Implementations could for example store the
+/// checkpoints in a DynamoDB table.
+#[async_trait]
+pub trait StreamCheckpointStore: std::fmt::Debug + Send + Sync {
+    async fn load(&self, shard_id: &str) -> Result<Option<StreamCheckpoint>, ServerError>;
+    async fn save(&self, shard_id: &str, checkpoint: StreamCheckpoint) -> Result<(), ServerError>;
+}
+
+/// Checkpoint store which only lives as long as the process (the default).
+#[derive(Debug, Default)]
+pub struct MemoryCheckpointStore {
+    checkpoints: Mutex<HashMap<String, StreamCheckpoint>>,
+}
+
+#[async_trait]
+impl StreamCheckpointStore for MemoryCheckpointStore {
+    async fn load(&self, shard_id: &str) -> Result<Option<StreamCheckpoint>, ServerError> {
+        Ok(self.checkpoints.lock().unwrap().get(shard_id).cloned())
+    }
+
+    async fn save(&self, shard_id: &str, checkpoint: StreamCheckpoint) -> Result<(), ServerError> {
+        self.checkpoints
+            .lock()
+            .unwrap()
+            .insert(shard_id.to_string(), checkpoint);
+        Ok(())
+    }
+}
+
+/// Where to start reading shards without a checkpoint, when the consumer
+/// starts. Shards created while the consumer is running are always read from
+/// the beginning, so that no records are missed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum StreamStartPosition {
+    /// Oldest records still in the stream (up to 24 hours old).
+    #[default]
+    TrimHorizon,
+    /// Only records written after the consumer started.
+    Latest,
+}
+
+/// Records read from a single shard, in order.
+#[derive(Debug, Clone)]
+pub struct StreamBatch {
+    pub shard_id: String,
+    pub records: Vec<Record>,
+}
+
+/// Reads a table's DynamoDB stream, delivering its raw records to a handler.
+///
+/// Takes care of discovering shards, reading parent shards before their
+/// children (so that the records for each item are delivered in order),
+/// renewing expired shard iterators, and checkpointing (see
+/// StreamCheckpointStore). A checkpoint is only saved once the handler has
+/// successfully processed a batch; if it fails, the batch is delivered again
+/// on the next poll. Records are therefore delivered at least once.
+///
+/// Example:
+///   let stream_arn = util.latest_stream_arn("my_table").await?.unwrap();
+///   let mut consumer = DynamoStreamConsumer::new(env, stream_arn)
+///       .await?
+///       .with_checkpoint_store(checkpoints);
+///   consumer.run(|batch| async move { process(batch.records).await }).await?;
+#[derive(Debug)]
+pub struct DynamoStreamConsumer<B: DynamoStreamsBackendImpl> {
+    backend: B,
+    stream_arn: String,
+    checkpoints: Arc<dyn StreamCheckpointStore>,
+    start_position: StreamStartPosition,
+    batch_size: Option<i32>,
+    poll_interval: Duration,
+    shard_refresh_interval: Duration,
+    shards: BTreeMap<String, ShardState>,
+    last_refresh: Option<Instant>,
+}
+
+#[derive(Debug)]
+struct ShardState {
+    parent_shard_id: Option<String>,
+    // Iterator type used if the shard has no checkpoint.
+    start: ShardIteratorType,
+    iterator: Option<String>,
+    finished: bool,
+}
+
+impl DynamoStreamConsumer<aws_sdk_dynamodbstreams::Client> {
+    pub async fn new(
+        env: EnvVariables<DynamoEnvConfig>,
+        stream_arn: impl Into<String>,
+    ) -> Result<Self, ServerError> {
+        let region_str = env.get(&DynamoEnvConfig::DynamoRegion)?;
+        let region = Region::new(region_str.clone());
+        let shared_config = aws_config::defaults(BehaviorVersion::v2024_03_28())
+            .region(region)
+            .load()
+            .await;
+        Ok(Self::from_backend(
+            aws_sdk_dynamodbstreams::Client::new(&shared_config),
+            stream_arn,
+        ))
+    }
+}
+
+impl<B: DynamoStreamsBackendImpl> DynamoStreamConsumer<B> {
+    pub fn from_backend(backend: B, stream_arn: impl Into<String>) -> Self {
+        Self {
+            backend,
+            stream_arn: stream_arn.into(),
+            checkpoints: Arc::new(MemoryCheckpointStore::default()),
+            start_position: StreamStartPosition::default(),
+            batch_size: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            shard_refresh_interval: DEFAULT_SHARD_REFRESH_INTERVAL,
+            shards: BTreeMap::new(),
+            last_refresh: None,
+        }
+    }
+
+    pub fn with_checkpoint_store(mut self, checkpoints: Arc<dyn StreamCheckpointStore>) -> Self {
+        self.checkpoints = checkpoints;
+        self
+    }
+
+    pub fn with_start_position(mut self, start_position: StreamStartPosition) -> Self {
+        self.start_position = start_position;
+        self
+    }
+
+    /// Max number of records per batch (up to 1000, the default).
+    pub fn with_batch_size(mut self, batch_size: i32) -> Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// Delay before polling again, when run() finds no new records.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Reads the next batch of records from each shard which is ready to be
+    /// read, and passes each non-empty batch to 'handler'. Returns the number
+    /// of records delivered.
+    pub async fn poll<F, Fut>(&mut self, mut handler: F) -> Result<usize, ServerError>
+    where
+        F: FnMut(StreamBatch) -> Fut,
+        Fut: Future<Output = Result<(), ServerError>>,
+    {
+        if self
+            .last_refresh
+            .is_none_or(|at| at.elapsed() >= self.shard_refresh_interval)
+        {
+            self.refresh_shards().await?;
+        }
+        let mut delivered = 0;
+        for shard_id in self.ready_shards() {
+            let Some(iterator) = self.shard_iterator(&shard_id).await? else {
+                continue;
+            };
+            let output = match self.backend.get_records(iterator, self.batch_size).await {
+                Ok(output) => output,
+                Err(e) => match e.into_service_error() {
+                    // Iterators expire after 15 minutes, in which case a new
+                    // one is requested from the last checkpoint.
+                    GetRecordsError::ExpiredIteratorException(_) => {
+                        self.shard_mut(&shard_id).iterator = None;
+                        continue;
+                    }
+                    other => return Err(DynamoCalloutError::with_debug(&other)),
+                },
+            };
+            let records = output.records.unwrap_or_default();
+            let last_sequence_number = records
+                .iter()
+                .rev()
+                .find_map(|r| r.dynamodb().and_then(|d| d.sequence_number()))
+                .map(str::to_string);
+            if !records.is_empty() {
+                delivered += records.len();
+                handler(StreamBatch {
+                    shard_id: shard_id.clone(),
+                    records,
+                })
+                .await?;
+            }
+            if let Some(sequence_number) = last_sequence_number {
+                self.checkpoints
+                    .save(&shard_id, StreamCheckpoint::SequenceNumber(sequence_number))
+                    .await?;
+            }
+            match output.next_shard_iterator {
+                Some(next) => self.shard_mut(&shard_id).iterator = Some(next),
+                None => {
+                    // The shard is closed and fully read, so its children can
+                    // now be read.
+                    self.checkpoints
+                        .save(&shard_id, StreamCheckpoint::ShardEnd)
+                        .await?;
+                    let shard = self.shard_mut(&shard_id);
+                    shard.iterator = None;
+                    shard.finished = true;
+                    self.last_refresh = None;
+                }
+            }
+        }
+        Ok(delivered)
+    }
+
+    /// Polls the stream until the handler (or a request) fails, waiting for
+    /// the poll interval whenever there are no new records.
+    pub async fn run<F, Fut>(&mut self, mut handler: F) -> Result<(), ServerError>
+    where
+        F: FnMut(StreamBatch) -> Fut,
+        Fut: Future<Output = Result<(), ServerError>>,
+    {
+        loop {
+            if self.poll(&mut handler).await? == 0 {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        }
+    }
+
+    // Describes the stream to discover new shards. Finished shards which were
+    // trimmed from the stream are forgotten.
+    async fn refresh_shards(&mut self) -> Result<(), ServerError> {
+        let start = match (self.shards.is_empty(), self.start_position) {
+            (true, StreamStartPosition::Latest) => ShardIteratorType::Latest,
+            _ => ShardIteratorType::TrimHorizon,
+        };
+        let mut discovered = HashSet::new();
+        let mut exclusive_start_shard_id = None;
+        loop {
+            let description = self
+                .backend
+                .describe_stream(self.stream_arn.clone(), exclusive_start_shard_id)
+                .await
+                .map_err(|e| DynamoCalloutError::with_debug(&e))?
+                .stream_description
+                .ok_or_else(|| DynamoCalloutError::with_debug(&"missing stream description"))?;
+            for shard in description.shards() {
+                let Some(shard_id) = shard.shard_id() else {
+                    continue;
+                };
+                discovered.insert(shard_id.to_string());
+                self.shards
+                    .entry(shard_id.to_string())
+                    .or_insert_with(|| ShardState {
+                        parent_shard_id: shard.parent_shard_id().map(str::to_string),
+                        start: start.clone(),
+                        iterator: None,
+                        finished: false,
+                    });
+            }
+            exclusive_start_shard_id = description.last_evaluated_shard_id().map(str::to_string);
+            if exclusive_start_shard_id.is_none() {
+                break;
+            }
+        }
+        self.shards
+            .retain(|shard_id, shard| !shard.finished || discovered.contains(shard_id));
+        self.last_refresh = Some(Instant::now());
+        Ok(())
+    }
+
+    // Shards which are not finished, and whose parent (if any) is finished or
+    // no longer in the stream.
+    fn ready_shards(&self) -> Vec<String> {
+        self.shards
+            .iter()
+            .filter(|(_, shard)| !shard.finished)
+            .filter(|(_, shard)| {
+                shard
+                    .parent_shard_id
+                    .as_ref()
+                    .and_then(|parent| self.shards.get(parent))
+                    .is_none_or(|parent| parent.finished)
+            })
+            .map(|(shard_id, _)| shard_id.clone())
+            .collect()
+    }
+
+    // The shard's current iterator, or a new one starting after its
+    // checkpoint. Returns None if the shard was already fully read.
+    async fn shard_iterator(&mut self, shard_id: &str) -> Result<Option<String>, ServerError> {
+        if let Some(iterator) = &self.shard_mut(shard_id).iterator {
+            return Ok(Some(iterator.clone()));
+        }
+        let (iterator_type, sequence_number) = match self.checkpoints.load(shard_id).await? {
+            Some(StreamCheckpoint::ShardEnd) => {
+                self.shard_mut(shard_id).finished = true;
+                return Ok(None);
+            }
+            Some(StreamCheckpoint::SequenceNumber(sequence_number)) => (
+                ShardIteratorType::AfterSequenceNumber,
+                Some(sequence_number),
+            ),
+            None => (self.shard_mut(shard_id).start.clone(), None),
+        };
+        let iterator = self
+            .backend
+            .get_shard_iterator(
+                self.stream_arn.clone(),
+                shard_id.to_string(),
+                iterator_type,
+                sequence_number,
+            )
+            .await
+            .map_err(|e| DynamoCalloutError::with_debug(&e))?
+            .shard_iterator
+            .ok_or_else(|| DynamoCalloutError::with_debug(&"missing shard iterator"))?;
+        self.shard_mut(shard_id).iterator = Some(iterator.clone());
+        Ok(Some(iterator))
+    }
+
+    fn shard_mut(&mut self, shard_id: &str) -> &mut ShardState {
+        self.shards
+            .get_mut(shard_id)
+            .expect("shard should have been discovered")
+    }
+}
+
+impl<C: DynamoBackendImpl> DynamoUtil<C> {
+    /// ARN of the table's current stream, or None if streams are not enabled
+    /// on the table.
+    pub async fn latest_stream_arn(&self, table: &str) -> Result<Option<String>, ServerError> {
+        Ok(self
+            .describe_table(table)
+            .await?
+            .and_then(|description| description.latest_stream_arn))
+    }
+}
+
+// Real implementation,
+// making actual calls to AWS.
+// --------------------------------------------------
+
+#[async_trait]
+impl DynamoStreamsBackendImpl for aws_sdk_dynamodbstreams::Client {
+    async fn describe_stream(
+        &self,
+        stream_arn: String,
+        exclusive_start_shard_id: Option<String>,
+    ) -> Result<DescribeStreamOutput, SdkError<DescribeStreamError>> {
+        self.describe_stream()
+            .stream_arn(stream_arn)
+            .set_exclusive_start_shard_id(exclusive_start_shard_id)
+            .send()
+            .await
+    }
+
+    async fn get_shard_iterator(
+        &self,
+        stream_arn: String,
+        shard_id: String,
+        shard_iterator_type: ShardIteratorType,
+        sequence_number: Option<String>,
+    ) -> Result<GetShardIteratorOutput, SdkError<GetShardIteratorError>> {
+        self.get_shard_iterator()
+            .stream_arn(stream_arn)
+            .shard_id(shard_id)
+            .shard_iterator_type(shard_iterator_type)
+            .set_sequence_number(sequence_number)
+            .send()
+            .await
+    }
+
+    async fn get_records(
+        &self,
+        shard_iterator: String,
+        limit: Option<i32>,
+    ) -> Result<GetRecordsOutput, SdkError<GetRecordsError>> {
+        self.get_records()
+            .shard_iterator(shard_iterator)
+            .set_limit(limit)
+            .send()
+            .await
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodbstreams::types::{Shard, StreamDescription, StreamRecord};
+    use mockall::predicate::*;
+
+    use super::*;
+
+    fn record(sequence_number: &str) -> Record {
+        Record::builder()
+            .dynamodb(
+                StreamRecord::builder()
+                    .sequence_number(sequence_number)
+                    .build(),
+            )
+            .build()
+    }
+
+    fn stream_with_split_shard() -> MockDynamoStreamsBackendImpl {
+        let mut backend = MockDynamoStreamsBackendImpl::new();
+        backend.expect_describe_stream().returning(|_, _| {
+            Ok(DescribeStreamOutput::builder()
+                .stream_description(
+                    StreamDescription::builder()
+                        .shards(Shard::builder().shard_id("parent").build())
+                        .shards(
+                            Shard::builder()
+                                .shard_id("child")
+                                .parent_shard_id("parent")
+                                .build(),
+                        )
+                        .build(),
+                )
+                .build())
+        });
+        backend
+            .expect_get_shard_iterator()
+            .with(
+                always(),
+                eq("parent".to_string()),
+                eq(ShardIteratorType::TrimHorizon),
+                eq(None),
+            )
+            .returning(|_, _, _, _| {
+                Ok(GetShardIteratorOutput::builder()
+                    .shard_iterator("parent-0")
+                    .build())
+            });
+        backend
+            .expect_get_shard_iterator()
+            .with(
+                always(),
+                eq("child".to_string()),
+                eq(ShardIteratorType::TrimHorizon),
+                eq(None),
+            )
+            .returning(|_, _, _, _| {
+                Ok(GetShardIteratorOutput::builder()
+                    .shard_iterator("child-0")
+                    .build())
+            });
+        // The parent shard is closed after its second record.
+        backend
+            .expect_get_records()
+            .with(eq("parent-0".to_string()), always())
+            .returning(|_, _| {
+                Ok(GetRecordsOutput::builder()
+                    .records(record("1"))
+                    .next_shard_iterator("parent-1")
+                    .build())
+            });
+        backend
+            .expect_get_records()
+            .with(eq("parent-1".to_string()), always())
+            .returning(|_, _| Ok(GetRecordsOutput::builder().records(record("2")).build()));
+        backend
+            .expect_get_records()
+            .with(eq("child-0".to_string()), always())
+            .returning(|_, _| {
+                Ok(GetRecordsOutput::builder()
+                    .records(record("3"))
+                    .next_shard_iterator("child-1")
+                    .build())
+            });
+        backend
+            .expect_get_records()
+            .with(eq("child-1".to_string()), always())
+            .returning(|_, _| {
+                Ok(GetRecordsOutput::builder()
+                    .next_shard_iterator("child-1")
+                    .build())
+            });
+        backend
+    }
+
+    fn sequence_numbers(batch: &StreamBatch) -> Vec<String> {
+        batch
+            .records
+            .iter()
+            .map(|r| r.dynamodb().unwrap().sequence_number().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_reads_parent_shard_before_child() {
+        let checkpoints = Arc::new(MemoryCheckpointStore::default());
+        let mut consumer = DynamoStreamConsumer::from_backend(stream_with_split_shard(), "arn")
+            .with_checkpoint_store(checkpoints.clone());
+        let mut delivered = Vec::new();
+        for _ in 0..4 {
+            consumer
+                .poll(|batch| {
+                    delivered.push((batch.shard_id.clone(), sequence_numbers(&batch)));
+                    async { Ok(()) }
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            delivered,
+            vec![
+                ("parent".to_string(), vec!["1".to_string()]),
+                ("parent".to_string(), vec!["2".to_string()]),
+                ("child".to_string(), vec!["3".to_string()]),
+            ]
+        );
+        assert_eq!(
+            checkpoints.load("parent").await.unwrap(),
+            Some(StreamCheckpoint::ShardEnd)
+        );
+        assert_eq!(
+            checkpoints.load("child").await.unwrap(),
+            Some(StreamCheckpoint::SequenceNumber("3".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_batch_is_redelivered() {
+        let checkpoints = Arc::new(MemoryCheckpointStore::default());
+        let mut consumer = DynamoStreamConsumer::from_backend(stream_with_split_shard(), "arn")
+            .with_checkpoint_store(checkpoints.clone());
+        let result = consumer
+            .poll(|_| async { Err(DynamoCalloutError::new()) })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(checkpoints.load("parent").await.unwrap(), None);
+
+        let mut delivered = Vec::new();
+        consumer
+            .poll(|batch| {
+                delivered.push(sequence_numbers(&batch));
+                async { Ok(()) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(delivered, vec![vec!["1".to_string()]]);
+    }
+}
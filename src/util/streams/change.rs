@@ -0,0 +1,313 @@
+use std::{any::Any, collections::HashMap};
+
+use aws_sdk_dynamodb::{primitives::Blob, types::AttributeValue};
+use aws_sdk_dynamodbstreams::types::{
+    AttributeValue as StreamAttributeValue, OperationType, Record,
+};
+use fractic_server_error::ServerError;
+
+use crate::{
+    errors::DynamoItemParsingError,
+    schema::{
+        id_calculations::{get_object_type, get_pk_sk_from_map},
+        parsing::parse_dynamo_map,
+        DynamoObject, DynamoRegistry, RegisteredType,
+    },
+    util::DynamoMap,
+};
+
+use super::StreamBatch;
+
+/// A change to an object, decoded from a stream record.
+///
+/// Requires the stream to include both the new and old images of items
+/// (StreamViewType::NewAndOldImages), since the old image is used for Modify
+/// and Remove events.
+#[derive(Debug, Clone)]
+pub enum Change<T> {
+    Insert(T),
+    Modify { old: T, new: T },
+    Remove(T),
+}
+
+impl<T> Change<T> {
+    /// The object after the change, or the removed object for Remove events.
+    pub fn object(&self) -> &T {
+        match self {
+            Change::Insert(object)
+            | Change::Modify { new: object, .. }
+            | Change::Remove(object) => object,
+        }
+    }
+}
+
+/// A change to an object of any type registered in a DynamoRegistry. The
+/// objects can be downcast to the concrete type (see downcast).
+#[derive(Debug)]
+pub struct RegisteredChange<'r> {
+    pub registered_type: &'r RegisteredType,
+    pub change: Change<Box<dyn Any + Send>>,
+}
+
+impl RegisteredChange<'_> {
+    /// The change as a change to an object of type T, or None if the object
+    /// is of a different type.
+    pub fn downcast<T: DynamoObject + 'static>(self) -> Option<Change<T>> {
+        if !self.registered_type.is::<T>() {
+            return None;
+        }
+        let downcast = |object: Box<dyn Any + Send>| {
+            *object
+                .downcast::<T>()
+                .expect("registered type should match the parsed object")
+        };
+        Some(match self.change {
+            Change::Insert(object) => Change::Insert(downcast(object)),
+            Change::Modify { old, new } => Change::Modify {
+                old: downcast(old),
+                new: downcast(new),
+            },
+            Change::Remove(object) => Change::Remove(downcast(object)),
+        })
+    }
+}
+
+impl StreamBatch {
+    /// Decodes the changes to objects of type T, skipping records for other
+    /// types of objects.
+    pub fn changes<T: DynamoObject>(&self) -> Result<Vec<Change<T>>, ServerError> {
+        let mut changes = Vec::new();
+        for record in &self.records {
+            let Some(images) = decode_images(record)? else {
+                continue;
+            };
+            if images.label != T::id_label() {
+                continue;
+            }
+            changes.push(images.into_change(parse_dynamo_map::<T>)?);
+        }
+        Ok(changes)
+    }
+
+    /// Decodes the changes to objects of any type registered in 'registry',
+    /// skipping records for unregistered types.
+    pub fn registered_changes<'r>(
+        &self,
+        registry: &'r DynamoRegistry,
+    ) -> Result<Vec<RegisteredChange<'r>>, ServerError> {
+        let mut changes = Vec::new();
+        for record in &self.records {
+            let Some(images) = decode_images(record)? else {
+                continue;
+            };
+            let Some(registered_type) = registry.get(&images.label) else {
+                continue;
+            };
+            changes.push(RegisteredChange {
+                registered_type,
+                change: images.into_change(|item| registered_type.parse(item))?,
+            });
+        }
+        Ok(changes)
+    }
+}
+
+// Images of a single stream record, converted to regular DynamoDB items.
+struct ChangeImages {
+    label: String,
+    event: OperationType,
+    old: Option<DynamoMap>,
+    new: Option<DynamoMap>,
+}
+
+impl ChangeImages {
+    fn into_change<T>(
+        self,
+        parse: impl Fn(&DynamoMap) -> Result<T, ServerError>,
+    ) -> Result<Change<T>, ServerError> {
+        let image = |image: Option<DynamoMap>, name: &str| {
+            image.ok_or_else(|| {
+                DynamoItemParsingError::new(&format!(
+                    "stream record has no {} image (the stream must include new and old images)",
+                    name
+                ))
+            })
+        };
+        Ok(match self.event {
+            OperationType::Insert => Change::Insert(parse(&image(self.new, "new")?)?),
+            OperationType::Modify => Change::Modify {
+                old: parse(&image(self.old, "old")?)?,
+                new: parse(&image(self.new, "new")?)?,
+            },
+            OperationType::Remove => Change::Remove(parse(&image(self.old, "old")?)?),
+            other => {
+                return Err(DynamoItemParsingError::new(&format!(
+                    "unsupported stream event '{}'",
+                    other.as_str()
+                )))
+            }
+        })
+    }
+}
+
+// Converts the record's images, or returns None if the record isn't for an
+// object (for example, an item written outside of DynamoUtil).
+fn decode_images(record: &Record) -> Result<Option<ChangeImages>, ServerError> {
+    let (Some(event), Some(stream_record)) = (record.event_name(), record.dynamodb()) else {
+        return Ok(None);
+    };
+    let Some(keys) = stream_record.keys() else {
+        return Ok(None);
+    };
+    let keys = convert_image(keys)?;
+    let Ok(label) = get_pk_sk_from_map(&keys)
+        .and_then(|(pk, sk)| get_object_type(pk, sk))
+        .map(str::to_string)
+    else {
+        return Ok(None);
+    };
+    Ok(Some(ChangeImages {
+        label,
+        event: event.clone(),
+        old: stream_record.old_image().map(convert_image).transpose()?,
+        new: stream_record.new_image().map(convert_image).transpose()?,
+    }))
+}
+
+// Stream records use the DynamoDB Streams SDK's own AttributeValue type, which
+// is converted to the regular DynamoDB type so that items can be parsed.
+fn convert_image(image: &HashMap<String, StreamAttributeValue>) -> Result<DynamoMap, ServerError> {
+    image
+        .iter()
+        .map(|(key, value)| Ok((key.clone(), convert_value(value)?)))
+        .collect()
+}
+
+fn convert_value(value: &StreamAttributeValue) -> Result<AttributeValue, ServerError> {
+    Ok(match value {
+        StreamAttributeValue::S(s) => AttributeValue::S(s.clone()),
+        StreamAttributeValue::N(n) => AttributeValue::N(n.clone()),
+        StreamAttributeValue::B(b) => AttributeValue::B(Blob::new(b.as_ref())),
+        StreamAttributeValue::Bool(b) => AttributeValue::Bool(*b),
+        StreamAttributeValue::Null(n) => AttributeValue::Null(*n),
+        StreamAttributeValue::Ss(ss) => AttributeValue::Ss(ss.clone()),
+        StreamAttributeValue::Ns(ns) => AttributeValue::Ns(ns.clone()),
+        StreamAttributeValue::Bs(bs) => {
+            AttributeValue::Bs(bs.iter().map(|b| Blob::new(b.as_ref())).collect())
+        }
+        StreamAttributeValue::L(l) => {
+            AttributeValue::L(l.iter().map(convert_value).collect::<Result<_, _>>()?)
+        }
+        StreamAttributeValue::M(m) => AttributeValue::M(convert_image(m)?),
+        other => {
+            return Err(DynamoItemParsingError::new(&format!(
+                "unsupported stream attribute value '{:?}'",
+                other
+            )))
+        }
+    })
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodbstreams::types::StreamRecord;
+    use fractic_core::collection;
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{AutoFields, DynamoObjectData, IdLogic, NestingLogic, PkSk},
+    };
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct TaskData {
+        name: String,
+        tags: Vec<String>,
+    }
+    dynamo_object!(Task, TaskData, "TASK", IdLogic::Uuid, NestingLogic::Root);
+
+    fn image(sk: &str, name: &str) -> HashMap<String, StreamAttributeValue> {
+        collection! {
+            "pk".to_string() => StreamAttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => StreamAttributeValue::S(sk.to_string()),
+            "name".to_string() => StreamAttributeValue::S(name.to_string()),
+            "tags".to_string() => StreamAttributeValue::L(vec![
+                StreamAttributeValue::S("urgent".to_string()),
+            ]),
+        }
+    }
+
+    fn record(event: OperationType, sk: &str, old: Option<&str>, new: Option<&str>) -> Record {
+        Record::builder()
+            .event_name(event)
+            .dynamodb(
+                StreamRecord::builder()
+                    .set_keys(Some(collection! {
+                        "pk".to_string() => StreamAttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => StreamAttributeValue::S(sk.to_string()),
+                    }))
+                    .set_old_image(old.map(|name| image(sk, name)))
+                    .set_new_image(new.map(|name| image(sk, name)))
+                    .build(),
+            )
+            .build()
+    }
+
+    fn batch() -> StreamBatch {
+        StreamBatch {
+            shard_id: "shard".to_string(),
+            records: vec![
+                record(OperationType::Insert, "TASK#1", None, Some("a")),
+                record(OperationType::Modify, "TASK#1", Some("a"), Some("b")),
+                // Objects of other types are skipped.
+                record(OperationType::Insert, "OTHER#1", None, Some("x")),
+                record(OperationType::Remove, "TASK#1", Some("b"), None),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_typed_changes() {
+        let changes = batch().changes::<Task>().unwrap();
+        assert_eq!(changes.len(), 3);
+        assert!(matches!(&changes[0], Change::Insert(task) if task.data().name == "a"));
+        assert!(matches!(
+            &changes[1],
+            Change::Modify { old, new } if old.data().name == "a" && new.data().name == "b"
+        ));
+        assert!(matches!(&changes[2], Change::Remove(task) if task.data().name == "b"));
+        assert_eq!(changes[2].object().data().tags, vec!["urgent".to_string()]);
+        assert_eq!(
+            changes[0].object().id(),
+            &PkSk {
+                pk: "ROOT".to_string(),
+                sk: "TASK#1".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_registered_changes() {
+        let registry = DynamoRegistry::new().register::<Task>();
+        let changes = batch().registered_changes(&registry).unwrap();
+        assert_eq!(changes.len(), 3);
+        let change = changes.into_iter().nth(1).unwrap().downcast::<Task>();
+        assert!(matches!(
+            change,
+            Some(Change::Modify { old, new }) if old.data().name == "a" && new.data().name == "b"
+        ));
+    }
+
+    #[test]
+    fn test_missing_image() {
+        let batch = StreamBatch {
+            shard_id: "shard".to_string(),
+            records: vec![record(OperationType::Remove, "TASK#1", None, None)],
+        };
+        assert!(batch.changes::<Task>().is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use fractic_env_config::EnvVariables;
+
+use crate::{env::DynamoEnvConfig, schema::DynamoObject};
+
+/// Maps object types to the table they are stored in, for setups where some
+/// types (such as high-churn telemetry objects) live in a separate table.
+/// Types without a registered table use the DynamoUtil's default table.
+///
+/// Types are identified by their id_label, so each label should only be used
+/// by a single type. Functions working on untyped items (such as
+/// query_generic and the raw_* functions) always use the default table.
+/// Subtree operations (such as copy_subtree, move_item and
+/// delete_item_recursive) can't find children across tables, so return
+/// DynamoInvalidOperation when any types are routed.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableRouter {
+    tables: HashMap<&'static str, String>,
+}
+
+impl TableRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores objects of type T in the given table.
+    pub fn route<T: DynamoObject>(mut self, table: impl Into<String>) -> Self {
+        self.tables.insert(T::id_label(), table.into());
+        self
+    }
+
+    /// The table registered for type T, if any.
+    pub fn table_for<T: DynamoObject>(&self) -> Option<&str> {
+        self.tables.get(T::id_label()).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub(crate) fn map_tables(mut self, f: impl Fn(&str) -> String) -> Self {
+        for table in self.tables.values_mut() {
+            *table = f(table);
+        }
+        self
+    }
+}
+
+/// Prefix and / or suffix added to table names (such as "staging_"), so that
+/// the same code can run against separate tables per environment.
+///
+/// DynamoUtil::new reads them from the optional DYNAMO_TABLE_PREFIX and
+/// DYNAMO_TABLE_SUFFIX environment variables, and applies them to the default
+/// table and the tables of the TableRouter (see DynamoUtil::with_table_naming).
+/// Table names passed directly to functions (such as create_table_for_schema)
+/// are used as-is, so should be taken from DynamoUtil::table, or converted
+/// with apply.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableNaming {
+    pub prefix: String,
+    pub suffix: String,
+}
+
+impl TableNaming {
+    pub fn new(prefix: impl Into<String>, suffix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            suffix: suffix.into(),
+        }
+    }
+
+    pub fn from_env(env: &EnvVariables<DynamoEnvConfig>) -> Self {
+        let optional = |key| env.get(key).map(String::clone).unwrap_or_default();
+        Self {
+            prefix: optional(&DynamoEnvConfig::DynamoTablePrefix),
+            suffix: optional(&DynamoEnvConfig::DynamoTableSuffix),
+        }
+    }
+
+    /// Physical name of the given logical table name.
+    pub fn apply(&self, table: &str) -> String {
+        format!("{}{}{}", self.prefix, table, self.suffix)
+    }
+
+    /// Logical name of the given physical table name (the reverse of apply).
+    /// Names without the prefix / suffix are returned unchanged.
+    pub fn strip<'a>(&self, table: &'a str) -> &'a str {
+        let table = table.strip_prefix(&self.prefix).unwrap_or(table);
+        table.strip_suffix(&self.suffix).unwrap_or(table)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefix.is_empty() && self.suffix.is_empty()
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{AutoFields, DynamoObjectData, IdLogic, NestingLogic},
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct EventData {}
+    dynamo_object!(Event, EventData, "EVENT", IdLogic::Uuid, NestingLogic::Root);
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct UserData {}
+    dynamo_object!(User, UserData, "USER", IdLogic::Uuid, NestingLogic::Root);
+
+    #[test]
+    fn test_table_router() {
+        let router = TableRouter::new().route::<Event>("telemetry_table");
+        assert_eq!(router.table_for::<Event>(), Some("telemetry_table"));
+        assert_eq!(router.table_for::<User>(), None);
+        assert!(!router.is_empty());
+        assert!(TableRouter::new().is_empty());
+    }
+
+    #[test]
+    fn test_table_naming() {
+        let naming = TableNaming::new("staging_", "_v2");
+        assert_eq!(naming.apply("my_table"), "staging_my_table_v2");
+        assert_eq!(naming.strip("staging_my_table_v2"), "my_table");
+        assert_eq!(naming.strip("my_table"), "my_table");
+        assert!(TableNaming::default().is_empty());
+
+        let router = TableRouter::new()
+            .route::<Event>("telemetry_table")
+            .map_tables(|table| naming.apply(table));
+        assert_eq!(
+            router.table_for::<Event>(),
+            Some("staging_telemetry_table_v2")
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use aws_sdk_dynamodb::types::{
+    AttributeDefinition, CreateGlobalSecondaryIndexAction, GlobalSecondaryIndex, KeySchemaElement,
+    KeyType, LocalSecondaryIndex, Projection, ProjectionType, ScalarAttributeType,
+};
+use fractic_server_error::ServerError;
+
+use crate::{errors::DynamoInvalidOperation, schema::DynamoObject};
+
+use super::IndexConfig;
+
+/// Key schema of a table managed by DynamoUtil, used to provision the table
+/// with DynamoUtil::create_table_for_schema (for example, in integration
+/// tests or dev environments).
+///
+/// All tables use string 'pk' / 'sk' keys. Secondary indexes are collected
+/// from the types stored in the table (see DynamoObject::indexes), or added
+/// directly for indexes which are only queried generically. All indexes use
+/// string keys and project all attributes.
+#[derive(Debug, Clone, Default)]
+pub struct TableSchema {
+    indexes: Vec<IndexConfig>,
+}
+
+impl TableSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Includes the indexes declared by type T.
+    pub fn with_type<T: DynamoObject>(mut self) -> Self {
+        self.indexes
+            .extend(T::indexes().into_iter().map(|index| index.config));
+        self
+    }
+
+    pub fn with_index(mut self, index: IndexConfig) -> Self {
+        self.indexes.push(index);
+        self
+    }
+
+    pub fn indexes(&self) -> &[IndexConfig] {
+        &self.indexes
+    }
+
+    /// Builds the CreateTable parameters for this schema. Indexes declared more
+    /// than once (for example, by several types sharing a GSI) are included
+    /// once, but must use the same key fields.
+    pub(crate) fn to_definition(&self) -> Result<TableDefinition, ServerError> {
+        let mut definition = TableDefinition {
+            key_schema: key_schema("pk", "sk"),
+            attribute_definitions: Vec::new(),
+            global_secondary_indexes: Vec::new(),
+            local_secondary_indexes: Vec::new(),
+        };
+        let mut attributes = vec!["pk", "sk"];
+        let mut indexes: Vec<&IndexConfig> = Vec::new();
+        for index in &self.indexes {
+            if let Some(existing) = indexes.iter().find(|i| i.name == index.name) {
+                if existing.partition_field != index.partition_field
+                    || existing.sort_field != index.sort_field
+                {
+                    return Err(DynamoInvalidOperation::new(&format!(
+                        "index '{}' is declared with different key fields",
+                        index.name
+                    )));
+                }
+                continue;
+            }
+            indexes.push(index);
+            for field in [index.partition_field, index.sort_field] {
+                if !attributes.contains(&field) {
+                    attributes.push(field);
+                }
+            }
+            if index.is_local() {
+                definition.local_secondary_indexes.push(
+                    LocalSecondaryIndex::builder()
+                        .index_name(index.name)
+                        .set_key_schema(Some(index_key_schema(index)))
+                        .projection(projection())
+                        .build()
+                        .expect("Invalid LocalSecondaryIndex"),
+                );
+            } else {
+                definition.global_secondary_indexes.push(
+                    GlobalSecondaryIndex::builder()
+                        .index_name(index.name)
+                        .set_key_schema(Some(index_key_schema(index)))
+                        .projection(projection())
+                        .build()
+                        .expect("Invalid GlobalSecondaryIndex"),
+                );
+            }
+        }
+        definition.attribute_definitions =
+            attributes.into_iter().map(attribute_definition).collect();
+        Ok(definition)
+    }
+}
+
+/// Builds the UpdateTable action creating a GSI on an existing table.
+pub(crate) fn create_global_index_action(index: &IndexConfig) -> CreateGlobalSecondaryIndexAction {
+    CreateGlobalSecondaryIndexAction::builder()
+        .index_name(index.name)
+        .set_key_schema(Some(index_key_schema(index)))
+        .projection(projection())
+        .build()
+        .expect("Invalid CreateGlobalSecondaryIndexAction")
+}
+
+/// Attribute definitions for the index's key fields.
+pub(crate) fn index_attribute_definitions(index: &IndexConfig) -> Vec<AttributeDefinition> {
+    [index.partition_field, index.sort_field]
+        .into_iter()
+        .map(attribute_definition)
+        .collect()
+}
+
+/// Whether an existing index's key schema uses the index's key fields.
+pub(crate) fn index_key_schema_matches(
+    index: &IndexConfig,
+    key_schema: &[KeySchemaElement],
+) -> bool {
+    key_schema.len() == 2
+        && key_schema.iter().all(|element| match element.key_type() {
+            KeyType::Hash => element.attribute_name() == index.partition_field,
+            KeyType::Range => element.attribute_name() == index.sort_field,
+            _ => false,
+        })
+}
+
+fn index_key_schema(index: &IndexConfig) -> Vec<KeySchemaElement> {
+    key_schema(index.partition_field, index.sort_field)
+}
+
+fn projection() -> Projection {
+    Projection::builder()
+        .projection_type(ProjectionType::All)
+        .build()
+}
+
+fn attribute_definition(name: &str) -> AttributeDefinition {
+    AttributeDefinition::builder()
+        .attribute_name(name)
+        .attribute_type(ScalarAttributeType::S)
+        .build()
+        .expect("Invalid AttributeDefinition")
+}
+
+/// Parameters passed to the backend's create_table.
+pub(crate) struct TableDefinition {
+    pub(crate) key_schema: Vec<KeySchemaElement>,
+    pub(crate) attribute_definitions: Vec<AttributeDefinition>,
+    pub(crate) global_secondary_indexes: Vec<GlobalSecondaryIndex>,
+    pub(crate) local_secondary_indexes: Vec<LocalSecondaryIndex>,
+}
+
+fn key_schema(partition_field: &str, sort_field: &str) -> Vec<KeySchemaElement> {
+    vec![
+        KeySchemaElement::builder()
+            .attribute_name(partition_field)
+            .key_type(KeyType::Hash)
+            .build()
+            .expect("Invalid KeySchemaElement"),
+        KeySchemaElement::builder()
+            .attribute_name(sort_field)
+            .key_type(KeyType::Range)
+            .build()
+            .expect("Invalid KeySchemaElement"),
+    ]
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        dynamo_object,
+        schema::{AutoFields, DynamoObjectData, IdLogic, IndexDefinition, NestingLogic},
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct TaskData {
+        owner: String,
+    }
+    dynamo_object!(
+        Task,
+        TaskData,
+        "TASK",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        indexes: vec![IndexDefinition {
+            config: IndexConfig::global("owner_index", "gsi1pk", "gsi1sk"),
+            keys: Box::new(|data: &TaskData| Some((data.owner.clone(), String::new()))),
+        }]
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default)]
+    pub struct NoteData {
+        owner: String,
+    }
+    dynamo_object!(
+        Note,
+        NoteData,
+        "NOTE",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        indexes: vec![IndexDefinition {
+            config: IndexConfig::global("owner_index", "gsi1pk", "gsi1sk"),
+            keys: Box::new(|data: &NoteData| Some((data.owner.clone(), String::new()))),
+        }]
+    );
+
+    fn attribute_names(definition: &TableDefinition) -> Vec<&str> {
+        definition
+            .attribute_definitions
+            .iter()
+            .map(|a| a.attribute_name())
+            .collect()
+    }
+
+    #[test]
+    fn test_table_definition() {
+        let definition = TableSchema::new()
+            .with_type::<Task>()
+            .with_type::<Note>()
+            .with_index(IndexConfig::local("lsi1", "lsi1sk"))
+            .to_definition()
+            .unwrap();
+
+        assert_eq!(definition.key_schema[0].attribute_name(), "pk");
+        assert_eq!(definition.key_schema[0].key_type(), &KeyType::Hash);
+        assert_eq!(definition.key_schema[1].attribute_name(), "sk");
+        assert_eq!(definition.key_schema[1].key_type(), &KeyType::Range);
+        assert_eq!(
+            attribute_names(&definition),
+            vec!["pk", "sk", "gsi1pk", "gsi1sk", "lsi1sk"]
+        );
+
+        // The shared GSI is only included once.
+        assert_eq!(definition.global_secondary_indexes.len(), 1);
+        let gsi = &definition.global_secondary_indexes[0];
+        assert_eq!(gsi.index_name(), "owner_index");
+        assert_eq!(gsi.key_schema()[0].attribute_name(), "gsi1pk");
+        assert_eq!(gsi.key_schema()[1].attribute_name(), "gsi1sk");
+
+        assert_eq!(definition.local_secondary_indexes.len(), 1);
+        let lsi = &definition.local_secondary_indexes[0];
+        assert_eq!(lsi.index_name(), "lsi1");
+        assert_eq!(lsi.key_schema()[0].attribute_name(), "pk");
+        assert_eq!(lsi.key_schema()[1].attribute_name(), "lsi1sk");
+    }
+
+    #[test]
+    fn test_table_definition_without_indexes() {
+        let definition = TableSchema::new().to_definition().unwrap();
+        assert_eq!(attribute_names(&definition), vec!["pk", "sk"]);
+        assert!(definition.global_secondary_indexes.is_empty());
+        assert!(definition.local_secondary_indexes.is_empty());
+    }
+
+    #[test]
+    fn test_index_key_schema_matches() {
+        let index = IndexConfig::global("owner_index", "gsi1pk", "gsi1sk");
+        assert!(index_key_schema_matches(&index, &index_key_schema(&index)));
+        assert!(!index_key_schema_matches(
+            &index,
+            &key_schema("gsi1pk", "other")
+        ));
+        assert!(!index_key_schema_matches(
+            &IndexConfig::local("lsi1", "gsi1sk"),
+            &index_key_schema(&index)
+        ));
+    }
+
+    #[test]
+    fn test_table_definition_conflicting_index() {
+        let result = TableSchema::new()
+            .with_type::<Task>()
+            .with_index(IndexConfig::global("owner_index", "owner", "gsi1sk"))
+            .to_definition();
+        assert!(result.is_err());
+    }
+}
@@ -2,30 +2,80 @@
 mod tests {
     use crate::errors::DynamoNotFound;
     use crate::schema::IdLogic;
-    use crate::util::{CreateOptions, TtlConfig, AUTO_FIELDS_TTL};
+    use crate::util::{
+        apply_merge_patch, build_key_condition,
+        cancellation::CancellationToken,
+        cursor::DynamoCursor,
+        export::ExportOptions,
+        filter::{FilterExpression, QueryFilter},
+        item_size::{DynamoMapExt, MAX_BATCH_WRITE_ITEMS, MAX_BATCH_WRITE_SIZE, MAX_ITEM_SIZE},
+        metrics::{ConsumedCapacityRecord, DynamoMetrics},
+        multi_region::UPDATED_REGION_ATTRIBUTE,
+        rate_limit::WriteRateLimit,
+        table_router::{TableNaming, TableRouter},
+        table_schema::TableSchema,
+        CreateOptions, DynamoInsertPosition, QueryOptions, ReadConsistency, ReturnedObject,
+        TtlConfig, AUTO_FIELDS_TTL,
+    };
     use crate::{
-        dynamo_object,
-        schema::{AutoFields, DynamoObject, DynamoObjectData, NestingLogic, PkSk},
+        dynamo_object, dynamo_object_family,
+        schema::{
+            parsing::serde_value_to_attribute_value, AutoFields, DynamoObject, DynamoObjectData,
+            DynamoObjectFamily, DynamoRegistry, ForeignKey, ForeignRef, ForeignRefList,
+            IndexDefinition, Migrations, NestingLogic, PkSk, Timestamp,
+        },
         util::{
-            backend::MockDynamoBackendImpl, DynamoQueryMatchType, DynamoUtil,
-            AUTO_FIELDS_CREATED_AT, AUTO_FIELDS_SORT, AUTO_FIELDS_UPDATED_AT,
+            backend::MockDynamoBackendImpl,
+            retry::{BackoffStrategy, RetryPolicy},
+            DynamoMap, DynamoQueryMatchType, DynamoUtil, IndexConfig, IndexKind,
+            AUTO_FIELDS_CREATED_AT, AUTO_FIELDS_CREATED_BY, AUTO_FIELDS_SCHEMA_VERSION,
+            AUTO_FIELDS_SORT, AUTO_FIELDS_UPDATED_AT, AUTO_FIELDS_UPDATED_BY, AUTO_FIELDS_VERSION,
         },
     };
 
     use aws_sdk_dynamodb::{
+        config::http::HttpResponse,
+        error::SdkError,
         operation::{
-            batch_write_item::BatchWriteItemOutput, delete_item::DeleteItemOutput,
-            get_item::GetItemOutput, put_item::PutItemOutput, query::QueryOutput,
+            batch_execute_statement::BatchExecuteStatementOutput,
+            batch_get_item::BatchGetItemOutput,
+            batch_write_item::BatchWriteItemOutput,
+            create_table::CreateTableOutput,
+            delete_item::DeleteItemOutput,
+            delete_table::DeleteTableOutput,
+            describe_export::DescribeExportOutput,
+            describe_table::DescribeTableOutput,
+            describe_time_to_live::DescribeTimeToLiveOutput,
+            execute_statement::ExecuteStatementOutput,
+            export_table_to_point_in_time::ExportTableToPointInTimeOutput,
+            get_item::GetItemOutput,
+            put_item::PutItemOutput,
+            query::QueryOutput,
+            scan::ScanOutput,
+            transact_write_items::{TransactWriteItemsError, TransactWriteItemsOutput},
             update_item::UpdateItemOutput,
+            update_table::UpdateTableOutput,
+            update_time_to_live::UpdateTimeToLiveOutput,
+        },
+        types::{
+            error::TransactionCanceledException, AttributeValue, BatchStatementError,
+            BatchStatementResponse, BillingMode, CancellationReason, ConsumedCapacity,
+            DeleteRequest, ExportDescription, ExportStatus, GlobalSecondaryIndexDescription,
+            IndexStatus, KeysAndAttributes, PutRequest, ReturnValue, Select, TableDescription,
+            TableStatus, TimeToLiveDescription, TimeToLiveStatus, WriteRequest,
         },
-        types::AttributeValue,
     };
     use chrono::{DateTime, Utc};
     use core::panic;
     use fractic_core::collection;
+    use futures::TryStreamExt;
     use mockall::predicate::*;
     use serde::{Deserialize, Serialize};
-    use std::collections::HashMap;
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
 
     #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
     pub struct TestDynamoObjectData {
@@ -40,6 +90,134 @@ mod tests {
         NestingLogic::TopLevelChildOfAny
     );
 
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct IndexedTestDynamoObjectData {
+        owner: Option<String>,
+        name: String,
+    }
+    dynamo_object!(
+        IndexedTestDynamoObject,
+        IndexedTestDynamoObjectData,
+        "INDEXED",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        indexes: vec![IndexDefinition {
+            config: IndexConfig::global("owner_index", "gsi1pk", "gsi1sk"),
+            keys: Box::new(|data: &IndexedTestDynamoObjectData| {
+                data.owner
+                    .as_ref()
+                    .map(|owner| (owner.clone(), data.name.clone()))
+            }),
+        }]
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct TimestampTestDynamoObjectData {}
+    dynamo_object!(
+        TimestampTestDynamoObject,
+        TimestampTestDynamoObjectData,
+        "EVENT",
+        IdLogic::Timestamp,
+        NestingLogic::TopLevelChildOfAny
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct ComponentTestDynamoObjectData {
+        status: String,
+    }
+    dynamo_object!(
+        ComponentTestDynamoObject,
+        ComponentTestDynamoObjectData,
+        "ORDER",
+        IdLogic::Uuid,
+        NestingLogic::TopLevelChildOfAny,
+        sk_component: |data: &ComponentTestDynamoObjectData| data.status.clone()
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct ShardedTestDynamoObjectData {}
+    dynamo_object!(
+        ShardedTestDynamoObject,
+        ShardedTestDynamoObjectData,
+        "TENANT",
+        IdLogic::Uuid,
+        NestingLogic::ShardedRoot(2)
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct TimestampMillisTestDynamoObjectData {}
+    dynamo_object!(
+        TimestampMillisTestDynamoObject,
+        TimestampMillisTestDynamoObjectData,
+        "EVENT",
+        IdLogic::TimestampMillis,
+        NestingLogic::TopLevelChildOfAny
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct VersionedTestDynamoObjectData {
+        name: String,
+    }
+    dynamo_object!(
+        VersionedTestDynamoObject,
+        VersionedTestDynamoObjectData,
+        "VERSIONED",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        versioned: true
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct MigratedTestDynamoObjectData {
+        title: String,
+    }
+    fn rename_name_to_title(_: u32, mut item: DynamoMap) -> DynamoMap {
+        if let Some(name) = item.remove("name") {
+            item.insert("title".to_string(), name);
+        }
+        item
+    }
+    dynamo_object!(
+        MigratedTestDynamoObject,
+        MigratedTestDynamoObjectData,
+        "MIGRATED",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        migrations: Migrations::new().add(0, rename_name_to_title)
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct PreservingTestDynamoObjectData {
+        name: String,
+    }
+    dynamo_object!(
+        PreservingTestDynamoObject,
+        PreservingTestDynamoObjectData,
+        "PRESERVING",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        versioned: true,
+        preserve_unknown_fields: true
+    );
+
+    #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+    pub struct AliasedTestDynamoObjectData {
+        title: String,
+    }
+    dynamo_object!(
+        AliasedTestDynamoObject,
+        AliasedTestDynamoObjectData,
+        "ALIASED",
+        IdLogic::Uuid,
+        NestingLogic::Root,
+        aliases: &[("name", "title")]
+    );
+
+    dynamo_object_family!(TestFamily {
+        Test(TestDynamoObject),
+        Event(TimestampTestDynamoObject),
+    });
+
     fn build_item_no_data() -> (TestDynamoObject, HashMap<String, AttributeValue>) {
         (
             TestDynamoObject {
@@ -122,13 +300,23 @@ mod tests {
             .with(
                 eq("my_table".to_string()),
                 eq(None),
-                eq("pk = :pk_val AND begins_with(sk, :sk_val)".to_string()),
+                eq("#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)".to_string()),
                 eq::<HashMap<String, AttributeValue>>(collection! {
                     ":pk_val".to_string() => AttributeValue::S("ROOT".to_string()),
                     ":sk_val".to_string() => AttributeValue::S("GROUP#123".to_string())
                 }),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq::<Option<HashMap<String, String>>>(Some(collection! {
+                    "#pk_field".to_string() => "pk".to_string(),
+                    "#sk_field".to_string() => "sk".to_string(),
+                })),
+                eq(None),
+                eq(None),
+                eq(None),
             )
-            .returning(|_, _, _, _| {
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
                 Ok(QueryOutput::builder()
                     .set_items(Some(vec![
                         build_item_high_sort().1,
@@ -145,6 +333,19 @@ mod tests {
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
         let result = util
             .query::<TestDynamoObject>(
@@ -154,6 +355,7 @@ mod tests {
                     sk: "GROUP#123".to_string(),
                 },
                 DynamoQueryMatchType::BeginsWith,
+                None,
             )
             .await
             .unwrap();
@@ -179,13 +381,23 @@ mod tests {
             .with(
                 eq("my_table".to_string()),
                 eq(None),
-                eq("pk = :pk_val AND begins_with(sk, :sk_val)".to_string()),
+                eq("#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)".to_string()),
                 eq::<HashMap<String, AttributeValue>>(collection! {
                     ":pk_val".to_string() => AttributeValue::S("ROOT".to_string()),
                     ":sk_val".to_string() => AttributeValue::S("GROUP#123#TEST".to_string())
                 }),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq::<Option<HashMap<String, String>>>(Some(collection! {
+                    "#pk_field".to_string() => "pk".to_string(),
+                    "#sk_field".to_string() => "sk".to_string(),
+                })),
+                eq(None),
+                eq(None),
+                eq(None),
             )
-            .returning(|_, _, _, _| {
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
                 Ok(QueryOutput::builder()
                     .set_items(Some(vec![
                         build_item_high_sort().1,
@@ -197,6 +409,19 @@ mod tests {
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
         let result = util
@@ -207,6 +432,7 @@ mod tests {
                     sk: "GROUP#123#TEST".to_string(),
                 },
                 DynamoQueryMatchType::BeginsWith,
+                None,
             )
             .await
             .unwrap();
@@ -219,596 +445,6525 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_get_item() {
+    async fn test_query_generic_multiple_pages() {
         let mut backend = MockDynamoBackendImpl::new();
         backend
-            .expect_get_item()
-            .with(
-                eq("my_table".to_string()),
-                eq::<HashMap<String, AttributeValue>>(collection! {
-                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
-                    "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string())
-                }),
-                eq(None),
-            )
-            .returning(|_, _, _| {
-                Ok(GetItemOutput::builder()
-                    .set_item(Some(build_item_high_sort().1))
+            .expect_query()
+            .withf(|_, _, _, _, _, exclusive_start_key, _, _, _, _, _| {
+                exclusive_start_key.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_high_sort().1]))
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, exclusive_start_key, _, _, _, _, _| {
+                exclusive_start_key
+                    .as_ref()
+                    .is_some_and(|k| k.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_low_sort().1]))
                     .build())
             });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
         let result = util
-            .get_item::<TestDynamoObject>(PkSk {
-                pk: "ROOT".to_string(),
-                sk: "GROUP#123#TEST#2".to_string(),
-            })
+            .query_generic(
+                None,
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123#TEST".to_string(),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
             .await
             .unwrap();
 
-        assert!(result.is_some());
-        let item = result.unwrap();
-        assert_eq!(item.pk(), "ROOT");
-        assert_eq!(item.sk(), "GROUP#123#TEST#2");
-        assert_eq!(item.data.val_non_null, "high_sort".to_string());
-        assert_eq!(item.data.val_nullable, None);
+        // Both pages should be returned, sorted together.
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0], build_item_low_sort().1);
+        assert_eq!(result[1], build_item_high_sort().1);
     }
 
     #[tokio::test]
-    async fn test_item_exists() {
+    async fn test_query_generic_limit() {
         let mut backend = MockDynamoBackendImpl::new();
         backend
-            .expect_get_item()
-            .with(
-                eq("my_table".to_string()),
-                eq::<HashMap<String, AttributeValue>>(collection! {
-                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
-                    "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string())
-                }),
-                eq(Some("pk".to_string())),
-            )
-            .returning(|_, _, _| {
-                Ok(GetItemOutput::builder()
-                    .set_item(Some(collection! {
+            .expect_query()
+            .withf(|_, _, _, _, limit, exclusive_start_key, _, _, _, _, _| {
+                *limit == Some(2) && exclusive_start_key.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_high_sort().1]))
+                    .count(1)
+                    .scanned_count(1)
+                    .set_last_evaluated_key(Some(collection! {
                         "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string()),
                     }))
                     .build())
             });
         backend
-            .expect_get_item()
-            .with(
-                eq("my_table".to_string()),
-                eq::<HashMap<String, AttributeValue>>(collection! {
-                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
-                    "sk".to_string() => AttributeValue::S("NOT_EXISTS#456".to_string())
-                }),
-                eq(Some("pk".to_string())),
-            )
-            .returning(|_, _, _| Ok(GetItemOutput::builder().set_item(None).build()));
+            .expect_query()
+            .withf(|_, _, _, _, limit, exclusive_start_key, _, _, _, _, _| {
+                // Only the remaining item should be requested.
+                *limit == Some(1) && exclusive_start_key.is_some()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_low_sort().1]))
+                    .count(1)
+                    .scanned_count(1)
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => AttributeValue::S("GROUP#123#TEST#3".to_string()),
+                    }))
+                    .build())
+            });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let expect_exists = util
-            .item_exists(PkSk {
-                pk: "ROOT".to_string(),
-                sk: "GROUP#123#TEST#2".to_string(),
-            })
-            .await;
-        let expect_not_exists = util
-            .item_exists(PkSk {
-                pk: "ROOT".to_string(),
-                sk: "NOT_EXISTS#456".to_string(),
-            })
-            .await;
+        // Should stop fetching pages once the limit is reached, even though
+        // more results are available.
+        let result = util
+            .query_generic(
+                None,
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123#TEST".to_string(),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                Some(QueryOptions {
+                    limit: Some(2),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
 
-        assert!(expect_exists.is_ok());
-        assert!(expect_not_exists.is_ok());
-        assert!(expect_exists.unwrap());
-        assert!(!expect_not_exists.unwrap());
+        assert_eq!(result.len(), 2);
+    }
+
+    #[test]
+    fn test_build_key_condition_reserved_words() {
+        // Index fields named after reserved words are only referenced through
+        // placeholders.
+        let (index_name, condition, names, values) = build_key_condition(
+            Some(IndexConfig::global("status_index", "status", "name")),
+            PkSk {
+                pk: "OPEN".to_string(),
+                sk: "a".to_string(),
+            },
+            DynamoQueryMatchType::GreaterThan,
+        )
+        .unwrap();
+        assert_eq!(index_name.as_deref(), Some("status_index"));
+        assert_eq!(condition, "#pk_field = :pk_val AND #sk_field > :sk_val");
+        assert_eq!(
+            names,
+            collection! {
+                "#pk_field".to_string() => "status".to_string(),
+                "#sk_field".to_string() => "name".to_string(),
+            }
+        );
+        assert_eq!(values.len(), 2);
+
+        // Partition-only queries don't reference the sort field.
+        let (_, condition, names, _) = build_key_condition(
+            None,
+            PkSk {
+                pk: "ROOT".to_string(),
+                sk: String::new(),
+            },
+            DynamoQueryMatchType::BeginsWith,
+        )
+        .unwrap();
+        assert_eq!(condition, "#pk_field = :pk_val");
+        assert_eq!(
+            names,
+            collection! { "#pk_field".to_string() => "pk".to_string() }
+        );
     }
 
     #[tokio::test]
-    async fn test_create_item() {
+    async fn test_query_generic_filter() {
         let mut backend = MockDynamoBackendImpl::new();
         backend
-            .expect_put_item()
-            .withf(|_, item| {
-                item.get(AUTO_FIELDS_CREATED_AT).is_some()
-                    && item.get(AUTO_FIELDS_UPDATED_AT).is_some()
-                    && item.get(AUTO_FIELDS_SORT).is_some()
-                    && item.get(AUTO_FIELDS_SORT).unwrap().as_n().unwrap() == "0.75"
-                    && item.get(AUTO_FIELDS_TTL).is_none()
-                    && item.get("val_non_null").is_some()
-                    && item.get("val_nullable").is_none()
+            .expect_query()
+            .withf(|_, _, condition, values, _, _, filter_expression, names, _, _, _| {
+                condition == "#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)"
+                    && *filter_expression
+                        == Some("attribute_exists(#f1) AND #f2 = :f2".to_string())
+                    && *names
+                        == Some(collection! {
+                            "#pk_field".to_string() => "pk".to_string(),
+                            "#sk_field".to_string() => "sk".to_string(),
+                            "#f1".to_string() => "val_nullable".to_string(),
+                            "#f2".to_string() => "val_non_null".to_string(),
+                        })
+                    && *values
+                        == collection! {
+                            ":pk_val".to_string() => AttributeValue::S("ROOT".to_string()),
+                            ":sk_val".to_string() => AttributeValue::S("GROUP#123#TEST".to_string()),
+                            ":f2".to_string() => AttributeValue::S("high_sort".to_string()),
+                        }
             })
-            .returning(|_, _| Ok(PutItemOutput::builder().build()));
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_high_sort().1]))
+                    .build())
+            });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let new_item = build_item_high_sort().0;
-
         let result = util
-            .create_item::<TestDynamoObject>(
+            .query_generic(
+                None,
                 PkSk {
                     pk: "ROOT".to_string(),
-                    sk: "GROUP#123".to_string(),
+                    sk: "GROUP#123#TEST".to_string(),
                 },
-                new_item.data,
-                Some(CreateOptions {
-                    custom_sort: Some(0.75),
+                DynamoQueryMatchType::BeginsWith,
+                Some(QueryOptions {
+                    filter: Some(
+                        FilterExpression::new()
+                            .attribute_exists("val_nullable")
+                            .equals("val_non_null", AttributeValue::S("high_sort".to_string())),
+                    ),
                     ..Default::default()
                 }),
             )
             .await
             .unwrap();
 
-        assert_eq!(result.pk(), "GROUP#123".to_string());
+        assert_eq!(result.len(), 1);
     }
 
     #[tokio::test]
-    async fn test_create_item_with_ttl() {
+    async fn test_query_generic_descending() {
         let mut backend = MockDynamoBackendImpl::new();
         backend
-            .expect_put_item()
-            .withf(|_, item| {
-                item.get(AUTO_FIELDS_CREATED_AT).is_some()
-                    && item.get(AUTO_FIELDS_UPDATED_AT).is_some()
-                    && item.get(AUTO_FIELDS_SORT).is_none()
-                    && item.get(AUTO_FIELDS_TTL).is_some()
-                    && DateTime::from_timestamp(
-                        item.get(AUTO_FIELDS_TTL)
-                            .unwrap()
-                            .as_n()
-                            .unwrap()
-                            .parse()
-                            .unwrap(),
-                        0,
-                    )
-                    .unwrap()
-                    .format("%Y-%m-%d")
-                    .to_string()
-                        == (Utc::now() + chrono::Duration::days(365))
-                            .format("%Y-%m-%d")
-                            .to_string()
-                    && item.get("val_non_null").is_some()
-                    && item.get("val_nullable").is_none()
+            .expect_query()
+            .withf(|_, _, _, _, _, _, _, _, _, scan_index_forward, _| {
+                *scan_index_forward == Some(false)
             })
-            .returning(|_, _| Ok(PutItemOutput::builder().build()));
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_item_no_data().1,
+                        build_item_low_sort().1,
+                        build_item_high_sort().1,
+                    ]))
+                    .build())
+            });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let new_item = build_item_high_sort().0;
-
         let result = util
-            .create_item::<TestDynamoObject>(
+            .query_generic(
+                None,
                 PkSk {
                     pk: "ROOT".to_string(),
-                    sk: "GROUP#123".to_string(),
+                    sk: "GROUP#123#TEST".to_string(),
                 },
-                new_item.data,
-                Some(CreateOptions {
-                    ttl: Some(TtlConfig::OneYear),
+                DynamoQueryMatchType::BeginsWith,
+                Some(QueryOptions {
+                    scan_index_forward: Some(false),
                     ..Default::default()
                 }),
             )
             .await
             .unwrap();
 
-        assert_eq!(result.pk(), "GROUP#123".to_string());
+        // Reverse of the ascending order (higher sort value first, and items
+        // without sort value before ordered items).
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0], build_item_no_data().1);
+        assert_eq!(result[1], build_item_high_sort().1);
+        assert_eq!(result[2], build_item_low_sort().1);
+    }
+
+    #[tokio::test]
+    async fn test_query_stream() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, exclusive_start_key, _, _, _, _, _| {
+                exclusive_start_key.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_item_high_sort().1,
+                        // Should be skipped, since it is of a different type.
+                        collection!(
+                            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                            "sk".to_string() => AttributeValue::S("GROUP#123#OTHEROBJECT#1".to_string())
+                        ),
+                    ]))
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, exclusive_start_key, _, _, _, _, _| {
+                exclusive_start_key.is_some()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_low_sort().1]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_stream::<TestDynamoObject>(
+                None,
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        // Ordered by 'sort' within each page only.
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].id(), build_item_high_sort().0.id());
+        assert_eq!(result[1].id(), build_item_low_sort().0.id());
+    }
+
+    #[tokio::test]
+    async fn test_query_stream_is_lazy() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, exclusive_start_key, _, _, _, _, _| {
+                exclusive_start_key.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_high_sort().1]))
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string()),
+                    }))
+                    .build())
+            });
+        // Second page should not be fetched, since only the first item is
+        // consumed.
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, exclusive_start_key, _, _, _, _, _| {
+                exclusive_start_key.is_some()
+            })
+            .never();
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let mut stream = Box::pin(
+            util.query_generic_stream(
+                None,
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
+            .unwrap(),
+        );
+        let first = stream.try_next().await.unwrap();
+        assert_eq!(first, Some(build_item_high_sort().1));
+    }
+
+    #[tokio::test]
+    async fn test_query_count() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(
+                |_, _, condition, values, _, exclusive_start_key, _, _, _, _, select| {
+                    condition == "#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)"
+                        && values.get(":pk_val").unwrap().as_s().unwrap() == "GROUP#123"
+                        && values.get(":sk_val").unwrap().as_s().unwrap() == "TEST#"
+                        && exclusive_start_key.is_none()
+                        && *select == Some(Select::Count)
+                },
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .count(3)
+                    .scanned_count(3)
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("TEST#3".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, exclusive_start_key, _, _, _, _, select| {
+                exclusive_start_key.is_some() && *select == Some(Select::Count)
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder().count(2).scanned_count(2).build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        // TestDynamoObject is TopLevelChildOfAny, so children are placed in
+        // the parent's sk partition.
+        let result = util
+            .query_count_with_inline_children::<TestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            })
+            .await
+            .unwrap();
+
+        // Counts should be summed across pages.
+        assert_eq!(result, 5);
+    }
+
+    #[tokio::test]
+    async fn test_query_count_with_inline_children() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let item = |sk: &str| {
+            collection! {
+                "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                "sk".to_string() => AttributeValue::S(sk.to_string()),
+                "val_non_null".to_string() => AttributeValue::S("value".to_string()),
+            }
+        };
+        util.raw_batch_put_item(vec![item("TEST#2"), item("TEST#2#NOTE#1")])
+            .await
+            .unwrap();
+        let parent_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+
+        // The inline child shares the sk prefix, so it's included in the
+        // count, but not in the query results.
+        let count = util
+            .query_count_with_inline_children::<TestDynamoObject>(parent_id)
+            .await
+            .unwrap();
+        assert_eq!(count, 2);
+        let objects = util
+            .query::<TestDynamoObject>(
+                None,
+                PkSk {
+                    pk: "GROUP#123".to_string(),
+                    sk: "TEST#".to_string(),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(objects.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_query_page() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, limit, exclusive_start_key, _, _, _, _, _| {
+                *limit == Some(1) && exclusive_start_key.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_high_sort().1]))
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, limit, exclusive_start_key, _, _, _, _, _| {
+                *limit == Some(1)
+                    && exclusive_start_key
+                        .as_ref()
+                        .is_some_and(|k| k.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_low_sort().1]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+        let parent_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+
+        let (page_1, cursor) = util
+            .query_page::<TestDynamoObject>(
+                None,
+                parent_id.clone(),
+                DynamoQueryMatchType::BeginsWith,
+                1,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(page_1.len(), 1);
+        assert_eq!(page_1[0].id(), build_item_high_sort().0.id());
+        assert!(cursor.is_some());
+
+        // Cursor should survive a round-trip through JSON (i.e. to a client).
+        let cursor: DynamoCursor =
+            serde_json::from_str(&serde_json::to_string(&cursor.unwrap()).unwrap()).unwrap();
+
+        let (page_2, cursor) = util
+            .query_page::<TestDynamoObject>(
+                None,
+                parent_id,
+                DynamoQueryMatchType::BeginsWith,
+                1,
+                Some(cursor),
+            )
+            .await
+            .unwrap();
+        assert_eq!(page_2.len(), 1);
+        assert_eq!(page_2[0].id(), build_item_low_sort().0.id());
+        assert!(cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_scan_all() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_scan()
+            .withf(|table, filter_expression, values, exclusive_start_key| {
+                table == "my_table"
+                    && *filter_expression == Some("contains(sk, :label)".to_string())
+                    && values
+                        .as_ref()
+                        .is_some_and(|v| v.get(":label").unwrap().as_s().unwrap() == "TEST#")
+                    && exclusive_start_key.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(ScanOutput::builder()
+                    .set_items(Some(vec![
+                        build_item_high_sort().1,
+                        // Parent of an inline child, matched by the label
+                        // filter but should be skipped.
+                        collection!(
+                            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                            "sk".to_string() => AttributeValue::S("TEST#1#OTHEROBJECT#1".to_string())
+                        ),
+                    ]))
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_scan()
+            .withf(|_, _, _, exclusive_start_key| exclusive_start_key.is_some())
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(ScanOutput::builder()
+                    .set_items(Some(vec![build_item_low_sort().1]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util.scan_all::<TestDynamoObject>().await.unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].id(), build_item_high_sort().0.id());
+        assert_eq!(result[1].id(), build_item_low_sort().0.id());
+    }
+
+    #[tokio::test]
+    async fn test_get_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .with(
+                eq("my_table".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string())
+                }),
+                eq(None),
+                eq(None),
+            )
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(build_item_high_sort().1))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .get_item::<TestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123#TEST#2".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert!(result.is_some());
+        let item = result.unwrap();
+        assert_eq!(item.pk(), "ROOT");
+        assert_eq!(item.sk(), "GROUP#123#TEST#2");
+        assert_eq!(item.data.val_non_null, "high_sort".to_string());
+        assert_eq!(item.data.val_nullable, None);
+    }
+
+    #[tokio::test]
+    async fn test_foreign_key_fetch() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .with(
+                eq("my_table".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string())
+                }),
+                eq(None),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(build_item_high_sort().1))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let foreign_key = ForeignKey::<TestDynamoObject>::new(PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123#TEST#2".to_string(),
+        })
+        .unwrap();
+        let item = foreign_key.fetch(&util).await.unwrap().unwrap();
+        assert_eq!(item.id(), foreign_key.id());
+        assert_eq!(item.data.val_non_null, "high_sort".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_table_router() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .withf(|table, _, _, _| table == "telemetry_table")
+            .times(2)
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(build_item_high_sort().1))
+                    .build())
+            });
+        backend
+            .expect_put_item()
+            .withf(|table, _, _, _, _| table == "telemetry_table")
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+        backend
+            .expect_query()
+            .withf(|table, _, _, _, _, _, _, _, _, _, _| table == "my_table")
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| Ok(QueryOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+        .with_table_router(TableRouter::new().route::<TestDynamoObject>("telemetry_table"));
+
+        assert_eq!(util.table_for::<TestDynamoObject>(), "telemetry_table");
+        assert_eq!(util.table_for::<IndexedTestDynamoObject>(), "my_table");
+
+        // Typed functions use the table registered for the type.
+        let (object, _) = build_item_high_sort();
+        util.get_item::<TestDynamoObject>(object.id().clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(util
+            .item_exists::<TestDynamoObject>(object.id().clone())
+            .await
+            .unwrap());
+        util.create_item::<TestDynamoObject>(
+            PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            },
+            object.data.clone(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        // Untyped functions use the default table.
+        util.query_generic(
+            None,
+            PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            },
+            DynamoQueryMatchType::BeginsWith,
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_table_naming() {
+        let util = DynamoUtil::new_in_memory("my_table")
+            .with_table_naming(TableNaming::new("staging_", ""))
+            // Routed tables are given as logical names.
+            .with_table_router(TableRouter::new().route::<TestDynamoObject>("telemetry_table"));
+        assert_eq!(util.table, "staging_my_table");
+        assert_eq!(
+            util.table_for::<TestDynamoObject>(),
+            "staging_telemetry_table"
+        );
+
+        // Replacing the naming renames the tables, rather than adding to the
+        // previous prefix.
+        let util = util.with_table_naming(TableNaming::new("prod_", "_v2"));
+        assert_eq!(util.table, "prod_my_table_v2");
+        assert_eq!(
+            util.table_for::<TestDynamoObject>(),
+            "prod_telemetry_table_v2"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_table_router_rejects_subtree_operations() {
+        // Children could be stored in any of the routed tables, so subtree
+        // operations are rejected before anything is read or written.
+        let util = DynamoUtil::new_in_memory("my_table")
+            .with_table_router(TableRouter::new().route::<TestDynamoObject>("telemetry_table"));
+        let id = build_item_high_sort().0.id;
+        assert!(util
+            .delete_item_recursive::<TestDynamoObject>(id.clone())
+            .await
+            .is_err());
+        assert!(util
+            .copy_subtree::<TestDynamoObject>(id.clone(), PkSk::root(), &DynamoRegistry::new())
+            .await
+            .is_err());
+        assert!(util
+            .move_item::<TestDynamoObject>(id, PkSk::root())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_foreign_ref_resolve() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .with(
+                eq("my_table".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                    "sk".to_string() => AttributeValue::S("TEST#2".to_string())
+                }),
+                eq(None),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(build_item_high_sort().1))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        // TestDynamoObject is a top-level child, so the full ID is rebuilt
+        // with the parent's sk as the pk.
+        let foreign_ref: ForeignRef = serde_json::from_value(serde_json::json!("TEST#2")).unwrap();
+        let parent_scope = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+        let item = foreign_ref
+            .resolve::<TestDynamoObject, _>(&util, &parent_scope)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(item.data.val_non_null, "high_sort".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_foreign_ref_list_resolve() {
+        let with_id = |mut item: DynamoMap, sk: &str| {
+            item.insert("pk".to_string(), AttributeValue::S("GROUP#123".to_string()));
+            item.insert("sk".to_string(), AttributeValue::S(sk.to_string()));
+            item
+        };
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_batch_get_item()
+            .withf(|table, keys, _| table == "my_table" && keys.len() == 3)
+            .times(1)
+            .returning(move |_, _, _| {
+                Ok(BatchGetItemOutput::builder()
+                    .responses(
+                        "my_table",
+                        vec![
+                            with_id(build_item_high_sort().1, "TEST#2"),
+                            with_id(build_item_low_sort().1, "TEST#1"),
+                        ],
+                    )
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let list: ForeignRefList =
+            serde_json::from_value(serde_json::json!("TEST#2,TEST#9,TEST#1")).unwrap();
+        let parent_scope = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+        let items = list
+            .resolve::<TestDynamoObject, _>(&util, &parent_scope)
+            .await
+            .unwrap();
+
+        // In list order, skipping the missing item.
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].data.val_non_null, "high_sort");
+        assert_eq!(items[1].data.val_non_null, "low_sort");
+    }
+
+    #[tokio::test]
+    async fn test_get_items() {
+        let mut backend = MockDynamoBackendImpl::new();
+        // First request: one item returned, one left unprocessed.
+        backend
+            .expect_batch_get_item()
+            .withf(|table, keys, _| table == "my_table" && keys.len() == 3)
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(BatchGetItemOutput::builder()
+                    .responses("my_table", vec![build_item_high_sort().1])
+                    .unprocessed_keys(
+                        "my_table",
+                        KeysAndAttributes::builder()
+                            .keys(collection! {
+                                "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                                "sk".to_string() => AttributeValue::S("GROUP#123#TEST#3".to_string()),
+                            })
+                            .build()
+                            .unwrap(),
+                    )
+                    .build())
+            });
+        // Second request: unprocessed key is retried.
+        backend
+            .expect_batch_get_item()
+            .withf(|_, keys, _| keys.len() == 1)
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(BatchGetItemOutput::builder()
+                    .responses("my_table", vec![build_item_low_sort().1])
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            // Rounds which return items don't count as retries, so partial
+            // responses are completed even with retries disabled.
+            retry: RetryPolicy::disabled(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let missing_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123#TEST#9".to_string(),
+        };
+        let result = util
+            .get_items::<TestDynamoObject>(vec![
+                build_item_low_sort().0.id,
+                missing_id,
+                build_item_high_sort().0.id,
+                // Duplicates should only be requested once.
+                build_item_low_sort().0.id,
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].as_ref().unwrap().data.val_non_null, "low_sort");
+        assert!(result[1].is_none());
+        assert_eq!(result[2].as_ref().unwrap().data.val_non_null, "high_sort");
+        assert_eq!(result[3].as_ref().unwrap().data.val_non_null, "low_sort");
+    }
+
+    #[tokio::test]
+    async fn test_get_items_unprocessed_exhausted() {
+        let mut backend = MockDynamoBackendImpl::new();
+        // No items are returned, so the round counts as a failed attempt.
+        backend
+            .expect_batch_get_item()
+            .times(1)
+            .returning(|_, keys, _| {
+                Ok(BatchGetItemOutput::builder()
+                    .unprocessed_keys(
+                        "my_table",
+                        KeysAndAttributes::builder()
+                            .set_keys(Some(keys))
+                            .build()
+                            .unwrap(),
+                    )
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: RetryPolicy::disabled(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        assert!(util
+            .get_items::<TestDynamoObject>(vec![build_item_low_sort().0.id])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_items_concurrent_chunks() {
+        let mut backend = MockDynamoBackendImpl::new();
+        // 150 keys are split into 2 chunks, each returning its items.
+        backend
+            .expect_batch_get_item()
+            .times(2)
+            .returning(|_, keys, _| {
+                let items = keys
+                    .into_iter()
+                    .map(|mut key| {
+                        let sk = key["sk"].as_s().unwrap().clone();
+                        key.insert("val_non_null".to_string(), AttributeValue::S(sk));
+                        key
+                    })
+                    .collect::<Vec<_>>();
+                Ok(BatchGetItemOutput::builder()
+                    .responses("my_table", items)
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 2,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        // Results keep the input order, regardless of the order in which the
+        // chunks complete.
+        let ids = (0..150)
+            .rev()
+            .map(|i| PkSk {
+                pk: "ROOT".to_string(),
+                sk: format!("GROUP#123#TEST#{}", i),
+            })
+            .collect::<Vec<_>>();
+        let result = util
+            .get_items::<TestDynamoObject>(ids.clone())
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 150);
+        for (id, item) in ids.iter().zip(result) {
+            assert_eq!(item.unwrap().data.val_non_null, id.sk);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_item_cached() {
+        let mut backend = MockDynamoBackendImpl::new();
+        // Fetched once, then served from the cache until the item is updated.
+        backend.expect_get_item().times(2).returning(|_, _, _, _| {
+            Ok(GetItemOutput::builder()
+                .set_item(Some(build_item_high_sort().1))
+                .build())
+        });
+        backend
+            .expect_update_item()
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+        .with_cache(std::time::Duration::from_secs(60));
+        let id = build_item_high_sort().0.id;
+
+        util.get_item::<TestDynamoObject>(id.clone()).await.unwrap();
+        util.get_item::<TestDynamoObject>(id.clone()).await.unwrap();
+        util.update_item(&build_item_high_sort().0).await.unwrap();
+        let result = util.get_item::<TestDynamoObject>(id).await.unwrap();
+
+        assert_eq!(result.unwrap().data.val_non_null, "high_sort");
+    }
+
+    #[tokio::test]
+    async fn test_query_cached() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .times(2)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![build_item_high_sort().1]))
+                    .build())
+            });
+        backend
+            .expect_delete_item()
+            .times(1)
+            .returning(|_, _, _, _, _, _| Ok(DeleteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+        .with_cache(std::time::Duration::from_secs(60));
+        let query_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+
+        for _ in 0..2 {
+            let result = util
+                .query::<TestDynamoObject>(
+                    None,
+                    query_id.clone(),
+                    DynamoQueryMatchType::BeginsWith,
+                    None,
+                )
+                .await
+                .unwrap();
+            assert_eq!(result.len(), 1);
+        }
+        // Deleting an item in the partition invalidates the cached query.
+        util.delete_item::<TestDynamoObject>(build_item_low_sort().0.id)
+            .await
+            .unwrap();
+        util.query::<TestDynamoObject>(None, query_id, DynamoQueryMatchType::BeginsWith, None)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_item_strong_consistency() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .withf(|_, _, _, consistent_read| *consistent_read == Some(true))
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(build_item_high_sort().1))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+        .with_read_consistency(ReadConsistency::Strong);
+
+        let result = util
+            .get_item::<TestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123#TEST#2".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert!(result.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_item_exists() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .with(
+                eq("my_table".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string())
+                }),
+                eq(Some("pk".to_string())),
+                eq(None),
+            )
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_get_item()
+            .with(
+                eq("my_table".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S("NOT_EXISTS#456".to_string())
+                }),
+                eq(Some("pk".to_string())),
+                eq(None),
+            )
+            .returning(|_, _, _, _| Ok(GetItemOutput::builder().set_item(None).build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let expect_exists = util
+            .item_exists::<TestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123#TEST#2".to_string(),
+            })
+            .await;
+        let expect_not_exists = util
+            .item_exists::<TestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "NOT_EXISTS#456".to_string(),
+            })
+            .await;
+
+        assert!(expect_exists.is_ok());
+        assert!(expect_not_exists.is_ok());
+        assert!(expect_exists.unwrap());
+        assert!(!expect_not_exists.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_create_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(|_, item, condition, _, _| {
+                item.get(AUTO_FIELDS_CREATED_AT).is_some()
+                    && item.get(AUTO_FIELDS_UPDATED_AT).is_some()
+                    && item.get(AUTO_FIELDS_SORT).is_some()
+                    && item.get(AUTO_FIELDS_SORT).unwrap().as_n().unwrap() == "0.75"
+                    && item.get(AUTO_FIELDS_TTL).is_none()
+                    && item.get("val_non_null").is_some()
+                    && item.get("val_nullable").is_none()
+                    && condition.is_none()
+            })
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let new_item = build_item_high_sort().0;
+
+        let result = util
+            .create_item::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                new_item.data,
+                Some(CreateOptions {
+                    custom_sort: Some(0.75),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.pk(), "GROUP#123".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_create_item_if_not_exists() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(|_, item, condition, _, _| {
+                item.get("pk").unwrap().as_s().unwrap() == "GROUP#123"
+                    && condition.as_deref() == Some("attribute_not_exists(pk)")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .create_item_if_not_exists::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                build_item_high_sort().0.data,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.pk(), "GROUP#123".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_create_item_with_ttl() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(|_, item, _, _, _| {
+                item.get(AUTO_FIELDS_CREATED_AT).is_some()
+                    && item.get(AUTO_FIELDS_UPDATED_AT).is_some()
+                    && item.get(AUTO_FIELDS_SORT).is_none()
+                    && item.get(AUTO_FIELDS_TTL).is_some()
+                    && DateTime::from_timestamp(
+                        item.get(AUTO_FIELDS_TTL)
+                            .unwrap()
+                            .as_n()
+                            .unwrap()
+                            .parse()
+                            .unwrap(),
+                        0,
+                    )
+                    .unwrap()
+                    .format("%Y-%m-%d")
+                    .to_string()
+                        == (Utc::now() + chrono::Duration::days(365))
+                            .format("%Y-%m-%d")
+                            .to_string()
+                    && item.get("val_non_null").is_some()
+                    && item.get("val_nullable").is_none()
+            })
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let new_item = build_item_high_sort().0;
+
+        let result = util
+            .create_item::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                new_item.data,
+                Some(CreateOptions {
+                    ttl: Some(TtlConfig::OneYear),
+                    ..Default::default()
+                }),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.pk(), "GROUP#123".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_batch_create_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_batch_put_item()
+            .withf(|_, items| {
+                items.len() == 2
+                    && items.iter().all(|item| {
+                        item.get(AUTO_FIELDS_CREATED_AT).is_some()
+                            && item.get(AUTO_FIELDS_UPDATED_AT).is_some()
+                            && item.get(AUTO_FIELDS_SORT).is_some()
+                            && item.get(AUTO_FIELDS_TTL).is_none()
+                            && item.get("val_non_null").is_some()
+                            && item.get("val_nullable").is_none()
+                    })
+            })
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let item1 = build_item_no_data().0;
+        let item2 = build_item_no_data().0;
+        let items = vec![
+            (
+                item1.data,
+                Some(CreateOptions {
+                    custom_sort: Some(0.12),
+                    ..Default::default()
+                }),
+            ),
+            (
+                item2.data,
+                Some(CreateOptions {
+                    custom_sort: Some(12.0),
+                    ..Default::default()
+                }),
+            ),
+        ];
+
+        let result = util
+            .batch_create_item::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                items,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_update_item_with_null() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, id, update_expr, values, keys, condition, _| {
+                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
+                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
+                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2 REMOVE #rmk1"
+                    && values.get(":v1").is_some()
+                    && values.get(":v2").is_some()
+                    && values.get(":v3").is_none()
+                    && {
+                        let mut v = vec![keys.get("#k1").unwrap(), keys.get("#k2").unwrap()];
+                        v.sort();
+                        v
+                    } == vec![&"updated_at".to_string(), &"val_non_null".to_string()]
+                    && keys.get("#rmk1").unwrap() == "val_nullable"
+                    && matches!(condition, Some(c) if c == "attribute_exists(pk)")
+            })
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let update_item = TestDynamoObject {
+            id: PkSk {
+                pk: "ABC#123".to_string(),
+                sk: "TEST#321".to_string(),
+            },
+            auto_fields: Default::default(),
+            data: TestDynamoObjectData {
+                val_non_null: "new_data".into(),
+                val_nullable: None,
+            },
+        };
+
+        let result = util.update_item(&update_item).await.unwrap();
+        assert_eq!(result, ());
+    }
+
+    #[tokio::test]
+    async fn test_update_item_non_null() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, id, update_expr, values, keys, condition, _| {
+                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
+                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
+                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2, #k3 = :v3"
+                    && values.get(":v1").is_some()
+                    && values.get(":v2").is_some()
+                    && values.get(":v3").is_some()
+                    && {
+                        let mut v = vec![
+                            keys.get("#k1").unwrap(),
+                            keys.get("#k2").unwrap(),
+                            keys.get("#k3").unwrap(),
+                        ];
+                        v.sort();
+                        v
+                    } == vec![
+                        &"updated_at".to_string(),
+                        &"val_non_null".to_string(),
+                        &"val_nullable".to_string(),
+                    ]
+                    && keys.get("#rmk1").is_none()
+                    && matches!(condition, Some(c) if c == "attribute_exists(pk)")
+            })
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let update_item = TestDynamoObject {
+            id: PkSk {
+                pk: "ABC#123".to_string(),
+                sk: "TEST#321".to_string(),
+            },
+            auto_fields: Default::default(),
+            data: TestDynamoObjectData {
+                val_non_null: "new_data".into(),
+                val_nullable: Some("non_null".into()),
+            },
+        };
+
+        let result = util.update_item(&update_item).await.unwrap();
+        assert_eq!(result, ());
+    }
+
+    #[tokio::test]
+    async fn test_versioned_object() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(|_, item, _, _, _| item.get(AUTO_FIELDS_VERSION).unwrap().as_n().unwrap() == "1")
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+        // Update of an object read at version 3.
+        backend
+            .expect_update_item()
+            .withf(|_, _, _, values, names, condition, _| {
+                condition.as_deref() == Some("attribute_exists(pk) AND #c1 = :cv1")
+                    && names.get("#c1") == Some(&AUTO_FIELDS_VERSION.to_string())
+                    && values.get(":cv1") == Some(&AttributeValue::N("3".to_string()))
+                    && values
+                        .values()
+                        .any(|v| v == &AttributeValue::N("4".to_string()))
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+        // Update of an object without a version (for example, written before
+        // versioning was enabled).
+        backend
+            .expect_update_item()
+            .withf(|_, _, _, values, names, condition, _| {
+                condition.as_deref() == Some("attribute_exists(pk) AND attribute_not_exists(#ver)")
+                    && names.get("#ver") == Some(&AUTO_FIELDS_VERSION.to_string())
+                    && values
+                        .values()
+                        .any(|v| v == &AttributeValue::N("1".to_string()))
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let mut object = util
+            .create_item::<VersionedTestDynamoObject>(
+                PkSk::root(),
+                VersionedTestDynamoObjectData {
+                    name: "first".to_string(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        util.update_item(&VersionedTestDynamoObject {
+            id: object.id.clone(),
+            auto_fields: AutoFields {
+                version: Some(3),
+                ..Default::default()
+            },
+            data: object.data.clone(),
+        })
+        .await
+        .unwrap();
+        object.data.name = "second".to_string();
+        util.update_item(&object).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_versioned_object_single_field_update() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let mut object = util
+            .create_item::<VersionedTestDynamoObject>(
+                PkSk::root(),
+                VersionedTestDynamoObjectData {
+                    name: "first".to_string(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        // Single-field updates also increment the version, so an update based
+        // on the object read before them is rejected.
+        util.increment_field::<VersionedTestDynamoObject>(object.id.clone(), "views", 1)
+            .await
+            .unwrap();
+        object.data.name = "second".to_string();
+        assert!(util.update_item(&object).await.is_err());
+
+        let mut object = util
+            .get_item::<VersionedTestDynamoObject>(object.id.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(object.auto_fields.version, Some(2));
+        object.data.name = "second".to_string();
+        util.update_item(&object).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replace_item() {
+        let created_at = Timestamp {
+            seconds: 1625247600,
+            nanos: 0,
+        };
+        let expected_created_at =
+            serde_value_to_attribute_value(serde_json::to_value(&created_at).unwrap())
+                .unwrap()
+                .unwrap();
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(move |_, item, condition, names, values| {
+                item.get("pk").unwrap().as_s().unwrap() == "ABC#123"
+                    && item.get("sk").unwrap().as_s().unwrap() == "TEST#321"
+                    && item.get("val_non_null").unwrap().as_s().unwrap() == "replaced"
+                    && item.get("val_nullable").is_none()
+                    && item.get(AUTO_FIELDS_UPDATED_AT).is_some()
+                    && item.get(AUTO_FIELDS_SORT).unwrap().as_n().unwrap() == "0.5"
+                    && item.get(AUTO_FIELDS_CREATED_AT) == Some(&expected_created_at)
+                    && condition.as_deref() == Some("attribute_exists(pk)")
+                    && names.is_none()
+                    && values.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.replace_item(&TestDynamoObject {
+            id: PkSk {
+                pk: "ABC#123".to_string(),
+                sk: "TEST#321".to_string(),
+            },
+            auto_fields: AutoFields {
+                created_at: Some(created_at),
+                sort: Some(0.5),
+                ..Default::default()
+            },
+            data: TestDynamoObjectData {
+                val_non_null: "replaced".into(),
+                val_nullable: None,
+            },
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replace_item_preserve_unknown_fields() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend.expect_put_item().times(0);
+        backend
+            .expect_update_item()
+            .withf(|_, id, update_expr, values, names, condition, _| {
+                let value_of = |field: &str| {
+                    names
+                        .iter()
+                        .find(|(_, name)| name.as_str() == field)
+                        .and_then(|(placeholder, _)| {
+                            values.get(&placeholder.replacen("#k", ":v", 1))
+                        })
+                };
+                id.get("sk").unwrap().as_s().unwrap() == "PRESERVING#123"
+                    // Unknown fields are neither written nor removed.
+                    && !names.values().any(|name| name == "added_later")
+                    && value_of("name").unwrap().as_s().unwrap() == "replaced"
+                    && value_of(AUTO_FIELDS_VERSION).unwrap().as_n().unwrap() == "4"
+                    && value_of(AUTO_FIELDS_CREATED_AT).is_some()
+                    // Known fields which are unset are removed, as they would
+                    // be by a put.
+                    && update_expr.contains("REMOVE")
+                    && names.values().any(|name| name == AUTO_FIELDS_SORT)
+                    && !names.values().any(|name| name == "pk" || name == "sk")
+                    && condition.as_deref() == Some("attribute_exists(pk) AND #ver = :ver")
+                    && values.get(":ver").unwrap().as_n().unwrap() == "3"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.replace_item(&PreservingTestDynamoObject {
+            id: PkSk {
+                pk: "ROOT".to_string(),
+                sk: "PRESERVING#123".to_string(),
+            },
+            auto_fields: AutoFields {
+                version: Some(3),
+                unknown_fields: collection! {
+                    "added_later".to_string() => serde_json::json!("value"),
+                },
+                ..Default::default()
+            },
+            data: PreservingTestDynamoObjectData {
+                name: "replaced".into(),
+            },
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update_item_removes_aliases() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, values, names, _, _| {
+                let set_title = names.iter().any(|(placeholder, name)| {
+                    name == "title"
+                        && values
+                            .get(&placeholder.replacen("#k", ":v", 1))
+                            .is_some_and(|v| v.as_s().unwrap() == "New")
+                });
+                let removed_alias = names
+                    .iter()
+                    .any(|(placeholder, name)| name == "name" && placeholder.starts_with("#rmk"));
+                update_expr.contains("REMOVE") && set_title && removed_alias
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.update_item(&AliasedTestDynamoObject::new(
+            PkSk {
+                pk: "ROOT".to_string(),
+                sk: "ALIASED#123".to_string(),
+            },
+            AliasedTestDynamoObjectData {
+                title: "New".into(),
+            },
+        ))
+        .await
+        .unwrap();
+    }
+
+    fn build_unmigrated_item() -> DynamoMap {
+        collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("MIGRATED#123".to_string()),
+            "name".to_string() => AttributeValue::S("old".to_string()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_item_migrated() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend.expect_get_item().returning(|_, _, _, _| {
+            Ok(GetItemOutput::builder()
+                .set_item(Some(build_unmigrated_item()))
+                .build())
+        });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let item = util
+            .get_item::<MigratedTestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "MIGRATED#123".to_string(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(item.data.title, "old");
+        assert_eq!(item.schema_version(), Some(1));
+        assert!(!item.has_unknown_fields());
+    }
+
+    #[tokio::test]
+    async fn test_create_item_schema_version() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(|_, item, _, _, _| {
+                item.get(AUTO_FIELDS_SCHEMA_VERSION)
+                    .unwrap()
+                    .as_n()
+                    .unwrap()
+                    == "1"
+                    && item.get("title").unwrap().as_s().unwrap() == "new"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+        backend
+            .expect_put_item()
+            .withf(|_, item, _, _, _| item.get(AUTO_FIELDS_SCHEMA_VERSION).is_none())
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.create_item::<MigratedTestDynamoObject>(
+            PkSk::root(),
+            MigratedTestDynamoObjectData {
+                title: "new".to_string(),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+
+        // Types without migrations are stored without a schema version.
+        util.create_item::<TestDynamoObject>(
+            PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            },
+            TestDynamoObjectData::default(),
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_migrate_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .withf(|_, _, _, consistent_read| *consistent_read == Some(true))
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(build_unmigrated_item()))
+                    .build())
+            });
+        backend
+            .expect_put_item()
+            .withf(|_, item, condition, names, values| {
+                item.get("title").unwrap().as_s().unwrap() == "old"
+                    && item.get("name").is_none()
+                    && item
+                        .get(AUTO_FIELDS_SCHEMA_VERSION)
+                        .unwrap()
+                        .as_n()
+                        .unwrap()
+                        == "1"
+                    && condition.as_deref()
+                        == Some("attribute_exists(pk) AND attribute_not_exists(#sv)")
+                    && names.as_ref().unwrap().get("#sv").unwrap() == AUTO_FIELDS_SCHEMA_VERSION
+                    && values.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let migrated = util
+            .migrate_item::<MigratedTestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "MIGRATED#123".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(migrated);
+    }
+
+    #[tokio::test]
+    async fn test_batch_update_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, key, _, _, _, condition, _| {
+                key.get("pk").unwrap().as_s().unwrap() == "ABC#123"
+                    && condition.as_deref() == Some("attribute_exists(pk)")
+            })
+            .times(30)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let objects = (0..30)
+            .map(|i| TestDynamoObject {
+                id: PkSk {
+                    pk: "ABC#123".to_string(),
+                    sk: format!("TEST#{}", i),
+                },
+                auto_fields: Default::default(),
+                data: TestDynamoObjectData {
+                    val_non_null: format!("value_{}", i),
+                    val_nullable: None,
+                },
+            })
+            .collect::<Vec<_>>();
+        util.batch_update_item(objects.iter().collect())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update_fields() {
+        #[derive(Serialize)]
+        struct TestPatch {
+            val_non_null: Option<String>,
+            val_nullable: Option<String>,
+        }
+
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, id, update_expr, values, keys, condition, _| {
+                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
+                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
+                    // Only the non-null patch field should be updated (no
+                    // REMOVE of val_non_null).
+                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2"
+                    && {
+                        let mut v = vec![keys.get("#k1").unwrap(), keys.get("#k2").unwrap()];
+                        v.sort();
+                        v
+                    } == vec![&"updated_at".to_string(), &"val_nullable".to_string()]
+                    && values.values().any(|v| v == &AttributeValue::S("patched".to_string()))
+                    && keys.get("#rmk1").is_none()
+                    && matches!(condition, Some(c) if c == "attribute_exists(pk)")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+        let id = PkSk {
+            pk: "ABC#123".to_string(),
+            sk: "TEST#321".to_string(),
+        };
+
+        util.update_fields::<TestDynamoObject, _>(
+            id.clone(),
+            &TestPatch {
+                val_non_null: None,
+                val_nullable: Some("patched".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+        // Empty patches are rejected.
+        assert!(util
+            .update_fields::<TestDynamoObject, _>(
+                id,
+                &TestPatch {
+                    val_non_null: None,
+                    val_nullable: None,
+                },
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_item_transaction_existing() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .with(
+                eq("my_table".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    "pk".to_string() => AttributeValue::S("ABC#123".to_string()),
+                    "sk".to_string() => AttributeValue::S("TEST#321".to_string())
+                }),
+                eq(None),
+                // Transaction should use a strongly consistent read.
+                eq(Some(true)),
+            )
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(collection! {
+                        // ID & auto fields should /not/ be included in the
+                        // condition expression of the transaction update:
+                        "pk".to_string() => AttributeValue::S("ABC#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("TEST#321".to_string()),
+                        "sort".to_string() => AttributeValue::N("0.75".to_string()),
+
+                        // Non-null data fields /should/ be checked for changes
+                        // in the transaction update condition expression:
+                        "val_non_null".to_string() => AttributeValue::S("old_data".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_update_item()
+            .withf(|_, id, update_expr, values, keys, condition, _| {
+                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
+                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
+                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2, #k3 = :v3"
+                    && values.get(":v1").is_some()
+                    && values.get(":v2").is_some()
+                    && values.get(":v3").is_some()
+                    && {
+                        let mut v = vec![
+                            keys.get("#k1").unwrap(),
+                            keys.get("#k2").unwrap(),
+                            keys.get("#k3").unwrap(),
+                        ];
+                        v.sort();
+                        v
+                    } == vec![
+                        &"updated_at".to_string(),
+                        &"val_non_null".to_string(),
+                        &"val_nullable".to_string(),
+                    ]
+                    && keys.get("#rmk1").is_none()
+                    && *condition == Some("attribute_exists(pk) AND #c1 = :cv1".to_string())
+                    && keys.get("#c1").unwrap() == "val_non_null"
+                    && values.get(":cv1").unwrap().as_s().unwrap() == "old_data"
+            })
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .update_item_transaction::<TestDynamoObject>(
+                PkSk {
+                    pk: "ABC#123".to_string(),
+                    sk: "TEST#321".to_string(),
+                },
+                |item| {
+                    let Some(mut item) = item else {
+                        return Err(DynamoNotFound::new());
+                    };
+                    item.val_non_null = "new_data".into();
+                    item.val_nullable = Some("non_null".into());
+                    Ok(item)
+                },
+            )
+            .await;
+        assert!(result.is_ok());
+
+        let object_after = result.unwrap();
+        assert_eq!(object_after.data.val_non_null, "new_data");
+        assert_eq!(object_after.data.val_nullable, Some("non_null".into()));
+    }
+
+    #[test]
+    fn test_apply_merge_patch() {
+        let mut target = serde_json::json!({
+            "title": "Goodbye!",
+            "author": { "givenName": "John", "familyName": "Doe" },
+            "tags": ["example", "sample"],
+            "content": "This will be unchanged"
+        });
+        apply_merge_patch(
+            &mut target,
+            &serde_json::json!({
+                "title": "Hello!",
+                "phoneNumber": "+01-123-456-7890",
+                "author": { "familyName": null },
+                "tags": ["example"]
+            }),
+        );
+        assert_eq!(
+            target,
+            serde_json::json!({
+                "title": "Hello!",
+                "author": { "givenName": "John" },
+                "tags": ["example"],
+                "content": "This will be unchanged",
+                "phoneNumber": "+01-123-456-7890"
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_patch_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .withf(|_, _, _, consistent_read| *consistent_read == Some(true))
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ABC#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("TEST#321".to_string()),
+                        "val_non_null".to_string() => AttributeValue::S("old_data".to_string()),
+                        "val_nullable".to_string() => AttributeValue::S("old_nullable".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_update_item()
+            .withf(|_, _, _, values, _, condition, _| {
+                values
+                    .values()
+                    .any(|value| value.as_s().is_ok_and(|s| s == "new_data"))
+                    && condition
+                        .as_deref()
+                        .is_some_and(|c| c.starts_with("attribute_exists(pk)"))
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let id = PkSk {
+            pk: "ABC#123".to_string(),
+            sk: "TEST#321".to_string(),
+        };
+        let result = util
+            .patch_item::<TestDynamoObject>(
+                id.clone(),
+                serde_json::json!({ "val_non_null": "new_data", "val_nullable": null }),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.data().val_non_null, "new_data");
+        assert_eq!(result.data().val_nullable, None);
+
+        // Invalid patches are rejected without any requests.
+        assert!(util
+            .patch_item::<TestDynamoObject>(id.clone(), serde_json::json!(["not an object"]))
+            .await
+            .is_err());
+        assert!(util
+            .patch_item::<TestDynamoObject>(id, serde_json::json!({ "pk": "OTHER" }))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_item_transaction_with_retry() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .withf(|_, _, _, consistent_read| *consistent_read == Some(true))
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("ABC#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("TEST#321".to_string()),
+                        "val_non_null".to_string() => AttributeValue::S("1".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_update_item()
+            .withf(|_, _, _, values, keys, condition, _| {
+                *condition == Some("attribute_exists(pk) AND #c1 = :cv1".to_string())
+                    && keys.get("#c1").unwrap() == "val_non_null"
+                    && values.get(":cv1").unwrap().as_s().unwrap() == "1"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let mut calls = 0;
+        let result = util
+            .update_item_transaction_with_retry::<TestDynamoObject>(
+                PkSk {
+                    pk: "ABC#123".to_string(),
+                    sk: "TEST#321".to_string(),
+                },
+                &RetryPolicy::default(),
+                |item| {
+                    calls += 1;
+                    let mut item = item.unwrap_or_default();
+                    let counter: i64 = item.val_non_null.parse().unwrap_or(0);
+                    item.val_non_null = (counter + 1).to_string();
+                    Ok(item)
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.data.val_non_null, "2");
+        assert_eq!(calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_item_transaction_new() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .withf(|table, _key, _projection, consistent_read| {
+                table == "my_table" && *consistent_read == Some(true)
+            })
+            .returning(|_, _, _, _| Ok(GetItemOutput::builder().set_item(None).build()));
+        backend
+            .expect_update_item()
+            .withf(|_, id, update_expr, values, keys, condition, _| {
+                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
+                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
+                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2, #k3 = :v3"
+                    && values.get(":v1").is_some()
+                    && values.get(":v2").is_some()
+                    && values.get(":v3").is_some()
+                    && {
+                        let mut v = vec![
+                            keys.get("#k1").unwrap(),
+                            keys.get("#k2").unwrap(),
+                            keys.get("#k3").unwrap(),
+                        ];
+                        v.sort();
+                        v
+                    } == vec![
+                        &"updated_at".to_string(),
+                        &"val_non_null".to_string(),
+                        &"val_nullable".to_string(),
+                    ]
+                    && keys.get("#rmk1").is_none()
+                    && *condition == Some("attribute_not_exists(pk)".to_string())
+                    && keys.get("#c1").is_none()
+                    && values.get(":cv1").is_none()
+            })
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .update_item_transaction::<TestDynamoObject>(
+                PkSk {
+                    pk: "ABC#123".to_string(),
+                    sk: "TEST#321".to_string(),
+                },
+                |item| {
+                    if item.is_some() {
+                        panic!("Item should not exist");
+                    }
+                    Ok(TestDynamoObjectData {
+                        val_non_null: "new_data".into(),
+                        val_nullable: Some("non_null".into()),
+                    })
+                },
+            )
+            .await;
+        assert!(result.is_ok());
+
+        let object_after = result.unwrap();
+        assert_eq!(object_after.data.val_non_null, "new_data");
+        assert_eq!(object_after.data.val_nullable, Some("non_null".into()));
+    }
+
+    #[tokio::test]
+    async fn test_create_item_with_index() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(|_, item, _, _, _| {
+                item.get("gsi1pk").unwrap().as_s().unwrap() == "USER#1"
+                    && item.get("gsi1sk").unwrap().as_s().unwrap() == "name"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.create_item::<IndexedTestDynamoObject>(
+            PkSk::root(),
+            IndexedTestDynamoObjectData {
+                owner: Some("USER#1".to_string()),
+                name: "name".to_string(),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_update_item_with_sparse_index() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, _, keys, _, _| {
+                // Since 'owner' is None, the object should be removed from the
+                // index.
+                let removed = keys
+                    .iter()
+                    .filter(|(k, _)| k.starts_with("#rmk"))
+                    .map(|(_, v)| v.as_str())
+                    .collect::<Vec<_>>();
+                update_expr.contains("REMOVE")
+                    && removed.contains(&"gsi1pk")
+                    && removed.contains(&"gsi1sk")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.update_item(&IndexedTestDynamoObject {
+            id: PkSk {
+                pk: "ROOT".to_string(),
+                sk: "INDEXED#1".to_string(),
+            },
+            auto_fields: Default::default(),
+            data: IndexedTestDynamoObjectData {
+                owner: None,
+                name: "name".to_string(),
+            },
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_by_index() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .with(
+                eq("my_table".to_string()),
+                eq(Some("owner_index".to_string())),
+                eq("#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    ":pk_val".to_string() => AttributeValue::S("USER#1".to_string()),
+                    ":sk_val".to_string() => AttributeValue::S("na".to_string()),
+                }),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq::<Option<HashMap<String, String>>>(Some(collection! {
+                    "#pk_field".to_string() => "gsi1pk".to_string(),
+                    "#sk_field".to_string() => "gsi1sk".to_string(),
+                })),
+                eq(None),
+                eq(None),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![collection! {
+                        "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                        "sk".to_string() => AttributeValue::S("INDEXED#1".to_string()),
+                        "owner".to_string() => AttributeValue::S("USER#1".to_string()),
+                        "name".to_string() => AttributeValue::S("name".to_string()),
+                    }]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_by_index::<IndexedTestDynamoObject>(
+                "owner_index",
+                "USER#1".to_string(),
+                "na".to_string(),
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].data.owner, Some("USER#1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_query_by_unregistered_index() {
+        let backend = MockDynamoBackendImpl::new();
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_by_index::<IndexedTestDynamoObject>(
+                "unknown_index",
+                "USER#1".to_string(),
+                "".to_string(),
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_local_index_strong_consistency() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .with(
+                eq("my_table".to_string()),
+                eq(Some("lsi1".to_string())),
+                eq("#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    ":pk_val".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                    ":sk_val".to_string() => AttributeValue::S("2024".to_string()),
+                }),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq::<Option<HashMap<String, String>>>(Some(collection! {
+                    "#pk_field".to_string() => "pk".to_string(),
+                    "#sk_field".to_string() => "lsi1sk".to_string(),
+                })),
+                eq(Some(true)),
+                eq(None),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| Ok(QueryOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: ReadConsistency::Strong,
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.query::<TestDynamoObject>(
+            Some(IndexConfig::local("lsi1", "lsi1sk")),
+            PkSk {
+                pk: "GROUP#123".to_string(),
+                sk: "2024".to_string(),
+            },
+            DynamoQueryMatchType::BeginsWith,
+            None,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_query_global_index_consistency() {
+        let mut backend = MockDynamoBackendImpl::new();
+        // The DynamoUtil's default strong consistency should not be applied to
+        // the GSI query.
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, _, _, _, _, consistent_read, _, _| consistent_read.is_none())
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| Ok(QueryOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: ReadConsistency::Strong,
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+        let index = IndexConfig::global("gsi1", "gsi1pk", "gsi1sk");
+        let id = PkSk {
+            pk: "USER#1".to_string(),
+            sk: "".to_string(),
+        };
+
+        util.query::<TestDynamoObject>(
+            Some(index),
+            id.clone(),
+            DynamoQueryMatchType::BeginsWith,
+            None,
+        )
+        .await
+        .unwrap();
+
+        // Explicitly requesting strong consistency on a GSI is an error.
+        let result = util
+            .query::<TestDynamoObject>(
+                Some(index),
+                id,
+                DynamoQueryMatchType::BeginsWith,
+                Some(QueryOptions {
+                    consistency: Some(ReadConsistency::Strong),
+                    ..Default::default()
+                }),
+            )
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_index_kind() {
+        assert!(IndexConfig::local("lsi1", "lsi1sk").is_local());
+        assert!(!IndexConfig::global("gsi1", "gsi1pk", "gsi1sk").is_local());
+        // A GSI keyed on the table's partition key is still a GSI.
+        assert!(!IndexConfig::global("gsi2", "pk", "created_at").is_local());
+        assert_eq!(
+            IndexConfig::global("gsi2", "pk", "created_at").kind(),
+            IndexKind::Global
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_between() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .with(
+                eq("my_table".to_string()),
+                eq(None),
+                eq("#pk_field = :pk_val AND #sk_field BETWEEN :sk_val AND :sk_max".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    ":pk_val".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                    ":sk_val".to_string() => AttributeValue::S("EVENT#0001630000000000".to_string()),
+                    ":sk_max".to_string() => AttributeValue::S("EVENT#0001630000060000".to_string()),
+                }),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq::<Option<HashMap<String, String>>>(Some(collection! {
+                    "#pk_field".to_string() => "pk".to_string(),
+                    "#sk_field".to_string() => "sk".to_string(),
+                })),
+                eq(None),
+                eq(None),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![collection! {
+                        "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("EVENT#0001630000001000".to_string()),
+                    }]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_between::<TimestampTestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                DateTime::from_timestamp(1630000000, 0).unwrap(),
+                DateTime::from_timestamp(1630000060, 0).unwrap(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].sk(), "EVENT#0001630000001000");
+    }
+
+    #[tokio::test]
+    async fn test_batch_create_item_timestamp_millis() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_batch_put_item()
+            .withf(|_, items| {
+                let sks = items
+                    .iter()
+                    .map(|item| item.get("sk").unwrap().as_s().unwrap().clone())
+                    .collect::<std::collections::HashSet<_>>();
+                // Items created in the same millisecond still get distinct IDs.
+                items.len() == 3
+                    && sks.len() == 3
+                    && sks
+                        .iter()
+                        .all(|sk| sk.starts_with("EVENT#") && sk.len() == 28)
+            })
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .batch_create_item::<TimestampMillisTestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                vec![(TimestampMillisTestDynamoObjectData {}, None); 3],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_query_between_timestamp_millis() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, condition, values, _, _, _, _, _, _, _| {
+                condition == "#pk_field = :pk_val AND #sk_field BETWEEN :sk_val AND :sk_max"
+                    && values.get(":sk_val")
+                        == Some(&AttributeValue::S(
+                            "EVENT#0001630000000000000000".to_string(),
+                        ))
+                    && values.get(":sk_max")
+                        == Some(&AttributeValue::S(
+                            "EVENT#0001630000060000zzzzzz".to_string(),
+                        ))
+            })
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![collection! {
+                        "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("EVENT#0001630000001000aB3xYz".to_string()),
+                    }]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_between::<TimestampMillisTestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                DateTime::from_timestamp(1630000000, 0).unwrap(),
+                DateTime::from_timestamp(1630000060, 0).unwrap(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].sk(), "EVENT#0001630000001000aB3xYz");
+    }
+
+    #[tokio::test]
+    async fn test_query_all_sharded() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, condition, values, _, _, _, _, _, _, _| {
+                condition == "#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)"
+                    && values.get(":sk_val") == Some(&AttributeValue::S("TENANT#".to_string()))
+            })
+            .times(2)
+            .returning(|_, _, _, values, _, _, _, _, _, _, _| {
+                let pk = values.get(":pk_val").unwrap().as_s().unwrap().clone();
+                let (sk, sort) = match pk.as_str() {
+                    "ROOT#00" => ("TENANT#b", "2.0"),
+                    "ROOT#01" => ("TENANT#a", "1.0"),
+                    _ => panic!("unexpected shard {}", pk),
+                };
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![collection! {
+                        "pk".to_string() => AttributeValue::S(pk),
+                        "sk".to_string() => AttributeValue::S(sk.to_string()),
+                        AUTO_FIELDS_SORT.to_string() => AttributeValue::N(sort.to_string()),
+                    }]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_all::<ShardedTestDynamoObject>(PkSk::root())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            result
+                .iter()
+                .map(|item| item.id().clone())
+                .collect::<Vec<_>>(),
+            vec![
+                PkSk {
+                    pk: "ROOT#01".to_string(),
+                    sk: "TENANT#a".to_string(),
+                },
+                PkSk {
+                    pk: "ROOT#00".to_string(),
+                    sk: "TENANT#b".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_between_requires_timestamp_ids() {
+        let backend = MockDynamoBackendImpl::new();
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_between::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                DateTime::from_timestamp(1630000000, 0).unwrap(),
+                DateTime::from_timestamp(1630000060, 0).unwrap(),
+                None,
+            )
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_first() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .with(
+                eq("my_table".to_string()),
+                eq(None),
+                eq("#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    ":pk_val".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                    ":sk_val".to_string() => AttributeValue::S("EVENT#".to_string()),
+                }),
+                eq(Some(1)),
+                eq(None),
+                eq(None),
+                eq::<Option<HashMap<String, String>>>(Some(collection! {
+                    "#pk_field".to_string() => "pk".to_string(),
+                    "#sk_field".to_string() => "sk".to_string(),
+                })),
+                eq(None),
+                eq(Some(true)),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![collection! {
+                        "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("EVENT#0001630000000000".to_string()),
+                    }]))
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("EVENT#0001630000000000".to_string()),
+                    }))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_first::<TimestampTestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.unwrap().sk(), "EVENT#0001630000000000");
+    }
+
+    #[tokio::test]
+    async fn test_query_last_skips_inline_children() {
+        let mut backend = MockDynamoBackendImpl::new();
+        // The first (Limit=1) page only contains an inline child of the last
+        // object.
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, limit, start_key, _, _, _, forward, _| {
+                *limit == Some(1) && start_key.is_none() && *forward == Some(false)
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![collection! {
+                        "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("EVENT#0001630000001000#OTHER#1".to_string()),
+                    }]))
+                    .set_last_evaluated_key(Some(collection! {
+                        "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("EVENT#0001630000001000#OTHER#1".to_string()),
+                    }))
+                    .build())
+            });
+        backend
+            .expect_query()
+            .withf(|_, _, _, _, limit, start_key, _, _, _, forward, _| {
+                limit.is_none() && start_key.is_some() && *forward == Some(false)
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                            "sk".to_string() => AttributeValue::S("EVENT#0001630000001000".to_string()),
+                        },
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                            "sk".to_string() => AttributeValue::S("EVENT#0001630000000000".to_string()),
+                        },
+                    ]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_last::<TimestampTestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result.unwrap().sk(), "EVENT#0001630000001000");
+    }
+
+    #[tokio::test]
+    async fn test_query_family() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_item_high_sort().1,
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                            "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2#EVENT#0001630000000000".to_string()),
+                        },
+                        // Not part of the family, should be skipped.
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                            "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2#OTHER#1".to_string()),
+                        },
+                    ]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_family::<TestFamily>(
+                None,
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123#TEST#2".to_string(),
+                },
+                DynamoQueryMatchType::BeginsWith,
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert!(matches!(&result[0], TestFamily::Test(o) if o.data.val_non_null == "high_sort"));
+        assert!(matches!(&result[1], TestFamily::Event(_)));
+    }
+
+    #[tokio::test]
+    async fn test_query_with_inline_children() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .with(
+                eq("my_table".to_string()),
+                eq(None),
+                eq("#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    ":pk_val".to_string() => AttributeValue::S("ROOT".to_string()),
+                    ":sk_val".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string()),
+                }),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq::<Option<HashMap<String, String>>>(Some(collection! {
+                    "#pk_field".to_string() => "pk".to_string(),
+                    "#sk_field".to_string() => "sk".to_string(),
+                })),
+                eq(None),
+                eq(None),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_item_high_sort().1,
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                            "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2#EVENT#0001630000000000".to_string()),
+                        },
+                        // Shares the sk prefix, but is not a child.
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                            "sk".to_string() => AttributeValue::S("GROUP#123#TEST#22".to_string()),
+                        },
+                    ]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let (parent, children) = util
+            .query_with_inline_children::<TestDynamoObject, TestFamily>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123#TEST#2".to_string(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(parent.data.val_non_null, "high_sort");
+        assert_eq!(children.len(), 1);
+        assert!(matches!(&children[0], TestFamily::Event(_)));
+    }
+
+    #[test]
+    fn test_family_try_from() {
+        let other: HashMap<String, AttributeValue> = collection! {
+            "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+            "sk".to_string() => AttributeValue::S("OTHER#1".to_string()),
+        };
+        assert!(matches!(
+            TestFamily::try_from(build_item_low_sort().1),
+            Ok(TestFamily::Test(_))
+        ));
+        assert!(TestFamily::parse_dynamo_map(&other).is_none());
+        assert!(TestFamily::try_from(other).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_partiql_query() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_execute_statement()
+            .with(
+                eq("SELECT * FROM my_table WHERE pk = ?".to_string()),
+                eq(Some(vec![AttributeValue::S("ROOT".to_string())])),
+                eq(None),
+                eq(None),
+            )
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(ExecuteStatementOutput::builder()
+                    .set_items(Some(vec![build_item_high_sort().1]))
+                    .next_token("token")
+                    .build())
+            });
+        backend
+            .expect_execute_statement()
+            .with(always(), always(), eq(None), eq(Some("token".to_string())))
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(ExecuteStatementOutput::builder()
+                    .set_items(Some(vec![build_item_low_sort().1]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .partiql_query::<TestDynamoObject>(
+                "SELECT * FROM my_table WHERE pk = ?",
+                vec![AttributeValue::S("ROOT".to_string())],
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].data.val_non_null, "high_sort");
+        assert_eq!(result[1].data.val_non_null, "low_sort");
+    }
+
+    #[tokio::test]
+    async fn test_partiql_batch_execute() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_batch_execute_statement()
+            .withf(|statements| statements.len() == 25)
+            .times(1)
+            .returning(|_| {
+                Ok(BatchExecuteStatementOutput::builder()
+                    .set_responses(Some(vec![BatchStatementResponse::builder().build(); 25]))
+                    .build())
+            });
+        backend
+            .expect_batch_execute_statement()
+            .withf(|statements| statements.len() == 5)
+            .times(1)
+            .returning(|_| {
+                Ok(BatchExecuteStatementOutput::builder()
+                    .set_responses(Some(vec![
+                        BatchStatementResponse::builder()
+                            .error(BatchStatementError::builder().message("failed").build())
+                            .build();
+                        5
+                    ]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .partiql_batch_execute(
+                (0..30)
+                    .map(|_| ("DELETE FROM my_table WHERE pk = ?".to_string(), vec![]))
+                    .collect(),
+            )
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transaction() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_transact_write_items()
+            .withf(|items, client_request_token| {
+                client_request_token.is_none()
+                    && items.len() == 4
+                    && items[0].put.as_ref().is_some_and(|put| {
+                        put.table_name == "my_table"
+                            && put.item.get("val_non_null").unwrap().as_s().unwrap() == "new"
+                    })
+                    && items[1].update.as_ref().is_some_and(|update| {
+                        update.key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                            && update.condition_expression.as_deref()
+                                == Some("attribute_exists(pk)")
+                    })
+                    && items[2].delete.as_ref().is_some_and(|delete| {
+                        delete.key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#3"
+                    })
+                    && items[3]
+                        .condition_check
+                        .as_ref()
+                        .is_some_and(|check| check.condition_expression == "#f1 = :f1")
+            })
+            .times(1)
+            .returning(|_, _| Ok(TransactWriteItemsOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let mut transaction = util.transaction();
+        let created = transaction
+            .create::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                TestDynamoObjectData {
+                    val_non_null: "new".to_string(),
+                    val_nullable: None,
+                },
+                None,
+            )
+            .unwrap();
+        transaction.update(&build_item_high_sort().0).unwrap();
+        transaction
+            .delete::<TestDynamoObject>(build_item_low_sort().0.id)
+            .unwrap();
+        transaction
+            .condition_check::<TestDynamoObject>(
+                build_item_no_data().0.id,
+                FilterExpression::new().equals("val_non_null", AttributeValue::S("".to_string())),
+            )
+            .unwrap();
+        assert_eq!(transaction.len(), 4);
+        transaction.commit().await.unwrap();
+
+        assert!(created.sk().starts_with("TEST#"));
+    }
+
+    #[tokio::test]
+    async fn test_transaction_client_request_token() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_transact_write_items()
+            .withf(|items, client_request_token| {
+                items.len() == 1 && client_request_token.as_deref() == Some("request-123")
+            })
+            .times(1)
+            .returning(|_, _| Ok(TransactWriteItemsOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let mut transaction = util.transaction();
+        transaction
+            .delete::<TestDynamoObject>(build_item_low_sort().0.id)
+            .unwrap();
+        transaction.set_client_request_token("request-123");
+        transaction.commit().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_item_idempotent() {
+        let tokens = Arc::new(Mutex::new(Vec::<String>::new()));
+        let tokens_clone = tokens.clone();
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_transact_write_items()
+            .withf(|items, client_request_token| {
+                client_request_token.is_some()
+                    && items.len() == 1
+                    && items[0].put.as_ref().is_some_and(|put| {
+                        put.condition_expression.as_deref() == Some("attribute_not_exists(pk)")
+                            && put.item.get("val_non_null").unwrap().as_s().unwrap() == "new"
+                    })
+            })
+            .times(3)
+            .returning(move |_, client_request_token| {
+                tokens_clone
+                    .lock()
+                    .unwrap()
+                    .push(client_request_token.unwrap());
+                Ok(TransactWriteItemsOutput::builder().build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let parent_id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+        let data = |val: &str| TestDynamoObjectData {
+            val_non_null: val.to_string(),
+            val_nullable: None,
+        };
+        let first = util
+            .create_item_idempotent::<TestDynamoObject>(parent_id.clone(), data("new"), None)
+            .await
+            .unwrap();
+        let second = util
+            .create_item_idempotent::<TestDynamoObject>(parent_id.clone(), data("new"), None)
+            .await
+            .unwrap();
+        let other_parent = util
+            .create_item_idempotent::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#456".to_string(),
+                },
+                data("new"),
+                None,
+            )
+            .await
+            .unwrap();
+
+        // Same arguments produce the same ID and token.
+        assert_eq!(first.id(), second.id());
+        assert_ne!(first.id(), other_parent.id());
+        let tokens = tokens.lock().unwrap();
+        assert_eq!(tokens[0], tokens[1]);
+        assert_ne!(tokens[0], tokens[2]);
+        assert!(tokens[0].len() <= 36);
+    }
+
+    #[tokio::test]
+    async fn test_create_item_idempotent_transaction_conflict() {
+        // A cancellation for any reason other than the item already existing is
+        // returned as an error, without reading the item.
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_transact_write_items()
+            .times(1)
+            .returning(|_, _| {
+                Err(SdkError::service_error(
+                    TransactWriteItemsError::TransactionCanceledException(
+                        TransactionCanceledException::builder()
+                            .set_cancellation_reasons(Some(vec![CancellationReason::builder()
+                                .code("TransactionConflict")
+                                .build()]))
+                            .build(),
+                    ),
+                    HttpResponse::new(400u16.try_into().unwrap(), "".into()),
+                ))
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .create_item_idempotent::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                TestDynamoObjectData {
+                    val_non_null: "new".to_string(),
+                    val_nullable: None,
+                },
+                None,
+            )
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_too_many_items() {
+        let backend = MockDynamoBackendImpl::new();
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let mut transaction = util.transaction();
+        for _ in 0..101 {
+            transaction
+                .delete::<TestDynamoObject>(build_item_low_sort().0.id)
+                .unwrap();
+        }
+
+        assert!(transaction.commit().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_cancellation_reasons() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let stale = util
+            .create_item::<VersionedTestDynamoObject>(
+                PkSk::root(),
+                VersionedTestDynamoObjectData {
+                    name: "first".to_string(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        let mut current = stale.clone();
+        current.data.name = "second".to_string();
+        util.update_item(&current).await.unwrap();
+
+        // The failed operation is reported with its position in the
+        // transaction.
+        let mut transaction = util.transaction();
+        transaction
+            .delete::<TestDynamoObject>(build_item_low_sort().0.id)
+            .unwrap();
+        transaction.update(&stale).unwrap();
+        let cancelled = transaction.try_commit().await.unwrap().unwrap_err();
+        assert_eq!(cancelled.reasons.len(), 1);
+        assert_eq!(cancelled.reasons[0].index, 1);
+        assert_eq!(cancelled.reasons[0].code, "ConditionalCheckFailed");
+        assert!(cancelled.is_condition_failure(1));
+        assert!(!cancelled.is_condition_failure(0));
+
+        let mut transaction = util.transaction();
+        transaction.update(&stale).unwrap();
+        assert!(transaction.commit().await.is_err());
+        let object = util
+            .get_item::<VersionedTestDynamoObject>(stale.id.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(object.data.name, "second");
+    }
+
+    #[tokio::test]
+    async fn test_increment_field() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, key, update_expr, values, names, condition, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && update_expr == "SET #k2 = :v2 ADD #k1 :v1"
+                    && names.get("#k1").unwrap() == "view_count"
+                    && names.get("#k2").unwrap() == AUTO_FIELDS_UPDATED_AT
+                    && values.get(":v1").unwrap().as_n().unwrap() == "-2"
+                    && values.get(":v2").is_some()
+                    && condition.as_deref() == Some("attribute_exists(pk)")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.increment_field::<TestDynamoObject>(build_item_high_sort().0.id, "view_count", -2)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_versioned_increment_expressions() {
+        // The version increment is part of the ADD clause built for the
+        // update, alongside any other ADD actions.
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, values, names, _, _| {
+                update_expr == "SET #k2 = :v2 ADD #k1 :v1, #ver :one"
+                    && names.get("#ver").unwrap() == AUTO_FIELDS_VERSION
+                    && values.get(":one").unwrap().as_n().unwrap() == "1"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, _, _, _, _| {
+                update_expr == "SET #k2 = :v2 REMOVE #k1 ADD #ver :one"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, _, _, _, _| update_expr == "SET #k1 = :v1 ADD #ver :one")
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let id = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "VERSIONED#1".to_string(),
+        };
+        util.increment_field::<VersionedTestDynamoObject>(id.clone(), "views", 1)
+            .await
+            .unwrap();
+        util.clear_ttl::<VersionedTestDynamoObject>(id.clone())
+            .await
+            .unwrap();
+        util.touch::<VersionedTestDynamoObject>(id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_batch_replace_all_ordered() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_item_low_sort().1,
+                        build_item_high_sort().1,
+                    ]))
+                    .build())
+            });
+        // Only the new item is written.
+        backend
+            .expect_batch_put_item()
+            .withf(|_, items| {
+                items.len() == 1
+                    && items[0].get("val_non_null").unwrap().as_s().unwrap() == "new"
+                    && items[0]
+                        .get(AUTO_FIELDS_SORT)
+                        .unwrap()
+                        .as_n()
+                        .unwrap()
+                        .parse::<f64>()
+                        .unwrap()
+                        < 0.10001
+            })
+            .times(1)
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+        // Moving high_sort to the front only rewrites its sort value.
+        backend
+            .expect_update_item()
+            .withf(|_, key, _, values, names, _, return_values| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && names.get("#k1").unwrap() == AUTO_FIELDS_SORT
+                    && values
+                        .get(":v1")
+                        .unwrap()
+                        .as_n()
+                        .unwrap()
+                        .parse::<f64>()
+                        .unwrap()
+                        < 0.10001
+                    && *return_values == Some(ReturnValue::AllNew)
+            })
+            .times(1)
+            .returning(|_, _, _, values, _, _, _| {
+                let mut item = build_item_high_sort().1;
+                item.insert(AUTO_FIELDS_SORT.to_string(), values[":v1"].clone());
+                Ok(UpdateItemOutput::builder()
+                    .set_attributes(Some(item))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let data = |val: &str| TestDynamoObjectData {
+            val_non_null: val.to_string(),
+            val_nullable: None,
+        };
+        let result = util
+            .batch_replace_all_ordered::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                vec![data("high_sort"), data("new"), data("low_sort")],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            result
+                .iter()
+                .map(|object| object.data().val_non_null.as_str())
+                .collect::<Vec<_>>(),
+            vec!["high_sort", "new", "low_sort"]
+        );
+        assert_eq!(result[0].sk(), "GROUP#123#TEST#2");
+        assert_eq!(result[2].sk(), "GROUP#123#TEST#3");
+        // The returned objects have their new sort values.
+        assert!(result[0].sort().unwrap() < result[1].sort().unwrap());
+        assert!(result[1].sort().unwrap() < result[2].sort().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_batch_replace_all_ordered_versioned() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let data = |name: &str| VersionedTestDynamoObjectData {
+            name: name.to_string(),
+        };
+        util.batch_replace_all_ordered::<VersionedTestDynamoObject>(
+            PkSk::root(),
+            vec![data("a"), data("b")],
+        )
+        .await
+        .unwrap();
+
+        // Reordering rewrites the sort value of moved items, which increments
+        // their version. The returned objects reflect both, so they can be
+        // updated directly.
+        let mut result = util
+            .batch_replace_all_ordered_by_key::<VersionedTestDynamoObject, _>(
+                PkSk::root(),
+                vec![data("b"), data("a")],
+                |data| data.name.clone(),
+            )
+            .await
+            .unwrap();
+        assert!(result[0].sort().unwrap() < result[1].sort().unwrap());
+        for object in &mut result {
+            object.data.name.push('!');
+            util.update_item(object).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_copy_subtree() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_get_item()
+            .withf(|_, key, _, consistent_read| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && *consistent_read == Some(true)
+            })
+            .times(1)
+            .returning(|_, _, _, _| {
+                Ok(GetItemOutput::builder()
+                    .set_item(Some(build_item_high_sort().1))
+                    .build())
+            });
+        backend
+            .expect_put_item()
+            .withf(|_, item, _, _, _| {
+                item.get("pk").unwrap().as_s().unwrap() == "GROUP#456"
+                    && item.get("sk").unwrap().as_s().unwrap().starts_with("TEST#")
+                    && item.get(AUTO_FIELDS_SORT).unwrap().as_n().unwrap() == "0.75"
+                    && item.get("val_non_null").unwrap().as_s().unwrap() == "high_sort"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+        backend
+            .expect_query()
+            .times(5)
+            .returning(|_, _, _, values, _, _, _, _, _, _, _| {
+                let pk = values.get(":pk_val").unwrap().as_s().unwrap().as_str();
+                let sk = values.get(":sk_val").map(|sk| sk.as_s().unwrap().as_str());
+                let item = |pk: &str, sk: &str| -> HashMap<String, AttributeValue> {
+                    collection! {
+                        "pk".to_string() => AttributeValue::S(pk.to_string()),
+                        "sk".to_string() => AttributeValue::S(sk.to_string()),
+                        "version".to_string() => AttributeValue::N("7".to_string()),
+                    }
+                };
+                let items = match (pk, sk) {
+                    // Inline children of the copied object.
+                    ("ROOT", Some("GROUP#123#TEST#2#")) => {
+                        vec![item("ROOT", "GROUP#123#TEST#2#NOTE#1")]
+                    }
+                    // Top-level children of the copied object.
+                    ("GROUP#123#TEST#2", None) => vec![
+                        item("GROUP#123#TEST#2", "EVENT#0001700000000000aB3xYz"),
+                        item("GROUP#123#TEST#2", "EVENT#0001700000000000aB3xYz#NOTE#2"),
+                    ],
+                    _ => vec![],
+                };
+                Ok(QueryOutput::builder().set_items(Some(items)).build())
+            });
+        backend
+            .expect_batch_put_item()
+            .withf(|_, items| {
+                let new_id = |index: usize| {
+                    (
+                        items[index].get("pk").unwrap().as_s().unwrap().clone(),
+                        items[index].get("sk").unwrap().as_s().unwrap().clone(),
+                    )
+                };
+                let (note_pk, note_sk) = new_id(0);
+                let (task_pk, task_sk) = new_id(1);
+                let (task_note_pk, task_note_sk) = new_id(2);
+                items.len() == 3
+                    && items.iter().all(|item| {
+                        item.get(AUTO_FIELDS_VERSION).unwrap().as_n().unwrap() == "1"
+                            && item.get(AUTO_FIELDS_CREATED_AT).is_some()
+                    })
+                    && note_pk == "GROUP#456"
+                    && note_sk.starts_with("TEST#")
+                    && note_sk.ends_with("#NOTE#1")
+                    // The task is stored under the copied object's sk.
+                    && task_pk == note_sk.trim_end_matches("#NOTE#1")
+                    // New IDs follow the registered type's IdLogic.
+                    && task_sk.starts_with("EVENT#")
+                    && task_sk.len() == "EVENT#".len() + 22
+                    && task_sk != "EVENT#0001700000000000aB3xYz"
+                    && task_note_pk == task_pk
+                    && task_note_sk == format!("{}#NOTE#2", task_sk)
+            })
+            .times(1)
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let copy = util
+            .copy_subtree::<TestDynamoObject>(
+                build_item_high_sort().0.id,
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#456".to_string(),
+                },
+                &DynamoRegistry::new().register::<TimestampMillisTestDynamoObject>(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(copy.pk(), "GROUP#456");
+        assert_eq!(copy.data().val_non_null, "high_sort");
+    }
+
+    #[tokio::test]
+    async fn test_copy_subtree_unregistered_child() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let parent = util
+            .create_item::<TestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#123".to_string(),
+                },
+                build_item_high_sort().0.data,
+                None,
+            )
+            .await
+            .unwrap();
+        util.create_item::<TimestampMillisTestDynamoObject>(
+            parent.id().clone(),
+            Default::default(),
+            None,
+        )
+        .await
+        .unwrap();
+
+        // The child's ID format is unknown without its registered type.
+        assert!(util
+            .copy_subtree::<TestDynamoObject>(
+                parent.id().clone(),
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#456".to_string(),
+                },
+                &DynamoRegistry::new()
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_item_recursive() {
+        let key = |pk: &str, sk: &str| -> HashMap<String, AttributeValue> {
+            collection! {
+                "pk".to_string() => AttributeValue::S(pk.to_string()),
+                "sk".to_string() => AttributeValue::S(sk.to_string()),
+            }
+        };
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .times(4)
+            .returning(move |_, _, _, values, _, _, _, _, _, _, _| {
+                let pk = values.get(":pk_val").unwrap().as_s().unwrap().as_str();
+                let sk = values.get(":sk_val").map(|sk| sk.as_s().unwrap().as_str());
+                let items = match (pk, sk) {
+                    // Inline children.
+                    ("GROUP#123", Some("TEST#2#")) => vec![key("GROUP#123", "TEST#2#NOTE#1")],
+                    // Top-level children.
+                    ("TEST#2", None) => vec![key("TEST#2", "TASK#1")],
+                    _ => vec![],
+                };
+                Ok(QueryOutput::builder().set_items(Some(items)).build())
+            });
+        let mut sequence = mockall::Sequence::new();
+        backend
+            .expect_batch_delete_item()
+            .with(
+                eq("my_table".to_string()),
+                eq(vec![key("TEST#2", "TASK#1")]),
+            )
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+        backend
+            .expect_batch_delete_item()
+            .with(
+                eq("my_table".to_string()),
+                eq(vec![key("GROUP#123", "TEST#2#NOTE#1")]),
+            )
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+        backend
+            .expect_delete_item()
+            .withf(|_, key, _, _, _, _| key.get("sk").unwrap().as_s().unwrap() == "TEST#2")
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _, _, _, _, _| Ok(DeleteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.delete_item_recursive::<TestDynamoObject>(PkSk {
+            pk: "GROUP#123".to_string(),
+            sk: "TEST#2".to_string(),
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_move_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, _, values, _, _, _, _, consistent_read, _, _| {
+                values.get(":pk_val").unwrap().as_s().unwrap() == "GROUP#123"
+                    && values.get(":sk_val").unwrap().as_s().unwrap() == "TEST#2"
+                    && *consistent_read == Some(true)
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                            "sk".to_string() => AttributeValue::S("TEST#2".to_string()),
+                            "val_non_null".to_string() => AttributeValue::S("moved".to_string()),
+                        },
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                            "sk".to_string() => AttributeValue::S("TEST#2#NOTE#1".to_string()),
+                        },
+                        // Not a child, despite sharing the sk prefix.
+                        collection! {
+                            "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                            "sk".to_string() => AttributeValue::S("TEST#20".to_string()),
+                        },
+                    ]))
+                    .build())
+            });
+        backend
+            .expect_transact_write_items()
+            .withf(|items, _| {
+                // Puts must not overwrite existing items at the new IDs.
+                let put_sk = |index: usize| {
+                    items[index]
+                        .put
+                        .as_ref()
+                        .filter(|put| {
+                            put.condition_expression.as_deref() == Some("attribute_not_exists(pk)")
+                        })
+                        .map(|put| {
+                            (
+                                put.item.get("pk").unwrap().as_s().unwrap().clone(),
+                                put.item.get("sk").unwrap().as_s().unwrap().clone(),
+                            )
+                        })
+                };
+                let delete_sk = |index: usize| {
+                    items[index]
+                        .delete
+                        .as_ref()
+                        .map(|delete| delete.key.get("sk").unwrap().as_s().unwrap().clone())
+                };
+                items.len() == 4
+                    && put_sk(0) == Some(("GROUP#456".to_string(), "TEST#2".to_string()))
+                    && delete_sk(1) == Some("TEST#2".to_string())
+                    && put_sk(2) == Some(("GROUP#456".to_string(), "TEST#2#NOTE#1".to_string()))
+                    && delete_sk(3) == Some("TEST#2#NOTE#1".to_string())
+            })
+            .times(1)
+            .returning(|_, _| Ok(TransactWriteItemsOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let moved = util
+            .move_item::<TestDynamoObject>(
+                PkSk {
+                    pk: "GROUP#123".to_string(),
+                    sk: "TEST#2".to_string(),
+                },
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#456".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(moved.pk(), "GROUP#456");
+        assert_eq!(moved.sk(), "TEST#2");
+        assert_eq!(moved.data().val_non_null, "moved");
+    }
+
+    #[tokio::test]
+    async fn test_move_item_existing_destination() {
+        let util = DynamoUtil::new_in_memory("my_table");
+        let item = |pk: &str, val: &str| {
+            collection! {
+                "pk".to_string() => AttributeValue::S(pk.to_string()),
+                "sk".to_string() => AttributeValue::S("TEST#2".to_string()),
+                "val_non_null".to_string() => AttributeValue::S(val.to_string()),
+            }
+        };
+        util.raw_batch_put_item(vec![
+            item("GROUP#123", "moved"),
+            item("GROUP#456", "existing"),
+        ])
+        .await
+        .unwrap();
+
+        // The item at the destination is not overwritten, and nothing is moved.
+        assert!(util
+            .move_item::<TestDynamoObject>(
+                PkSk {
+                    pk: "GROUP#123".to_string(),
+                    sk: "TEST#2".to_string(),
+                },
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "GROUP#456".to_string(),
+                },
+            )
+            .await
+            .is_err());
+        assert_eq!(util.backend.items("my_table").len(), 2);
+        let existing = util
+            .get_item::<TestDynamoObject>(PkSk {
+                pk: "GROUP#456".to_string(),
+                sk: "TEST#2".to_string(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(existing.data().val_non_null, "existing");
+
+        // Sharded objects can't be moved.
+        assert!(util
+            .move_item::<ShardedTestDynamoObject>(
+                PkSk {
+                    pk: "ROOT#00".to_string(),
+                    sk: "TENANT#1".to_string(),
+                },
+                PkSk::root(),
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_query_by_sk_component() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .withf(|_, _, condition, values, _, _, _, _, _, _, _| {
+                condition == "#pk_field = :pk_val AND begins_with(#sk_field, :sk_val)"
+                    && values.get(":pk_val").unwrap().as_s().unwrap() == "SHOP#1"
+                    && values.get(":sk_val").unwrap().as_s().unwrap() == "ORDER#pending~"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![collection! {
+                        "pk".to_string() => AttributeValue::S("SHOP#1".to_string()),
+                        "sk".to_string() => AttributeValue::S("ORDER#pending~abc".to_string()),
+                        "status".to_string() => AttributeValue::S("pending".to_string()),
+                    }]))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .query_by_sk_component::<ComponentTestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "SHOP#1".to_string(),
+                },
+                "pending",
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].data().status, "pending");
+
+        // Components can't contain separators.
+        assert!(util
+            .query_by_sk_component::<ComponentTestDynamoObject>(
+                PkSk {
+                    pk: "ROOT".to_string(),
+                    sk: "SHOP#1".to_string(),
+                },
+                "a#b",
+                None,
+            )
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_sk_component() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, key, _, _, _, _, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "ORDER#pending~abc"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+        backend
+            .expect_query()
+            .times(2)
+            .returning(|_, _, _, values, _, _, _, _, _, _, _| {
+                let items = match values.get(":pk_val").unwrap().as_s().unwrap().as_str() {
+                    // The order itself.
+                    "SHOP#1" => vec![collection! {
+                        "pk".to_string() => AttributeValue::S("SHOP#1".to_string()),
+                        "sk".to_string() => AttributeValue::S("ORDER#pending~abc".to_string()),
+                        "status".to_string() => AttributeValue::S("shipped".to_string()),
+                    }],
+                    // Top-level children of the order.
+                    "ORDER#pending~abc" => vec![collection! {
+                        "pk".to_string() => AttributeValue::S("ORDER#pending~abc".to_string()),
+                        "sk".to_string() => AttributeValue::S("LINE#1".to_string()),
+                    }],
+                    pk => panic!("unexpected query for {}", pk),
+                };
+                Ok(QueryOutput::builder().set_items(Some(items)).build())
+            });
+        backend
+            .expect_transact_write_items()
+            .withf(|items, _| {
+                let put_key = |index: usize| {
+                    items[index].put.as_ref().map(|put| {
+                        (
+                            put.item.get("pk").unwrap().as_s().unwrap().clone(),
+                            put.item.get("sk").unwrap().as_s().unwrap().clone(),
+                        )
+                    })
+                };
+                items.len() == 4
+                    && put_key(0) == Some(("SHOP#1".to_string(), "ORDER#shipped~abc".to_string()))
+                    && put_key(2) == Some(("ORDER#shipped~abc".to_string(), "LINE#1".to_string()))
+            })
+            .times(1)
+            .returning(|_, _| Ok(TransactWriteItemsOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let object = ComponentTestDynamoObject::new(
+            PkSk {
+                pk: "SHOP#1".to_string(),
+                sk: "ORDER#pending~abc".to_string(),
+            },
+            ComponentTestDynamoObjectData {
+                status: "shipped".to_string(),
+            },
+        );
+        let updated = util.update_sk_component(&object).await.unwrap();
+        assert_eq!(updated.sk(), "ORDER#shipped~abc");
+        assert_eq!(updated.data().status, "shipped");
+    }
+
+    #[tokio::test]
+    async fn test_move_item_ordered() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .times(1)
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_item_low_sort().1,
+                        build_item_high_sort().1,
+                    ]))
+                    .build())
+            });
+        backend
+            .expect_update_item()
+            .withf(|_, key, update_expr, values, names, condition, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && update_expr == "SET #k2 = :v2, #k1 = :v1"
+                    && names.get("#k1").unwrap() == AUTO_FIELDS_SORT
+                    && values
+                        .get(":v1")
+                        .unwrap()
+                        .as_n()
+                        .unwrap()
+                        .parse::<f64>()
+                        .unwrap()
+                        < 0.10001
+                    && condition.as_deref() == Some("attribute_exists(pk)")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.move_item_ordered::<TestDynamoObject>(
+            build_item_high_sort().0.id,
+            DynamoInsertPosition::First,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rebalance_sort() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_query()
+            .times(1)
+            .withf(|_, _, _, _, _, _, _, _, consistent_read, _, _| *consistent_read == Some(true))
+            .returning(|_, _, _, _, _, _, _, _, _, _, _| {
+                Ok(QueryOutput::builder()
+                    .set_items(Some(vec![
+                        build_item_low_sort().1,
+                        build_item_high_sort().1,
+                        // Unordered items are left unchanged.
+                        build_item_no_data().1,
+                    ]))
+                    .build())
+            });
+        let mut sequence = mockall::Sequence::new();
+        backend
+            .expect_update_item()
+            .withf(|_, key, _, values, names, _, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && names.get("#k1").unwrap() == AUTO_FIELDS_SORT
+                    && values.get(":v1").unwrap().as_n().unwrap() == "2"
+            })
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+        backend
+            .expect_update_item()
+            .withf(|_, key, _, values, names, _, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#3"
+                    && names.get("#k1").unwrap() == AUTO_FIELDS_SORT
+                    && values.get(":v1").unwrap().as_n().unwrap() == "1"
+            })
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let rewritten = util
+            .rebalance_sort::<TestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(rewritten, 2);
+    }
+
+    #[tokio::test]
+    async fn test_touch() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, key, update_expr, values, names, condition, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && update_expr == "SET #k1 = :v1"
+                    && names.len() == 1
+                    && names.get("#k1").unwrap() == AUTO_FIELDS_UPDATED_AT
+                    && values.len() == 1
+                    && values.get(":v1").unwrap().as_s().is_ok()
+                    && condition.as_deref() == Some("attribute_exists(pk)")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.touch::<TestDynamoObject>(build_item_high_sort().0.id)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_principal() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(|_, item, _, _, _| {
+                item.get(AUTO_FIELDS_CREATED_BY).unwrap().as_s().unwrap() == "user_1"
+                    && item.get(AUTO_FIELDS_UPDATED_BY).unwrap().as_s().unwrap() == "user_1"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, values, names, _, _| {
+                update_expr.starts_with("SET ")
+                    && !names.contains_key("#by")
+                    && names.values().any(|name| name == AUTO_FIELDS_UPDATED_BY)
+                    && !names.values().any(|name| name == AUTO_FIELDS_CREATED_BY)
+                    && values
+                        .values()
+                        .any(|value| value.as_s().is_ok_and(|s| s == "user_1"))
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, values, names, _, _| {
+                update_expr == "SET #k1 = :v1, #by = :by"
+                    && names.get("#by").unwrap() == AUTO_FIELDS_UPDATED_BY
+                    && values.get(":by").unwrap().as_s().unwrap() == "user_1"
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+        .with_principal("user_1");
+
+        let item = build_item_high_sort().0;
+        util.create_item::<TestDynamoObject>(
+            PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            },
+            item.data.clone(),
+            None,
+        )
+        .await
+        .unwrap();
+        util.update_item(&item).await.unwrap();
+        util.touch::<TestDynamoObject>(item.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_updated_region() {
+        let util = DynamoUtil::new_in_memory("my_table").with_updated_region("us-east-1");
+        let region = |id: &PkSk| {
+            util.backend
+                .items("my_table")
+                .into_iter()
+                .find(|item| PkSk::from_map(item).unwrap() == *id)
+                .unwrap()
+                .get(UPDATED_REGION_ATTRIBUTE)
+                .cloned()
+        };
+        let parent = PkSk {
+            pk: "ROOT".to_string(),
+            sk: "GROUP#123".to_string(),
+        };
+        let data = build_item_high_sort().0.data;
+        let mut objects = Vec::new();
+        for _ in 0..4 {
+            let object = util
+                .create_item::<TestDynamoObject>(parent.clone(), data.clone(), None)
+                .await
+                .unwrap();
+            // Puts are tagged by the backend (see MultiRegionBackend).
+            assert_eq!(region(object.id()), None);
+            objects.push(object);
+        }
+
+        // Each kind of update sets the region as part of its expression.
+        util.update_item(&objects[0]).await.unwrap();
+        util.touch::<TestDynamoObject>(objects[1].id().clone())
+            .await
+            .unwrap();
+        util.increment_field::<TestDynamoObject>(objects[2].id().clone(), "view_count", 1)
+            .await
+            .unwrap();
+        let mut transaction = util.transaction();
+        transaction.update(&objects[3]).unwrap();
+        transaction.commit().await.unwrap();
+        for object in &objects {
+            assert_eq!(
+                region(object.id()),
+                Some(AttributeValue::S("us-east-1".to_string()))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_principal() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_put_item()
+            .withf(|_, item, _, _, _| {
+                item.get(AUTO_FIELDS_CREATED_BY).is_none()
+                    && item.get(AUTO_FIELDS_UPDATED_BY).is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| Ok(PutItemOutput::builder().build()));
+        backend
+            .expect_update_item()
+            .withf(|_, _, _, _, names, _, _| {
+                !names.values().any(|name| name == AUTO_FIELDS_UPDATED_BY)
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let item = build_item_high_sort().0;
+        util.create_item::<TestDynamoObject>(
+            PkSk {
+                pk: "ROOT".to_string(),
+                sk: "GROUP#123".to_string(),
+            },
+            item.data.clone(),
+            None,
+        )
+        .await
+        .unwrap();
+        util.update_item(&item).await.unwrap();
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingMetrics {
+        records: Mutex<Vec<ConsumedCapacityRecord>>,
+    }
+
+    impl DynamoMetrics for RecordingMetrics {
+        fn record_consumed_capacity(&self, record: &ConsumedCapacityRecord) {
+            self.records.lock().unwrap().push(record.clone());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consumed_capacity_metrics() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend.expect_get_item().times(1).returning(|_, _, _, _| {
+            Ok(GetItemOutput::builder()
+                .set_item(Some(build_item_high_sort().1))
+                .consumed_capacity(
+                    ConsumedCapacity::builder()
+                        .table_name("my_table")
+                        .capacity_units(0.5)
+                        .build(),
+                )
+                .build())
+        });
+        backend
+            .expect_put_item()
+            .times(1)
+            .returning(|_, _, _, _, _| {
+                Ok(PutItemOutput::builder()
+                    .consumed_capacity(
+                        ConsumedCapacity::builder()
+                            .table_name("my_table")
+                            .capacity_units(1.0)
+                            .write_capacity_units(1.0)
+                            .build(),
+                    )
+                    .build())
+            });
+        // Responses without consumed capacity are not reported.
+        backend
+            .expect_delete_item()
+            .times(1)
+            .returning(|_, _, _, _, _, _| Ok(DeleteItemOutput::builder().build()));
+
+        let metrics = Arc::new(RecordingMetrics::default());
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+        .with_metrics(metrics.clone());
+
+        let item = util
+            .get_item::<TestDynamoObject>(build_item_high_sort().0.id().clone())
+            .await
+            .unwrap()
+            .unwrap();
+        util.update_item(&item).await.unwrap();
+        util.delete_item::<TestDynamoObject>(item.id().clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *metrics.records.lock().unwrap(),
+            vec![
+                ConsumedCapacityRecord {
+                    operation: "GetItem",
+                    table: "my_table".to_string(),
+                    label: Some("TEST".to_string()),
+                    read_capacity_units: 0.5,
+                    write_capacity_units: 0.0,
+                },
+                ConsumedCapacityRecord {
+                    operation: "PutItem",
+                    table: "my_table".to_string(),
+                    label: Some("TEST".to_string()),
+                    read_capacity_units: 0.0,
+                    write_capacity_units: 1.0,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_rate_limit() {
+        let util = DynamoUtil::new_in_memory("my_table")
+            .with_write_rate_limit(WriteRateLimit::ItemsPerSecond(10.0))
+            .unwrap();
+        let items = (0..12)
+            .map(|i| {
+                collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S(format!("LOG#{}", i)),
+                }
+            })
+            .collect::<Vec<DynamoMap>>();
+
+        // A second's worth of items is sent immediately, and the remaining 2
+        // items wait for the limiter to refill.
+        let start = std::time::Instant::now();
+        util.raw_batch_put_item(items).await.unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(190));
+        assert_eq!(util.backend.items("my_table").len(), 12);
+    }
+
+    #[tokio::test]
+    async fn test_cancellation() {
+        let token = CancellationToken::new();
+        let mut backend = MockDynamoBackendImpl::new();
+        // The token is cancelled while the first page is being fetched, so the
+        // second page is never requested.
+        let cancel = token.clone();
+        backend.expect_scan().times(1).returning(move |_, _, _, _| {
+            cancel.cancel();
+            Ok(ScanOutput::builder()
+                .set_items(Some(vec![build_item_high_sort().1]))
+                .set_last_evaluated_key(Some(collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S("GROUP#123#TEST#2".to_string()),
+                }))
+                .build())
+        });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: Some(token),
+            updated_region: None,
+        };
+        assert!(util.scan_all::<TestDynamoObject>().await.is_err());
+
+        // A passed deadline stops operations before any request is sent.
+        let expired = CancellationToken::new().with_deadline(std::time::Instant::now());
+        assert!(DynamoUtil::new_in_memory("my_table")
+            .with_cancellation(expired)
+            .get_item::<TestDynamoObject>(build_item_high_sort().0.id)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let mut backend = MockDynamoBackendImpl::new();
+        // Only the first 2 requests are sent; once the breaker opens, requests
+        // are rejected without calling the backend.
+        backend
+            .expect_get_item()
+            .times(2)
+            .returning(|_, _, _, _| Err(SdkError::timeout_error("connection timed out")));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: RetryPolicy::disabled(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+        .with_circuit_breaker(2, Duration::from_secs(60));
+
+        let id = build_item_high_sort().0.id().clone();
+        for _ in 0..2 {
+            let result = util.get_item::<TestDynamoObject>(id.clone()).await;
+            assert!(result.is_err());
+        }
+        assert!(util.circuit_breaker.as_ref().unwrap().is_open());
+        let result = util.get_item::<TestDynamoObject>(id.clone()).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_set_ttl() {
+        let expiry = Utc::now() + chrono::Duration::days(3);
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(move |_, key, update_expr, values, names, condition, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && update_expr == "SET #k2 = :v2, #k1 = :v1"
+                    && names.get("#k1").unwrap() == AUTO_FIELDS_TTL
+                    && values.get(":v1").unwrap().as_n().unwrap() == &expiry.timestamp().to_string()
+                    && condition.as_deref() == Some("attribute_exists(pk)")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.set_ttl::<TestDynamoObject>(
+            build_item_high_sort().0.id,
+            TtlConfig::CustomDate(expiry),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_clear_ttl() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, values, names, condition, _| {
+                update_expr == "SET #k2 = :v2 REMOVE #k1"
+                    && names.get("#k1").unwrap() == AUTO_FIELDS_TTL
+                    && values.len() == 1
+                    && values.get(":v2").is_some()
+                    && condition.as_deref() == Some("attribute_exists(pk)")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.clear_ttl::<TestDynamoObject>(build_item_high_sort().0.id)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_append_to_list() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, key, update_expr, values, names, condition, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && update_expr
+                        == "SET #k2 = :v2, #k1 = list_append(if_not_exists(#k1, :v3), :v1)"
+                    && names.get("#k1").unwrap() == "tags"
+                    && names.get("#k2").unwrap() == AUTO_FIELDS_UPDATED_AT
+                    && values.get(":v1").unwrap()
+                        == &AttributeValue::L(vec![
+                            AttributeValue::S("a".to_string()),
+                            AttributeValue::S("b".to_string()),
+                        ])
+                    && values.get(":v3").unwrap() == &AttributeValue::L(vec![])
+                    && condition.as_deref() == Some("attribute_exists(pk)")
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.append_to_list::<TestDynamoObject, _>(
+            build_item_high_sort().0.id,
+            "tags",
+            vec!["a", "b"],
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_set_operations() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, values, names, _, _| {
+                update_expr == "SET #k2 = :v2 ADD #k1 :v1"
+                    && names.get("#k1").unwrap() == "tags"
+                    && values.get(":v1").unwrap()
+                        == &AttributeValue::Ss(vec!["a".to_string(), "b".to_string()])
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+        backend
+            .expect_update_item()
+            .withf(|_, _, update_expr, values, names, _, _| {
+                update_expr == "SET #k2 = :v2 DELETE #k1 :v1"
+                    && names.get("#k1").unwrap() == "member_ids"
+                    && values.get(":v1").unwrap()
+                        == &AttributeValue::Ns(vec!["-1".to_string(), "7".to_string()])
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+        let id = build_item_high_sort().0.id;
+
+        // Duplicates should be removed.
+        util.add_to_set::<TestDynamoObject, _>(
+            id.clone(),
+            "tags",
+            vec!["b".to_string(), "a".to_string(), "b".to_string()],
+        )
+        .await
+        .unwrap();
+        util.remove_from_set::<TestDynamoObject, _>(id.clone(), "member_ids", vec![7, -1])
+            .await
+            .unwrap();
+        // Empty sets are not supported by DynamoDB.
+        assert!(util
+            .add_to_set::<TestDynamoObject, String>(id, "tags", vec![])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_delete_item()
+            .with(
+                eq("my_table".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                    "sk".to_string() => AttributeValue::S("LIST#123#TEST#456".to_string())
+                }),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq(None),
+            )
+            .returning(|_, _, _, _, _, _| Ok(DeleteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .delete_item::<TestDynamoObject>(PkSk {
+                pk: "GROUP#123".to_string(),
+                sk: "LIST#123#TEST#456".to_string(),
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(result, ());
+    }
+
+    #[tokio::test]
+    async fn test_delete_item_with_conditions() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_delete_item()
+            .withf(|_, key, condition, names, values, _| {
+                key.get("sk").unwrap().as_s().unwrap() == "LIST#123#TEST#456"
+                    && condition.as_deref() == Some("#f1 = :f1")
+                    && names.as_ref().unwrap().get("#f1").unwrap() == "val_non_null"
+                    && values.as_ref().unwrap().get(":f1").unwrap()
+                        == &AttributeValue::S("draft".to_string())
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _| Ok(DeleteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+        let id = PkSk {
+            pk: "GROUP#123".to_string(),
+            sk: "LIST#123#TEST#456".to_string(),
+        };
+
+        util.delete_item_with_conditions::<TestDynamoObject>(
+            id.clone(),
+            QueryFilter::Eq(
+                "val_non_null".to_string(),
+                AttributeValue::S("draft".to_string()),
+            ),
+        )
+        .await
+        .unwrap();
+        // At least one condition is required.
+        assert!(util
+            .delete_item_with_conditions::<TestDynamoObject>(id, FilterExpression::new())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_item_invalid_type() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_delete_item()
+            .with(
+                eq("my_table".to_string()),
+                eq::<HashMap<String, AttributeValue>>(collection! {
+                    "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
+                    "sk".to_string() => AttributeValue::S("LIST#123#WRONGTYPE#456".to_string())
+                }),
+                eq(None),
+                eq(None),
+                eq(None),
+                eq(None),
+            )
+            .returning(|_, _, _, _, _, _| Ok(DeleteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .delete_item::<TestDynamoObject>(PkSk {
+                pk: "GROUP#123".to_string(),
+                sk: "LIST#123#WRONGTYPE#456".to_string(),
+            })
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batch_delete_item() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_batch_delete_item()
+            .with(
+                eq("my_table".to_string()),
+                eq(vec![
+                    collection! {
+                        "pk".to_string() => AttributeValue::S("ABC#123".to_string()),
+                        "sk".to_string() => AttributeValue::S("TEST#321".to_string()),
+                    },
+                    collection! {
+                        "pk".to_string() => AttributeValue::S("DEF#456".to_string()),
+                        "sk".to_string() => AttributeValue::S("TEST#654".to_string()),
+                    },
+                ]),
+            )
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let keys = vec![
+            PkSk {
+                pk: "ABC#123".to_string(),
+                sk: "TEST#321".to_string(),
+            },
+            PkSk {
+                pk: "DEF#456".to_string(),
+                sk: "TEST#654".to_string(),
+            },
+        ];
+
+        let result = util
+            .batch_delete_item::<TestDynamoObject>(keys)
+            .await
+            .unwrap();
+        assert_eq!(result, ());
+    }
+
+    #[tokio::test]
+    async fn test_raw_batch_put_item_retries_unprocessed_items() {
+        let (_, item_1) = build_item_no_data();
+        let (_, item_2) = build_item_high_sort();
+        let unprocessed = item_2.clone();
+        let mut backend = MockDynamoBackendImpl::new();
+        let mut seq = mockall::Sequence::new();
+        backend
+            .expect_batch_put_item()
+            .withf(|_, items| items.len() == 2)
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(move |_, _| {
+                Ok(BatchWriteItemOutput::builder()
+                    .unprocessed_items(
+                        "my_table",
+                        vec![WriteRequest::builder()
+                            .put_request(
+                                PutRequest::builder()
+                                    .set_item(Some(unprocessed.clone()))
+                                    .build()
+                                    .unwrap(),
+                            )
+                            .build()],
+                    )
+                    .build())
+            });
+        backend
+            .expect_batch_put_item()
+            .with(eq("my_table".to_string()), eq(vec![item_2.clone()]))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: RetryPolicy::default()
+                .with_max_attempts(3)
+                .with_backoff(BackoffStrategy::Fixed(Duration::from_millis(1))),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.raw_batch_put_item(vec![item_1, item_2]).await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_batch_create_item() {
+    async fn test_batch_delete_item_unprocessed_items_exhausted() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_batch_delete_item()
+            .times(2)
+            .returning(|_, keys| {
+                Ok(BatchWriteItemOutput::builder()
+                    .unprocessed_items(
+                        "my_table",
+                        keys.into_iter()
+                            .map(|key| {
+                                WriteRequest::builder()
+                                    .delete_request(
+                                        DeleteRequest::builder()
+                                            .set_key(Some(key))
+                                            .build()
+                                            .unwrap(),
+                                    )
+                                    .build()
+                            })
+                            .collect(),
+                    )
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: RetryPolicy::default()
+                .with_max_attempts(2)
+                .with_backoff(BackoffStrategy::Fixed(Duration::from_millis(1))),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let result = util
+            .batch_delete_item::<TestDynamoObject>(vec![PkSk {
+                pk: "ABC#123".to_string(),
+                sk: "TEST#321".to_string(),
+            }])
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_raw_batch_put_item_concurrent_chunks() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_batch_put_item()
+            .withf(|_, items| items.len() == 25 || items.len() == 10)
+            .times(3)
+            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        }
+        .with_batch_concurrency(4);
+
+        let items = (0..60)
+            .map(|i| {
+                collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S(format!("TEST#{}", i)),
+                }
+            })
+            .collect::<Vec<HashMap<String, AttributeValue>>>();
+        util.raw_batch_put_item(items).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_raw_batch_put_item_large_items() {
+        // Every request stays within both the item count and the total size
+        // limit of BatchWriteItem.
         let mut backend = MockDynamoBackendImpl::new();
         backend
             .expect_batch_put_item()
             .withf(|_, items| {
-                items.len() == 2
-                    && items.iter().all(|item| {
-                        item.get(AUTO_FIELDS_CREATED_AT).is_some()
-                            && item.get(AUTO_FIELDS_UPDATED_AT).is_some()
-                            && item.get(AUTO_FIELDS_SORT).is_some()
-                            && item.get(AUTO_FIELDS_TTL).is_none()
-                            && item.get("val_non_null").is_some()
-                            && item.get("val_nullable").is_none()
-                    })
+                items.len() <= MAX_BATCH_WRITE_ITEMS
+                    && items
+                        .iter()
+                        .map(|item| item.estimated_size())
+                        .sum::<usize>()
+                        <= MAX_BATCH_WRITE_SIZE
             })
+            .times(2)
             .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let item1 = build_item_no_data().0;
-        let item2 = build_item_no_data().0;
-        let items = vec![
-            (
-                item1.data,
-                Some(CreateOptions {
-                    custom_sort: Some(0.12),
-                    ..Default::default()
-                }),
-            ),
-            (
-                item2.data,
-                Some(CreateOptions {
-                    custom_sort: Some(12.0),
-                    ..Default::default()
-                }),
-            ),
-        ];
+        let items = (0..30)
+            .map(|i| {
+                collection! {
+                    "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                    "sk".to_string() => AttributeValue::S(format!("TEST#{}", i)),
+                    "data".to_string() => AttributeValue::S("x".repeat(MAX_ITEM_SIZE - 1024)),
+                }
+            })
+            .collect::<Vec<HashMap<String, AttributeValue>>>();
+        util.raw_batch_put_item(items).await.unwrap();
+    }
 
-        let result = util
-            .batch_create_item::<TestDynamoObject>(
+    #[tokio::test]
+    async fn test_item_too_large_rejected_before_write() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend.expect_put_item().never();
+        backend.expect_update_item().never();
+        backend.expect_batch_put_item().never();
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let (mut object, _) = build_item_high_sort();
+        object.data.val_non_null = "x".repeat(MAX_ITEM_SIZE);
+
+        assert!(util
+            .create_item::<TestDynamoObject>(
                 PkSk {
                     pk: "ROOT".to_string(),
                     sk: "GROUP#123".to_string(),
                 },
-                items,
+                object.data.clone(),
+                None,
             )
             .await
+            .is_err());
+        assert!(util.update_item(&object).await.is_err());
+        assert!(util.replace_item(&object).await.is_err());
+        assert!(util
+            .raw_batch_put_item(vec![collection! {
+                "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                "sk".to_string() => AttributeValue::S("TEST#1".to_string()),
+                "data".to_string() => AttributeValue::S("x".repeat(MAX_ITEM_SIZE)),
+            }])
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_item_returning() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_update_item()
+            .withf(|_, _, _, _, _, _, return_values| *return_values == Some(ReturnValue::AllOld))
+            .times(1)
+            .returning(|_, _, _, _, _, _, _| {
+                Ok(UpdateItemOutput::builder()
+                    .set_attributes(Some(build_item_high_sort().1))
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let (mut object, _) = build_item_high_sort();
+        let old_value = object.data.val_non_null.clone();
+        object.data.val_non_null = "new_value".to_string();
+        let result = util
+            .update_item_returning(&object, ReturnedObject::Old)
+            .await
             .unwrap();
+        assert_eq!(result.id, object.id);
+        assert_eq!(result.data.val_non_null, old_value);
+    }
 
-        assert_eq!(result.len(), 2);
+    #[tokio::test]
+    async fn test_delete_item_returning() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_delete_item()
+            .withf(|_, key, _, _, _, return_values| {
+                key.get("sk").unwrap().as_s().unwrap() == "GROUP#123#TEST#2"
+                    && *return_values == Some(ReturnValue::AllOld)
+            })
+            .times(1)
+            .returning(|_, _, _, _, _, _| {
+                Ok(DeleteItemOutput::builder()
+                    .set_attributes(Some(build_item_high_sort().1))
+                    .build())
+            });
+        backend
+            .expect_delete_item()
+            .withf(|_, key, _, _, _, _| key.get("sk").unwrap().as_s().unwrap() == "TEST#404")
+            .times(1)
+            .returning(|_, _, _, _, _, _| Ok(DeleteItemOutput::builder().build()));
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        let (expected, _) = build_item_high_sort();
+        let deleted = util
+            .delete_item_returning::<TestDynamoObject>(expected.id.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(deleted.id, expected.id);
+        assert_eq!(deleted.data, expected.data);
+        let missing = util
+            .delete_item_returning::<TestDynamoObject>(PkSk {
+                pk: "ROOT".to_string(),
+                sk: "TEST#404".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(missing.is_none());
     }
 
     #[tokio::test]
-    async fn test_update_item_with_null() {
+    async fn test_create_table_for_schema() {
         let mut backend = MockDynamoBackendImpl::new();
         backend
-            .expect_update_item()
-            .withf(|_, id, update_expr, values, keys, condition| {
-                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
-                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
-                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2 REMOVE #rmk1"
-                    && values.get(":v1").is_some()
-                    && values.get(":v2").is_some()
-                    && values.get(":v3").is_none()
-                    && {
-                        let mut v = vec![keys.get("#k1").unwrap(), keys.get("#k2").unwrap()];
-                        v.sort();
-                        v
-                    } == vec![&"updated_at".to_string(), &"val_non_null".to_string()]
-                    && keys.get("#rmk1").unwrap() == "val_nullable"
-                    && matches!(condition, Some(c) if c == "attribute_exists(pk)")
+            .expect_create_table()
+            .withf(|table, key_schema, attributes, gsis, lsis, billing_mode| {
+                table == "test_table"
+                    && key_schema[0].attribute_name() == "pk"
+                    && key_schema[1].attribute_name() == "sk"
+                    && attributes.len() == 4
+                    && gsis.as_ref().is_some_and(|gsis| {
+                        gsis.len() == 1 && gsis[0].index_name() == "owner_index"
+                    })
+                    && lsis.is_none()
+                    && *billing_mode == BillingMode::PayPerRequest
             })
-            .returning(|_, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+            .times(1)
+            .returning(|_, _, _, _, _, _| Ok(CreateTableOutput::builder().build()));
+        backend
+            .expect_describe_table()
+            .withf(|table| table == "test_table")
+            .times(1)
+            .returning(|_| {
+                Ok(DescribeTableOutput::builder()
+                    .table(
+                        TableDescription::builder()
+                            .table_status(TableStatus::Active)
+                            .build(),
+                    )
+                    .build())
+            });
+
+        let util = DynamoUtil {
+            backend,
+            table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
+        };
+
+        util.create_table_for_schema(
+            "test_table",
+            &TableSchema::new()
+                .with_type::<TestDynamoObject>()
+                .with_type::<IndexedTestDynamoObject>(),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_delete_table() {
+        let mut backend = MockDynamoBackendImpl::new();
+        backend
+            .expect_delete_table()
+            .withf(|table| table == "test_table")
+            .times(1)
+            .returning(|_| Ok(DeleteTableOutput::builder().build()));
+        backend
+            .expect_describe_table()
+            .times(1)
+            .returning(|_| Ok(DescribeTableOutput::builder().build()));
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let update_item = TestDynamoObject {
-            id: PkSk {
-                pk: "ABC#123".to_string(),
-                sk: "TEST#321".to_string(),
-            },
-            auto_fields: Default::default(),
-            data: TestDynamoObjectData {
-                val_non_null: "new_data".into(),
-                val_nullable: None,
-            },
-        };
+        util.delete_table("test_table").await.unwrap();
+    }
 
-        let result = util.update_item(&update_item).await.unwrap();
-        assert_eq!(result, ());
+    fn describe_ttl_output(status: TimeToLiveStatus, attribute: &str) -> DescribeTimeToLiveOutput {
+        DescribeTimeToLiveOutput::builder()
+            .time_to_live_description(
+                TimeToLiveDescription::builder()
+                    .time_to_live_status(status)
+                    .attribute_name(attribute)
+                    .build(),
+            )
+            .build()
     }
 
     #[tokio::test]
-    async fn test_update_item_non_null() {
+    async fn test_ensure_ttl_enabled() {
         let mut backend = MockDynamoBackendImpl::new();
+        let mut sequence = mockall::Sequence::new();
         backend
-            .expect_update_item()
-            .withf(|_, id, update_expr, values, keys, condition| {
-                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
-                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
-                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2, #k3 = :v3"
-                    && values.get(":v1").is_some()
-                    && values.get(":v2").is_some()
-                    && values.get(":v3").is_some()
-                    && {
-                        let mut v = vec![
-                            keys.get("#k1").unwrap(),
-                            keys.get("#k2").unwrap(),
-                            keys.get("#k3").unwrap(),
-                        ];
-                        v.sort();
-                        v
-                    } == vec![
-                        &"updated_at".to_string(),
-                        &"val_non_null".to_string(),
-                        &"val_nullable".to_string(),
-                    ]
-                    && keys.get("#rmk1").is_none()
-                    && matches!(condition, Some(c) if c == "attribute_exists(pk)")
+            .expect_describe_time_to_live()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| Ok(DescribeTimeToLiveOutput::builder().build()));
+        backend
+            .expect_update_time_to_live()
+            .withf(|table, specification| {
+                table == "my_table"
+                    && specification.attribute_name() == AUTO_FIELDS_TTL
+                    && specification.enabled()
             })
-            .returning(|_, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _| Ok(UpdateTimeToLiveOutput::builder().build()));
+        backend
+            .expect_describe_time_to_live()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| Ok(describe_ttl_output(TimeToLiveStatus::Enabled, "ttl")));
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let update_item = TestDynamoObject {
-            id: PkSk {
-                pk: "ABC#123".to_string(),
-                sk: "TEST#321".to_string(),
-            },
-            auto_fields: Default::default(),
-            data: TestDynamoObjectData {
-                val_non_null: "new_data".into(),
-                val_nullable: Some("non_null".into()),
-            },
-        };
-
-        let result = util.update_item(&update_item).await.unwrap();
-        assert_eq!(result, ());
+        util.ensure_ttl_enabled("my_table").await.unwrap();
     }
 
     #[tokio::test]
-    async fn test_update_item_transaction_existing() {
+    async fn test_ensure_ttl_enabled_other_attribute() {
         let mut backend = MockDynamoBackendImpl::new();
         backend
-            .expect_get_item()
-            .with(
-                eq("my_table".to_string()),
-                eq::<HashMap<String, AttributeValue>>(collection! {
-                    "pk".to_string() => AttributeValue::S("ABC#123".to_string()),
-                    "sk".to_string() => AttributeValue::S("TEST#321".to_string())
-                }),
-                eq(None),
-            )
-            .returning(|_, _, _| {
-                Ok(GetItemOutput::builder()
-                    .set_item(Some(collection! {
-                        // ID & auto fields should /not/ be included in the
-                        // condition expression of the transaction update:
-                        "pk".to_string() => AttributeValue::S("ABC#123".to_string()),
-                        "sk".to_string() => AttributeValue::S("TEST#321".to_string()),
-                        "sort".to_string() => AttributeValue::N("0.75".to_string()),
-
-                        // Non-null data fields /should/ be checked for changes
-                        // in the transaction update condition expression:
-                        "val_non_null".to_string() => AttributeValue::S("old_data".to_string()),
-                    }))
-                    .build())
-            });
-        backend
-            .expect_update_item()
-            .withf(|_, id, update_expr, values, keys, condition| {
-                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
-                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
-                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2, #k3 = :v3"
-                    && values.get(":v1").is_some()
-                    && values.get(":v2").is_some()
-                    && values.get(":v3").is_some()
-                    && {
-                        let mut v = vec![
-                            keys.get("#k1").unwrap(),
-                            keys.get("#k2").unwrap(),
-                            keys.get("#k3").unwrap(),
-                        ];
-                        v.sort();
-                        v
-                    } == vec![
-                        &"updated_at".to_string(),
-                        &"val_non_null".to_string(),
-                        &"val_nullable".to_string(),
-                    ]
-                    && keys.get("#rmk1").is_none()
-                    && *condition == Some("attribute_exists(pk) AND #c1 = :cv1".to_string())
-                    && keys.get("#c1").unwrap() == "val_non_null"
-                    && values.get(":cv1").unwrap().as_s().unwrap() == "old_data"
-            })
-            .returning(|_, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+            .expect_describe_time_to_live()
+            .times(1)
+            .returning(|_| Ok(describe_ttl_output(TimeToLiveStatus::Enabled, "expires_at")));
+        backend.expect_update_time_to_live().never();
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let result = util
-            .update_item_transaction::<TestDynamoObject>(
-                PkSk {
-                    pk: "ABC#123".to_string(),
-                    sk: "TEST#321".to_string(),
-                },
-                |item| {
-                    let Some(mut item) = item else {
-                        return Err(DynamoNotFound::new());
-                    };
-                    item.val_non_null = "new_data".into();
-                    item.val_nullable = Some("non_null".into());
-                    Ok(item)
-                },
-            )
-            .await;
-        assert!(result.is_ok());
-
-        let object_after = result.unwrap();
-        assert_eq!(object_after.data.val_non_null, "new_data");
-        assert_eq!(object_after.data.val_nullable, Some("non_null".into()));
+        assert!(util.ensure_ttl_enabled("my_table").await.is_err());
     }
 
     #[tokio::test]
-    async fn test_update_item_transaction_new() {
+    async fn test_ensure_index() {
+        let index = IndexConfig::global("owner_index", "gsi1pk", "gsi1sk");
         let mut backend = MockDynamoBackendImpl::new();
+        let mut sequence = mockall::Sequence::new();
         backend
-            .expect_get_item()
-            .withf(|table, _key, _projection| table == "my_table")
-            .returning(|_, _, _| Ok(GetItemOutput::builder().set_item(None).build()));
+            .expect_describe_table()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| {
+                Ok(DescribeTableOutput::builder()
+                    .table(TableDescription::builder().build())
+                    .build())
+            });
         backend
-            .expect_update_item()
-            .withf(|_, id, update_expr, values, keys, condition| {
-                id.get("pk").unwrap().as_s().unwrap() == "ABC#123"
-                    && id.get("sk").unwrap().as_s().unwrap() == "TEST#321"
-                    && update_expr.trim() == "SET #k1 = :v1, #k2 = :v2, #k3 = :v3"
-                    && values.get(":v1").is_some()
-                    && values.get(":v2").is_some()
-                    && values.get(":v3").is_some()
-                    && {
-                        let mut v = vec![
-                            keys.get("#k1").unwrap(),
-                            keys.get("#k2").unwrap(),
-                            keys.get("#k3").unwrap(),
-                        ];
-                        v.sort();
-                        v
-                    } == vec![
-                        &"updated_at".to_string(),
-                        &"val_non_null".to_string(),
-                        &"val_nullable".to_string(),
-                    ]
-                    && keys.get("#rmk1").is_none()
-                    && *condition == Some("attribute_not_exists(pk)".to_string())
-                    && keys.get("#c1").is_none()
-                    && values.get(":cv1").is_none()
+            .expect_update_table()
+            .withf(|table, attributes, updates| {
+                table == "my_table"
+                    && attributes.len() == 2
+                    && updates.len() == 1
+                    && updates[0]
+                        .create()
+                        .is_some_and(|create| create.index_name() == "owner_index")
             })
-            .returning(|_, _, _, _, _, _| Ok(UpdateItemOutput::builder().build()));
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_, _, _| Ok(UpdateTableOutput::builder().build()));
+        backend
+            .expect_describe_table()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|_| {
+                Ok(DescribeTableOutput::builder()
+                    .table(
+                        TableDescription::builder()
+                            .global_secondary_indexes(
+                                GlobalSecondaryIndexDescription::builder()
+                                    .index_name("owner_index")
+                                    .index_status(IndexStatus::Active)
+                                    .build(),
+                            )
+                            .build(),
+                    )
+                    .build())
+            });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let result = util
-            .update_item_transaction::<TestDynamoObject>(
-                PkSk {
-                    pk: "ABC#123".to_string(),
-                    sk: "TEST#321".to_string(),
-                },
-                |item| {
-                    if item.is_some() {
-                        panic!("Item should not exist");
-                    }
-                    Ok(TestDynamoObjectData {
-                        val_non_null: "new_data".into(),
-                        val_nullable: Some("non_null".into()),
-                    })
-                },
-            )
-            .await;
-        assert!(result.is_ok());
+        util.ensure_index("my_table", index).await.unwrap();
+    }
 
-        let object_after = result.unwrap();
-        assert_eq!(object_after.data.val_non_null, "new_data");
-        assert_eq!(object_after.data.val_nullable, Some("non_null".into()));
+    fn describe_table_output(
+        table_status: TableStatus,
+        index_status: IndexStatus,
+    ) -> DescribeTableOutput {
+        DescribeTableOutput::builder()
+            .table(
+                TableDescription::builder()
+                    .table_status(table_status)
+                    .global_secondary_indexes(
+                        GlobalSecondaryIndexDescription::builder()
+                            .index_name("owner_index")
+                            .index_status(index_status)
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build()
     }
 
     #[tokio::test]
-    async fn test_delete_item() {
+    async fn test_wait_for_table_active() {
         let mut backend = MockDynamoBackendImpl::new();
         backend
-            .expect_delete_item()
-            .with(
-                eq("my_table".to_string()),
-                eq::<HashMap<String, AttributeValue>>(collection! {
-                    "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
-                    "sk".to_string() => AttributeValue::S("LIST#123#TEST#456".to_string())
-                }),
-            )
-            .returning(|_, _| Ok(DeleteItemOutput::builder().build()));
+            .expect_describe_table()
+            .withf(|table| table == "my_table")
+            .times(1)
+            .returning(|_| {
+                Ok(describe_table_output(
+                    TableStatus::Active,
+                    IndexStatus::Active,
+                ))
+            });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let result = util
-            .delete_item::<TestDynamoObject>(PkSk {
-                pk: "GROUP#123".to_string(),
-                sk: "LIST#123#TEST#456".to_string(),
-            })
+        util.wait_for_table_active("my_table", std::time::Duration::from_secs(10))
             .await
             .unwrap();
-
-        assert_eq!(result, ());
     }
 
     #[tokio::test]
-    async fn test_delete_item_invalid_type() {
+    async fn test_wait_for_table_active_timeout() {
         let mut backend = MockDynamoBackendImpl::new();
-        backend
-            .expect_delete_item()
-            .with(
-                eq("my_table".to_string()),
-                eq::<HashMap<String, AttributeValue>>(collection! {
-                    "pk".to_string() => AttributeValue::S("GROUP#123".to_string()),
-                    "sk".to_string() => AttributeValue::S("LIST#123#WRONGTYPE#456".to_string())
-                }),
-            )
-            .returning(|_, _| Ok(DeleteItemOutput::builder().build()));
+        backend.expect_describe_table().times(1).returning(|_| {
+            Ok(describe_table_output(
+                TableStatus::Active,
+                IndexStatus::Creating,
+            ))
+        });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
         let result = util
-            .delete_item::<TestDynamoObject>(PkSk {
-                pk: "GROUP#123".to_string(),
-                sk: "LIST#123#WRONGTYPE#456".to_string(),
-            })
+            .wait_for_table_active("my_table", std::time::Duration::ZERO)
             .await;
-
         assert!(result.is_err());
     }
 
+    fn export_output(status: ExportStatus) -> ExportDescription {
+        ExportDescription::builder()
+            .export_arn("arn:export")
+            .export_status(status)
+            .build()
+    }
+
     #[tokio::test]
-    async fn test_batch_delete_item() {
+    async fn test_export_table() {
         let mut backend = MockDynamoBackendImpl::new();
+        backend.expect_describe_table().times(1).returning(|_| {
+            Ok(DescribeTableOutput::builder()
+                .table(TableDescription::builder().table_arn("arn:table").build())
+                .build())
+        });
         backend
-            .expect_batch_delete_item()
-            .with(
-                eq("my_table".to_string()),
-                eq(vec![
-                    collection! {
-                        "pk".to_string() => AttributeValue::S("ABC#123".to_string()),
-                        "sk".to_string() => AttributeValue::S("TEST#321".to_string()),
-                    },
-                    collection! {
-                        "pk".to_string() => AttributeValue::S("DEF#456".to_string()),
-                        "sk".to_string() => AttributeValue::S("TEST#654".to_string()),
-                    },
-                ]),
-            )
-            .returning(|_, _| Ok(BatchWriteItemOutput::builder().build()));
+            .expect_export_table_to_point_in_time()
+            .withf(|table_arn, bucket, prefix, _, export_time| {
+                table_arn == "arn:table"
+                    && bucket == "my_bucket"
+                    && prefix.as_deref() == Some("exports/")
+                    && export_time.is_none()
+            })
+            .times(1)
+            .returning(|_, _, _, _, _| {
+                Ok(ExportTableToPointInTimeOutput::builder()
+                    .export_description(export_output(ExportStatus::InProgress))
+                    .build())
+            });
+        backend
+            .expect_describe_export()
+            .with(eq("arn:export".to_string()))
+            .times(1)
+            .returning(|_| {
+                Ok(DescribeExportOutput::builder()
+                    .export_description(export_output(ExportStatus::Completed))
+                    .build())
+            });
 
         let util = DynamoUtil {
             backend,
             table: "my_table".to_string(),
+            table_router: Default::default(),
+            table_naming: Default::default(),
+            read_consistency: Default::default(),
+            retry: Default::default(),
+            timeout: Default::default(),
+            circuit_breaker: None,
+            cache: None,
+            batch_concurrency: 1,
+            principal: None,
+            metrics: None,
+            write_limiter: None,
+            cancellation: None,
+            updated_region: None,
         };
 
-        let keys = vec![
-            PkSk {
-                pk: "ABC#123".to_string(),
-                sk: "TEST#321".to_string(),
-            },
-            PkSk {
-                pk: "DEF#456".to_string(),
-                sk: "TEST#654".to_string(),
-            },
-        ];
-
-        let result = util
-            .batch_delete_item::<TestDynamoObject>(keys)
+        let mut statuses = Vec::new();
+        let progress = util
+            .export_table(
+                "my_table",
+                ExportOptions::new("my_bucket").with_prefix("exports/"),
+                std::time::Duration::from_millis(100),
+                |progress| statuses.push(progress.status.clone()),
+            )
             .await
             .unwrap();
-        assert_eq!(result, ());
+        assert_eq!(progress.export_arn, "arn:export");
+        assert_eq!(
+            statuses,
+            vec![ExportStatus::InProgress, ExportStatus::Completed]
+        );
     }
 }
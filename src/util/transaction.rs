@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use aws_sdk_dynamodb::{
+    operation::transact_write_items::TransactWriteItemsError,
+    types::{AttributeValue, ConditionCheck, Delete, Put, TransactWriteItem, Update},
+};
+use fractic_core::collection;
+use fractic_server_error::ServerError;
+
+use crate::{
+    errors::{
+        DynamoCalloutError, DynamoInvalidOperation, DynamoTransactionCancelled,
+        DynamoVersionConflict,
+    },
+    schema::{DynamoObject, PkSk},
+};
+
+use super::{
+    backend::DynamoBackendImpl, filter::FilterExpression, prepare_new_item, prepare_update,
+    validate_id, CreateOptions, DynamoMap, DynamoUtil, PreparedUpdate,
+};
+
+// Max number of items supported by DynamoDB in a single transaction.
+pub(crate) const MAX_TRANSACTION_ITEMS: usize = 100;
+
+/// Collects write operations (across any DynamoObject types) to be committed
+/// atomically: either all operations succeed, or none are applied.
+///
+/// Example:
+///   let mut transaction = util.transaction();
+///   let order = transaction.create::<Order>(user_id, order_data, None)?;
+///   transaction.update(&inventory)?;
+///   transaction.delete::<CartItem>(cart_item_id)?;
+///   transaction.commit().await?;
+pub struct DynamoTransaction<'a, B: DynamoBackendImpl> {
+    util: &'a DynamoUtil<B>,
+    items: Vec<TransactWriteItem>,
+    ids: Vec<PkSk>,
+    // Indexes (in 'items') of updates of versioned objects, whose failed
+    // conditions are reported as DynamoVersionConflict.
+    versioned_updates: Vec<usize>,
+    client_request_token: Option<String>,
+}
+
+/// Returned by DynamoTransaction::try_commit if DynamoDB cancels the
+/// transaction, with the reason for each operation which failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionCancelled {
+    pub reasons: Vec<TransactionCancellationReason>,
+}
+
+/// Why an operation of a cancelled transaction failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionCancellationReason {
+    /// Position of the operation in the transaction, in the order the
+    /// operations were added.
+    pub index: usize,
+    /// DynamoDB's reason code, such as "ConditionalCheckFailed" or
+    /// "TransactionConflict".
+    pub code: String,
+    pub message: Option<String>,
+}
+
+impl TransactionCancelled {
+    /// Whether the operation at 'index' failed because its condition (such as
+    /// an update's existence or version check) was not met.
+    pub fn is_condition_failure(&self, index: usize) -> bool {
+        self.reasons
+            .iter()
+            .any(|reason| reason.index == index && reason.code == "ConditionalCheckFailed")
+    }
+}
+
+impl<'a, B: DynamoBackendImpl> DynamoTransaction<'a, B> {
+    pub(crate) fn new(util: &'a DynamoUtil<B>) -> Self {
+        Self {
+            util,
+            items: Vec::new(),
+            ids: Vec::new(),
+            versioned_updates: Vec::new(),
+            client_request_token: None,
+        }
+    }
+
+    /// Adds the creation of a new object. The returned object (including its
+    /// newly generated ID) is only written once the transaction is committed.
+    pub fn create<T: DynamoObject>(
+        &mut self,
+        parent_id: PkSk,
+        data: T::Data,
+        options: Option<CreateOptions>,
+    ) -> Result<T, ServerError> {
+        let (id, map) = prepare_new_item::<T>(
+            &parent_id,
+            &data,
+            options.as_ref(),
+            self.util.principal.as_deref(),
+        )?;
+        let put = Put::builder()
+            .table_name(self.util.table_for::<T>())
+            .set_item(Some(map))
+            .build()
+            .expect("Invalid Put");
+        self.push(id.clone(), TransactWriteItem::builder().put(put).build());
+        Ok(T::new(id, data))
+    }
+
+    /// Adds an update of an existing object, with the same semantics as
+    /// DynamoUtil::update_item. If the object does not exist, the transaction
+    /// is cancelled.
+    pub fn update<T: DynamoObject>(&mut self, object: &T) -> Result<(), ServerError> {
+        let PreparedUpdate {
+            key,
+            update_expression,
+            attribute_values,
+            attribute_names,
+            condition_expression,
+        } = prepare_update(
+            object,
+            object.version(),
+            HashMap::default(),
+            vec![DynamoUtil::<B>::ITEM_EXISTS_CONDITION.to_string()],
+            self.util.principal.as_deref(),
+            self.util.updated_region.as_deref(),
+        )?;
+        let update = Update::builder()
+            .table_name(self.util.table_for::<T>())
+            .set_key(Some(key))
+            .update_expression(update_expression)
+            .set_expression_attribute_values(Some(attribute_values))
+            .set_expression_attribute_names(Some(attribute_names))
+            .condition_expression(condition_expression)
+            .build()
+            .expect("Invalid Update");
+        if T::versioned() {
+            self.versioned_updates.push(self.items.len());
+        }
+        self.push(
+            object.id().clone(),
+            TransactWriteItem::builder().update(update).build(),
+        );
+        Ok(())
+    }
+
+    pub fn delete<T: DynamoObject>(&mut self, id: PkSk) -> Result<(), ServerError> {
+        validate_id::<T>(&id)?;
+        let delete = Delete::builder()
+            .table_name(self.util.table_for::<T>())
+            .set_key(Some(key_for_id(&id)))
+            .build()
+            .expect("Invalid Delete");
+        self.push(id, TransactWriteItem::builder().delete(delete).build());
+        Ok(())
+    }
+
+    // Adds a write of a raw item (of any type), for internal operations which
+    // rewrite items without parsing them (such as DynamoUtil::move_item). The
+    // transaction is cancelled if an item already exists with the same ID.
+    pub(crate) fn put_raw(&mut self, table: &str, item: DynamoMap) -> Result<(), ServerError> {
+        let id = PkSk::from_map(&item)?;
+        let put = Put::builder()
+            .table_name(table)
+            .set_item(Some(item))
+            .condition_expression("attribute_not_exists(pk)")
+            .build()
+            .expect("Invalid Put");
+        self.push(id, TransactWriteItem::builder().put(put).build());
+        Ok(())
+    }
+
+    // Adds a delete of a raw item (of any type). See put_raw.
+    pub(crate) fn delete_raw(&mut self, table: &str, id: PkSk) {
+        let delete = Delete::builder()
+            .table_name(table)
+            .set_key(Some(key_for_id(&id)))
+            .build()
+            .expect("Invalid Delete");
+        self.push(id, TransactWriteItem::builder().delete(delete).build());
+    }
+
+    /// Adds a condition on an object which is not otherwise written by the
+    /// transaction. If the condition fails, the transaction is cancelled.
+    pub fn condition_check<T: DynamoObject>(
+        &mut self,
+        id: PkSk,
+        condition: FilterExpression,
+    ) -> Result<(), ServerError> {
+        validate_id::<T>(&id)?;
+        if condition.is_empty() {
+            return Err(DynamoInvalidOperation::new(
+                "condition check requires at least one condition",
+            ));
+        }
+        let (expression, names, values) = condition.build()?;
+        let check = ConditionCheck::builder()
+            .table_name(self.util.table_for::<T>())
+            .set_key(Some(key_for_id(&id)))
+            .condition_expression(expression)
+            .set_expression_attribute_names(Some(names))
+            .set_expression_attribute_values(match values.is_empty() {
+                true => None,
+                false => Some(values),
+            })
+            .build()
+            .expect("Invalid ConditionCheck");
+        // Condition checks don't modify the item, so it doesn't need to be
+        // evicted from the cache.
+        self.items
+            .push(TransactWriteItem::builder().condition_check(check).build());
+        Ok(())
+    }
+
+    /// Makes the commit idempotent: if a transaction with the same token was
+    /// already committed in the last 10 minutes (for example, by a previous
+    /// attempt of a retried Lambda invocation), the commit succeeds without
+    /// applying the operations again. Committing different operations with the
+    /// same token within that window fails.
+    ///
+    /// The token must be at most 36 characters long.
+    pub fn set_client_request_token(&mut self, token: impl Into<String>) {
+        self.client_request_token = Some(token.into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Commits all operations atomically. If the transaction is cancelled (for
+    /// example, because a condition failed), a DynamoTransactionCancelled error
+    /// is returned, listing the reason for each failed operation. As in
+    /// DynamoUtil::update_item, a failed condition on an update of a versioned
+    /// object is returned as DynamoVersionConflict instead. Use try_commit to
+    /// handle cancellations based on the individual reasons.
+    pub async fn commit(self) -> Result<(), ServerError> {
+        let versioned_updates = self.versioned_updates.clone();
+        let Err(cancelled) = self.try_commit().await? else {
+            return Ok(());
+        };
+        if let Some(index) = versioned_updates
+            .into_iter()
+            .find(|index| cancelled.is_condition_failure(*index))
+        {
+            return Err(DynamoVersionConflict::new(&format!(
+                "object updated by operation {} was modified or deleted concurrently",
+                index
+            )));
+        }
+        Err(DynamoTransactionCancelled::new(
+            &cancelled
+                .reasons
+                .iter()
+                .map(|reason| format!("item {}: {}", reason.index, reason.code))
+                .collect::<Vec<String>>()
+                .join(", "),
+        ))
+    }
+
+    /// Same as commit, but if DynamoDB cancels the transaction, returns
+    /// Ok(Err(TransactionCancelled)) with the reason for each failed operation,
+    /// so that the caller can tell which operation failed and why.
+    pub async fn try_commit(self) -> Result<Result<(), TransactionCancelled>, ServerError> {
+        if self.items.is_empty() {
+            return Ok(Ok(()));
+        }
+        if self.items.len() > MAX_TRANSACTION_ITEMS {
+            return Err(DynamoInvalidOperation::new(&format!(
+                "transaction contains {} items, but at most {} are supported",
+                self.items.len(),
+                MAX_TRANSACTION_ITEMS
+            )));
+        }
+        // DynamoDB deduplicates transactions with a client request token, so
+        // only those are safe to retry if an attempt times out.
+        let result = self
+            .util
+            .send_with(self.client_request_token.is_some(), || {
+                self.util
+                    .backend
+                    .transact_write_items(self.items.clone(), self.client_request_token.clone())
+            })
+            .await?;
+        let response = match result.map_err(|e| e.into_service_error()) {
+            Ok(response) => response,
+            Err(TransactWriteItemsError::TransactionCanceledException(e)) => {
+                // Operations which didn't fail are reported with code "None".
+                let reasons = e
+                    .cancellation_reasons()
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, reason)| reason.code() != Some("None"))
+                    .map(|(index, reason)| TransactionCancellationReason {
+                        index,
+                        code: reason.code().unwrap_or("Unknown").to_string(),
+                        message: reason.message().map(str::to_string),
+                    })
+                    .collect();
+                return Ok(Err(TransactionCancelled { reasons }));
+            }
+            Err(TransactWriteItemsError::IdempotentParameterMismatchException(_)) => {
+                return Err(DynamoInvalidOperation::new(
+                    "client request token was already used for a different transaction",
+                ));
+            }
+            Err(other) => return Err(DynamoCalloutError::with_debug(&other)),
+        };
+        if self.util.metrics.is_some() {
+            let keys: Vec<DynamoMap> = self.ids.iter().map(key_for_id).collect();
+            self.util.record_capacity(
+                "TransactWriteItems",
+                response.consumed_capacity.iter().flatten(),
+                &keys,
+            );
+        }
+        for id in &self.ids {
+            self.util.invalidate_cache(id);
+        }
+        Ok(Ok(()))
+    }
+
+    fn push(&mut self, id: PkSk, item: TransactWriteItem) {
+        self.items.push(item);
+        self.ids.push(id);
+    }
+}
+
+fn key_for_id(id: &PkSk) -> DynamoMap {
+    collection! {
+        "pk".to_string() => AttributeValue::S(id.pk.clone()),
+        "sk".to_string() => AttributeValue::S(id.sk.clone()),
+    }
+}
@@ -0,0 +1,156 @@
+use std::{collections::HashMap, sync::Arc};
+
+use fractic_env_config::EnvVariables;
+use fractic_server_error::ServerError;
+
+use crate::{env::DynamoEnvConfig, errors::DynamoInvalidOperation};
+
+use super::{
+    backend::DynamoBackendImpl, cache::DynamoCache, table_router::TableRouter, DynamoUtil,
+};
+
+/// Several DynamoUtils keyed by logical name (such as "main", "audit" or
+/// "cache"), for services using more than one table:
+///
+///   let utils = DynamoUtilSet::new(env)
+///       .await?
+///       .with_table("main", "my_table")
+///       .with_table("audit", "my_audit_table");
+///   utils.util("audit")?.create_item(...)
+///
+/// Utils added with with_table are copies of the set's template, sharing its
+/// backend (and so its client and credentials) and settings, such as retries,
+/// timeouts, the circuit breaker and the principal. Each util gets its own
+/// cache (if the template has one) and no table router, since those are
+/// specific to a table. Settings should therefore be configured on the
+/// template before adding tables.
+#[derive(Debug, Clone)]
+pub struct DynamoUtilSet<B: DynamoBackendImpl> {
+    template: DynamoUtil<B>,
+    utils: HashMap<String, DynamoUtil<B>>,
+}
+
+impl<B: DynamoBackendImpl + Clone> DynamoUtilSet<B> {
+    /// Set whose utils are created from 'template' (whose own table is not
+    /// used).
+    pub fn from_template(template: DynamoUtil<B>) -> Self {
+        Self {
+            template,
+            utils: HashMap::new(),
+        }
+    }
+
+    /// Adds a util using 'table', created from the template. The template's
+    /// table_naming is applied to the table name.
+    pub fn with_table(mut self, name: impl Into<String>, table: impl Into<String>) -> Self {
+        let util = DynamoUtil {
+            table: self.template.table_naming.apply(&table.into()),
+            table_router: TableRouter::default(),
+            cache: self
+                .template
+                .cache
+                .as_ref()
+                .map(|cache| Arc::new(DynamoCache::new(cache.ttl()))),
+            ..self.template.clone()
+        };
+        self.utils.insert(name.into(), util);
+        self
+    }
+
+    /// Adds a separately configured util.
+    pub fn with_util(mut self, name: impl Into<String>, util: DynamoUtil<B>) -> Self {
+        self.utils.insert(name.into(), util);
+        self
+    }
+
+    /// The template utils are created from, for example to adjust its
+    /// settings before adding tables.
+    pub fn template_mut(&mut self) -> &mut DynamoUtil<B> {
+        &mut self.template
+    }
+
+    pub fn get(&self, name: &str) -> Option<&DynamoUtil<B>> {
+        self.utils.get(name)
+    }
+
+    /// Same as get, but returns DynamoInvalidOperation if no util was added
+    /// with the given name.
+    pub fn util(&self, name: &str) -> Result<&DynamoUtil<B>, ServerError> {
+        self.get(name).ok_or_else(|| {
+            DynamoInvalidOperation::new(&format!("no DynamoUtil named '{}' in the set", name))
+        })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.utils.keys().map(String::as_str)
+    }
+}
+
+impl DynamoUtilSet<aws_sdk_dynamodb::Client> {
+    /// Set of utils sharing a single client, created in the same way as
+    /// DynamoUtil::new. Tables are added with with_table.
+    pub async fn new(env: EnvVariables<DynamoEnvConfig>) -> Result<Self, ServerError> {
+        Ok(Self::from_template(
+            DynamoUtil::new(env, String::new()).await?,
+        ))
+    }
+}
+
+// Tests.
+// --------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use aws_sdk_dynamodb::types::AttributeValue;
+    use fractic_core::collection;
+
+    use super::*;
+    use crate::util::memory_backend::MemoryDynamoBackend;
+
+    fn set() -> DynamoUtilSet<MemoryDynamoBackend> {
+        DynamoUtilSet::from_template(
+            DynamoUtil::new_in_memory("unused")
+                .with_batch_concurrency(2)
+                .with_cache(std::time::Duration::from_secs(60)),
+        )
+        .with_table("main", "main_table")
+        .with_table("audit", "audit_table")
+    }
+
+    #[tokio::test]
+    async fn test_util_set() {
+        let utils = set();
+        let audit = utils.util("audit").unwrap();
+        assert_eq!(audit.table, "audit_table");
+        assert_eq!(audit.batch_concurrency, 2);
+        audit
+            .raw_batch_put_item(vec![collection! {
+                "pk".to_string() => AttributeValue::S("ROOT".to_string()),
+                "sk".to_string() => AttributeValue::S("LOG#1".to_string()),
+            }])
+            .await
+            .unwrap();
+
+        // Utils share the backend, but write to their own table.
+        let main = utils.get("main").unwrap();
+        assert_eq!(main.backend.items("audit_table").len(), 1);
+        assert!(main.backend.items("main_table").is_empty());
+
+        // Caches are per table.
+        assert!(!Arc::ptr_eq(
+            main.cache.as_ref().unwrap(),
+            audit.cache.as_ref().unwrap()
+        ));
+
+        let mut names = utils.names().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["audit", "main"]);
+    }
+
+    #[test]
+    fn test_unknown_util() {
+        let utils = set();
+        assert!(utils.get("cache").is_none());
+        assert!(utils.util("cache").is_err());
+    }
+}